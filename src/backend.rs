@@ -0,0 +1,470 @@
+//! The terminal everything is drawn on. The game, the browser and the practice view only talk to a [`Backend`], the
+//! implementation is picked with `--backend`: `console` is the default, except on Windows where it's `crossterm`
+//! because console can't read the arrow keys of the old console host. Both remember the rows they drew and skip the ones that didn't change, so redrawing a whole frame only
+//! sends the dirty rows to the terminal.
+//!
+//! Taps and clicks on the board are only read by crossterm, console doesn't understand mouse events. Termux on phones
+//! reports taps as clicks.
+
+use std::{cell::{Cell, RefCell}, collections::VecDeque, io::{self, Write}, sync::mpsc::{self, Receiver, RecvTimeoutError}, thread, time::Duration};
+
+use console::{Key, Term};
+use crossterm::{
+    cursor, event::{self, Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind}, queue, terminal,
+};
+
+use crate::colors::Colors;
+
+pub trait Backend {
+    /// Rows and columns
+    fn size(&self) -> (u16, u16);
+    /// Replaces row `y` from column `x` on with the text, which can have the color codes of `cformat!`
+    fn draw(&self, x: u16, y: u16, text: &str) -> io::Result<()>;
+    /// Empties the screen
+    fn clear(&self) -> io::Result<()>;
+    fn flush(&self) -> io::Result<()>;
+    /// Waits up to `timeout` for a key, forever if it's `None`. The keys are `console`'s for both backends.
+    fn read_key(&self, timeout: Option<Duration>) -> io::Result<Option<Key>>;
+    /// Like `read_key`, but also reports taps. Backends that can't read them only return keys.
+    fn read_input(&self, timeout: Option<Duration>) -> io::Result<Option<Input>> {
+        Ok(self.read_key(timeout)?.map(Input::Key))
+    }
+    /// Waits for the next key
+    fn wait_key(&self) -> io::Result<Key> {
+        loop {
+            if let Some(key) = self.read_key(None)? {
+                return Ok(key);
+            }
+        }
+    }
+    fn enter_alt_screen(&self) -> io::Result<()>;
+    fn leave_alt_screen(&self) -> io::Result<()>;
+    fn show_cursor(&self) -> io::Result<()>;
+    fn hide_cursor(&self) -> io::Result<()>;
+    /// Sets the title of the terminal window, tmux shows it as the pane title
+    fn set_title(&self, title: &str) -> io::Result<()>;
+    /// Brings back the title from before the first `set_title`
+    fn restore_title(&self) -> io::Result<()>;
+}
+
+#[derive(Clone, Debug)]
+pub enum Input {
+    Key(Key),
+    /// Column and row of a tap or left click
+    Tap(u16, u16),
+}
+
+/// The names accepted by `--backend`
+pub const NAMES: [&str; 2] = ["console", "crossterm"];
+
+/// The backend used without `--backend`
+pub const DEFAULT: &str = if cfg!(windows) { "crossterm" } else { "console" };
+
+/// Opens the backend, which converts the colors of the drawn text to `colors`
+pub fn open(name: &str, colors: Colors) -> Result<Box<dyn Backend>, String> {
+    let colors = colors.resolve();
+    match name {
+        "console" => Ok(Box::new(ConsoleBackend::new(colors))),
+        "crossterm" => Ok(Box::new(CrosstermBackend::new(colors))),
+        _ => Err(format!("unknown backend `{name}`, expected one of {}", NAMES.join(", "))),
+    }
+}
+
+/// What each row shows, so drawing the same text again can be skipped
+#[derive(Default)]
+struct Rows(RefCell<Vec<Option<(u16, String)>>>);
+impl Rows {
+    /// Remembers the text and returns whether it differs from what the row shows
+    fn changed(&self, x: u16, y: u16, text: &str) -> bool {
+        let mut rows = self.0.borrow_mut();
+        let y = y as usize;
+        if rows.len() <= y {
+            rows.resize(y + 1, None);
+        }
+        let row = Some((x, text.to_owned()));
+        if rows[y] == row {
+            return false;
+        }
+        rows[y] = row;
+        true
+    }
+
+    fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+/// Whether the title was changed. The title from before is pushed on xterm's title stack then and popped to restore
+/// it, terminals without the stack get an empty title instead which lets them show their own again. The sequences are
+/// written by themselves outside of the drawn rows, so they don't move the cursor.
+#[derive(Default)]
+struct Title(Cell<bool>);
+impl Title {
+    fn set(&self, title: &str) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        if !self.0.replace(true) {
+            write!(stdout, "\x1b[22;0t")?;
+        }
+        // names come from the network, they mustn't end the sequence early
+        let title: String = title.chars().filter(|c| !c.is_control()).collect();
+        write!(stdout, "\x1b]2;{title}\x07")?;
+        stdout.flush()
+    }
+
+    fn restore(&self) -> io::Result<()> {
+        if !self.0.replace(false) {
+            return Ok(());
+        }
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b]2;\x07\x1b[23;0t")?;
+        stdout.flush()
+    }
+}
+
+pub struct ConsoleBackend {
+    term: Term,
+    rows: Rows,
+    /// `Term::read_key` can't time out, so keys are read on a thread that is started by the first read. Starting it
+    /// earlier would take the input from prompts that read whole lines.
+    keys: RefCell<Option<Receiver<Key>>>,
+    colors: Colors,
+    title: Title,
+}
+impl ConsoleBackend {
+    pub fn new(colors: Colors) -> Self {
+        Self { term: Term::stdout(), rows: Rows::default(), keys: RefCell::new(None), colors, title: Title::default() }
+    }
+}
+impl Backend for ConsoleBackend {
+    fn size(&self) -> (u16, u16) {
+        self.term.size()
+    }
+
+    fn draw(&self, x: u16, y: u16, text: &str) -> io::Result<()> {
+        if !self.rows.changed(x, y, text) {
+            return Ok(());
+        }
+        self.term.move_cursor_to(0, y as usize)?;
+        self.term.clear_line()?;
+        self.term.move_cursor_to(x as usize, y as usize)?;
+        print!("{}", self.colors.convert(text));
+        Ok(())
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        self.rows.clear();
+        self.term.clear_screen()
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+
+    fn read_key(&self, timeout: Option<Duration>) -> io::Result<Option<Key>> {
+        let mut keys = self.keys.borrow_mut();
+        let keys = keys.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel();
+            let term = self.term.clone();
+            thread::spawn(move || {
+                while let Ok(key) = term.read_key() {
+                    if tx.send(normalize(key)).is_err() {
+                        break;
+                    }
+                }
+            });
+            rx
+        });
+        let disconnected = || io::Error::new(io::ErrorKind::BrokenPipe, "the terminal stopped sending keys");
+        match timeout {
+            None => keys.recv().map(Some).map_err(|_| disconnected()),
+            Some(timeout) => match keys.recv_timeout(timeout) {
+                Ok(key) => Ok(Some(key)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(disconnected()),
+            },
+        }
+    }
+
+    fn enter_alt_screen(&self) -> io::Result<()> {
+        self.rows.clear();
+        print!("\x1b[?1049h");
+        self.flush()
+    }
+
+    fn leave_alt_screen(&self) -> io::Result<()> {
+        self.rows.clear();
+        print!("\x1b[?1049l");
+        self.flush()
+    }
+
+    fn show_cursor(&self) -> io::Result<()> {
+        self.term.show_cursor()
+    }
+
+    fn hide_cursor(&self) -> io::Result<()> {
+        self.term.hide_cursor()
+    }
+
+    fn set_title(&self, title: &str) -> io::Result<()> {
+        self.title.set(title)
+    }
+
+    fn restore_title(&self) -> io::Result<()> {
+        self.title.restore()
+    }
+}
+
+pub struct CrosstermBackend {
+    rows: Rows,
+    colors: Colors,
+    title: Title,
+}
+impl CrosstermBackend {
+    pub fn new(colors: Colors) -> Self {
+        Self { rows: Rows::default(), colors, title: Title::default() }
+    }
+}
+impl CrosstermBackend {
+    fn read(&self, timeout: Option<Duration>, mouse: bool) -> io::Result<Option<Input>> {
+        // raw mode only while reading, like console does it, so the lines printed in between still start at the left.
+        // The same goes for the mouse, the terminal's own selection keeps working the rest of the time.
+        terminal::enable_raw_mode()?;
+        if mouse {
+            crossterm::execute!(io::stdout(), event::EnableMouseCapture)?;
+        }
+        let input = read_crossterm(timeout);
+        if mouse {
+            crossterm::execute!(io::stdout(), event::DisableMouseCapture)?;
+        }
+        terminal::disable_raw_mode()?;
+        // raw mode turns ^C into a key, console ends the program for it and so does this
+        if let Ok(Some(Input::Key(Key::CtrlC))) = input {
+            self.show_cursor()?;
+            self.restore_title()?;
+            std::process::exit(130);
+        }
+        input
+    }
+}
+impl Backend for CrosstermBackend {
+    fn size(&self) -> (u16, u16) {
+        // the same fallback as console
+        terminal::size().map_or((24, 79), |(cols, rows)| (rows, cols))
+    }
+
+    fn draw(&self, x: u16, y: u16, text: &str) -> io::Result<()> {
+        if !self.rows.changed(x, y, text) {
+            return Ok(());
+        }
+        let mut stdout = io::stdout();
+        queue!(stdout, cursor::MoveTo(0, y), terminal::Clear(terminal::ClearType::CurrentLine), cursor::MoveTo(x, y))?;
+        write!(stdout, "{}", self.colors.convert(text))
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        self.rows.clear();
+        crossterm::execute!(io::stdout(), terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0))
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+
+    /// A tap ends the wait without a key, the mouse isn't captured while only keys are read so there are none
+    fn read_key(&self, timeout: Option<Duration>) -> io::Result<Option<Key>> {
+        Ok(self.read(timeout, false)?.and_then(|input| match input {
+            Input::Key(key) => Some(key),
+            Input::Tap(..) => None,
+        }))
+    }
+
+    fn read_input(&self, timeout: Option<Duration>) -> io::Result<Option<Input>> {
+        self.read(timeout, true)
+    }
+
+    fn enter_alt_screen(&self) -> io::Result<()> {
+        self.rows.clear();
+        crossterm::execute!(io::stdout(), terminal::EnterAlternateScreen)
+    }
+
+    fn leave_alt_screen(&self) -> io::Result<()> {
+        self.rows.clear();
+        crossterm::execute!(io::stdout(), terminal::LeaveAlternateScreen)
+    }
+
+    fn show_cursor(&self) -> io::Result<()> {
+        crossterm::execute!(io::stdout(), cursor::Show)
+    }
+
+    fn hide_cursor(&self) -> io::Result<()> {
+        crossterm::execute!(io::stdout(), cursor::Hide)
+    }
+
+    fn set_title(&self, title: &str) -> io::Result<()> {
+        self.title.set(title)
+    }
+
+    fn restore_title(&self) -> io::Result<()> {
+        self.title.restore()
+    }
+}
+
+/// Draws into memory and plays back given keys, for running the game without a terminal
+pub struct MemoryBackend {
+    size: (u16, u16),
+    rows: Rows,
+    keys: RefCell<VecDeque<Key>>,
+    colors: Colors,
+    frames: Cell<usize>,
+    title: RefCell<String>,
+}
+impl MemoryBackend {
+    pub fn new(size: (u16, u16), keys: impl IntoIterator<Item = Key>, colors: Colors) -> Self {
+        let keys = RefCell::new(keys.into_iter().collect());
+        Self { size, rows: Rows::default(), keys, colors, frames: Cell::new(0), title: RefCell::default() }
+    }
+
+    /// The title that was set last, empty if there is none
+    pub fn title(&self) -> String {
+        self.title.borrow().clone()
+    }
+
+    /// How often the screen was flushed
+    pub fn frames(&self) -> usize {
+        self.frames.get()
+    }
+
+    /// The drawn rows as the terminal would show them, with the converted color codes
+    pub fn screen(&self) -> String {
+        self.rows.0.borrow().iter()
+            .map(|row| row.as_ref().map_or(String::new(), |(x, text)| {
+                format!("{}{}", " ".repeat(*x as usize), self.colors.convert(text))
+            }))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+impl Backend for MemoryBackend {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn draw(&self, x: u16, y: u16, text: &str) -> io::Result<()> {
+        self.rows.changed(x, y, text);
+        Ok(())
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        self.rows.clear();
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.frames.set(self.frames.get() + 1);
+        Ok(())
+    }
+
+    /// Waiting without a timeout fails once all keys were read, it would never end
+    fn read_key(&self, timeout: Option<Duration>) -> io::Result<Option<Key>> {
+        match (self.keys.borrow_mut().pop_front(), timeout) {
+            (Some(key), _) => Ok(Some(key)),
+            (None, Some(_)) => Ok(None),
+            (None, None) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no keys left")),
+        }
+    }
+
+    fn enter_alt_screen(&self) -> io::Result<()> {
+        self.clear()
+    }
+
+    fn leave_alt_screen(&self) -> io::Result<()> {
+        self.clear()
+    }
+
+    fn show_cursor(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn hide_cursor(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_title(&self, title: &str) -> io::Result<()> {
+        *self.title.borrow_mut() = title.to_owned();
+        Ok(())
+    }
+
+    fn restore_title(&self) -> io::Result<()> {
+        self.title.borrow_mut().clear();
+        Ok(())
+    }
+}
+
+/// The visible characters of a line of drawn text with the background color code they are drawn on
+pub fn cells(line: &str) -> Vec<(char, Option<String>)> {
+    let mut cells = Vec::new();
+    let mut background = None;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if let Some(sequence) = rest.strip_prefix("\x1b[") {
+            let end = sequence.find('m').unwrap_or(sequence.len());
+            let params = &sequence[..end];
+            if params.is_empty() || params == "0" {
+                background = None;
+            } else if let Some(start) = params.find("48;") {
+                background = Some(params[start..].to_owned());
+            }
+            rest = &sequence[(end + 1).min(sequence.len())..];
+        } else {
+            cells.push((c, background.clone()));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    cells
+}
+
+fn read_crossterm(timeout: Option<Duration>) -> io::Result<Option<Input>> {
+    loop {
+        if let Some(timeout) = timeout {
+            if !event::poll(timeout)? {
+                return Ok(None);
+            }
+        }
+        // Windows also reports releases, resizes are picked up by the next frame anyway
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            Event::Mouse(mouse) if mouse.kind == MouseEventKind::Down(MouseButton::Left) => {
+                return Ok(Some(Input::Tap(mouse.column, mouse.row)));
+            }
+            _ => continue,
+        };
+        return Ok(Some(Input::Key(match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Key::CtrlC,
+            KeyCode::Char(c) => normalize(Key::Char(c)),
+            KeyCode::Left => Key::ArrowLeft,
+            KeyCode::Right => Key::ArrowRight,
+            KeyCode::Up => Key::ArrowUp,
+            KeyCode::Down => Key::ArrowDown,
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Esc => Key::Escape,
+            KeyCode::Backspace => Key::Backspace,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::Tab => Key::Tab,
+            KeyCode::BackTab => Key::BackTab,
+            KeyCode::Delete => Key::Del,
+            KeyCode::Insert => Key::Insert,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            _ => Key::Unknown,
+        })));
+    }
+}
+
+/// Some terminals send Enter as a carriage return or line feed character instead, mostly on Windows
+fn normalize(key: Key) -> Key {
+    match key {
+        Key::Char('\r' | '\n') => Key::Enter,
+        key => key,
+    }
+}
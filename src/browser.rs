@@ -0,0 +1,230 @@
+use std::{error::Error, fs, path::{Path, PathBuf}, time::SystemTime};
+
+use color_format::cformat;
+use console::{Key, truncate_str};
+use vecm::vec2;
+
+use crate::{
+    backend::Backend, config::Config, lang::tr, notation, pgn::{self, PgnGame, Tags}, piece::Piece, practice, variations::Cursor,
+};
+
+struct Entry {
+    path: PathBuf,
+    // None if the file couldn't be read as PGN
+    tags: Option<Tags>,
+}
+impl Entry {
+    fn line(&self) -> String {
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let Some(tags) = &self.tags else {
+            return cformat!("#r<{}>  {}", tr!("browser.unreadable"), file_name);
+        };
+        let tag = |name| pgn::tag(tags, name).unwrap_or("?");
+        let result = format!("{:<7}", tag("Result"));
+        let mut line = tr!("browser.entry", date = tag("Date"), white = tag("White"), black = tag("Black"), result = result);
+        if let Some(opening) = pgn::tag(tags, "Opening") {
+            line.push_str("  ");
+            line.push_str(opening);
+        }
+        line
+    }
+}
+
+fn list(dir: &Path) -> Vec<Entry> {
+    let Ok(read_dir) = fs::read_dir(dir) else { return Vec::new() };
+    let mut files: Vec<(SystemTime, PathBuf)> = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pgn"))
+        .map(|path| (fs::metadata(&path).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH), path))
+        .collect();
+    // newest first
+    files.sort_by(|a, b| b.cmp(a));
+    files.into_iter()
+        .map(|(_, path)| Entry { tags: pgn::read_tags(&path).ok(), path })
+        .collect()
+}
+
+/// Lists the saved games and lets the user open them in the replay view or delete them
+pub fn run(term: &dyn Backend, config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut entries = list(&config.games_dir);
+    let mut selected = 0;
+    let mut scroll = 0;
+    let mut status = String::new();
+
+    term.enter_alt_screen()?;
+    term.hide_cursor()?;
+    term.clear()?;
+    loop {
+        let (rows, cols) = term.size();
+        let visible = (rows as usize).saturating_sub(4).max(1);
+        if selected < scroll {
+            scroll = selected;
+        } else if selected >= scroll + visible {
+            scroll = selected + 1 - visible;
+        }
+
+        term.draw(0, 0, &tr!("browser.title", folder = config.games_dir.display(), count = entries.len()))?;
+        for i in 0..visible {
+            let y = i as u16 + 2;
+            let Some(entry) = entries.get(scroll + i) else {
+                term.draw(0, y, "")?;
+                continue;
+            };
+            let line = entry.line();
+            let line = truncate_str(&line, (cols as usize).saturating_sub(2), "…");
+            if scroll + i == selected {
+                term.draw(0, y, &cformat!("#b<>> {}", line))?;
+            } else {
+                term.draw(0, y, &format!("  {line}"))?;
+            }
+        }
+        if status.is_empty() {
+            status = tr!("browser.keys");
+        }
+        term.draw(0, rows - 1, &truncate_str(&status, cols as usize, "…"))?;
+        term.flush()?;
+        status.clear();
+
+        match term.wait_key()? {
+            Key::ArrowUp | Key::Char('e') => selected = selected.saturating_sub(1),
+            Key::ArrowDown | Key::Char('n') => if selected + 1 < entries.len() { selected += 1 },
+            Key::Enter | Key::Char(' ') => if let Some(entry) = entries.get(selected) {
+                let pgn = fs::read_to_string(&entry.path).map_err(Box::<dyn Error>::from)
+                    .and_then(|text| pgn::parse(&text).ok_or_else(|| "invalid PGN".into()));
+                if let Err(err) = pgn.and_then(|pgn| replay(term, pgn, Some(&entry.path), 0, config)) {
+                    status = tr!("browser.cant_open", path = entry.path.display(), error = err);
+                }
+                term.clear()?;
+            }
+            Key::Char('d') | Key::Del => if let Some(entry) = entries.get(selected) {
+                term.draw(0, rows - 1, &tr!("browser.delete", path = entry.path.display()))?;
+                term.flush()?;
+                if term.wait_key()? == Key::Char('y') {
+                    match fs::remove_file(&entry.path) {
+                        Ok(()) => {
+                            entries.remove(selected);
+                            selected = selected.min(entries.len().saturating_sub(1));
+                        }
+                        Err(err) => status = tr!("browser.delete_failed", error = err),
+                    }
+                }
+            }
+            Key::Escape | Key::Char('q') => break,
+            _ => {}
+        }
+    }
+    term.clear()?;
+    term.show_cursor()?;
+    term.leave_alt_screen()?;
+    Ok(())
+}
+
+/// Steps through a game starting at `ply` of the main line. Moves can be tried out from any position, they are added
+/// as variations that can be saved into the file the game was read from, games without one can't be saved.
+pub fn replay(term: &dyn Backend, mut pgn: PgnGame, path: Option<&Path>, ply: usize, config: &Config) -> Result<(), Box<dyn Error>> {
+    let (_, start_turn) = pgn.start()?;
+    pgn.to_game()?;
+    let mut cursor = Cursor::default();
+    for _ in 0..ply {
+        pgn.moves.forward(&mut cursor);
+    }
+    // a move that is being typed in SAN
+    let mut typing: Option<String> = None;
+    // games from before the tag are assumed to follow the current rules
+    let mut status = match pgn::tag(&pgn.tags, "Rules") {
+        Some(rules) if rules != pgn::rules() => tr!("replay.other_rules", rules = rules, current = pgn::rules()),
+        _ => String::new(),
+    };
+    // variations were added since the file was read or saved
+    let mut changed = false;
+
+    term.clear()?;
+    loop {
+        let mut game = pgn.replay(&pgn.moves.moves_to(&cursor))?;
+        // no cursor in the replay view
+        game.cursor = vec2![-1, -1];
+        game.glyphs = config.glyphs.clone();
+        let (rows, cols) = term.size();
+        // the board takes up the first 14 rows, the last one is for the status
+        let list_height = (rows as usize).saturating_sub(17).max(1);
+        game.status = Some(pgn.moves.display(start_turn, &cursor, cols as usize - 2, list_height).join("\n"));
+        crate::render(&game, term)?;
+        let line = match &typing {
+            Some(text) => tr!("replay.typing", text = text),
+            None if !status.is_empty() => std::mem::take(&mut status),
+            None => {
+                let ply = game.history.len();
+                let position = match game.history.last() {
+                    Some(last) => tr!("replay.ply", ply = ply, san = last.san),
+                    None => tr!("replay.start"),
+                };
+                let save = if path.is_some() { format!("  {}", tr!("replay.save_key")) } else { String::new() };
+                format!("{position}  {}{save}  {}", tr!("replay.keys"), tr!("replay.back_keys"))
+            }
+        };
+        term.draw(0, rows - 1, &truncate_str(&line, cols as usize, "…"))?;
+        term.flush()?;
+
+        let key = term.wait_key()?;
+        if let Some(text) = &mut typing {
+            match key {
+                Key::Enter => {
+                    match notation::parse_san(&game.board, game.turn, text) {
+                        Some((from, to, promotion)) => {
+                            let promotion = promotion.unwrap_or(Piece::Queen);
+                            let san = notation::san_promote(&game.board, &game.possible_moves, from, to, promotion);
+                            changed |= pgn.moves.play(&mut cursor, &san);
+                        }
+                        None => status = tr!("replay.illegal", san = text),
+                    }
+                    typing = None;
+                }
+                Key::Escape => typing = None,
+                Key::Backspace => { text.pop(); }
+                Key::Char(c) if c.is_ascii_graphic() => text.push(c),
+                _ => {}
+            }
+            continue;
+        }
+        match key {
+            Key::ArrowLeft | Key::Char('m') => { pgn.moves.back(&mut cursor); }
+            Key::ArrowRight | Key::Char('i') => { pgn.moves.forward(&mut cursor); }
+            Key::ArrowDown | Key::Char('n') => if !pgn.moves.enter(&mut cursor) {
+                status = tr!("replay.no_variation");
+            }
+            Key::ArrowUp | Key::Char('e') => { pgn.moves.exit(&mut cursor); }
+            Key::Tab => { pgn.moves.next_sibling(&mut cursor); }
+            Key::Home => cursor = Cursor::default(),
+            Key::End => while pgn.moves.forward(&mut cursor) {},
+            Key::Enter | Key::Char(' ') => typing = Some(String::new()),
+            Key::Char('w') => match path.map(|path| (path, fs::write(path, pgn.write()))) {
+                Some((path, Ok(()))) => {
+                    changed = false;
+                    status = tr!("replay.saved", path = path.display());
+                }
+                Some((_, Err(err))) => status = tr!("replay.save_failed", error = err),
+                None => {}
+            }
+            Key::Char('p') => {
+                let original = pgn.moves.next_move(&cursor)
+                    .and_then(|san| notation::parse_san(&game.board, game.turn, san))
+                    .map(|(from, to, _)| (from, to));
+                practice::run(term, &game, original, config.score_format)?;
+                term.clear()?;
+            }
+            Key::Escape | Key::Char('q') => {
+                if changed && path.is_some() {
+                    term.draw(0, rows - 1, &tr!("replay.leave"))?;
+                    term.flush()?;
+                    if term.wait_key()? != Key::Char('y') {
+                        continue;
+                    }
+                }
+                break;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+//! The colors of the board are hardcoded RGB values, which only truecolor terminals show as they are. For the others
+//! the color codes in the drawn text are replaced by the nearest colors of the 256 or 16 color palettes, or removed.
+
+use std::{borrow::Cow, env};
+
+/// How many colors the terminal can show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum Colors {
+    /// Detected from the environment when the terminal is opened
+    Auto,
+    TrueColor,
+    #[cfg_attr(feature = "serde", serde(rename = "256"))]
+    Palette256,
+    #[cfg_attr(feature = "serde", serde(rename = "16"))]
+    Basic16,
+    None,
+}
+impl Colors {
+    pub const ALL: [Self; 5] = [Self::Auto, Self::TrueColor, Self::Palette256, Self::Basic16, Self::None];
+
+    /// The name in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::TrueColor => "truecolor",
+            Self::Palette256 => "256",
+            Self::Basic16 => "16",
+            Self::None => "none",
+        }
+    }
+
+    /// `Auto` replaced by what the environment supports. On Windows this also turns on the processing of escape
+    /// codes, without it the console can't show any colors.
+    pub fn resolve(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+        let dumb = env::var("TERM").is_ok_and(|term| term == "dumb");
+        if env::var_os("NO_COLOR").is_some() || dumb || !escape_codes_enabled() {
+            return Self::None;
+        }
+        if env::var("COLORTERM").is_ok_and(|colorterm| colorterm == "truecolor" || colorterm == "24bit") {
+            return Self::TrueColor;
+        }
+        if cfg!(windows) {
+            // Windows Terminal sets WT_SESSION, the old console host only gets the palette right
+            return if env::var_os("WT_SESSION").is_some() { Self::TrueColor } else { Self::Palette256 };
+        }
+        match env::var("TERM") {
+            Ok(term) if term == "linux" => Self::Basic16,
+            Ok(term) if term.ends_with("256color") => Self::Palette256,
+            _ => Self::TrueColor,
+        }
+    }
+
+    /// The text with its color codes converted to what the terminal can show
+    pub fn convert(self, text: &str) -> Cow<'_, str> {
+        if matches!(self, Self::Auto | Self::TrueColor) || !text.contains('\x1b') {
+            return Cow::Borrowed(text);
+        }
+        let mut converted = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("\x1b[") {
+            converted.push_str(&rest[..start]);
+            rest = &rest[start + 2..];
+            // only SGR sequences (`ESC [ ... m`) set colors, others are kept as they are
+            let end = rest.find(|c: char| !(c.is_ascii_digit() || c == ';'));
+            match end {
+                Some(end) if rest[end..].starts_with('m') => {
+                    let params = self.convert_params(&rest[..end]);
+                    if !params.is_empty() || rest[..end].is_empty() {
+                        converted.push_str("\x1b[");
+                        converted.push_str(&params);
+                        converted.push('m');
+                    }
+                    rest = &rest[end + 1..];
+                }
+                _ => converted.push_str("\x1b["),
+            }
+        }
+        converted.push_str(rest);
+        Cow::Owned(converted)
+    }
+
+    fn convert_params(self, params: &str) -> String {
+        let params: Vec<u16> = params.split(';').map(|param| param.parse().unwrap_or(0)).collect();
+        let mut converted = Vec::new();
+        let mut i = 0;
+        while i < params.len() {
+            let param = params[i];
+            i += 1;
+            let background = param == 48 || (40..=49).contains(&param) || (100..=107).contains(&param);
+            let rgb = match (param, params.get(i)) {
+                (38 | 48, Some(2)) if i + 3 < params.len() => {
+                    let rgb = [params[i + 1], params[i + 2], params[i + 3]].map(|c| c.min(255) as u8);
+                    i += 4;
+                    rgb
+                }
+                (38 | 48, Some(5)) if i + 1 < params.len() => {
+                    let rgb = palette_rgb(params[i + 1].min(255) as u8);
+                    i += 2;
+                    rgb
+                }
+                _ => {
+                    // basic colors and styles are understood everywhere, colors only if there are any
+                    let color = (30..=49).contains(&param) || (90..=107).contains(&param);
+                    if !color || self != Self::None {
+                        converted.push(param.to_string());
+                    }
+                    continue;
+                }
+            };
+            match self {
+                Self::Palette256 => {
+                    let prefix = if background { 48 } else { 38 };
+                    converted.push(format!("{prefix};5;{}", nearest(rgb, 16..=255)));
+                }
+                Self::Basic16 => {
+                    let index = nearest(rgb, 0..=15) as u16;
+                    let base = match (background, index < 8) {
+                        (false, true) => 30,
+                        (false, false) => 90 - 8,
+                        (true, true) => 40,
+                        (true, false) => 100 - 8,
+                    };
+                    converted.push((base + index).to_string());
+                }
+                Self::Auto | Self::TrueColor | Self::None => {}
+            }
+        }
+        converted.join(";")
+    }
+}
+
+#[cfg(windows)]
+fn escape_codes_enabled() -> bool {
+    crossterm::ansi_support::supports_ansi()
+}
+#[cfg(not(windows))]
+fn escape_codes_enabled() -> bool {
+    true
+}
+
+/// The palette entry in `range` that is closest to the color
+fn nearest(rgb: [u8; 3], range: std::ops::RangeInclusive<u8>) -> u8 {
+    let distance = |other: [u8; 3]| -> u32 {
+        rgb.iter().zip(other).map(|(a, b)| (i32::from(*a) - i32::from(b)).pow(2) as u32).sum()
+    };
+    range.min_by_key(|index| distance(palette_rgb(*index))).expect("palette range isn't empty")
+}
+
+/// The color of a palette entry with xterm's default palette
+fn palette_rgb(index: u8) -> [u8; 3] {
+    const BASIC: [[u8; 3]; 16] = [
+        [0, 0, 0], [205, 0, 0], [0, 205, 0], [205, 205, 0], [0, 0, 238], [205, 0, 205], [0, 205, 205], [229, 229, 229],
+        [127, 127, 127], [255, 0, 0], [0, 255, 0], [255, 255, 0], [92, 92, 255], [255, 0, 255], [0, 255, 255],
+        [255, 255, 255],
+    ];
+    const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match index {
+        0..=15 => BASIC[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            [CUBE[(i / 36) as usize], CUBE[(i / 6 % 6) as usize], CUBE[(i % 6) as usize]]
+        }
+        _ => [8 + (index - 232) * 10; 3],
+    }
+}
+
+/// Whether the board's glyphs can be shown. The old Windows console host usually has a font and codepage without
+/// them, Windows Terminal and other terminals are fine.
+pub fn unicode_supported() -> bool {
+    !cfg!(windows) || env::var_os("WT_SESSION").is_some() || env::var_os("TERM_PROGRAM").is_some()
+}
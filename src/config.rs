@@ -0,0 +1,315 @@
+use std::{path::PathBuf, env, fs, io};
+
+use crate::{colors::Colors, glyphs::Glyphs, lang, score::ScoreFormat};
+
+/// Declares the options once for the config file, the `--option` command line argument and the settings menu.
+/// Every option has a default, its label in the menu is the text `setting.<key>` of the catalog.
+macro_rules! options {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $($(#[doc = $doc:literal])* $key:ident: $ty:ty = $default:expr,)*
+        }
+    ) => {
+        $(#[$meta])*
+        pub struct $name {
+            $($(#[doc = $doc])* pub $key: $ty,)*
+        }
+        impl Default for $name {
+            fn default() -> Self {
+                Self { $($key: $default,)* }
+            }
+        }
+        impl $name {
+            /// Sets an option from its text in the config file format
+            pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+                match key {
+                    $(stringify!($key) => self.$key = Value::parse(value)?,)*
+                    key => return Err(format!("unknown option `{key}`")),
+                }
+                Ok(())
+            }
+
+            /// All options with their current values, in declaration order
+            pub fn settings(&self) -> Vec<Setting> {
+                vec![$(Setting {
+                    key: stringify!($key),
+                    label: lang::text(concat!("setting.", stringify!($key))),
+                    value: self.$key.write(),
+                    editable: self.$key.next().is_some(),
+                    locked: false,
+                },)*]
+            }
+
+            /// Switches an option to its next value, options that can't be changed in the menu are left alone
+            pub fn change(&mut self, key: &str) {
+                match key {
+                    $(stringify!($key) => if let Some(next) = self.$key.next() { self.$key = next },)*
+                    _ => {}
+                }
+            }
+        }
+    };
+}
+
+options! {
+    /// Options read from the config file. The file uses a flat `key = value` format (a subset of TOML):
+    /// ```toml
+    /// autosave_games = true
+    /// games_dir = "/home/me/chess"
+    /// ```
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+    pub struct Config {
+        /// Save every finished game as a PGN file in `games_dir`
+        autosave_games: bool = true,
+        games_dir: PathBuf = data_dir().join("games"),
+        /// Show how many legal moves each piece has, can be toggled in game
+        move_counts: bool = false,
+        /// Print the game as plain text lines for screen readers instead of drawing the board, also set by `--accessible`
+        accessible: bool = false,
+        /// Draw the sidebar below the board even on wide terminals, also set by `--compact`. Terminals narrower than
+        /// 60 columns always get the compact layout.
+        compact: bool = false,
+        /// The piece glyphs, a preset (`unicode`, `ascii`, `filled` or `outlined`) optionally followed by replacements
+        /// of single pieces like `"outlined N=N n=n"`, or all 12 pieces as `"K=♔ Q=♕ ... p=♟"`
+        glyphs: Glyphs = Glyphs::default(),
+        /// How many colors the terminal shows: `auto`, `truecolor`, `256`, `16` or `none`. The board's colors are
+        /// replaced by the nearest ones the terminal has.
+        colors: Colors = Colors::Auto,
+        /// How scores are shown: `pawns` like `+1.3` or `winprob` for the chance to win in percent
+        score_format: ScoreFormat = ScoreFormat::Pawns,
+        /// Show the message of the day of the server when an online game starts
+        motd: bool = true,
+        /// Show a random chess tip on the start and end screens. More tips can be added in `tips.txt` next to the
+        /// config file.
+        tips: bool = true,
+        /// Show whose move it is in the terminal's title, which tmux can show as the window name. `--no-title` turns it
+        /// off for terminals that print the escape codes instead.
+        title: bool = true,
+        /// The name used for online games, asked for on the first run. The name prompt suggests it.
+        name: String = String::new(),
+        /// The language of the interface as a code like `"de"`, also set by `--lang`. Empty takes it from the
+        /// environment.
+        lang: String = String::new(),
+        /// Clean up the data folder once a day when termchess starts, `--no-maintenance` skips it for one start
+        maintenance: bool = true,
+        /// How many autosaved games are kept, the oldest ones are deleted first. 0 keeps all of them.
+        keep_games: usize = 1000,
+        /// How many megabytes the autosaved games may take together, 0 for no limit
+        games_mb: usize = 0,
+        /// How many daily backups of the data folder are kept in its `backups` folder, 0 makes none
+        keep_backups: usize = 7,
+        /// The log file is rotated once it has more megabytes than this, 0 never rotates it
+        log_mb: usize = 1,
+    }
+}
+impl Config {
+    pub fn path() -> PathBuf {
+        config_dir().join("config.toml")
+    }
+
+    /// Loads the config file, falling back to the defaults if it doesn't exist. Invalid entries are reported and
+    /// ignored.
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        let Ok(content) = fs::read_to_string(Self::path()) else { return config };
+        for (i, line) in content.lines().enumerate() {
+            if let Err(err) = config.parse_line(line) {
+                eprintln!("config.toml:{}: {err}", i + 1);
+            }
+        }
+        config
+    }
+
+    /// Writes the current values to the config file, including the ones given on the command line. Lines of options
+    /// that are already in the file are replaced in place so comments and their order are kept, other options are
+    /// only added if they differ from the default.
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path();
+        let settings = self.settings();
+        let mut written = Vec::new();
+        let mut lines: Vec<String> = fs::read_to_string(&path).unwrap_or_default()
+            .lines()
+            .map(|line| {
+                let key = line.split_once('=').map(|(key, _)| key.trim());
+                match settings.iter().find(|setting| Some(setting.key) == key) {
+                    Some(setting) => {
+                        written.push(setting.key);
+                        format!("{} = {}", setting.key, setting.value)
+                    }
+                    None => line.to_owned(),
+                }
+            })
+            .collect();
+        for (setting, default) in settings.iter().zip(Self::default().settings()) {
+            if !written.contains(&setting.key) && setting.value != default.value {
+                lines.push(format!("{} = {}", setting.key, setting.value));
+            }
+        }
+        fs::create_dir_all(config_dir())?;
+        fs::write(path, lines.join("\n") + "\n")
+    }
+
+    /// Parses a `key = value` pair, like a line of the config file
+    pub fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        let (key, value) = line.split_once('=').ok_or("expected `key = value`")?;
+        self.set(key.trim(), value.trim())
+    }
+}
+
+/// An option as it's listed in the settings menu
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Setting {
+    pub key: &'static str,
+    pub label: String,
+    /// The value as it's written in the config file
+    pub value: String,
+    /// Whether the menu can change it, the others can only be set in the config file
+    pub editable: bool,
+    /// Turned off by a strict game, shown with a lock
+    pub locked: bool,
+}
+
+/// Options that assist the player during a game, a strict game locks them
+pub const ASSISTANCE: [&str; 1] = ["move_counts"];
+
+/// A type options can have
+trait Value: Sized {
+    fn parse(value: &str) -> Result<Self, String>;
+
+    /// The value in the config file format
+    fn write(&self) -> String;
+
+    /// The value after this one when it's changed in the settings menu, None if that's not possible
+    fn next(&self) -> Option<Self> {
+        None
+    }
+}
+impl Value for bool {
+    fn parse(value: &str) -> Result<Self, String> {
+        parse_bool(value)
+    }
+
+    fn write(&self) -> String {
+        self.to_string()
+    }
+
+    fn next(&self) -> Option<Self> {
+        Some(!self)
+    }
+}
+impl Value for String {
+    fn parse(value: &str) -> Result<Self, String> {
+        parse_string(value)
+    }
+
+    fn write(&self) -> String {
+        write_string(self)
+    }
+}
+impl Value for usize {
+    fn parse(value: &str) -> Result<Self, String> {
+        value.parse().map_err(|_| format!("expected a number, found `{value}`"))
+    }
+
+    fn write(&self) -> String {
+        self.to_string()
+    }
+}
+impl Value for PathBuf {
+    fn parse(value: &str) -> Result<Self, String> {
+        parse_string(value).map(PathBuf::from)
+    }
+
+    fn write(&self) -> String {
+        write_string(&self.display().to_string())
+    }
+}
+impl Value for Glyphs {
+    fn parse(value: &str) -> Result<Self, String> {
+        Glyphs::parse(&parse_string(value)?)
+    }
+
+    fn write(&self) -> String {
+        write_string(&Glyphs::write(self))
+    }
+
+    fn next(&self) -> Option<Self> {
+        Some(self.next_preset())
+    }
+}
+
+impl Value for Colors {
+    fn parse(value: &str) -> Result<Self, String> {
+        let name = parse_string(value).unwrap_or_else(|_| value.to_owned());
+        Colors::ALL.into_iter()
+            .find(|colors| colors.name() == name)
+            .ok_or_else(|| format!("expected auto, truecolor, 256, 16 or none, found `{value}`"))
+    }
+
+    fn write(&self) -> String {
+        write_string(self.name())
+    }
+
+    fn next(&self) -> Option<Self> {
+        let i = Colors::ALL.iter().position(|colors| colors == self).expect("all variants are listed");
+        Some(Colors::ALL[(i + 1) % Colors::ALL.len()])
+    }
+}
+
+impl Value for ScoreFormat {
+    fn parse(value: &str) -> Result<Self, String> {
+        let name = parse_string(value).unwrap_or_else(|_| value.to_owned());
+        ScoreFormat::ALL.into_iter()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| format!("expected pawns or winprob, found `{value}`"))
+    }
+
+    fn write(&self) -> String {
+        write_string(self.name())
+    }
+
+    fn next(&self) -> Option<Self> {
+        let i = ScoreFormat::ALL.iter().position(|format| format == self).expect("all variants are listed");
+        Some(ScoreFormat::ALL[(i + 1) % ScoreFormat::ALL.len()])
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("expected true or false, found `{value}`")),
+    }
+}
+
+fn parse_string(value: &str) -> Result<String, String> {
+    value.strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .map(|v| v.replace("\\\"", "\"").replace("\\\\", "\\"))
+        .ok_or_else(|| format!("expected a quoted string, found `{value}`"))
+}
+
+fn write_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn home_dir() -> PathBuf {
+    env::var_os("HOME").map_or_else(|| PathBuf::from("."), PathBuf::from)
+}
+
+pub fn config_dir() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .map_or_else(|| home_dir().join(".config"), PathBuf::from)
+        .join("termchess")
+}
+
+pub fn data_dir() -> PathBuf {
+    env::var_os("XDG_DATA_HOME")
+        .map_or_else(|| home_dir().join(".local/share"), PathBuf::from)
+        .join("termchess")
+}
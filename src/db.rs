@@ -0,0 +1,325 @@
+//! A local database of games imported from PGN files, for finding the games that reached a position:
+//! ```text
+//! $ termchess db import games.pgn
+//! $ termchess db find --fen "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"
+//! ```
+//! It lives in the `db` folder of the data directory as three files:
+//! - `games.pgn` has the text of every imported game
+//! - `games.tsv` has a line per game with where its text is in `games.pgn`, its hash and the tags that are listed
+//! - `positions.bin` has the Zobrist hashes of the positions in the games, sorted so a query can binary search the
+//!   file instead of reading it
+//!
+//! Every position of the first `ALL_PLIES` plies is indexed, later only every `EVERY_PLIES`th and the final one.
+//! Openings are where games share positions, later on the index would mostly grow with positions of a single game.
+
+use std::{
+    collections::HashSet, error::Error, fs::{self, File, OpenOptions}, io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use vecm::vec2;
+
+use crate::{
+    backend, board::Board, browser, config::{self, Config}, game::Game, lang::tr, notation,
+    pgn::{self, PgnGame, Tags}, piece::Piece,
+};
+
+/// Every position up to this ply is indexed
+const ALL_PLIES: usize = 40;
+/// After `ALL_PLIES` only every this many plies is indexed
+const EVERY_PLIES: usize = 4;
+/// Hash, game number and ply of a position
+const RECORD_SIZE: u64 = 16;
+
+pub enum Command {
+    Import(PathBuf),
+    Find,
+}
+
+pub fn run(command: Command, fen: Option<&str>, backend_name: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    let dir = config::data_dir().join("db");
+    match command {
+        Command::Import(path) => import(&dir, &path),
+        Command::Find => {
+            let fen = fen.ok_or("give the position to find with --fen")?;
+            let (board, turn) = Board::from_fen(fen).ok_or("invalid FEN provided as argument")?;
+            find(&dir, board.zobrist(turn), backend_name, config)
+        }
+    }
+}
+
+/// A line of `games.tsv`
+struct Entry {
+    /// Where the text of the game is in `games.pgn`
+    offset: u64,
+    len: u64,
+    /// `Game::moves_hash`, a game that is imported twice is skipped
+    hash: u64,
+    white: String,
+    black: String,
+    result: String,
+    eco: String,
+    date: String,
+}
+impl Entry {
+    fn new(offset: u64, len: u64, hash: u64, tags: &Tags) -> Self {
+        // tabs and line breaks would break the line up
+        let tag = |name| pgn::tag(tags, name).unwrap_or("?").replace(['\t', '\n', '\r'], " ");
+        Self {
+            offset, len, hash,
+            white: tag("White"), black: tag("Black"), result: tag("Result"), eco: tag("ECO"), date: tag("Date"),
+        }
+    }
+
+    fn write(&self) -> String {
+        format!(
+            "{}\t{}\t{:016x}\t{}\t{}\t{}\t{}\t{}\n",
+            self.offset, self.len, self.hash, self.white, self.black, self.result, self.eco, self.date,
+        )
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let mut next = || fields.next().map(str::to_owned);
+        Some(Self {
+            offset: next()?.parse().ok()?,
+            len: next()?.parse().ok()?,
+            hash: u64::from_str_radix(&next()?, 16).ok()?,
+            white: next()?, black: next()?, result: next()?, eco: next()?, date: next()?,
+        })
+    }
+}
+
+/// Reads the games of a PGN file into the database. Games that can't be read are reported and skipped, the others
+/// are still imported.
+fn import(dir: &Path, path: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let index_path = dir.join("games.tsv");
+    let mut known = HashSet::new();
+    let mut count = 0;
+    if let Ok(file) = File::open(&index_path) {
+        for line in BufReader::new(file).lines() {
+            let entry = Entry::parse(&line?).ok_or("the database index is broken")?;
+            known.insert(entry.hash);
+            count += 1;
+        }
+    }
+
+    let mut texts = OpenOptions::new().create(true).append(true).open(dir.join("games.pgn"))?;
+    let mut offset = texts.seek(SeekFrom::End(0))?;
+    let mut index = OpenOptions::new().create(true).append(true).open(&index_path)?;
+    let file = File::open(path).map_err(|err| format!("can't open {}: {err}", path.display()))?;
+    let mut records = Vec::new();
+    let (mut imported, mut duplicates, mut failed) = (0, 0, 0);
+    for game in pgn::Games::new(BufReader::new(file)) {
+        let (line, text) = game?;
+        let (text, dropped) = drop_broken_tags(&text);
+        if dropped > 0 {
+            let key = if dropped == 1 { "db.dropped_tags_one" } else { "db.dropped_tags" };
+            eprintln!("{}", tr!(key, line = line, count = dropped));
+        }
+        let result = pgn::parse(&text).ok_or_else(|| "unreadable PGN".to_owned())
+            .and_then(|pgn| positions(&pgn).map(|(game, positions)| (pgn, game, positions)));
+        let (pgn, game, positions) = match result {
+            Ok(read) => read,
+            Err(err) => {
+                eprintln!("{}", tr!("db.skipped", line = line, error = err));
+                failed += 1;
+                continue;
+            }
+        };
+        if !known.insert(game.moves_hash()) {
+            duplicates += 1;
+            continue;
+        }
+        let text = text.trim().to_owned() + "\n\n";
+        texts.write_all(text.as_bytes())?;
+        let entry = Entry::new(offset, text.len() as u64, game.moves_hash(), &pgn.tags);
+        index.write_all(entry.write().as_bytes())?;
+        offset += text.len() as u64;
+        records.extend(positions.into_iter().map(|(hash, ply)| (hash, count, ply)));
+        count += 1;
+        imported += 1;
+    }
+    texts.flush()?;
+    index.flush()?;
+    merge_positions(dir, records)?;
+    println!("{}", tr!("db.imported", imported = imported, duplicates = duplicates, failed = failed));
+    Ok(())
+}
+
+/// The text without the tag lines that can't be read, and how many there were
+fn drop_broken_tags(text: &str) -> (String, usize) {
+    let mut dropped = 0;
+    let mut tags = true;
+    let mut kept = String::with_capacity(text.len());
+    for line in text.lines() {
+        if tags && line.trim_start().starts_with('[') && pgn::parse_tag(line).is_none() {
+            dropped += 1;
+            continue;
+        }
+        tags &= line.trim().is_empty() || line.trim_start().starts_with('[');
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    (kept, dropped)
+}
+
+/// Replays the main line and returns the hashes of the indexed positions with their plies
+fn positions(pgn: &PgnGame) -> Result<(Game, Vec<(u64, u32)>), String> {
+    let (board, turn) = pgn.start()?;
+    let mut game = Game::new(vec2![0, 0], String::new(), String::new(), board, turn);
+    let moves = pgn.moves.mainline();
+    let mut positions = vec![(game.board.zobrist(game.turn), 0)];
+    for (i, san) in moves.iter().enumerate() {
+        let (from, to, promotion) = notation::parse_san(&game.board, game.turn, san)
+            .ok_or_else(|| format!("illegal move {san} at ply {}", i + 1))?;
+        game.play_move_promote(from, to, promotion.unwrap_or(Piece::Queen));
+        let ply = i + 1;
+        if ply <= ALL_PLIES || ply % EVERY_PLIES == 0 || ply == moves.len() {
+            positions.push((game.board.zobrist(game.turn), ply as u32));
+        }
+    }
+    Ok((game, positions))
+}
+
+/// Merges the new records into the sorted `positions.bin`. Only the new records are held in memory, the old ones are
+/// streamed from the old file into a new one that replaces it.
+fn merge_positions(dir: &Path, mut records: Vec<(u64, u32, u32)>) -> io::Result<()> {
+    records.sort_unstable();
+    let path = dir.join("positions.bin");
+    let merged_path = dir.join("positions.bin.new");
+    let mut old = match File::open(&path) {
+        Ok(file) => Some(BufReader::new(file)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+    let mut merged = BufWriter::new(File::create(&merged_path)?);
+    let mut next_old = || old.as_mut().map_or(Ok(None), read_record);
+    let mut old_record = next_old()?;
+    let mut new = records.into_iter().peekable();
+    loop {
+        let record = match (old_record, new.peek()) {
+            (Some(o), Some(n)) if *n < o => new.next().unwrap(),
+            (Some(o), _) => {
+                old_record = next_old()?;
+                o
+            }
+            (None, Some(_)) => new.next().unwrap(),
+            (None, None) => break,
+        };
+        write_record(&mut merged, record)?;
+    }
+    merged.into_inner()?.sync_all()?;
+    fs::rename(merged_path, path)
+}
+
+fn read_record(reader: &mut impl Read) -> io::Result<Option<(u64, u32, u32)>> {
+    let mut bytes = [0; RECORD_SIZE as usize];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let hash = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let game = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    let ply = u32::from_le_bytes(bytes[12..].try_into().unwrap());
+    Ok(Some((hash, game, ply)))
+}
+
+fn write_record(writer: &mut impl Write, (hash, game, ply): (u64, u32, u32)) -> io::Result<()> {
+    writer.write_all(&hash.to_le_bytes())?;
+    writer.write_all(&game.to_le_bytes())?;
+    writer.write_all(&ply.to_le_bytes())
+}
+
+/// The games that reached the position with the first ply they reached it at, found by binary searching the file
+fn lookup(dir: &Path, hash: u64) -> io::Result<Vec<(u32, u32)>> {
+    let mut file = match File::open(dir.join("positions.bin")) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    // the first record with the hash or a greater one
+    let (mut low, mut high) = (0, file.metadata()?.len() / RECORD_SIZE);
+    while low < high {
+        let middle = (low + high) / 2;
+        file.seek(SeekFrom::Start(middle * RECORD_SIZE))?;
+        match read_record(&mut file)? {
+            Some((h, ..)) if h < hash => low = middle + 1,
+            _ => high = middle,
+        }
+    }
+    file.seek(SeekFrom::Start(low * RECORD_SIZE))?;
+    let mut reader = BufReader::new(file);
+    let mut games: Vec<(u32, u32)> = Vec::new();
+    while let Some((h, game, ply)) = read_record(&mut reader)? {
+        if h != hash {
+            break;
+        }
+        // the records of a hash are sorted by game and then ply, so the first is the earliest
+        if games.last().map(|(last, _)| *last) != Some(game) {
+            games.push((game, ply));
+        }
+    }
+    Ok(games)
+}
+
+/// Lists the games that reached the position and opens the chosen ones in the replay view at that position
+fn find(dir: &Path, hash: u64, backend_name: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    let found = lookup(dir, hash)?;
+    if found.is_empty() {
+        println!("{}", tr!("db.none_found"));
+        return Ok(());
+    }
+    // only the lines of the found games are kept
+    let mut entries = Vec::new();
+    if let Ok(file) = File::open(dir.join("games.tsv")) {
+        let mut wanted = found.iter().peekable();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if let Some((_, ply)) = wanted.next_if(|(game, _)| *game as usize == number) {
+                entries.push((Entry::parse(&line).ok_or("the database index is broken")?, *ply));
+            }
+        }
+    }
+    for (i, (entry, ply)) in entries.iter().enumerate() {
+        let (number, result) = (format!("{:>4}", i + 1), format!("{:<7}", entry.result));
+        println!(
+            "{}",
+            tr!("db.entry", number = number, date = entry.date, white = entry.white, black = entry.black,
+                result = result, eco = entry.eco, ply = ply),
+        );
+    }
+    let mut term = None;
+    let mut texts = File::open(dir.join("games.pgn"))?;
+    loop {
+        print!("{} ", tr!("db.open"));
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(());
+        }
+        let Some((entry, ply)) = answer.parse::<usize>().ok().and_then(|i| entries.get(i.wrapping_sub(1))) else {
+            println!("{}", tr!("db.no_game", number = answer));
+            continue;
+        };
+        let mut text = vec![0; entry.len as usize];
+        texts.seek(SeekFrom::Start(entry.offset))?;
+        texts.read_exact(&mut text)?;
+        let pgn = pgn::parse(&String::from_utf8_lossy(&text)).ok_or("the game in the database is broken")?;
+        let term: &dyn backend::Backend = &**match &mut term {
+            Some(term) => term,
+            None => term.insert(backend::open(backend_name, config.colors)?),
+        };
+        term.enter_alt_screen()?;
+        term.hide_cursor()?;
+        let result = browser::replay(term, pgn, None, *ply as usize, config);
+        term.clear()?;
+        term.show_cursor()?;
+        term.leave_alt_screen()?;
+        result?;
+    }
+}
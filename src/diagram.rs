@@ -0,0 +1,143 @@
+//! Diagrams of the final position to share: the board as it's drawn in the terminal, as ANSI text, or a standalone
+//! SVG. Both show the board from the side it was played from and have a caption with the players and the result.
+
+use std::{fs, io, path::Path};
+
+use vecm::vec2;
+
+use crate::{game::{Game, GameEnd}, glyphs::Glyphs, input::Mode, lang::tr, piece::Color};
+
+/// The board takes the first lines of the compact layout: the files, the ranks and the border below them
+const BOARD_LINES: usize = 10;
+/// Width and height of a square in the SVG
+pub const SQUARE: usize = 45;
+/// Height of the caption below the board in the SVG
+const CAPTION_HEIGHT: usize = 30;
+
+/// Writes the diagram, an SVG if the path ends in `.svg` and ANSI text otherwise
+pub fn write(game: &Game, end: Option<&GameEnd>, path: &Path) -> io::Result<()> {
+    let svg = matches!(path.extension().and_then(|ext| ext.to_str()), Some(ext) if ext.eq_ignore_ascii_case("svg"));
+    fs::write(path, if svg { self::svg(game, end) } else { ansi(game, end) })
+}
+
+/// The players with the result between them and the length of the game, like `Bob 1–0 Alice, 34 moves`
+pub fn caption(game: &Game, end: Option<&GameEnd>) -> String {
+    let result = match end {
+        Some(GameEnd::Winner(Color::White)) => "1–0",
+        Some(GameEnd::Winner(Color::Black)) => "0–1",
+        Some(GameEnd::Draw) => "½–½",
+        Some(GameEnd::Aborted) | None => "vs",
+    };
+    // a move is a move of each side, the last one may be White's alone
+    let moves = game.history.len() / 2 + game.history.len() % 2;
+    let key = if moves == 1 { "diagram.caption_one" } else { "diagram.caption" };
+    tr!(key, white = game.white.name, result = result, black = game.black.name, moves = moves)
+}
+
+/// The board as the game draws it, without the cursor, the move markers and the panels
+pub fn ansi(game: &Game, end: Option<&GameEnd>) -> String {
+    let mut board = game.clone();
+    board.cursor = vec2![-1, -1];
+    board.mode = Mode::Navigating;
+    board.show_move_counts = false;
+    board.influence = None;
+    board.threats = None;
+    let text = format!("{board:#}");
+    let lines: Vec<&str> = text.lines().take(BOARD_LINES).collect();
+    format!("{}\n{}\n", lines.join("\n"), caption(game, end))
+}
+
+/// A standalone SVG with the squares and the Unicode chess pieces on them
+pub fn svg(game: &Game, end: Option<&GameEnd>) -> String {
+    let glyphs = Glyphs::preset("unicode").expect("unicode preset exists");
+    let (width, height) = (8 * SQUARE, 8 * SQUARE + CAPTION_HEIGHT);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n"
+    );
+    svg.push_str(&format!("<rect width=\"{width}\" height=\"{height}\" fill=\"#ffffff\"/>\n"));
+    for row in 0..8 {
+        for column in 0..8 {
+            let square = game.square_on_screen(column, row);
+            let (x, y) = (column as usize * SQUARE, row as usize * SQUARE);
+            // the same colors as on the terminal, a1 is dark
+            let fill = if (square.x + square.y) % 2 == 1 { "#eeeeee" } else { "#769656" };
+            svg.push_str(&format!("<rect x=\"{x}\" y=\"{y}\" width=\"{SQUARE}\" height=\"{SQUARE}\" fill=\"{fill}\"/>\n"));
+            if let Some((piece, color)) = game.board[square] {
+                // U+FE0E keeps the pawn from turning into an emoji
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" font-size=\"36\" text-anchor=\"middle\" dominant-baseline=\"central\">{}\u{fe0e}</text>\n",
+                    x + SQUARE / 2, y + SQUARE / 2, glyphs.glyph(piece, color),
+                ));
+            }
+        }
+    }
+    svg.push_str(&format!(
+        "<text x=\"{}\" y=\"{}\" font-size=\"16\" font-family=\"sans-serif\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+        width / 2, 8 * SQUARE + CAPTION_HEIGHT / 2, escape(&caption(game, end)),
+    ));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use vecm::vec2;
+
+    use super::{ansi, svg, SQUARE};
+    use crate::{backend::cells, board::Board, game::{Game, GameEnd}, glyphs::Glyphs, input::Mode, notation, piece::Color};
+
+    /// Both kinds of diagrams of a known position show its pieces on their squares, without the cursor, and the
+    /// caption
+    #[test]
+    fn diagram() {
+        let fen = "r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4";
+        let (board, turn) = Board::from_fen(fen).unwrap();
+        let mut game = Game::new(vec2![4, 1], "Bob".to_owned(), "Alice".to_owned(), board, turn);
+        game.glyphs = Glyphs::preset("ascii").unwrap();
+        game.mode = Mode::PieceSelected(vec2![4, 1]);
+        let end = GameEnd::Winner(Color::White);
+        let caption = "Bob 1–0 Alice, 0 moves";
+
+        let text = ansi(&game, Some(&end));
+        let lines: Vec<_> = text.lines().map(cells).collect();
+        for row in 0..8 {
+            for column in 0..8 {
+                let square = game.square_on_screen(column, row);
+                let expected =
+                    board[square].map_or(' ', |(piece, color)| cells(&game.glyphs.character(piece, color))[0].0);
+                let line = &lines[row as usize + 1];
+                let (glyph, marker) = (line[2 + 2 * column as usize].0, line[3 + 2 * column as usize].0);
+                let name = notation::square(square);
+                assert!(glyph == expected && marker == ' ', "the text diagram draws `{glyph}{marker}` on {name}");
+            }
+        }
+        assert_eq!(text.lines().last(), Some(caption), "the text diagram doesn't end with the caption");
+
+        let svg = svg(&game, Some(&end));
+        let unicode = Glyphs::preset("unicode").unwrap();
+        let attribute = |element: &str, name: &str| -> Option<usize> {
+            element.split(&format!(" {name}=\"")).nth(1)?.split('"').next()?.parse().ok()
+        };
+        let mut pieces = 0;
+        for element in svg.split("<text").skip(1) {
+            let (Some(x), Some(y)) = (attribute(element, "x"), attribute(element, "y")) else { continue };
+            let text = element.split_once('>').and_then(|(_, rest)| rest.split_once('<'));
+            let text = text.map_or("", |(text, _)| text);
+            if y > 8 * SQUARE {
+                assert_eq!(text, caption, "the SVG's caption is wrong");
+                continue;
+            }
+            let square = game.square_on_screen((x / SQUARE) as i8, (y / SQUARE) as i8);
+            let expected = board[square].map(|(piece, color)| unicode.glyph(piece, color));
+            let name = notation::square(square);
+            assert_eq!(Some(text.trim_end_matches('\u{fe0e}')), expected, "the SVG puts `{text}` on {name}");
+            pieces += 1;
+        }
+        let expected = (0..64).filter(|i| board[vec2![i % 8, i / 8]].is_some()).count();
+        assert_eq!(pieces, expected, "the SVG has the wrong number of pieces");
+    }
+}
@@ -0,0 +1,68 @@
+//! The preset opponents of `--difficulty`. Besides searching less deep the weaker ones also look at less: the easy
+//! CPU only counts material, the medium one gives the placement of the pieces half the weight. They also don't always
+//! play the best move, the easy one picks any move up to half a pawn worse.
+
+use crate::ai::EvalParams;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+impl Difficulty {
+    pub const ALL: [Self; 3] = [Self::Easy, Self::Medium, Self::Hard];
+
+    /// The name on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Easy => "easy",
+            Self::Medium => "medium",
+            Self::Hard => "hard",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|difficulty| difficulty.name() == name)
+    }
+
+    /// The search depth, unless `--ai` gives another one
+    pub fn depth(self) -> usize {
+        match self {
+            Self::Easy => 0,
+            Self::Medium => 1,
+            Self::Hard => 2,
+        }
+    }
+
+    /// The weights of the evaluation
+    pub fn params(self) -> EvalParams {
+        let full = EvalParams::default();
+        match self {
+            Self::Easy => EvalParams { positional_percent: 0, jitter: 500, ..full },
+            Self::Medium => EvalParams { positional_percent: 50, pawn_advance: 50, jitter: 200, ..full },
+            Self::Hard => full,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vecm::vec2;
+
+    use super::Difficulty;
+    use crate::{ai, board::Board, piece::Color};
+
+    /// The easy CPU sees nothing in developing a knight, the hard one does. Every difficulty is read from its name.
+    #[test]
+    fn difficulty() {
+        let mut board = Board::starting_position();
+        board.move_piece(vec2![6, 0], vec2![5, 2]);
+        let easy = ai::evaluate(&board, Color::White, &Difficulty::Easy.params());
+        let hard = ai::evaluate(&board, Color::White, &Difficulty::Hard.params());
+        assert!(easy == 0 && hard > 0, "after Nf3 the easy CPU scores {easy} and the hard one {hard}");
+        for difficulty in Difficulty::ALL {
+            assert_eq!(Difficulty::parse(difficulty.name()), Some(difficulty));
+        }
+    }
+}
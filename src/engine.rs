@@ -0,0 +1,333 @@
+//! The engine as a long lived worker thread. It's started once per game and searches the positions it's sent, so
+//! whatever it keeps between searches survives from one move to the next. The messages are the same for everyone who
+//! talks to an engine: the CPU player, the practice view and, with the UCI commands mapped onto them, a UCI frontend
+//! or an external engine.
+
+use std::{
+    sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver, Sender, TryRecvError}}, thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{ai::{self, EvalParams, Info, Move, OpeningBook, Progress, Score}, board::Board, piece::Color};
+
+/// A fast search reports thousands of root moves per second, only this many progress updates are sent per second.
+/// Updates with a new best move and the last one of a search are always sent.
+pub const PROGRESS_PER_SECOND: u32 = 10;
+
+/// What the worker runs for a `Go`, `ai::search_pv` on all cores except in checks that need a search that behaves in a certain way
+pub type Search = fn(&Go, Progress) -> Option<(Vec<Move>, Score)>;
+
+/// What the engine is asked to do
+pub enum Request {
+    /// Search the position, answered by progress followed by `BestMove` or `Stopped`
+    Go(Box<Go>),
+    /// End the worker after the current search
+    Quit,
+}
+
+pub struct Go {
+    pub board: Board,
+    pub turn: Color,
+    /// Zobrist hashes of the game's positions before `board`, oldest first
+    pub history: Vec<u64>,
+    pub depth: usize,
+    /// Search deeper and deeper for this long instead, `depth` is the deepest it goes then
+    pub time: Option<Duration>,
+    /// The weights of the evaluation, those of the engine
+    pub params: EvalParams,
+    /// A random number that picks between book moves and between the moves within the jitter of the best
+    pub roll: u64,
+    /// Set to stop the search early
+    pub stop: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+pub enum Response {
+    /// A move at the root was searched, throttled to `PROGRESS_PER_SECOND`
+    Progress(Info),
+    /// The search is done, the move is `None` if there is no legal move. The line is the principal variation, which
+    /// starts with the move.
+    BestMove(Option<Move>, Score, Vec<Move>),
+    /// The search was stopped before it finished
+    Stopped,
+}
+
+/// The handle of an engine worker, which is stopped when the handle is dropped
+pub struct Engine {
+    requests: Sender<Request>,
+    responses: Receiver<Response>,
+    /// The stop flag of the latest search
+    stop: Arc<AtomicBool>,
+    /// Searches that were requested and haven't answered with `BestMove` or `Stopped` yet. Only the answers of the
+    /// latest one are passed on, the others were replaced.
+    pending: usize,
+    /// The weights of the evaluation for every search
+    params: EvalParams,
+    /// Where the rolls of the searches come from
+    rng: Rng,
+    thread: Option<JoinHandle<()>>,
+}
+impl Engine {
+    pub fn spawn() -> Self {
+        Self::start(search_all_cores, None, EvalParams::default(), None)
+    }
+
+    /// An engine for a CPU player. It plays the moves of the book without searching while the game is in it and
+    /// evaluates positions with `params`, which set how well it plays. The random choices between book moves and
+    /// between moves within the jitter come from `seed`, the same seed plays the same game against the same moves.
+    /// Without one they differ every time.
+    pub fn cpu(book: Option<OpeningBook>, params: EvalParams, seed: Option<u64>) -> Self {
+        Self::start(search_all_cores, book, params, seed)
+    }
+
+    #[cfg(test)]
+    pub fn with_search(search: Search) -> Self {
+        Self::start(search, None, EvalParams::default(), None)
+    }
+
+    fn start(search: Search, book: Option<OpeningBook>, params: EvalParams, seed: Option<u64>) -> Self {
+        let (requests, rx) = mpsc::channel();
+        let (tx, responses) = mpsc::channel();
+        let thread = thread::spawn(move || run(rx, tx, search, book));
+        let rng = Rng::new(seed.unwrap_or_else(|| {
+            // the time is random enough for a game
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+        }));
+        Self {
+            requests, responses, stop: Arc::new(AtomicBool::new(false)), pending: 0, params, rng, thread: Some(thread),
+        }
+    }
+
+    /// Starts searching the position, a search that is still running is stopped and its answers are dropped. `history`
+    /// has the hashes of the positions that came before it in the game, see `Game::earlier_positions`. With a `time`
+    /// the search goes deeper until it's up, `depth` is the limit then.
+    pub fn go(&mut self, board: &Board, turn: Color, history: Vec<u64>, depth: usize, time: Option<Duration>) {
+        self.stop();
+        self.stop = Arc::new(AtomicBool::new(false));
+        let (params, roll, stop) = (self.params, self.rng.roll(), Arc::clone(&self.stop));
+        let go = Go { board: *board, turn, history, depth, time, params, roll, stop };
+        self.requests.send(Request::Go(Box::new(go))).expect("engine thread ended");
+        self.pending += 1;
+    }
+
+    /// Stops the running search at the next position it looks at, it answers with `Stopped`
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a search was requested and hasn't finished yet
+    pub fn is_searching(&self) -> bool {
+        self.pending > 0
+    }
+
+    /// The next answer of the latest search if there is one
+    pub fn try_recv(&mut self) -> Option<Response> {
+        loop {
+            match self.responses.try_recv() {
+                Ok(response) => if let Some(response) = self.latest(response) {
+                    return Some(response);
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => panic!("engine thread ended"),
+            }
+        }
+    }
+
+    /// Waits for the next answer of the latest search
+    pub fn recv(&mut self) -> Response {
+        loop {
+            let response = self.responses.recv().expect("engine thread ended");
+            if let Some(response) = self.latest(response) {
+                return response;
+            }
+        }
+    }
+
+    /// Like `try_recv`, but all waiting progress is read and only the newest is returned, so a reader that only looks
+    /// now and then doesn't fall behind. `BestMove` and `Stopped` end the draining, they are never skipped.
+    pub fn try_recv_latest(&mut self) -> Option<Response> {
+        let mut latest = None;
+        while let Some(response) = self.try_recv() {
+            let done = !matches!(response, Response::Progress(_));
+            latest = Some(response);
+            if done {
+                break;
+            }
+        }
+        latest
+    }
+
+    /// Waits for an answer like `recv` and skips over the progress behind it like `try_recv_latest`
+    pub fn recv_latest(&mut self) -> Response {
+        match self.recv() {
+            Response::Progress(info) => self.try_recv_latest().unwrap_or(Response::Progress(info)),
+            response => response,
+        }
+    }
+
+    /// The response if it belongs to the latest search
+    fn latest(&mut self, response: Response) -> Option<Response> {
+        let latest = self.pending == 1;
+        if matches!(response, Response::BestMove(..) | Response::Stopped) {
+            self.pending -= 1;
+        }
+        latest.then_some(response)
+    }
+}
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop();
+        // the worker ends by itself if it already panicked
+        let _ = self.requests.send(Request::Quit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The search of `Engine::spawn`, the moves at the root are searched on every core
+fn search_all_cores(go: &Go, progress: Progress) -> Option<(Vec<Move>, Score)> {
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    match go.time {
+        Some(time) => {
+            let (board, history, depth) = (&go.board, &go.history, go.depth);
+            ai::search_timed_pv(board, go.turn, history, depth, time, threads, go.params, go.roll, &go.stop, progress)
+        }
+        None => {
+            ai::search_pv(&go.board, go.turn, &go.history, go.depth, threads, go.params, go.roll, &go.stop, progress)
+        }
+    }
+}
+
+fn run(requests: Receiver<Request>, responses: Sender<Response>, search: Search, book: Option<OpeningBook>) {
+    let interval = Duration::from_secs(1) / PROGRESS_PER_SECOND;
+    while let Ok(Request::Go(go)) = requests.recv() {
+        // a book move has no score, it's known to be fine
+        if let Some(mov) = book.as_ref().and_then(|book| book.pick(&go.board, go.turn, go.roll)) {
+            if responses.send(Response::BestMove(Some(mov), 0, vec![mov])).is_err() {
+                break;
+            }
+            continue;
+        }
+        let mut sent: Option<(Instant, Move)> = None;
+        let mut progress = |info: &Info| {
+            let due = match sent {
+                Some((time, best)) => best != info.best || info.searched == info.moves || time.elapsed() >= interval,
+                None => true,
+            };
+            if due {
+                sent = Some((Instant::now(), info.best));
+                let _ = responses.send(Response::Progress(*info));
+            }
+        };
+        let response = match search(&go, &mut progress) {
+            Some((pv, score)) => Response::BestMove(pv.first().copied(), score, pv),
+            None => Response::Stopped,
+        };
+        if responses.send(response).is_err() {
+            break;
+        }
+    }
+}
+
+// xorshift, good enough for picking moves
+struct Rng(u64);
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift never leaves 0
+        Self(seed.max(1))
+    }
+
+    fn roll(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::{Duration, Instant}};
+
+    use vecm::vec2;
+
+    use super::{Engine, Response};
+    use crate::{ai::{EvalParams, Move, OpeningBook}, board::Board, piece::Color};
+
+    /// Waits for the end of the engine's search, the progress before it is skipped
+    fn search_end(engine: &mut Engine) -> Response {
+        loop {
+            match engine.recv() {
+                Response::Progress(_) => {}
+                response => return response,
+            }
+        }
+    }
+
+    /// Book moves are played without a search, after the book the engine searches again
+    #[test]
+    fn opening_book() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let book = OpeningBook::parse(&format!("{start} e2e4 3\n{start} d2d4 1\n")).unwrap();
+        let board = Board::starting_position();
+        let (e4, d4) = (Move { from: vec2![4, 1], to: vec2![4, 3] }, Move { from: vec2![3, 1], to: vec2![3, 3] });
+        let mut engine = Engine::cpu(Some(book), EvalParams::default(), None);
+        engine.go(&board, Color::White, Vec::new(), 0, None);
+        match search_end(&mut engine) {
+            Response::BestMove(Some(mov), _, _) if mov == e4 || mov == d4 => {}
+            response => panic!("the engine answered {response:?} in the book"),
+        }
+        let mut after = board;
+        after.move_piece(e4.from, e4.to);
+        engine.go(&after, Color::Black, Vec::new(), 0, None);
+        let (legal, _) = after.moves(Color::Black);
+        match search_end(&mut engine) {
+            Response::BestMove(Some(mov), _, _) if legal.get(&mov.from).is_some_and(|to| to.contains(&mov.to)) => {}
+            response => panic!("the engine answered {response:?} after the book"),
+        }
+    }
+
+    /// A search that would take minutes ends right away when it's stopped, and dropping the engine in the middle of
+    /// one doesn't leave a thread searching on
+    #[test]
+    fn engine_stop() {
+        let board = Board::starting_position();
+        let mut engine = Engine::spawn();
+        engine.go(&board, Color::White, Vec::new(), 6, None);
+        thread::sleep(Duration::from_millis(50));
+        let start = Instant::now();
+        engine.stop();
+        loop {
+            match engine.recv() {
+                Response::Progress(_) => {}
+                Response::Stopped => break,
+                response => panic!("a stopped search answered {response:?}"),
+            }
+        }
+        engine.go(&board, Color::White, Vec::new(), 6, None);
+        thread::sleep(Duration::from_millis(50));
+        // dropping waits for the worker to end
+        drop(engine);
+        assert!(start.elapsed() < Duration::from_secs(2), "stopping the engine took {:?}", start.elapsed());
+    }
+
+    /// The same seed plays the same moves with a jitter
+    #[test]
+    fn seed() {
+        let board = Board::starting_position();
+        let jittered = EvalParams { jitter: 1000, ..EvalParams::default() };
+        let played = |seed| {
+            let mut engine = Engine::cpu(None, jittered, Some(seed));
+            (0..4).map(|_| {
+                engine.go(&board, Color::White, Vec::new(), 0, None);
+                match search_end(&mut engine) {
+                    Response::BestMove(mov, ..) => mov,
+                    _ => None,
+                }
+            }).collect::<Vec<_>>()
+        };
+        let moves = played(7);
+        assert!(!moves.contains(&None) && played(7) == moves, "the seed played {moves:?} and then other moves");
+    }
+}
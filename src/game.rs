@@ -1,19 +1,90 @@
-use std::{fmt, collections::{HashMap, HashSet}};
+use std::{fmt, collections::{HashMap, HashSet}, path::PathBuf, time::{Duration, Instant}};
 
 use color_format::{cwrite, cformat};
+use console::{pad_str, truncate_str, measure_text_width, Alignment};
 
-use crate::{piece::{Piece, Color}, Pos, board::Board};
+pub use termchess_core::GameEnd;
 
+use crate::{piece::{Piece, Color}, Pos, board::Board, fnv1a, config::{self, Config, Setting}, glyphs::Glyphs, input::Mode, lang::{self, tr}, notation, online::{ClockTimes, Features}, threats, tutorial};
 
+/// Maximum display width of player names in the sidebar. Widths are measured in terminal columns, so wide characters
+/// like CJK or emoji count twice.
+const NAME_WIDTH: usize = 24;
+/// Number of chat messages shown below the board
+const CHAT_LINES: usize = 5;
+/// Number of chat messages in the compact layout
+const COMPACT_CHAT_LINES: usize = 2;
+/// Maximum display width of the status and chat lines in the compact layout, so they don't wrap on phone screens
+const COMPACT_TEXT_WIDTH: usize = 40;
+/// Columns of the board with its frame, the sidebar starts after it
+const BOARD_WIDTH: usize = 20;
+/// Lines of moves shown in the move list next to the sidebar, PageUp and PageDown scroll through the others
+const HISTORY_ROWS: usize = 8;
+/// Columns of a line of the move list like `123. exd8=Q+  Kxd8`
+const HISTORY_WIDTH: usize = 22;
+/// Clocks with less time than this are drawn in red and with tenths of a second
+const LOW_TIME: Duration = Duration::from_secs(10);
+/// Key hints of online games with the feature each action needs
+const ONLINE_KEYS: [(&str, Features); 2] = [("keys.chat", Features::CHAT), ("keys.abort", Features::ABORT)];
+
+#[derive(Clone)]
 pub struct Game {
     pub board: Board,
     pub turn: Color,
     pub cursor: Pos,
-    pub moving: Option<Pos>,
+    pub mode: Mode,
     pub possible_moves: HashMap<Pos, HashSet<Pos>>,
     pub white: Player,
     pub black: Player,
     pub flip_board: bool,
+    pub history: Vec<HistoryEntry>,
+    /// Lines of the move list scrolled back from the latest move. Only the view scrolls, the game stays where it is.
+    pub history_scroll: usize,
+    /// White attackers minus black attackers for every square, only computed while the influence map is shown
+    pub influence: Option<[[i8; 8]; 8]>,
+    /// Hanging pieces and mate threats against the side to move, only computed while the threats panel is shown
+    pub threats: Option<Vec<String>>,
+    /// Show the number of legal moves on each piece of the side to move while no piece is selected
+    pub show_move_counts: bool,
+    /// How the pieces are drawn
+    pub glyphs: Glyphs,
+    /// A message shown below the board until the next move
+    pub status: Option<String>,
+    /// Chat messages of an online game, oldest first
+    pub chat: Vec<String>,
+    /// The protocol features this game can use, `None` if it isn't played online
+    pub features: Option<Features>,
+    /// Always draw the compact layout, narrow terminals get it anyway
+    pub compact: bool,
+    /// Lets the player take the seat back after losing the connection to the server, shown with the online keys
+    pub reconnect_code: Option<u16>,
+    /// The player whose draw offer stands in an online game
+    pub draw_offer: Option<Color>,
+    /// The player whose takeback request stands in an online game
+    pub takeback: Option<Color>,
+    /// The clocks of an online game with a time control
+    pub time_left: Option<TimeLeft>,
+    /// The points of the player and the opponent in the online games before this one, rematches keep it
+    pub score: Score,
+    /// When the side to move started thinking. Think times are only recorded while it's set, so games that are
+    /// replayed from a file don't get any.
+    pub clock: Option<Instant>,
+    /// The plies at which a side of a game between two CPUs was taken over by hand, noted in the PGN
+    pub takeovers: Vec<(usize, Color)>,
+    /// Where a diagram of the final position is written when the game ends, set by `--export-diagram`
+    pub diagram: Option<PathBuf>,
+    /// Whether helpers like the control map, the threats panel and the move count badges can be used. Strict games
+    /// turn them off whatever the config says, see `--strict` and `GameInfo::assistance_allowed`.
+    pub assistance: bool,
+    /// The step of the guided game of the tutorial, which shows what to do next in the status
+    pub guide: Option<tutorial::Step>,
+    /// The move counters of the starting position, those of its FEN if it had one
+    pub start_clocks: Clocks,
+    /// Show the line the CPU expects in the status after each of its moves, set by `--debug-ai`
+    pub show_pv: bool,
+    /// Legal moves of the positions visited so far indexed by ply, so stepping back and forth through the history
+    /// doesn't generate them again. Each entry keeps its board, entries from a ply whose board changed are dropped.
+    move_cache: Vec<(Board, HashMap<Pos, HashSet<Pos>>)>,
 }
 impl Game {
     pub fn new(cursor: Pos, white_name: String, black_name: String, board: Board, turn: Color) -> Self {
@@ -22,10 +93,33 @@ impl Game {
             turn,
             cursor,
             possible_moves: HashMap::new(),
-            moving: None,
+            mode: Mode::Navigating,
             white: Player::new(white_name),
             black: Player::new(black_name),
             flip_board: false,
+            history: Vec::new(),
+            history_scroll: 0,
+            influence: None,
+            threats: None,
+            show_move_counts: false,
+            glyphs: Glyphs::default(),
+            status: None,
+            chat: Vec::new(),
+            features: None,
+            reconnect_code: None,
+            draw_offer: None,
+            takeback: None,
+            time_left: None,
+            score: Score::default(),
+            compact: false,
+            clock: None,
+            takeovers: Vec::new(),
+            diagram: None,
+            assistance: true,
+            guide: None,
+            start_clocks: Clocks::default(),
+            show_pv: false,
+            move_cache: Vec::new(),
         };
         
         board.compute_moves();
@@ -33,25 +127,66 @@ impl Game {
         board
     }
 
-    // optionally returns the winner
+    /// Brings the legal moves up to date with the board and returns how the game ended, if it did. Whether a decisive
+    /// end was a mate and a draw a stalemate can be told with `is_checkmate` and `is_stalemate` afterwards.
     pub fn compute_moves(&mut self) -> Option<GameEnd> {
-        let (possible, count) = self.board.moves(self.turn);
-        if count == 0 {
-            self.possible_moves.clear();
-            let king_pos = self.board.find_king(self.turn).expect("king not found");
-            let end = if self.board.moves(!self.turn).0.iter().any(|(_, moves)| moves.contains(&king_pos)) {
-                GameEnd::Winner(!self.turn)
-            } else {
-                GameEnd::Draw
-            };
-            return Some(end)
+        // every board change ends up here, so everything drawn from the position is brought up to date before the
+        // next key is handled
+        if self.influence.is_some() {
+            self.influence = Some(self.compute_influence());
+        }
+        if self.threats.is_some() {
+            self.threats = Some(threats::find(&self.board, self.turn));
+        }
+        let ply = self.history.len();
+        match self.move_cache.get(ply) {
+            Some((board, moves)) if *board == self.board => self.possible_moves = moves.clone(),
+            _ => {
+                // a different move was played here, the cached positions after it are unreachable now
+                self.move_cache.truncate(ply);
+                // mate and stalemate go by every piece having no moves in it, no need to look for a legal move first
+                self.possible_moves = self.board.moves(self.turn).0;
+                if self.move_cache.len() == ply {
+                    self.move_cache.push((self.board, self.possible_moves.clone()));
+                }
+            }
+        }
+        // a piece picked up during the opponent's turn stays selected once it's our turn, as long as it can still
+        // move. Its marked squares come from the new moves, a square it could reach before the opponent's move
+        // can't be played anymore. Any other selection doesn't fit the new position.
+        match self.mode {
+            Mode::PieceSelected(from) if !matches!(self.possible_moves.get(&from), Some(moves) if !moves.is_empty()) => {
+                self.mode = Mode::Navigating;
+            }
+            Mode::Promoting { from, to, .. } if !matches!(self.possible_moves.get(&from), Some(moves) if moves.contains(&to)) => {
+                self.mode = Mode::Navigating;
+            }
+            _ => {}
+        }
+        if self.is_checkmate() {
+            return Some(GameEnd::Winner(!self.turn));
+        }
+        if self.is_stalemate() || self.board.has_insufficient_material() {
+            return Some(GameEnd::Draw);
         }
-        self.possible_moves = possible;
         None
     }
 
     pub fn play_move(&mut self, from: Pos, to: Pos) -> Option<GameEnd> {
-        let taken = self.board.move_piece(from, to);
+        self.play_move_promote(from, to, Piece::Queen)
+    }
+
+    /// Like `play_move`, a pawn that reaches the last rank becomes `promotion`
+    pub fn play_move_promote(&mut self, from: Pos, to: Pos, promotion: Piece) -> Option<GameEnd> {
+        let before = self.board;
+        let san = notation::san_promote(&self.board, &self.possible_moves, from, to, promotion);
+        let taken = self.board.move_piece_promote(from, to, promotion);
+        self.status = None;
+        let think = self.clock.map(|start| start.elapsed());
+        if self.clock.is_some() {
+            self.clock = Some(Instant::now());
+        }
+        self.history.push(HistoryEntry { board: before, from, to, taken, san, think });
         if let Some(piece) = taken {
             if self.turn == Color::White {
                 self.white.taken_pieces.push(piece);
@@ -59,51 +194,516 @@ impl Game {
                 self.black.taken_pieces.push(piece);
             }
         }
+        // like on the server a move withdraws the own draw offer, the opponent's stays until it's answered
+        if self.draw_offer == Some(self.turn) {
+            self.draw_offer = None;
+        }
+        // but any move withdraws a takeback request, it would undo a different move now
+        self.takeback = None;
+        if let Some(time_left) = &mut self.time_left {
+            time_left.moved(self.turn, self.history.len());
+        }
         self.turn = if self.turn == Color::White { Color::Black } else { Color::White };
         self.compute_moves()
     }
 
-    fn after_text(&self, f: &mut fmt::Formatter<'_>, y: i32) -> fmt::Result {
+    /// The square of the piece that was picked up
+    pub fn selected(&self) -> Option<Pos> {
+        match self.mode {
+            Mode::PieceSelected(from) => Some(from),
+            _ => None,
+        }
+    }
+
+    /// Whether a feature that assists the player can be used, says that it's off if the game is strict. `feature` is
+    /// the key of its name.
+    fn assisted(&mut self, feature: &str) -> bool {
+        if !self.assistance {
+            self.status = Some(tr!("strict.off", feature = tr!(feature)));
+        }
+        self.assistance
+    }
+
+    pub fn toggle_influence(&mut self) {
+        if !self.assisted("assist.control_map") {
+            return;
+        }
+        self.influence = match self.influence {
+            Some(_) => None,
+            None => Some(self.compute_influence()),
+        };
+    }
+
+    pub fn toggle_threats(&mut self) {
+        if !self.assisted("assist.threats_panel") {
+            return;
+        }
+        self.threats = match self.threats {
+            Some(_) => None,
+            None => Some(threats::find(&self.board, self.turn)),
+        };
+    }
+
+    pub fn toggle_move_counts(&mut self) {
+        if self.assisted("assist.move_counts") {
+            self.show_move_counts = !self.show_move_counts;
+        }
+    }
+
+    /// The entries of the settings menu, a strict game locks the ones that assist the player
+    pub fn settings(&self, config: &Config) -> Vec<Setting> {
+        let mut settings = config.settings();
+        if !self.assistance {
+            for setting in settings.iter_mut().filter(|setting| config::ASSISTANCE.contains(&setting.key)) {
+                setting.editable = false;
+                setting.locked = true;
+            }
+        }
+        settings
+    }
+
+    fn compute_influence(&self) -> [[i8; 8]; 8] {
+        let mut influence = [[0; 8]; 8];
+        for (y, row) in influence.iter_mut().enumerate() {
+            for (x, square) in row.iter_mut().enumerate() {
+                let pos = Pos::new(x as i8, y as i8);
+                *square = self.board.attackers(pos, Color::White) as i8 - self.board.attackers(pos, Color::Black) as i8;
+            }
+        }
+        influence
+    }
+
+    /// Zobrist hashes of the positions before the current one, oldest first, for the search to see repetitions
+    pub fn earlier_positions(&self) -> Vec<u64> {
+        let plies = self.history.len();
+        self.history.iter().enumerate().map(|(ply, entry)| {
+            let turn = if (plies - ply) % 2 == 1 { !self.turn } else { self.turn };
+            entry.board.zobrist(turn)
+        }).collect()
+    }
+
+    /// Takes back the last move. Returns false if there is no move to take back.
+    pub fn undo(&mut self) -> bool {
+        let Some(entry) = self.history.pop() else { return false };
+        self.board = entry.board;
+        self.turn = !self.turn;
+        if entry.taken.is_some() {
+            let player = if self.turn == Color::White { &mut self.white } else { &mut self.black };
+            player.taken_pieces.pop();
+        }
+        self.compute_moves();
+        true
+    }
+
+    /// Takes back the last move of a game between two players at the same keyboard, for a misclick. The cursor goes
+    /// back to the piece that moved.
+    pub fn undo_move(&mut self) {
+        let Some(entry) = self.history.last() else {
+            self.status = Some(tr!("undo.nothing"));
+            return;
+        };
+        let (from, san) = (entry.from, entry.san.clone());
+        self.undo();
+        self.cursor = from;
+        self.mode = Mode::Navigating;
+        // a side that was taken over after the move is played by the CPU again
+        self.takeovers.retain(|&(ply, _)| ply <= self.history.len());
+        if self.clock.is_some() {
+            self.clock = Some(Instant::now());
+        }
+        self.status = Some(tr!("undo.done", san = san));
+    }
+
+    /// How many plies a takeback of the last move of `color` undoes: only that move while the opponent is to move, the
+    /// opponent's answer as well otherwise. `None` if `color` hasn't moved yet.
+    pub fn takeback_plies(&self, color: Color) -> Option<usize> {
+        let plies = if self.turn == color { 2 } else { 1 };
+        (plies <= self.history.len()).then_some(plies)
+    }
+
+    /// Undoes moves until `plies` are left, after the server took them back. Offers and requests made in the positions
+    /// that are gone don't stand anymore, neither does a piece that was picked up.
+    pub fn take_back_to(&mut self, plies: usize) -> Result<(), String> {
+        if plies > self.history.len() {
+            return Err(format!("can't take back to ply {plies}, only {} were played", self.history.len()));
+        }
+        while self.history.len() > plies {
+            self.undo();
+        }
+        self.draw_offer = None;
+        self.takeback = None;
+        if matches!(self.mode, Mode::PieceSelected(_) | Mode::Promoting { .. }) {
+            self.mode = Mode::Navigating;
+        }
+        Ok(())
+    }
+
+    /// Replays the given moves from the starting position, replacing the moves played so far
+    pub fn replace_moves(&mut self, moves: &[(Pos, Pos)]) -> Result<Option<GameEnd>, String> {
+        // the think times of the moves that stay the same are kept, the replayed moves didn't take any time
+        let thinks: Vec<_> = self.history.iter().map(|entry| (entry.from, entry.to, entry.think)).collect();
+        let clock = self.clock.take();
+        // the replayed moves didn't take any time either
+        let time_left = self.time_left.take();
+        while self.undo() {}
+        let mut end = None;
+        for (i, &(from, to)) in moves.iter().enumerate() {
+            if end.is_some() || !self.possible_moves.get(&from).is_some_and(|moves| moves.contains(&to)) {
+                self.clock = clock;
+                self.time_left = time_left;
+                return Err(format!("illegal move {} -> {} at ply {}", notation::square(from), notation::square(to), i + 1));
+            }
+            end = self.play_move(from, to);
+            if let Some(&(old_from, old_to, think)) = thinks.get(i) {
+                if (old_from, old_to) == (from, to) {
+                    self.history[i].think = think;
+                }
+            }
+        }
+        self.clock = clock;
+        self.time_left = time_left;
+        Ok(end)
+    }
+
+    /// The position and side to move the game started from
+    pub fn start_position(&self) -> (Board, Color) {
+        match self.history.first() {
+            Some(first) => (first.board, first.board[first.from].expect("history entry without piece").1),
+            None => (self.board, self.turn),
+        }
+    }
+
+    /// The move list in lines of a move of White and Black each, starting with `...` for White if Black moved first
+    fn history_lines(&self) -> Vec<String> {
+        let black_first = self.start_position().1 == Color::Black;
+        let sans = black_first.then_some("...").into_iter().chain(self.history.iter().map(|entry| entry.san.as_str()));
+        let sans: Vec<_> = sans.collect();
+        sans.chunks(2).zip(self.start_clocks.fullmove..).map(|(moves, number)| match moves {
+            [white, black] => format!("{number:>3}. {white:<8} {black}"),
+            [white] => format!("{number:>3}. {white}"),
+            _ => unreachable!("chunks of two"),
+        }).collect()
+    }
+
+    /// Scrolls the move list back by a page, or forward with `back` false. It stops at the first and the latest move.
+    pub fn scroll_history(&mut self, back: bool) {
+        let most = self.history_lines().len().saturating_sub(HISTORY_ROWS);
+        self.history_scroll = if back {
+            (self.history_scroll + HISTORY_ROWS).min(most)
+        } else {
+            self.history_scroll.saturating_sub(HISTORY_ROWS)
+        };
+    }
+
+    /// The move list as drawn next to the sidebar: a title and the lines that are scrolled to, empty before the first
+    /// move
+    fn history_panel(&self) -> Vec<String> {
+        let lines = self.history_lines();
+        if lines.is_empty() {
+            return lines;
+        }
+        let end = lines.len() - self.history_scroll.min(lines.len().saturating_sub(HISTORY_ROWS));
+        let start = end.saturating_sub(HISTORY_ROWS);
+        let title = if lines.len() > HISTORY_ROWS {
+            tr!("history.scrolled", first = start + 1, last = end, count = lines.len())
+        } else {
+            tr!("history.title")
+        };
+        [cformat!("#y<{}>", title)].into_iter().chain(lines[start..end].iter().cloned()).collect()
+    }
+
+    /// The move counters of the current position, counted on from `start_clocks` through the history
+    #[cfg(test)]
+    pub fn clocks(&self) -> Clocks {
+        self.history.iter().fold(self.start_clocks, |clocks, entry| {
+            let (piece, color) = entry.board[entry.from].expect("history entry without piece");
+            clocks.after(piece == Piece::Pawn || entry.taken.is_some(), color)
+        })
+    }
+
+    /// The current position as FEN with its move counters
+    #[cfg(test)]
+    pub fn fen(&self) -> String {
+        let clocks = self.clocks();
+        self.board.to_fen(self.turn, clocks.halfmove, clocks.fullmove)
+    }
+
+    /// A hash of the game's moves and the position it started from, independent of player names, dates and any other
+    /// metadata, so identical games can be detected. The hash is 64-bit FNV-1a over the piece placement, side to move,
+    /// castling and en passant fields of the starting FEN, followed by two bytes per move: the from and to square
+    /// indices as `rank * 8 + file`. This encoding is part of the saved data and must stay the same across versions.
+    pub fn moves_hash(&self) -> u64 {
+        let (start, turn) = self.start_position();
+        let fen = start.to_fen(turn, 0, 1);
+        let position = fen.rsplitn(3, ' ').nth(2).expect("FEN has counters");
+
+        let moves = self.history.iter().flat_map(|entry| [
+            (entry.from.y * 8 + entry.from.x) as u8,
+            (entry.to.y * 8 + entry.to.x) as u8,
+        ]);
+        fnv1a(position.bytes().chain(moves))
+    }
+
+    /// The whole position as plain sentences for the accessible mode, one piece per line
+    pub fn describe(&self) -> Vec<String> {
+        use Piece::*;
+
+        let mut lines = Vec::new();
+        for color in [Color::White, Color::Black] {
+            for piece in [King, Queen, Rook, Bishop, Knight, Pawn] {
+                for rank in 0..8 {
+                    for file in 0..8 {
+                        let pos = Pos::new(file, rank);
+                        if self.board[pos] == Some((piece, color)) {
+                            lines.push(tr!(
+                                "describe.piece",
+                                color = lang::color(color), piece = lang::piece(piece), square = notation::square(pos),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        lines.push(self.describe_turn());
+        lines
+    }
+
+    pub fn describe_turn(&self) -> String {
+        if self.is_check() {
+            tr!("turn.in_check", color = lang::color(self.turn))
+        } else {
+            tr!("turn.to_move", color = lang::color(self.turn))
+        }
+    }
+
+    /// Whether the side to move is in check
+    pub fn is_check(&self) -> bool {
+        self.checked_king().is_some()
+    }
+
+    /// Whether the side to move is in check without a legal move. Like `is_stalemate` it goes by the moves of the last
+    /// `compute_moves`.
+    pub fn is_checkmate(&self) -> bool {
+        self.possible_moves.values().all(HashSet::is_empty) && self.is_check()
+    }
+
+    /// Whether the side to move has no legal move without being in check
+    pub fn is_stalemate(&self) -> bool {
+        self.possible_moves.values().all(HashSet::is_empty) && !self.is_check()
+    }
+
+    /// The square of the king of the side to move if it's in check
+    fn checked_king(&self) -> Option<Pos> {
+        self.board.find_king(self.turn).filter(|&king| self.board.threatens(king, !self.turn))
+    }
+
+    /// The terminal title for the players of the colors in `me`, like `♟ termchess — your move, in check (vs Bob)`
+    pub fn title(&self, me: &[Color], end: Option<&GameEnd>) -> String {
+        let player = |color| if color == Color::White { &self.white } else { &self.black };
+        // the opponent is only named when a single player sits at this terminal
+        let opponent = match me {
+            [color] => Some(&player(!*color).name),
+            _ => None,
+        };
+        let mut state = match end {
+            Some(GameEnd::Winner(winner)) if opponent.is_some() => {
+                if me.contains(winner) { tr!("title.you_won") } else { tr!("title.you_lost") }
+            }
+            Some(GameEnd::Winner(winner)) => tr!("title.won", name = player(*winner).name),
+            Some(GameEnd::Draw) => tr!("title.draw"),
+            Some(GameEnd::Aborted) => tr!("title.aborted"),
+            None if opponent.is_some() && me.contains(&self.turn) => tr!("title.your_move"),
+            None if opponent.is_some() => tr!("title.waiting", name = player(self.turn).name),
+            None => tr!("turn.to_move", color = lang::color(self.turn)),
+        };
+        if end.is_none() && self.is_check() {
+            state = tr!("title.in_check", state = state);
+        }
+        let icon = if crate::colors::unicode_supported() { "♟ " } else { "" };
+        match opponent {
+            Some(opponent) => format!("{icon}{}", tr!("title.vs", state = state, opponent = opponent)),
+            None => format!("{icon}{}", tr!("title.plain", state = state)),
+        }
+    }
+
+    pub fn describe_square(&self, pos: Pos) -> String {
+        let square = notation::square(pos);
+        let s = match self.board[pos] {
+            Some((piece, color)) => tr!("square.piece", square = square, color = lang::color(color), piece = lang::piece(piece)),
+            None => tr!("square.empty", square = square),
+        };
+        match self.selected() {
+            Some(from) if self.possible_moves.get(&from).is_some_and(|moves| moves.contains(&pos)) => {
+                tr!("square.legal_move", square = s)
+            }
+            _ => s,
+        }
+    }
+
+    /// The square shown at a column and row of the board on screen, both counted from the top left corner. Squares
+    /// are indexed `[rank][file]` from White's a1. The board is drawn with White at the bottom, flipped it's turned
+    /// around so Black is at the bottom: the ranks and the files are both reversed, otherwise the board would be
+    /// mirrored. Everything that goes between the screen and the board uses this and `screen_of`.
+    pub fn square_on_screen(&self, column: i8, row: i8) -> Pos {
+        if self.flip_board { Pos::new(7 - column, row) } else { Pos::new(column, 7 - row) }
+    }
+
+    /// The column and row on screen of a square, the reverse of `square_on_screen`
+    pub fn screen_of(&self, square: Pos) -> (i8, i8) {
+        if self.flip_board { (7 - square.x, square.y) } else { (square.x, 7 - square.y) }
+    }
+
+    /// Moves the cursor by columns and rows on screen, so the arrow keys go the same way however the board is turned.
+    /// The cursor stops at the edges.
+    pub fn move_cursor(&mut self, right: i8, down: i8) {
+        let (column, row) = self.screen_of(self.cursor);
+        self.cursor = self.square_on_screen((column + right).clamp(0, 7), (row + down).clamp(0, 7));
+    }
+
+    /// The square drawn at a column and row of the text of the board, both layouts draw the board the same way
+    pub fn square_at(&self, column: i32, row: i32) -> Option<Pos> {
+        // the first row has the files and every rank starts with its number
+        let (column, row) = ((column - 2).div_euclid(2), row - 1);
+        if !(0..8).contains(&column) || !(0..8).contains(&row) {
+            return None;
+        }
+        Some(self.square_on_screen(column as i8, row as i8))
+    }
+
+    /// The player's name in their color
+    fn name_tag(&self, color: Color) -> String {
+        match color {
+            Color::White => cformat!("#bg:rgb(255,255,255);rgb(0,0,0)<{}>", truncate_str(&self.white.name, NAME_WIDTH, "…")),
+            Color::Black => cformat!("#bg:rgb(0,0,0)<{}>", truncate_str(&self.black.name, NAME_WIDTH, "…")),
+        }
+    }
+
+    /// The time left of a player after their name, nothing in games without a clock
+    pub fn clock_tag(&self, color: Color) -> String {
+        let Some(time_left) = &self.time_left else { return String::new() };
+        let left = time_left.of(color, self.turn);
+        let (minutes, seconds) = (left.as_secs() / 60, left.as_secs() % 60);
+        if left < LOW_TIME {
+            cformat!("  #r<{}:{:02}.{}>", minutes, seconds, left.subsec_millis() / 100)
+        } else {
+            format!("  {minutes}:{seconds:02}")
+        }
+    }
+
+    /// `Check!` after the name of a player in check, nothing otherwise
+    fn check_tag(&self, color: Color) -> String {
+        if color == self.turn && self.is_check() { cformat!("  #r<{}>", tr!("sidebar.check")) } else { String::new() }
+    }
+
+    fn after_text(&self, f: &mut impl fmt::Write, y: i32) -> fmt::Result {
         cwrite!(f, "    ")?;
         match y {
-            0 => cwrite!(f, "#bg:rgb(255,255,255);rgb(0,0,0)<{}>", self.white.name)?,
+            0 => {
+                let color = Color::White;
+                write!(f, "{}{}{}", self.name_tag(color), self.clock_tag(color), self.check_tag(color))?;
+            }
             1 => {
                 for piece in &self.white.taken_pieces {
-                    cwrite!(f, "{}", piece.character(Color::Black))?;
+                    cwrite!(f, "{}", self.glyphs.character(*piece, Color::Black))?;
+                }
+            }
+            // the threats panel fills the rows between the players, top to bottom however the board is turned
+            2..=5 => if let Some(threats) = &self.threats {
+                let row = (self.screen_of(Pos::new(0, y as i8)).1 - 2) as usize;
+                match row {
+                    0 if threats.is_empty() => cwrite!(f, "#g<{}>", tr!("threats.none"))?,
+                    0 => cwrite!(f, "#y<{}>", tr!("threats.title"))?,
+                    // the last row says how many didn't fit
+                    3 if threats.len() > 3 => cwrite!(f, "#y<{}>", tr!("threats.more", count = threats.len() - 2))?,
+                    _ => if let Some(threat) = threats.get(row - 1) {
+                        cwrite!(f, "#y<{}>", threat)?;
+                    }
                 }
             }
             6 => {
                 for piece in &self.black.taken_pieces {
-                    cwrite!(f, "{}", piece.character(Color::White))?;
+                    cwrite!(f, "{}", self.glyphs.character(*piece, Color::White))?;
                 }
             }
-            7 => cwrite!(f, "#bg:rgb(0,0,0)<{}>", self.black.name)?,
+            7 => {
+                let color = Color::Black;
+                write!(f, "{}{}{}", self.name_tag(color), self.clock_tag(color), self.check_tag(color))?;
+            }
             _ => {}
         }
         Ok(())
     }
+
+    /// The sidebar of the compact layout, which goes below the board. Captured pieces are only counted.
+    fn below_text(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the player at the top of the board first
+        let players = if self.flip_board { [Color::White, Color::Black] } else { [Color::Black, Color::White] };
+        for color in players {
+            let player = if color == Color::White { &self.white } else { &self.black };
+            write!(f, "\n{}{}", self.name_tag(color), self.clock_tag(color))?;
+            if !player.taken_pieces.is_empty() {
+                write!(f, " {}", tr!("sidebar.took", count = player.taken_pieces.len()))?;
+            }
+            write!(f, "{}", self.check_tag(color))?;
+        }
+        match self.threats.as_deref() {
+            Some([]) => cwrite!(f, "\n#g<{}>", tr!("threats.none"))?,
+            Some([threat]) => cwrite!(f, "\n#y<{}>", tr!("threats.one", threat = threat))?,
+            Some([threat, more @ ..]) => cwrite!(f, "\n#y<{}>", tr!("threats.first", threat = threat, more = more.len()))?,
+            None => {}
+        }
+        Ok(())
+    }
 }
+/// The alternate form `{:#}` is the compact layout for narrow terminals: the sidebar goes below the board and the
+/// texts are shortened. A width like `{:100}` is the terminal's, the wide layout draws the move list right of the
+/// sidebar if it fits.
 impl fmt::Display for Game {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let compact = f.alternate();
+        // the sidebar of each line of the board is put together first, the move list starts after the widest one
+        let mut sidebar = vec![String::new(); 9];
+        if !compact {
+            for (y, line) in (-1..8).zip(&mut sidebar) {
+                self.after_text(line, y)?;
+            }
+        }
+        let column = sidebar.iter().map(|line| measure_text_width(line)).max().unwrap_or(0).max(4 + NAME_WIDTH) + 2;
+        let history = match f.width() {
+            Some(width) if !compact && BOARD_WIDTH + column + HISTORY_WIDTH <= width => self.history_panel(),
+            _ => Vec::new(),
+        };
+        // the sidebar goes by rank, the move list by line on screen
+        let right_of_board = |f: &mut fmt::Formatter<'_>, y: i32, line: usize| {
+            let sidebar = &sidebar[(y + 1) as usize];
+            match history.get(line) {
+                Some(moves) => write!(f, "{}{moves}", pad_str(sidebar, column, Alignment::Left, None)),
+                None => write!(f, "{sidebar}"),
+            }
+        };
         cwrite!(f, "#bg:rgb(102,51,0);black<## >")?;
-        for file in 0..8 {
-            cwrite!(f, "#bg:rgb(102,51,0);g<{} >", (b'a' + file) as char)?;
+        for column in 0..8 {
+            let file = self.square_on_screen(column, 0).x;
+            cwrite!(f, "#bg:rgb(102,51,0);g<{} >", (b'a' + file as u8) as char)?;
         }
         cwrite!(f, "#bg:rgb(102,51,0)<  >")?;
-        self.after_text(f, -1)?;
+        right_of_board(f, -1, 0)?;
         writeln!(f)?;
 
-        let mut bg_white = true;
-        for i in 0usize..8 {
-
-            let rank = if self.flip_board { i } else { 7-i };
-            let row = self.board.iter().nth(rank).unwrap();
+        // the king in check is drawn on red whatever else is shown on its square, the markers of the cursor stay
+        let checked_king = self.checked_king();
+        // so the move the opponent or the CPU just played can be seen, undoing a move takes its squares along
+        let last_move = self.history.last().map(|entry| [entry.from, entry.to]);
+        for row in 0..8 {
+            let rank = self.square_on_screen(0, row).y as usize;
 
             cwrite!(f, "#bg:rgb(102,51,0);g<{} >", rank + 1)?;
-            for (file, piece) in row.into_iter().enumerate() {
+            for column in 0..8 {
+                let file = self.square_on_screen(column, row).x as usize;
+                let piece = self.board[Pos::new(file as i8, rank as i8)];
                 let on_cursor = self.cursor.x == file as i8 && self.cursor.y == rank as i8;
-                let moving = self.moving.unwrap_or(self.cursor);
-                let extra = if self.possible_moves.get(&moving).map_or(false, |s| s.contains(&Pos::new(file as i8, rank as i8))) {
+                let moving = self.selected().unwrap_or(self.cursor);
+                let extra = if self.possible_moves.get(&moving).is_some_and(|s| s.contains(&Pos::new(file as i8, rank as i8))) {
                     if on_cursor {
                         cformat!("#b<##>")
                     } else {
@@ -111,42 +711,266 @@ impl fmt::Display for Game {
                     }
                    
                 } else if on_cursor {
-                    if self.moving.is_some() {
+                    if self.selected().is_some() {
                         cformat!("#g<<>")
                     } else {
                         cformat!("#r<<>")
                     }
                 } else { " ".to_owned() };
 
+                // move count badges only use the marker slot while nothing else needs it
+                let move_count = match piece {
+                    Some((_, color)) if self.show_move_counts && self.assistance && self.selected().is_none() && color == self.turn => {
+                        self.possible_moves.get(&Pos::new(file as i8, rank as i8)).map(HashSet::len)
+                    }
+                    _ => None
+                };
+                let extra = match move_count {
+                    Some(count) if extra == " " && count > 0 => {
+                        let badge = char::from_digit(count.min(9) as u32, 10).unwrap();
+                        cformat!("#rgb(40,40,40)<{}>", badge)
+                    }
+                    _ => extra,
+                };
+
+                let picked_up = self.selected() == Some(Pos::new(file as i8, rank as i8));
+                let extra = if picked_up && extra == " " { cformat!("#g<·>") } else { extra };
+
                 let p = if let Some((piece, color)) = piece {
-                    piece.character(color)
+                    if picked_up || move_count == Some(0) {
+                        self.glyphs.dimmed_character(piece, color)
+                    } else {
+                        self.glyphs.character(piece, color)
+                    }
                 } else {
                     // doesn't matter which color spaces have
                     String::from(" ")
                 };
-                match bg_white {
-                    // color used twice here because it is reset by inner string
-                    true => {
-                        cwrite!(f, "#bg:rgb(238,238,238)<{}>", p)?;
-                        cwrite!(f, "#bg:rgb(238,238,238)<{}>", extra)?;
-                    }
-                    false => {
-                        cwrite!(f, "#bg:rgb(118,150,86)<{}>", p)?;
-                        cwrite!(f, "#bg:rgb(118,150,86)<{}>", extra)?;
+                // the last move stays visible while the control map is shown
+                let tint = match self.influence.map(|influence| influence[rank][file].signum()) {
+                    _ if last_move.is_some_and(|squares| squares.contains(&Pos::new(file as i8, rank as i8))) => {
+                        Tint::LastMove
                     }
+                    Some(1) => Tint::White,
+                    Some(-1) => Tint::Black,
+                    _ => Tint::None,
+                };
+                // a1 is dark, the color follows from the square so it's right both ways the board is turned
+                let light = (file + rank) % 2 == 1;
+                // color used twice here because it is reset by inner string
+                if checked_king == Some(Pos::new(file as i8, rank as i8)) {
+                    cwrite!(f, "#bg:rgb(224,72,60)<{}>#bg:rgb(224,72,60)<{}>", p, extra)?;
+                    continue;
+                }
+                match (light, tint) {
+                    (true, Tint::None) => cwrite!(f, "#bg:rgb(238,238,238)<{}>#bg:rgb(238,238,238)<{}>", p, extra)?,
+                    (true, Tint::White) => cwrite!(f, "#bg:rgb(206,220,244)<{}>#bg:rgb(206,220,244)<{}>", p, extra)?,
+                    (true, Tint::Black) => cwrite!(f, "#bg:rgb(244,210,204)<{}>#bg:rgb(244,210,204)<{}>", p, extra)?,
+                    (true, Tint::LastMove) => cwrite!(f, "#bg:rgb(246,238,150)<{}>#bg:rgb(246,238,150)<{}>", p, extra)?,
+                    (false, Tint::None) => cwrite!(f, "#bg:rgb(118,150,86)<{}>#bg:rgb(118,150,86)<{}>", p, extra)?,
+                    (false, Tint::White) => cwrite!(f, "#bg:rgb(98,142,122)<{}>#bg:rgb(98,142,122)<{}>", p, extra)?,
+                    (false, Tint::Black) => cwrite!(f, "#bg:rgb(146,136,78)<{}>#bg:rgb(146,136,78)<{}>", p, extra)?,
+                    (false, Tint::LastMove) => cwrite!(f, "#bg:rgb(186,196,76)<{}>#bg:rgb(186,196,76)<{}>", p, extra)?,
                 }
-                bg_white = !bg_white;
             }
-            bg_white = !bg_white;
             cwrite!(f, "#bg:rgb(102,51,0);g<  >")?;
-            self.after_text(f, rank as i32)?;
-            self.after_text(f, 8)?;
+            right_of_board(f, rank as i32, row as usize + 1)?;
             writeln!(f)?;
         }
-        cwrite!(f, "#bg:rgb(102,51,0)<{}>", " ".repeat(2*8+4))
+        cwrite!(f, "#bg:rgb(102,51,0)<{}>", " ".repeat(2*8+4))?;
+        if compact {
+            self.below_text(f)?;
+        }
+        if let Some(features) = self.features {
+            writeln!(f)?;
+            for (i, &(key, feature)) in ONLINE_KEYS.iter().enumerate() {
+                // aborting is only possible until both players have moved
+                if feature == Features::ABORT && self.history.len() >= 2 {
+                    continue;
+                }
+                let hint = tr!(key);
+                let separator = if i == 0 { "" } else { "  " };
+                if features.contains(feature) {
+                    write!(f, "{separator}{hint}")?;
+                } else {
+                    cwrite!(f, "{}#rgb(127,127,127)<{}>", separator, hint)?;
+                }
+            }
+            if let Some(code) = self.reconnect_code {
+                let key = if compact { "keys.code" } else { "keys.reconnect_code" };
+                write!(f, "  {}", tr!(key, code = format!("{code:04}")))?;
+            }
+        }
+        // the compact layout has no empty lines between the sections, the screen is short
+        let (gap, chat_lines, width) = if compact {
+            ("", COMPACT_CHAT_LINES, COMPACT_TEXT_WIDTH)
+        } else {
+            ("\n", CHAT_LINES, usize::MAX)
+        };
+        if let Some(status) = &self.status {
+            write!(f, "\n{gap}{}", truncate_str(status, width, "…"))?;
+        }
+        if (!self.chat.is_empty() || matches!(self.mode, Mode::TextInput(_))) && !compact {
+            writeln!(f)?;
+        }
+        for line in &self.chat[self.chat.len().saturating_sub(chat_lines)..] {
+            write!(f, "\n{}", truncate_str(line, width, "…"))?;
+        }
+        if let Mode::TextInput(input) = &self.mode {
+            write!(f, "\n{} {input}_", tr!("chat.say"))?;
+        }
+        if let Mode::Promoting { piece, .. } = &self.mode {
+            write!(f, "\n{gap}{}", tr!("promote.title"))?;
+            for promotion in Piece::PROMOTIONS {
+                let glyph = self.glyphs.glyph(promotion, self.turn);
+                if promotion == *piece {
+                    cwrite!(f, " #y<[{} {}]>", glyph, lang::piece(promotion))?;
+                } else {
+                    write!(f, " {glyph}")?;
+                }
+            }
+            write!(f, "\n{}", tr!("promote.keys"))?;
+        }
+        if let Mode::Settings { selected, entries } = &self.mode {
+            cwrite!(f, "\n\n#y<{}>  {}", tr!("settings.title"), tr!("settings.keys"))?;
+            for (i, setting) in entries.iter().enumerate() {
+                let marker = if i == *selected { '>' } else { ' ' };
+                if setting.locked {
+                    cwrite!(f, "\n{} #rgb(127,127,127)<🔒 {}: {}>", marker, setting.label, setting.value)?;
+                } else if setting.editable {
+                    write!(f, "\n{marker} {}: {}", setting.label, setting.value)?;
+                } else {
+                    cwrite!(f, "\n{} #rgb(127,127,127)<{}: {}>", marker, setting.label, setting.value)?;
+                }
+            }
+        }
+        if let Mode::Menu { selected, entries } = &self.mode {
+            cwrite!(f, "\n\n#y<{}>  {}", tr!("menu.title"), tr!("menu.keys"))?;
+            for (i, entry) in entries.iter().enumerate() {
+                let marker = if i == *selected { '>' } else { ' ' };
+                write!(f, "\n{marker} {}", tr!(entry.key()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// background tint of a square on top of its light/dark color
+enum Tint {
+    None,
+    // controlled by white
+    White,
+    // controlled by black
+    Black,
+    // the from or to square of the last move, also over the control map's tints
+    LastMove,
+}
+
+/// The halfmove clock and the fullmove number of FEN
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Clocks {
+    /// Plies since the last capture or pawn move
+    pub halfmove: u32,
+    /// Starts at 1 and goes up after every move of Black
+    pub fullmove: u32,
+}
+impl Default for Clocks {
+    fn default() -> Self {
+        Self { halfmove: 0, fullmove: 1 }
+    }
+}
+impl Clocks {
+    /// The counters after a move of `color`, `reset` if it moved a pawn or took a piece
+    #[cfg(test)]
+    pub fn after(self, reset: bool, color: Color) -> Self {
+        Self {
+            halfmove: if reset { 0 } else { self.halfmove + 1 },
+            fullmove: if color == Color::Black { self.fullmove + 1 } else { self.fullmove },
+        }
     }
 }
 
+/// The clocks of an online game. The server sends the real times after every move, in between they run here so they
+/// can be drawn.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeLeft {
+    /// The times of White and Black when they were last set
+    pub white: Duration,
+    pub black: Duration,
+    /// Added to a player's time after each of their moves
+    pub increment: Duration,
+    /// Since when the clock of the side to move runs, `None` until both players made their first move
+    pub running: Option<Instant>,
+}
+impl TimeLeft {
+    pub fn new(initial: Duration, increment: Duration) -> Self {
+        Self { white: initial, black: initial, increment, running: None }
+    }
+
+    /// The time `color` has left now while `turn` is to move
+    pub fn of(&self, color: Color, turn: Color) -> Duration {
+        let left = if color == Color::White { self.white } else { self.black };
+        match self.running {
+            Some(since) if color == turn => left.saturating_sub(since.elapsed()),
+            _ => left,
+        }
+    }
+
+    /// Stops the clock of `color` after its move and starts the other one, `plies` were played including the move.
+    /// Only a guess until the server's times arrive, those replace it with `set`.
+    pub fn moved(&mut self, color: Color, plies: usize) {
+        let left = self.of(color, color) + self.increment;
+        *if color == Color::White { &mut self.white } else { &mut self.black } = left;
+        self.running = (plies >= 2).then(Instant::now);
+    }
+
+    /// Freezes the clocks when the game is over
+    pub fn stop(&mut self, turn: Color) {
+        let left = self.of(turn, turn);
+        *if turn == Color::White { &mut self.white } else { &mut self.black } = left;
+        self.running = None;
+    }
+
+    /// Takes the times the server sent after `plies` moves, the clock of the side to move runs from now on
+    pub fn set(&mut self, times: ClockTimes, plies: usize) {
+        self.white = Duration::from_millis(times.white.into());
+        self.black = Duration::from_millis(times.black.into());
+        self.running = (plies >= 2).then(Instant::now);
+    }
+}
+
+/// The points of the player and their opponent over a series of online games, counted in half points because a draw
+/// gives each of them one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub mine: u32,
+    pub theirs: u32,
+}
+impl Score {
+    /// The score after a game in which the player had `color`, aborted games don't count
+    pub fn after(self, end: &GameEnd, color: Color) -> Self {
+        match end {
+            GameEnd::Winner(winner) if *winner == color => Self { mine: self.mine + 2, ..self },
+            GameEnd::Winner(_) => Self { theirs: self.theirs + 2, ..self },
+            GameEnd::Draw => Self { mine: self.mine + 1, theirs: self.theirs + 1 },
+            GameEnd::Aborted => self,
+        }
+    }
+}
+impl fmt::Display for Score {
+    /// Like `2–1` or `1½–½`, the player's points first
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let points = |half_points: u32| match (half_points / 2, half_points % 2) {
+            (0, 1) => "½".to_owned(),
+            (whole, 1) => format!("{whole}½"),
+            (whole, _) => whole.to_string(),
+        };
+        write!(f, "{}–{}", points(self.mine), points(self.theirs))
+    }
+}
+
+#[derive(Clone)]
 pub struct Player {
     pub name: String,
     pub taken_pieces: Vec<Piece>,
@@ -160,7 +984,275 @@ impl Player {
     }
 }
 
-pub enum GameEnd {
-    Draw,
-    Winner(Color),
-}
\ No newline at end of file
+/// A played move together with the position it was played in
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistoryEntry {
+    pub board: Board,
+    #[cfg_attr(feature = "serde", serde(with = "crate::save::square"))]
+    pub from: Pos,
+    #[cfg_attr(feature = "serde", serde(with = "crate::save::square"))]
+    pub to: Pos,
+    pub taken: Option<Piece>,
+    pub san: String,
+    /// How long the player took for the move, if it was played with the clock running
+    pub think: Option<Duration>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicBool, thread, time::Duration};
+
+    use console::{measure_text_width, strip_ansi_codes, Key};
+    use vecm::vec2;
+
+    use super::{Clocks, Game, GameEnd, Score, TimeLeft, NAME_WIDTH};
+    use crate::{ai, backend::cells, board::Board, config::Config, input, notation, pgn, piece::Color};
+
+    fn start() -> Game {
+        Game::new(vec2![0, 0], "White".to_owned(), "Black".to_owned(), Board::starting_position(), Color::White)
+    }
+
+    fn from_fen(fen: &str) -> Game {
+        let (board, turn) = Board::from_fen(fen).unwrap();
+        Game::new(vec2![0, 0], "White".to_owned(), "Black".to_owned(), board, turn)
+    }
+
+    fn square(name: &str) -> crate::Pos {
+        notation::parse_square(name).unwrap()
+    }
+
+    /// The background codes of the piece and the marker of a square as the board draws them
+    fn square_background(game: &Game, name: &str) -> [Option<String>; 2] {
+        let (column, row) = game.screen_of(square(name));
+        let line = cells(game.to_string().lines().nth(row as usize + 1).unwrap());
+        let x = 2 + 2 * column as usize;
+        [line[x].1.clone(), line[x + 1].1.clone()]
+    }
+
+    /// Names of wide characters are cut to the name width in columns, not in characters, and start in the same column
+    /// of the sidebar
+    #[test]
+    fn wide_names() {
+        let (white, black) = ("🐉".repeat(20), "王".repeat(30));
+        let game = Game::new(vec2![0, 0], white, black, Board::starting_position(), Color::White);
+        let text = strip_ansi_codes(&game.to_string()).into_owned();
+        let mut columns = Vec::new();
+        for first in ['🐉', '王'] {
+            let line = text.lines().find(|line| line.contains(first)).expect("the name isn't drawn");
+            let start = line.find(first).unwrap();
+            let end = line.find('…').expect("the long name isn't cut") + '…'.len_utf8();
+            let name = &line[start..end];
+            let width = measure_text_width(name);
+            assert!((NAME_WIDTH - 1..=NAME_WIDTH).contains(&width), "the name `{name}` is {width} columns wide");
+            columns.push(measure_text_width(&line[..start]));
+        }
+        assert_eq!(columns[0], columns[1], "the names start in different columns");
+    }
+
+    /// The halfmove clock counts the plies since the last capture or pawn move and the fullmove number goes up after
+    /// Black moved. Games from a FEN count on from its counters, also when they're written as PGN and read back.
+    #[test]
+    fn clocks() {
+        let mut game = start();
+        let moves = [
+            ("e2", "e4", (0, 1)), ("g8", "f6", (1, 2)), ("g1", "f3", (2, 2)), ("f6", "e4", (0, 3)),
+            ("b1", "c3", (1, 3)),
+        ];
+        for (from, to, (halfmove, fullmove)) in moves {
+            game.play_move(square(from), square(to));
+            assert_eq!(game.clocks(), Clocks { halfmove, fullmove }, "wrong counters after {from}-{to}");
+        }
+        assert!(game.fen().ends_with(" b KQkq - 1 3"), "the FEN of the game is `{}`", game.fen());
+        game.undo();
+        assert_eq!(game.clocks(), Clocks { halfmove: 0, fullmove: 3 }, "wrong counters after taking back a move");
+
+        let fen = "8/5k2/8/8/8/8/1K6/8 b - - 12 71";
+        let text = format!("[SetUp \"1\"]\n[FEN \"{fen}\"]\n\n1... Ke6 2. Kb3 *\n");
+        let game = pgn::parse(&text).unwrap().to_game().unwrap();
+        assert_eq!(game.fen(), "8/8/4k3/8/8/1K6/8/8 b - - 14 72");
+        let written = pgn::write(&game, None);
+        assert!(
+            written.contains(&format!("[FEN \"{fen}\"]")) && written.contains("71... Ke6 72. Kb3"),
+            "the PGN of the game from `{fen}` is\n{written}",
+        );
+    }
+
+    /// Check, mate and stalemate are told apart after `compute_moves`, only mate has a winner. The king in check is
+    /// named next to its player.
+    #[test]
+    fn end_states() {
+        let positions = [
+            ("4k3/8/8/8/8/8/4R3/4K3 b - - 0 1", (true, false, false)),
+            ("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", (true, true, false)),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", (false, false, true)),
+            ("4k3/8/8/8/8/8/3R4/4K3 b - - 0 1", (false, false, false)),
+        ];
+        for (fen, expected) in positions {
+            let mut game = from_fen(fen);
+            let turn = game.turn;
+            let end = game.compute_moves();
+            let state = (game.is_check(), game.is_checkmate(), game.is_stalemate());
+            assert_eq!(state, expected, "`{fen}` has the wrong (check, mate, stalemate)");
+            // the side to move is the one that got mated
+            let expected_end = match expected {
+                (_, true, _) => Some(GameEnd::Winner(!turn)),
+                (_, _, true) => Some(GameEnd::Draw),
+                _ => None,
+            };
+            assert_eq!(end.as_ref().map(GameEnd::result), expected_end.as_ref().map(GameEnd::result), "`{fen}` ended");
+            let named = game.to_string().contains("Check!");
+            assert_eq!(named, game.is_check(), "the board of `{fen}` doesn't say whether the king is in check");
+        }
+    }
+
+    /// Games end in a draw as soon as neither side has the material to mate, also when the last piece that could mate
+    /// is taken
+    #[test]
+    fn insufficient_material() {
+        let positions = [
+            ("8/8/4k3/8/8/3K4/8/8 w - - 0 1", true),
+            ("8/8/4k3/8/8/3K4/8/2B3b1 w - - 0 1", true),
+            ("8/8/4k3/8/8/3K4/8/2B2b2 w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/4P3/8 w - - 0 1", false),
+        ];
+        for (fen, insufficient) in positions {
+            let end = from_fen(fen).compute_moves();
+            let result = end.as_ref().map(GameEnd::result);
+            assert_eq!(insufficient, matches!(end, Some(GameEnd::Draw)), "the game of `{fen}` ended with {result:?}");
+        }
+        let mut game = from_fen("8/8/4k3/8/4r3/3K4/8/8 w - - 0 1");
+        let end = game.play_move(vec2![3, 2], vec2![4, 3]);
+        assert!(matches!(end, Some(GameEnd::Draw)), "taking the last rook didn't draw the game");
+    }
+
+    /// A strict game draws none of the helpers, even with all of them turned on in the config and by their keys. The
+    /// same keys in a normal game show all of them, so the test can't pass because nothing is drawn at all.
+    #[test]
+    fn strict() {
+        // the influence tints, the move count badges and the threats panel
+        const ASSISTANCE: [&str; 6] = [
+            "48;2;206;220;244", "48;2;244;210;204", "48;2;98;142;122", "48;2;146;136;78", "38;2;40;40;40", "no threats",
+        ];
+        let config = Config { move_counts: true, ..Config::default() };
+        for assistance in [true, false] {
+            let mut game = start();
+            game.show_move_counts = config.move_counts;
+            game.assistance = assistance;
+            for key in [Key::Char('c'), Key::Char('h')] {
+                input::handle_key(&mut game, key, &[Color::White]);
+            }
+            let screen = game.to_string();
+            let shown: Vec<_> = ASSISTANCE.iter().filter(|code| screen.contains(*code)).collect();
+            if assistance {
+                assert!(shown.len() >= 4, "a normal game only draws {shown:?} of the helpers");
+            } else {
+                assert!(shown.is_empty(), "a strict game draws {shown:?}");
+            }
+            let settings = game.settings(&config);
+            let move_counts = settings.iter().find(|setting| setting.key == "move_counts").unwrap();
+            assert!(
+                move_counts.editable == assistance && move_counts.locked != assistance,
+                "the move count setting isn't {} in the menu", if assistance { "open" } else { "locked" },
+            );
+        }
+    }
+
+    /// The from and to squares of the last move are drawn on yellow, the cursor and its markers on them included, and
+    /// only until the move is taken back. The control map doesn't tint them over.
+    #[test]
+    fn last_move_highlight() {
+        const LIGHT: &str = "48;2;246;238;150";
+        let mut game = start();
+        game.cursor = square("e4");
+        game.play_move(square("e2"), square("e4"));
+        for name in ["e2", "e4"] {
+            let background = square_background(&game, name);
+            assert_eq!(background, [Some(LIGHT.to_owned()), Some(LIGHT.to_owned())], "{name} of the last move");
+        }
+        assert_ne!(square_background(&game, "d3")[0].as_deref(), Some(LIGHT), "d3 isn't part of the last move");
+        // White controls e2 and d3, only d3 gets the control map's tint
+        game.toggle_influence();
+        let [e2, d3] = [square_background(&game, "e2"), square_background(&game, "d3")];
+        assert!(
+            e2[0].as_deref() == Some(LIGHT) && d3[0].as_deref() == Some("48;2;206;220;244"),
+            "with the control map e2 is drawn on {e2:?} and d3 on {d3:?}",
+        );
+        game.toggle_influence();
+        game.undo();
+        assert_ne!(square_background(&game, "e4")[0].as_deref(), Some(LIGHT), "e4 is highlighted after the takeback");
+    }
+
+    /// The king in check is drawn on red until the check is resolved, after that its squares get the last move's tint
+    /// like any others
+    #[test]
+    fn king_in_check_highlight() {
+        const CHECK: &str = "48;2;224;72;60";
+        let mut game = from_fen("4k3/8/8/8/8/8/3R4/4K3 w - - 0 1");
+        game.cursor = square("e8");
+        game.play_move(square("d2"), square("e2"));
+        let background = square_background(&game, "e8");
+        assert_eq!(background, [Some(CHECK.to_owned()), Some(CHECK.to_owned())], "the king in check on e8");
+        game.play_move(square("e8"), square("d8"));
+        let [e8, d8] = [square_background(&game, "e8"), square_background(&game, "d8")];
+        assert!(
+            e8[0].as_deref() == Some("48;2;246;238;150") && d8[0].as_deref() != Some(CHECK),
+            "after Kd8 the king's squares are drawn on {e8:?} and {d8:?}",
+        );
+    }
+
+    /// A queen against a rook played out by the search doesn't repeat a position three times, the side that is ahead
+    /// avoids it
+    #[test]
+    fn repetition() {
+        let mut game = from_fen("8/8/8/4k3/8/3r4/8/4K1Q1 w - - 0 1");
+        for _ in 0..40 {
+            let history = game.earlier_positions();
+            let best = match ai::search(&game.board, game.turn, &history, 2, &AtomicBool::new(false), &mut |_| {}) {
+                Some((Some(best), _)) => best,
+                result => panic!("the search ended with {result:?} at `{}`", game.fen()),
+            };
+            if game.play_move(best.from, best.to).is_some() {
+                break;
+            }
+            let position = game.board.zobrist(game.turn);
+            let seen = game.earlier_positions().iter().filter(|&&earlier| earlier == position).count();
+            let moves: Vec<_> = game.history.iter().map(|entry| entry.san.as_str()).collect();
+            assert!(seen < 2, "the queen against the rook repeated a position three times: {}", moves.join(" "));
+        }
+    }
+
+    /// A player's clock only runs once both players moved and gets the increment after each of their moves
+    #[test]
+    fn clock() {
+        let mut game = start();
+        game.time_left = Some(TimeLeft::new(Duration::from_secs(60), Duration::from_secs(2)));
+        game.play_move(vec2![4, 1], vec2![4, 3]);
+        let running = game.time_left.is_none_or(|time_left| time_left.running.is_some());
+        let tag = game.clock_tag(Color::White);
+        assert!(tag == "  1:02" && !running, "after the first move White's clock shows `{tag}`");
+        game.play_move(vec2![4, 6], vec2![4, 4]);
+        thread::sleep(Duration::from_millis(20));
+        let time_left = game.time_left.unwrap();
+        let (white, black) = (time_left.of(Color::White, game.turn), time_left.of(Color::Black, game.turn));
+        assert!(
+            black == Duration::from_secs(62) && white < Duration::from_secs(62),
+            "after both first moves the clocks are {time_left:?}",
+        );
+    }
+
+    /// A win counts twice as much as a draw in the score of a match
+    #[test]
+    fn match_score() {
+        let won = Score::default().after(&GameEnd::Winner(Color::White), Color::White);
+        let lost = won.after(&GameEnd::Winner(Color::White), Color::Black);
+        let scores = [
+            (won.after(&GameEnd::Draw, Color::Black), "1½–½"),
+            (lost.after(&GameEnd::Winner(Color::Black), Color::Black), "2–1"),
+            (Score::default().after(&GameEnd::Aborted, Color::White), "0–0"),
+        ];
+        for (score, expected) in scores {
+            assert_eq!(score.to_string(), expected, "the score {score:?} reads wrong");
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use color_format::cformat;
+use console::measure_text_width;
+
+use crate::piece::{Color, Piece};
+
+/// FEN letters of the pieces in the order of the `Piece` variants, uppercase for White
+const LETTERS: [char; 6] = ['K', 'Q', 'B', 'N', 'R', 'P'];
+
+/// The built-in sets with the glyphs of White followed by Black, in `LETTERS` order
+const PRESETS: [(&str, [&str; 12]); 4] = [
+    ("unicode", ["♔", "♕", "♗", "♘", "♖", "♙", "♚", "♛", "♝", "♞", "♜", "♟"]),
+    ("ascii", ["K", "Q", "B", "N", "R", "P", "k", "q", "b", "n", "r", "p"]),
+    // the color comes from the text color, so the filled glyphs work for both sides
+    ("filled", ["♚", "♛", "♝", "♞", "♜", "♟", "♚", "♛", "♝", "♞", "♜", "♟"]),
+    ("outlined", ["♔", "♕", "♗", "♘", "♖", "♙", "♔", "♕", "♗", "♘", "♖", "♙"]),
+];
+
+/// The text drawn for each piece. Every glyph is one terminal column wide so the board stays aligned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glyphs {
+    /// White's glyphs followed by Black's, in `LETTERS` order
+    table: [String; 12],
+}
+impl Default for Glyphs {
+    /// Terminals that can't show the chess glyphs get letters
+    fn default() -> Self {
+        let preset = if crate::colors::unicode_supported() { "filled" } else { "ascii" };
+        Self::preset(preset).expect("default preset exists")
+    }
+}
+impl Glyphs {
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS.iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, table)| Self { table: table.map(str::to_owned) })
+    }
+
+    /// Parses a preset name, optionally followed by replacements of single pieces keyed by their FEN letter like
+    /// `outlined N=N n=n`. Without a preset all 12 pieces have to be given.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut words = text.split_whitespace().peekable();
+        let preset = words.peek().and_then(|name| Self::preset(name));
+        if preset.is_some() {
+            words.next();
+        }
+        let mut glyphs = preset.unwrap_or_else(|| Self { table: Default::default() });
+        for word in words {
+            let (letter, glyph) = word.split_once('=')
+                .ok_or_else(|| format!("expected a preset name or `letter=glyph`, found `{word}`"))?;
+            let mut chars = letter.chars();
+            let index = match (chars.next(), chars.next()) {
+                (Some(c), None) => index(c).ok_or_else(|| format!("`{letter}` isn't a piece letter"))?,
+                _ => return Err(format!("`{letter}` isn't a piece letter")),
+            };
+            if glyph.chars().any(char::is_control) || measure_text_width(glyph) != 1 {
+                return Err(format!("the glyph of {letter} has to be one column wide, found `{glyph}`"));
+            }
+            glyphs.table[index] = glyph.to_owned();
+        }
+        if let Some(missing) = glyphs.table.iter().position(String::is_empty) {
+            return Err(format!("no glyph for {}, give all pieces or start with a preset", letter(missing)));
+        }
+        Ok(glyphs)
+    }
+
+    /// The preset name if it's a preset, all glyphs otherwise, in the format `parse` reads
+    pub fn write(&self) -> String {
+        match PRESETS.iter().find(|(_, table)| self.table.iter().eq(table.iter())) {
+            Some((name, _)) => (*name).to_owned(),
+            None => self.table.iter()
+                .enumerate()
+                .map(|(i, glyph)| format!("{}={glyph}", letter(i)))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    /// The preset after this one, custom sets go back to the first preset
+    pub fn next_preset(&self) -> Self {
+        let current = PRESETS.iter().position(|(_, table)| self.table.iter().eq(table.iter()));
+        let (_, table) = PRESETS[current.map_or(0, |i| (i + 1) % PRESETS.len())];
+        Self { table: table.map(str::to_owned) }
+    }
+
+    /// The glyph without a color
+    pub fn glyph(&self, piece: Piece, color: Color) -> &str {
+        let offset = if color == Color::White { 0 } else { 6 };
+        &self.table[offset + piece as usize]
+    }
+
+    pub fn character(&self, piece: Piece, color: Color) -> String {
+        match color {
+            Color::White => cformat!("#rgb(180,180,180)<{}>", self.glyph(piece, color)),
+            Color::Black => cformat!("#rgb(86,83,82)<{}>", self.glyph(piece, color)),
+        }
+    }
+
+    /// A washed out version of `character`
+    pub fn dimmed_character(&self, piece: Piece, color: Color) -> String {
+        match color {
+            Color::White => cformat!("#rgb(150,150,150)<{}>", self.glyph(piece, color)),
+            Color::Black => cformat!("#rgb(128,125,124)<{}>", self.glyph(piece, color)),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Glyphs {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.write())
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Glyphs {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Self::parse(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Position in the table of a piece's FEN letter
+fn index(letter: char) -> Option<usize> {
+    let i = LETTERS.iter().position(|c| *c == letter.to_ascii_uppercase())?;
+    Some(if letter.is_ascii_uppercase() { i } else { i + 6 })
+}
+
+fn letter(index: usize) -> char {
+    if index < 6 { LETTERS[index] } else { LETTERS[index - 6].to_ascii_lowercase() }
+}
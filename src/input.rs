@@ -0,0 +1,611 @@
+//! Key handling of the game screen. Each mode decides what a key does, the game loop only carries out the resulting
+//! [`Action`]. Messages from the server and the CPU's moves can arrive in any mode:
+//! - a new position keeps a selected piece only if it's still there and can move on its turn, and a chosen promotion
+//!   only if the pawn can still go there, see `Game::compute_moves`
+//! - a chat message that is being typed is kept until it's sent or cancelled
+//! - when the game ends or the connection is lost the game screen is left, whatever the mode
+//!
+//! Escape puts a picked up piece back, with nothing picked up it opens the pause menu with the actions that are rarely
+//! needed, like resigning.
+//!
+//! Taps on the board work like the keys: the first tap moves the cursor to the square, tapping the square under the
+//! cursor selects it like space does. Two taps on the same square are easier to get right on a phone than one. While
+//! a promotion is chosen a tap on the pawn's square confirms it.
+
+use console::Key;
+
+use crate::{config::Setting, game::Game, lang::{self, tr}, online::Features, piece::{Color, Piece}, tutorial, Pos};
+
+/// What the keys are currently used for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Moving the cursor around the board
+    Navigating,
+    /// A piece was picked up, the cursor chooses where it goes. Pieces can be picked up while the opponent is still
+    /// thinking, they are moved once it's their turn.
+    PieceSelected(Pos),
+    /// Typing a chat message
+    TextInput(String),
+    /// The settings menu is open, `entries` are the options as they were when the menu was last updated
+    Settings { selected: usize, entries: Vec<Setting> },
+    /// A pawn is put on the last rank, the arrows choose the piece it becomes
+    Promoting { from: Pos, to: Pos, piece: Piece },
+    /// The pause menu is open, escape opens it when no piece is picked up
+    Menu { selected: usize, entries: Vec<MenuEntry> },
+}
+
+/// An entry of the pause menu, each one does what a key or the end of a game would do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuEntry {
+    Resume,
+    Resign,
+    Abort,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    RequestTakeback,
+    AcceptTakeback,
+    DeclineTakeback,
+    Settings,
+    Help,
+    Quit,
+}
+impl MenuEntry {
+    /// The entries that can be used in the game. Online games can only be resigned if both clients can, and only be
+    /// aborted until both players have moved.
+    pub fn available(game: &Game, me: &[Color]) -> Vec<Self> {
+        let mut entries = vec![MenuEntry::Resume];
+        if !me.is_empty() && game.features.is_none_or(|features| features.contains(Features::RESIGN)) {
+            entries.push(MenuEntry::Resign);
+        }
+        let online_abort = game.features.is_some_and(|features| features.contains(Features::ABORT));
+        if !me.is_empty() && game.history.len() < 2 && online_abort {
+            entries.push(MenuEntry::Abort);
+        }
+        let draw_offers = game.features.is_some_and(|features| features.contains(Features::DRAW_OFFER));
+        match game.draw_offer {
+            _ if me.is_empty() || !draw_offers => {}
+            Some(color) if !me.contains(&color) => entries.extend([MenuEntry::AcceptDraw, MenuEntry::DeclineDraw]),
+            // the own offer stands until it's answered or withdrawn by a move
+            Some(_) => {}
+            None => entries.push(MenuEntry::OfferDraw),
+        }
+        let takebacks = game.features.is_some_and(|features| features.contains(Features::TAKEBACK));
+        match game.takeback {
+            _ if me.is_empty() || !takebacks => {}
+            Some(color) if !me.contains(&color) => {
+                entries.extend([MenuEntry::AcceptTakeback, MenuEntry::DeclineTakeback]);
+            }
+            // the own request stands until it's answered or a move withdraws it
+            Some(_) => {}
+            None if me.iter().any(|&color| game.takeback_plies(color).is_some()) => {
+                entries.push(MenuEntry::RequestTakeback);
+            }
+            None => {}
+        }
+        entries.extend([MenuEntry::Settings, MenuEntry::Help, MenuEntry::Quit]);
+        entries
+    }
+
+    /// The key of the entry's text
+    pub fn key(self) -> &'static str {
+        match self {
+            MenuEntry::Resume => "menu.resume",
+            MenuEntry::Resign => "menu.resign",
+            MenuEntry::Abort => "menu.abort",
+            MenuEntry::OfferDraw => "menu.offer_draw",
+            MenuEntry::AcceptDraw => "menu.accept_draw",
+            MenuEntry::DeclineDraw => "menu.decline_draw",
+            MenuEntry::RequestTakeback => "menu.request_takeback",
+            MenuEntry::AcceptTakeback => "menu.accept_takeback",
+            MenuEntry::DeclineTakeback => "menu.decline_takeback",
+            MenuEntry::Settings => "menu.settings",
+            MenuEntry::Help => "menu.help",
+            MenuEntry::Quit => "menu.quit",
+        }
+    }
+}
+
+/// What the game loop has to do after a key was handled
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    None,
+    /// Play a legal move of the side to move
+    Move(Pos, Pos),
+    /// Play a legal move that takes a pawn to the last rank, where it becomes the piece
+    Promote(Pos, Pos, Piece),
+    /// Send a chat message
+    Chat(String),
+    /// Ask the server to abort the game
+    Abort,
+    /// Offer the opponent of an online game a draw
+    OfferDraw,
+    /// Answer the opponent's draw offer
+    AcceptDraw,
+    DeclineDraw,
+    /// Ask the opponent of an online game to take back the last own move
+    RequestTakeback,
+    /// Answer the opponent's takeback request
+    AcceptTakeback,
+    DeclineTakeback,
+    /// Stop or continue the CPU's thinking
+    TogglePause,
+    /// Open the settings menu, the game loop fills in the options
+    OpenSettings,
+    /// Switch an option to its next value and apply it
+    ChangeSetting(&'static str),
+    /// Write the options to the config file
+    SaveSettings,
+    /// End the guided game of the tutorial
+    SkipTutorial,
+    /// Let the CPU to move play one move and pause the game after it
+    Step,
+    /// Play the side to move by hand instead of the paused CPU
+    TakeOver,
+    /// Give up the game, the opponent wins
+    Resign,
+    /// Leave the game without a result
+    Quit,
+}
+
+/// Handles a key on the game screen. `me` are the colors the local player moves, the keys that need a server only
+/// work if the game's features include what they do.
+pub fn handle_key(game: &mut Game, key: Key, me: &[Color]) -> Action {
+    if let Mode::TextInput(text) = &mut game.mode {
+        match key {
+            Key::Enter | Key::Char('\n') => {
+                let text = std::mem::take(text);
+                game.mode = Mode::Navigating;
+                if !text.trim().is_empty() {
+                    return Action::Chat(text);
+                }
+            }
+            Key::Escape => game.mode = Mode::Navigating,
+            Key::Backspace => { text.pop(); }
+            Key::Char(c) if !c.is_control() => text.push(c),
+            _ => {}
+        }
+        return Action::None;
+    }
+    if let Mode::Settings { selected, entries } = &mut game.mode {
+        match key {
+            Key::Char('e') | Key::ArrowUp => *selected = selected.saturating_sub(1),
+            Key::Char('n') | Key::ArrowDown => *selected = (*selected + 1).min(entries.len().saturating_sub(1)),
+            Key::Char(' ') | Key::Char('\n') | Key::Enter => match entries.get(*selected) {
+                Some(setting) if setting.editable => return Action::ChangeSetting(setting.key),
+                _ => {}
+            }
+            Key::Char('s') => return Action::SaveSettings,
+            Key::Escape | Key::Char('o') => game.mode = Mode::Navigating,
+            _ => {}
+        }
+        return Action::None;
+    }
+    if let Mode::Menu { selected, entries } = &mut game.mode {
+        match key {
+            Key::Char('e') | Key::ArrowUp => *selected = selected.saturating_sub(1),
+            Key::Char('n') | Key::ArrowDown => *selected = (*selected + 1).min(entries.len().saturating_sub(1)),
+            Key::Char(' ') | Key::Char('\n') | Key::Enter => {
+                let entry = entries.get(*selected).copied().unwrap_or(MenuEntry::Resume);
+                game.mode = Mode::Navigating;
+                match entry {
+                    MenuEntry::Resume => {}
+                    MenuEntry::Resign => return Action::Resign,
+                    MenuEntry::Abort => return Action::Abort,
+                    MenuEntry::OfferDraw => return Action::OfferDraw,
+                    MenuEntry::AcceptDraw => return Action::AcceptDraw,
+                    MenuEntry::DeclineDraw => return Action::DeclineDraw,
+                    MenuEntry::RequestTakeback => return Action::RequestTakeback,
+                    MenuEntry::AcceptTakeback => return Action::AcceptTakeback,
+                    MenuEntry::DeclineTakeback => return Action::DeclineTakeback,
+                    MenuEntry::Settings => return Action::OpenSettings,
+                    MenuEntry::Help => game.status = Some(tutorial::controls()),
+                    MenuEntry::Quit => return Action::Quit,
+                }
+            }
+            Key::Escape => game.mode = Mode::Navigating,
+            _ => {}
+        }
+        return Action::None;
+    }
+    if let Mode::Promoting { from, to, piece } = &mut game.mode {
+        let index = Piece::PROMOTIONS.iter().position(|promotion| promotion == piece).unwrap_or(0);
+        let count = Piece::PROMOTIONS.len();
+        match key {
+            Key::Char('m' | 'e') | Key::ArrowLeft | Key::ArrowUp => *piece = Piece::PROMOTIONS[(index + count - 1) % count],
+            Key::Char('i' | 'n') | Key::ArrowRight | Key::ArrowDown => *piece = Piece::PROMOTIONS[(index + 1) % count],
+            Key::Char(' ') | Key::Char('\n') | Key::Enter => {
+                let action = Action::Promote(*from, *to, *piece);
+                game.mode = Mode::Navigating;
+                return action;
+            }
+            Key::Escape => game.mode = Mode::Navigating,
+            _ => {}
+        }
+        return Action::None;
+    }
+
+    // nobody moves with this keyboard in a local game between two CPUs, the keys control the game instead
+    let watching = me.is_empty() && game.features.is_none();
+    match key {
+        Key::Char(' ') if watching => return Action::TogglePause,
+        Key::Char('.') if watching => return Action::Step,
+        Key::Char('y') if watching => return Action::TakeOver,
+        Key::Char('m') | Key::ArrowLeft => game.move_cursor(-1, 0),
+        Key::Char('i') | Key::ArrowRight => game.move_cursor(1, 0),
+        Key::Char('e') | Key::ArrowUp => game.move_cursor(0, -1),
+        Key::Char('n') | Key::ArrowDown => game.move_cursor(0, 1),
+        Key::Char(' ') | Key::Char('\n') | Key::Enter => return select(game, me),
+        Key::Escape if matches!(game.mode, Mode::PieceSelected(_)) => game.mode = Mode::Navigating,
+        Key::Escape => game.mode = Mode::Menu { selected: 0, entries: MenuEntry::available(game, me) },
+        // either player can abort an online game until both have moved
+        Key::Char('a') if !me.is_empty() && game.history.len() < 2 => if online_feature(game, Features::ABORT, "feature.abort") {
+            return Action::Abort;
+        }
+        // resigning asks first, the menu opens with it selected and enter confirms
+        Key::Char('r') if !me.is_empty() => if game.features.is_none() || online_feature(game, Features::RESIGN, "feature.resign") {
+            let entries = MenuEntry::available(game, me);
+            let selected = entries.iter().position(|entry| *entry == MenuEntry::Resign).unwrap_or(0);
+            game.mode = Mode::Menu { selected, entries };
+        }
+        // offering and answering a draw go through the menu like resigning
+        Key::Char('d') if !me.is_empty() => if online_feature(game, Features::DRAW_OFFER, "feature.draw_offer") {
+            let entries = MenuEntry::available(game, me);
+            match entries.iter().position(|entry| matches!(entry, MenuEntry::OfferDraw | MenuEntry::AcceptDraw)) {
+                Some(selected) => game.mode = Mode::Menu { selected, entries },
+                None => game.status = Some(tr!("draw.pending")),
+            }
+        }
+        // two players at the same keyboard can take back a misclick, an opponent anywhere else has to agree first
+        Key::Char('u') if me.len() == 2 && game.features.is_none() => game.undo_move(),
+        Key::Char('u') if !me.is_empty() && game.features.is_none() => game.status = Some(tr!("undo.not_alone")),
+        Key::Char('u') if !me.is_empty() => if online_feature(game, Features::TAKEBACK, "feature.takeback") {
+            let entries = MenuEntry::available(game, me);
+            let answer = |entry: &MenuEntry| matches!(entry, MenuEntry::RequestTakeback | MenuEntry::AcceptTakeback);
+            match entries.iter().position(answer) {
+                Some(selected) => game.mode = Mode::Menu { selected, entries },
+                None if game.takeback.is_some() => game.status = Some(tr!("takeback.pending")),
+                None => game.status = Some(tr!("takeback.nothing")),
+            }
+        }
+        Key::Char('t') => if online_feature(game, Features::CHAT, "feature.chat") {
+            game.mode = Mode::TextInput(String::new());
+        }
+        Key::Char('c') => game.toggle_influence(),
+        Key::Char('b') => game.toggle_move_counts(),
+        Key::Char('h') => game.toggle_threats(),
+        Key::Char('p') => return Action::TogglePause,
+        Key::Char('o') => return Action::OpenSettings,
+        Key::Char('x') if game.guide.is_some() => return Action::SkipTutorial,
+        Key::PageUp => game.scroll_history(true),
+        Key::PageDown => game.scroll_history(false),
+        _ => {}
+    }
+    Action::None
+}
+
+/// Handles a tap on a square of the board, only while the cursor is used
+pub fn handle_tap(game: &mut Game, square: Pos, me: &[Color]) -> Action {
+    match game.mode {
+        Mode::Navigating | Mode::PieceSelected(_) if game.cursor == square => select(game, me),
+        Mode::Navigating | Mode::PieceSelected(_) => {
+            game.cursor = square;
+            Action::None
+        }
+        Mode::Promoting { to, .. } if to == square => handle_key(game, Key::Enter, me),
+        Mode::TextInput(_) | Mode::Settings { .. } | Mode::Promoting { .. } | Mode::Menu { .. } => Action::None,
+    }
+}
+
+/// Whether an online game can use the feature, says why not if it's missing from the client of one of the players.
+/// `name` is the key of the feature's name.
+fn online_feature(game: &mut Game, feature: Features, name: &str) -> bool {
+    match game.features {
+        Some(features) if features.contains(feature) => true,
+        Some(_) => {
+            game.status = Some(tr!("feature.unsupported", feature = tr!(name)));
+            false
+        }
+        None => false,
+    }
+}
+
+/// Picks up the piece under the cursor or puts down the selected one
+fn select(game: &mut Game, me: &[Color]) -> Action {
+    let cursor = game.cursor;
+    let mine = matches!(game.board[cursor], Some((_, color)) if me.contains(&color));
+    match game.mode {
+        Mode::PieceSelected(from) if game.possible_moves.get(&from).is_some_and(|moves| moves.contains(&cursor)) => {
+            // the protocol has no promotions, a pawn that reaches the last rank in an online game becomes a queen
+            if game.board.is_promotion(from, cursor) && game.features.is_none() {
+                game.mode = Mode::Promoting { from, to: cursor, piece: Piece::Queen };
+                return Action::None;
+            }
+            game.mode = Mode::Navigating;
+            Action::Move(from, cursor)
+        }
+        // picking up another piece of our own replaces the selection
+        Mode::PieceSelected(from) if mine && from != cursor => {
+            game.mode = Mode::PieceSelected(cursor);
+            Action::None
+        }
+        // says why the piece can't go there, unless it's put back or moved ahead of the turn
+        Mode::PieceSelected(from) => {
+            game.mode = Mode::Navigating;
+            if from != cursor && me.contains(&game.turn) {
+                // the reason can give away a pin or a check, strict games don't help with it
+                game.status = Some(if game.assistance {
+                    tr!("move.illegal_because", reason = lang::legality(game.board.classify_move(from, cursor, game.turn)))
+                } else {
+                    tr!("move.illegal")
+                });
+            }
+            Action::None
+        }
+        _ if mine => {
+            game.mode = Mode::PieceSelected(cursor);
+            Action::None
+        }
+        _ => Action::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use console::Key;
+    use vecm::vec2;
+
+    use super::{handle_key, Action, MenuEntry, Mode};
+    use crate::{
+        board::Board, game::Game, lang::tr, notation, online::Features, piece::{Color, Piece}, tutorial, Pos,
+    };
+
+    /// The actions of the keys pressed one after the other
+    fn press(game: &mut Game, keys: impl IntoIterator<Item = Key>, me: &[Color]) -> Vec<Action> {
+        keys.into_iter().map(|key| handle_key(game, key, me)).collect()
+    }
+
+    fn start(cursor: Pos) -> Game {
+        Game::new(cursor, "White".to_owned(), "Black".to_owned(), Board::starting_position(), Color::White)
+    }
+
+    /// Trying to put a piece where it can't go says why in the status line
+    #[test]
+    fn illegal_move() {
+        let mut game = start(vec2![4, 1]);
+        // e2 to e5
+        let keys = [Key::Enter, Key::ArrowUp, Key::ArrowUp, Key::ArrowUp, Key::Enter];
+        press(&mut game, keys, &[Color::White, Color::Black]);
+        assert_eq!(game.status.as_deref(), Some("Can't move there, the piece can't move that way"));
+    }
+
+    /// A piece picked up while the opponent is thinking stays in hand when their move arrives, but the squares it can
+    /// go to are those of the new position. Moves in between the keys are handled like the game loop does with a move
+    /// from the server: played before the next key. No key may play a move that was only legal before the opponent's
+    /// move.
+    #[test]
+    fn remote_move_with_selection() {
+        let a8 = vec![Key::ArrowUp; 7];
+        let f3 = vec![Key::ArrowRight, Key::ArrowRight, Key::ArrowUp];
+        // the bishop blocks the rook's way to a8, it's still in hand. The bishop pins the knight, it's put back.
+        let cases = [
+            ("7k/1b6/8/8/8/8/8/R3K3 b - - 0 1", vec2![0, 0], vec2![1, 6], vec2![0, 5], a8, true),
+            ("4kb2/8/8/8/8/8/3N4/4K3 b - - 0 1", vec2![3, 1], vec2![5, 7], vec2![1, 3], f3, false),
+        ];
+        for (fen, piece, remote_from, remote_to, keys, kept) in cases {
+            let (board, turn) = Board::from_fen(fen).unwrap();
+            let mut game = Game::new(piece, "White".to_owned(), "Black".to_owned(), board, turn);
+            let me = [Color::White];
+            handle_key(&mut game, Key::Enter, &me);
+            game.play_move(remote_from, remote_to);
+            assert_eq!(
+                matches!(game.mode, Mode::PieceSelected(_)), kept,
+                "after the opponent's move in {fen} the mode is {:?}", game.mode,
+            );
+            for key in keys.into_iter().chain([Key::Enter]) {
+                if let Action::Move(from, to) = handle_key(&mut game, key, &me) {
+                    let (legal, _) = game.board.moves(game.turn);
+                    let legal = legal.get(&from).is_some_and(|moves| moves.contains(&to));
+                    assert!(legal, "{from:?} to {to:?} was played after the opponent's move in {fen}");
+                    game.play_move(from, to);
+                }
+            }
+            assert_eq!(game.history.len(), 1, "a move that was only legal before the opponent's move was played");
+        }
+    }
+
+    /// A pawn put on the last rank asks what it becomes, the arrows go through the pieces and space promotes. Escape
+    /// puts the pawn back. Online games have no choice, the protocol only knows queens.
+    #[test]
+    fn promotion() {
+        let (board, turn) = Board::from_fen("8/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+        let me = [Color::White];
+        let (e7, e8) = (vec2![4, 6], vec2![4, 7]);
+        let mut game = Game::new(e7, "White".to_owned(), "Black".to_owned(), board, turn);
+        press(&mut game, [Key::Enter, Key::ArrowUp, Key::Enter, Key::Escape], &me);
+        assert_eq!(game.mode, Mode::Navigating, "escape didn't put the pawn back");
+        assert_eq!(game.board[e7], Some((Piece::Pawn, Color::White)), "escape didn't put the pawn back");
+        game.cursor = e7;
+        let mut keys = vec![Key::Enter, Key::ArrowUp, Key::Enter];
+        keys.extend([Key::ArrowRight, Key::ArrowRight, Key::ArrowRight, Key::Enter]);
+        let actions = press(&mut game, keys, &me);
+        assert_eq!(actions.last(), Some(&Action::Promote(e7, e8, Piece::Knight)));
+        game.play_move_promote(e7, e8, Piece::Knight);
+        assert_eq!(game.board[e8], Some((Piece::Knight, Color::White)));
+        assert_eq!(game.history.last().map(|entry| entry.san.as_str()), Some("e8=N"));
+
+        let mut game = Game::new(e7, "White".to_owned(), "Black".to_owned(), board, turn);
+        game.features = Some(Features::SUPPORTED);
+        let actions = press(&mut game, [Key::Enter, Key::ArrowUp, Key::Enter], &me);
+        assert_eq!(actions.last(), Some(&Action::Move(e7, e8)), "a promotion in an online game had a choice");
+    }
+
+    /// The guided game narrates the keys of the first move and can be left at any step
+    #[test]
+    fn tutorial() {
+        let mut game = start(vec2![0, 0]);
+        game.guide = Some(tutorial::Step::default());
+        let steps = [
+            (vec![], "Move the cursor"),
+            (vec![Key::ArrowRight; 4].into_iter().chain([Key::ArrowUp]).collect(), "Press space"),
+            (vec![Key::Char(' ')], "Squares marked #"),
+            (vec![Key::ArrowUp, Key::ArrowUp, Key::Char(' ')], "The computer answered"),
+        ];
+        for (keys, expected) in steps {
+            for key in keys {
+                if let Action::Move(from, to) = handle_key(&mut game, key, &[Color::White]) {
+                    game.play_move(from, to);
+                }
+            }
+            tutorial::update(&mut game);
+            let status = game.status.as_deref().unwrap_or_default();
+            assert!(status.starts_with(expected), "the tutorial showed `{status}` instead of `{expected}...`");
+        }
+        assert_eq!(handle_key(&mut game, Key::Char('x'), &[Color::White]), Action::SkipTutorial);
+    }
+
+    /// Escape puts a picked up piece back and only opens the pause menu when nothing is picked up. The menu offers
+    /// resigning unless a client in the online game can't, and aborting in online games that haven't started. Its
+    /// entries give their actions, r opens it on resigning.
+    #[test]
+    fn menu() {
+        let me = [Color::White];
+        let mut game = start(vec2![4, 1]);
+        press(&mut game, [Key::Enter, Key::Escape], &me);
+        assert_eq!(game.mode, Mode::Navigating, "escape with a piece picked up didn't put it back");
+        handle_key(&mut game, Key::Escape, &me);
+        let expected = [MenuEntry::Resume, MenuEntry::Resign, MenuEntry::Settings, MenuEntry::Help, MenuEntry::Quit];
+        let opened = matches!(&game.mode, Mode::Menu { entries, .. } if entries[..] == expected);
+        assert!(opened, "escape opened {:?} in a game against the CPU", game.mode);
+        assert!(game.to_string().contains("> resume"), "the menu isn't drawn");
+        assert_eq!(handle_key(&mut game, Key::Char('x'), &me), Action::None);
+        assert!(matches!(game.mode, Mode::Menu { .. }), "the menu doesn't keep other keys from the game");
+        assert_eq!(press(&mut game, [Key::ArrowDown, Key::Enter], &me), [Action::None, Action::Resign]);
+        assert_eq!(game.mode, Mode::Navigating);
+
+        let mut game = start(vec2![4, 1]);
+        game.features = Some(Features::SUPPORTED);
+        handle_key(&mut game, Key::Escape, &me);
+        let expected = [
+            MenuEntry::Resume, MenuEntry::Resign, MenuEntry::Abort, MenuEntry::OfferDraw, MenuEntry::Settings,
+            MenuEntry::Help, MenuEntry::Quit,
+        ];
+        let opened = matches!(&game.mode, Mode::Menu { entries, .. } if entries[..] == expected);
+        assert!(opened, "escape opened {:?} in an online game", game.mode);
+        let actions = press(&mut game, [Key::ArrowDown, Key::ArrowDown, Key::Enter], &me);
+        assert_eq!(actions, [Action::None, Action::None, Action::Abort]);
+        assert_eq!(press(&mut game, [Key::Char('r'), Key::Enter], &me), [Action::None, Action::Resign]);
+
+        game.features = Some(Features::ABORT);
+        handle_key(&mut game, Key::Escape, &me);
+        let resign = matches!(&game.mode, Mode::Menu { entries, .. } if entries.contains(&MenuEntry::Resign));
+        assert!(!resign, "the menu offers resigning to a client that can't");
+        game.mode = Mode::Navigating;
+        handle_key(&mut game, Key::Char('r'), &me);
+        assert_eq!(game.mode, Mode::Navigating);
+        assert_eq!(game.status.as_deref(), Some("resigning isn't supported by everyone in this game"));
+    }
+
+    /// d opens the menu on offering a draw, or on accepting the opponent's offer, also when it isn't the player's
+    /// turn. A move withdraws the own offer but not the opponent's.
+    #[test]
+    fn draw_offer() {
+        let me = [Color::Black];
+        let mut game = start(vec2![4, 6]);
+        game.features = Some(Features::SUPPORTED);
+        assert_eq!(press(&mut game, [Key::Char('d'), Key::Enter], &me), [Action::None, Action::OfferDraw]);
+        game.draw_offer = Some(Color::Black);
+        handle_key(&mut game, Key::Char('d'), &me);
+        let told = game.mode == Mode::Navigating && game.status.is_some();
+        assert!(told, "d with the own offer standing opened a menu");
+        game.play_move(vec2![4, 1], vec2![4, 3]);
+        assert_eq!(game.draw_offer, Some(Color::Black), "the opponent's move withdrew the draw offer");
+        game.play_move(vec2![4, 6], vec2![4, 4]);
+        assert_eq!(game.draw_offer, None, "the offering player's move didn't withdraw the draw offer");
+
+        game.draw_offer = Some(Color::White);
+        handle_key(&mut game, Key::Char('d'), &me);
+        match &game.mode {
+            Mode::Menu { selected, entries }
+                if entries.get(*selected) == Some(&MenuEntry::AcceptDraw) && entries.contains(&MenuEntry::DeclineDraw)
+                    && !entries.contains(&MenuEntry::OfferDraw) => {}
+            mode => panic!("d with the opponent's offer standing opened {mode:?}"),
+        }
+        assert_eq!(press(&mut game, [Key::ArrowDown, Key::Enter], &me), [Action::None, Action::DeclineDraw]);
+
+        game.features = Some(Features::ABORT);
+        handle_key(&mut game, Key::Char('d'), &me);
+        assert_eq!(game.mode, Mode::Navigating);
+        assert_eq!(game.status.as_deref(), Some("offering draws isn't supported by everyone in this game"));
+    }
+
+    /// u asks to take back the own last move once there is one, the accepted takeback goes back to where the game was
+    /// before that move
+    #[test]
+    fn takeback() {
+        let me = [Color::White];
+        let mut game = start(vec2![4, 1]);
+        game.features = Some(Features::SUPPORTED);
+        handle_key(&mut game, Key::Char('u'), &me);
+        assert_eq!(game.mode, Mode::Navigating);
+        assert_eq!(game.status, Some(tr!("takeback.nothing")));
+        for (from, to) in [("e2", "e4"), ("e7", "e5")] {
+            game.play_move(notation::parse_square(from).unwrap(), notation::parse_square(to).unwrap());
+        }
+        assert_eq!(press(&mut game, [Key::Char('u'), Key::Enter], &me), [Action::None, Action::RequestTakeback]);
+        assert_eq!(game.takeback_plies(Color::White), Some(2));
+        game.takeback = Some(Color::Black);
+        game.take_back_to(1).unwrap();
+        assert!(
+            game.turn == Color::Black && game.history.len() == 1 && game.takeback.is_none(),
+            "taking back 1... e5 left {} plies with {:?} to move", game.history.len(), game.turn,
+        );
+        let offered = MenuEntry::available(&game, &me).contains(&MenuEntry::RequestTakeback);
+        assert!(offered, "the menu doesn't offer taking back 1. e4");
+    }
+
+    /// The move list goes right of the sidebar on terminals wide enough for it and shows the latest moves. PageUp and
+    /// PageDown scroll it without touching the game.
+    #[test]
+    fn history_panel() {
+        let mut game = start(vec2![4, 1]);
+        // the knights go out and back five times, that's ten lines of moves
+        for (from, to) in [("g1", "f3"), ("g8", "f6"), ("f3", "g1"), ("f6", "g8")].repeat(5) {
+            game.play_move(notation::parse_square(from).unwrap(), notation::parse_square(to).unwrap());
+        }
+        let fen = game.fen();
+        let latest = format!("{game:100}");
+        let scrolled = tr!("history.scrolled", first = 3, last = 10, count = 10);
+        assert!(latest.contains(&scrolled) && latest.contains("10. Ng1"), "the latest moves are drawn as\n{latest}");
+        assert!(!game.to_string().contains("Ng1") && !format!("{game:60}").contains("Ng1"), "the list had no room");
+        handle_key(&mut game, Key::PageUp, &[Color::White]);
+        let first = format!("{game:100}");
+        let scrolled = tr!("history.scrolled", first = 1, last = 8, count = 10);
+        assert!(first.contains(&scrolled) && first.contains("  1. Nf3      Nf6"), "PageUp scrolled to\n{first}");
+        assert!(game.fen() == fen && game.history.len() == 20, "scrolling the move list changed the game");
+        handle_key(&mut game, Key::PageDown, &[Color::White]);
+        assert!(format!("{game:100}") == latest, "PageDown didn't scroll back to the latest moves");
+    }
+
+    /// u takes back the last move when both players sit at the keyboard, with the piece it took and its legal moves.
+    /// Against anyone else it does nothing.
+    #[test]
+    fn hotseat_undo() {
+        let mut game = start(vec2![4, 1]);
+        let square = |name| notation::parse_square(name).unwrap();
+        game.play_move(square("e2"), square("e4"));
+        game.play_move(square("d7"), square("d5"));
+        let before = game.fen();
+        game.play_move(square("e4"), square("d5"));
+        let fen = game.fen();
+        handle_key(&mut game, Key::Char('u'), &[Color::White]);
+        assert_eq!(game.fen(), fen, "u against another player took back exd5");
+        handle_key(&mut game, Key::Char('u'), &[Color::White, Color::Black]);
+        let (e4, d5) = (square("e4"), square("d5"));
+        let legal = game.possible_moves.get(&e4).is_some_and(|moves| moves.contains(&d5));
+        assert!(
+            game.fen() == before && legal && game.white.taken_pieces.is_empty() && game.cursor == e4,
+            "taking back exd5 left `{}` with the cursor on {}", game.fen(), game.cursor,
+        );
+    }
+}
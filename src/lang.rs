@@ -0,0 +1,307 @@
+//! The texts of the interface in the player's language. Every text has an identifier like `end.draw` and is looked up
+//! in a catalog of `key = text` lines, empty lines and lines starting with `#` are skipped:
+//! ```text
+//! end.won_white = {name} won as White!
+//! ```
+//! Parts in braces are filled in by [`tr!`], so a translation can put them in any order. English and German are
+//! embedded, a `lang/<code>.txt` in the config folder adds a language or replaces texts of an embedded one. Texts
+//! missing from a catalog are taken from English. SAN and the other move notations are the same in every language.
+//!
+//! The language is `--lang`, else the `lang` option, else the first of `LC_ALL`, `LC_MESSAGES` and `LANG` that is set.
+
+use std::{collections::HashMap, env, fmt::{self, Display}, fs, sync::RwLock};
+
+use crate::{board::MoveLegality, config, piece::{Color, Piece}};
+
+/// The catalogs that come with termchess, English first since every other one falls back to it
+const EMBEDDED: [(&str, &str); 2] = [("en", include_str!("lang/en.txt")), ("de", include_str!("lang/de.txt"))];
+
+/// The catalog of the current language, English until `init` picked one
+#[cfg(not(test))]
+static CURRENT: RwLock<Option<Catalog>> = RwLock::new(None);
+// every test runs on a thread of its own, one that switches the language doesn't change the texts of the others
+#[cfg(test)]
+thread_local!(static CURRENT: RwLock<Option<Catalog>> = const { RwLock::new(None) });
+
+/// The texts of one language with the English ones for the keys it doesn't have
+pub struct Catalog {
+    texts: HashMap<String, String>,
+}
+impl Catalog {
+    /// The embedded texts of the language with the user's file on top, English where both have nothing. Invalid lines
+    /// of the user's file are reported and skipped.
+    pub fn load(code: &str) -> Self {
+        let mut catalog = Self::embedded(code);
+        let path = config::config_dir().join("lang").join(format!("{code}.txt"));
+        if let Ok(content) = fs::read_to_string(&path) {
+            if let Err(errors) = catalog.extend(&content) {
+                for (line, err) in errors {
+                    eprintln!("{}:{line}: {err}", path.display());
+                }
+            }
+        }
+        catalog
+    }
+
+    /// Only the embedded texts, an unknown language gets the English ones
+    pub fn embedded(code: &str) -> Self {
+        let mut catalog = Self { texts: HashMap::new() };
+        for (_, content) in EMBEDDED.iter().filter(|(embedded, _)| *embedded == "en" || *embedded == code) {
+            catalog.extend(content).expect("embedded catalogs are valid");
+        }
+        catalog
+    }
+
+    /// Adds the texts of a catalog file, replacing the ones with the same keys. Returns the invalid lines with their
+    /// numbers, the valid ones are added anyway.
+    pub fn extend(&mut self, content: &str) -> Result<(), Vec<(usize, String)>> {
+        let mut errors = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, text)) if !key.trim().is_empty() => {
+                    self.texts.insert(key.trim().to_owned(), text.trim().to_owned());
+                }
+                _ => errors.push((i + 1, "expected `key = text`".to_owned())),
+            }
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// The text of a key, the key itself if no catalog has it so a missing text can still be found
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.texts.get(key).map_or(key, String::as_str)
+    }
+
+    /// The keys of the catalog in no particular order
+    #[cfg(test)]
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.texts.keys().map(String::as_str)
+    }
+}
+
+/// Whether a catalog for the language comes with termchess
+pub fn is_embedded(code: &str) -> bool {
+    EMBEDDED.iter().any(|(embedded, _)| *embedded == code)
+}
+
+/// The language code to use, `setting` is `--lang` or the `lang` option and empty if neither was given. Locale names
+/// like `de_AT.UTF-8` are cut down to the language, the `C` and `POSIX` locales are English.
+pub fn detect(setting: &str) -> String {
+    let locale = if setting.is_empty() {
+        ["LC_ALL", "LC_MESSAGES", "LANG"].into_iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+    } else {
+        setting.to_owned()
+    };
+    let code = locale.split(['_', '.', '@', '-']).next().unwrap_or_default().to_lowercase();
+    match code.as_str() {
+        "" | "c" | "posix" => "en".to_owned(),
+        _ => code,
+    }
+}
+
+/// Picks the language for the rest of the program. A language that was asked for by name but has no catalog is an
+/// error, the texts stay English then. One that only came from the environment falls back to English quietly.
+pub fn init(setting: &str) -> Result<(), String> {
+    let code = detect(setting);
+    let known = is_embedded(&code) || config::config_dir().join("lang").join(format!("{code}.txt")).exists();
+    set(Catalog::load(&code));
+    if !known && !setting.is_empty() {
+        return Err(format!("no texts for the language `{code}`, add them as lang/{code}.txt in the config folder"));
+    }
+    Ok(())
+}
+
+/// Replaces the catalog all texts are taken from
+pub fn set(catalog: Catalog) {
+    current(|current| *current.write().unwrap() = Some(catalog));
+}
+
+fn with<T>(f: impl FnOnce(&Catalog) -> T) -> T {
+    current(|current| {
+        if let Some(catalog) = &*current.read().unwrap() {
+            return f(catalog);
+        }
+        f(current.write().unwrap().get_or_insert_with(|| Catalog::embedded("en")))
+    })
+}
+
+#[cfg(not(test))]
+fn current<T>(f: impl FnOnce(&RwLock<Option<Catalog>>) -> T) -> T {
+    f(&CURRENT)
+}
+
+#[cfg(test)]
+fn current<T>(f: impl FnOnce(&RwLock<Option<Catalog>>) -> T) -> T {
+    CURRENT.with(f)
+}
+
+/// The text of a key in the current language
+pub fn text(key: &str) -> String {
+    with(|catalog| catalog.get(key).to_owned())
+}
+
+/// The text of a key with its placeholders filled in, see [`fill`]
+pub fn format(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    with(|catalog| fill(catalog.get(key), args))
+}
+
+/// Replaces every `{name}` in the text by the argument of that name. Braces around anything else are kept, and
+/// inserted arguments aren't looked at again, so a player called `{name}` stays that.
+pub fn fill(text: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let arg = rest[1..].find('}').and_then(|end| {
+            let name = &rest[1..end + 1];
+            args.iter().find(|(arg, _)| *arg == name).map(|(_, value)| (end + 2, value))
+        });
+        match arg {
+            Some((len, value)) => {
+                fmt::write(&mut filled, format_args!("{value}")).expect("writing to a string can't fail");
+                rest = &rest[len..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// The text of a key, the named arguments fill in its placeholders:
+/// `tr!("end.won_white", name = game.white.name)`
+macro_rules! tr {
+    ($key:expr) => {
+        $crate::lang::text($key)
+    };
+    ($key:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::lang::format($key, &[$((stringify!($name), &$value as &dyn ::std::fmt::Display)),+])
+    };
+}
+pub(crate) use tr;
+
+/// The name of the side, like `White`
+pub fn color(color: Color) -> String {
+    text(match color {
+        Color::White => "color.white",
+        Color::Black => "color.black",
+    })
+}
+
+/// The name of the piece for sentences, like `knight`
+pub fn piece(piece: Piece) -> String {
+    text(match piece {
+        Piece::King => "piece.king",
+        Piece::Queen => "piece.queen",
+        Piece::Bishop => "piece.bishop",
+        Piece::Knight => "piece.knight",
+        Piece::Rook => "piece.rook",
+        Piece::Pawn => "piece.pawn",
+    })
+}
+
+/// Why a move can't be played, as the end of the sentence in the status line
+pub fn legality(legality: MoveLegality) -> String {
+    use MoveLegality::*;
+
+    text(match legality {
+        Legal => "legality.legal",
+        OffTheBoard => "legality.off_the_board",
+        NoPieceThere => "legality.no_piece",
+        NotYourPiece => "legality.not_your_piece",
+        OwnPieceThere => "legality.own_piece",
+        PieceCantMoveThatWay => "legality.cant_move_that_way",
+        NothingToCapture => "legality.nothing_to_capture",
+        PathBlocked => "legality.path_blocked",
+        WouldLeaveKingInCheck => "legality.leaves_king_in_check",
+        DoesntEscapeCheck => "legality.doesnt_escape_check",
+        CastlingRightLost => "legality.castling_right_lost",
+        KingCantCastleOutOfCheck => "legality.castle_out_of_check",
+        KingWouldCastleThroughCheck => "legality.castle_through_check",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use vecm::vec2;
+
+    use super::{detect, legality, set, Catalog, EMBEDDED};
+    use crate::{board::{Board, MoveLegality}, config::Config, game::Game, piece::Color};
+
+    /// Every embedded catalog has only keys English has too, with the same placeholders
+    #[test]
+    fn embedded_catalogs() {
+        let placeholders = |text: &str| -> Vec<String> {
+            let mut names: Vec<_> =
+                text.split('{').skip(1).filter_map(|part| Some(part.split_once('}')?.0.to_owned())).collect();
+            names.sort();
+            names
+        };
+        let english = Catalog::embedded("en");
+        for (code, content) in EMBEDDED {
+            let mut catalog = Catalog::embedded("xx");
+            catalog.extend(content).unwrap_or_else(|errors| panic!("{code}.txt has invalid lines {errors:?}"));
+            for key in catalog.keys() {
+                assert_ne!(english.get(key), key, "{code}.txt has `{key}`, which English doesn't");
+                assert_eq!(
+                    placeholders(catalog.get(key)), placeholders(english.get(key)),
+                    "`{key}` of {code}.txt doesn't have the placeholders of English",
+                );
+            }
+        }
+    }
+
+    /// Locale names are cut down to their language, the `C` locale is English
+    #[test]
+    fn locales() {
+        for (setting, code) in [("de_AT.UTF-8", "de"), ("C", "en"), ("pt-BR", "pt")] {
+            assert_eq!(detect(setting), code, "the locale {setting} gave another language");
+        }
+    }
+
+    /// Switching the language changes the texts and the settings menu, a text missing from a language comes from
+    /// English, and a placeholder in an inserted name stays as it is
+    #[test]
+    fn switching() {
+        let english = Catalog::embedded("en");
+        let mut partial = Catalog::embedded("xx");
+        partial.extend("# only one text\nend.draw = Remis!").unwrap();
+        assert_eq!(partial.get("end.draw"), "Remis!");
+        assert_eq!(partial.get("end.aborted"), english.get("end.aborted"), "a partial catalog didn't fall back");
+
+        set(Catalog::embedded("de"));
+        let won = tr!("end.won_white", name = "{name}");
+        let label = Game::new(vec2![0, 0], String::new(), String::new(), Board::starting_position(), Color::White)
+            .settings(&Config::default())[0].label.clone();
+        set(Catalog::embedded("en"));
+        assert_eq!(won, "{name} gewinnt mit Weiß!", "German filled in the winner wrong");
+        assert_ne!(label, english.get("setting.autosave_games"), "the settings menu stayed English in German");
+    }
+
+    /// Every reason a move can be illegal reads differently
+    #[test]
+    fn legality_reasons() {
+        use MoveLegality::*;
+
+        let reasons = [
+            Legal, OffTheBoard, NoPieceThere, NotYourPiece, OwnPieceThere, PieceCantMoveThatWay, NothingToCapture,
+            PathBlocked, WouldLeaveKingInCheck, DoesntEscapeCheck, CastlingRightLost, KingCantCastleOutOfCheck,
+            KingWouldCastleThroughCheck,
+        ];
+        let texts: HashSet<String> = reasons.into_iter().map(legality).collect();
+        assert_eq!(texts.len(), reasons.len(), "two reasons have the same text");
+    }
+}
@@ -0,0 +1,120 @@
+//! A minimal writer for the `log` facade. Every line starts with a UTC timestamp and the level:
+//! ```text
+//! 2024-03-09T17:02:11.482Z INFO  game 3 ply 5: applied g1f3, position 5c0e4fb2a1d7c39e
+//! ```
+//! Messages about an online game are tagged with `game <id>` and, where it is known, `ply <n>`. The id is assigned by
+//! the server and sent to both clients, so the same move can be found in all three logs by searching for its tag:
+//! ```text
+//! $ grep "game 3 ply 5:" server.log client-white.log client-black.log
+//! server.log:2024-03-09T17:02:11.480Z DEBUG game 3 ply 5: received Move(Move { x1: 6, y1: 0, x2: 5, y2: 2 }) from White
+//! server.log:2024-03-09T17:02:11.481Z INFO  game 3 ply 5: applied g1f3, position 5c0e4fb2a1d7c39e
+//! client-white.log:2024-03-09T17:02:11.475Z INFO  game 3 ply 5: applied g1f3, position 5c0e4fb2a1d7c39e
+//! client-black.log:2024-03-09T17:02:11.509Z INFO  game 3 ply 5: applied g1f3, position 5c0e4fb2a1d7c39e
+//! ```
+//! Differing position hashes for the same tag show where the boards went apart. The `correlation` test below finds
+//! a move like this in the logs of a server and two clients.
+
+use std::{fs, io::{self, Write}, path::Path, sync::Mutex, time::{SystemTime, UNIX_EPOCH}};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::pgn::civil_from_days;
+
+struct Logger {
+    level: LevelFilter,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut out = self.out.lock().unwrap();
+        // there is nowhere to report a failed log write to
+        let _ = writeln!(out, "{} {:<5} {}", timestamp(), record.level(), record.args());
+        let _ = out.flush();
+    }
+
+    fn flush(&self) {
+        let _ = self.out.lock().unwrap().flush();
+    }
+}
+
+/// Installs the logger. Without a file the log goes to stdout, which must not be used while the board is drawn.
+pub fn init(level: LevelFilter, file: Option<&Path>) -> io::Result<()> {
+    let out: Box<dyn Write + Send> = match file {
+        Some(path) => {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            Box::new(fs::OpenOptions::new().append(true).create(true).open(path)?)
+        }
+        None => Box::new(io::stdout()),
+    };
+    log::set_boxed_logger(Box::new(Logger { level, out: Mutex::new(out) }))
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs / 3600, secs / 60 % 60, secs % 60, now.subsec_millis(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{self, Write}, sync::{Arc, Mutex}};
+
+    use log::{Level, LevelFilter, Log, Record};
+
+    use super::Logger;
+
+    /// A log kept in memory that can still be read after the logger took it
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The line of one move is found in the logs of the server and both clients by its tag, and only that line
+    #[test]
+    fn correlation() {
+        let logs = [Shared::default(), Shared::default(), Shared::default()];
+        for log in &logs {
+            let logger = Logger { level: LevelFilter::Info, out: Mutex::new(Box::new(log.clone())) };
+            let log = |level, args| logger.log(&Record::builder().level(level).args(args).build());
+            log(Level::Info, format_args!("game 3 ply 4: applied e7e5, position 9a1b03c4d2e5f607"));
+            log(Level::Debug, format_args!("game 3 ply 5: received Move(Move {{ x1: 6, y1: 0, x2: 5, y2: 2 }})"));
+            log(Level::Info, format_args!("game 3 ply 5: applied g1f3, position 5c0e4fb2a1d7c39e"));
+            log(Level::Info, format_args!("game 3 ply 50: applied h2h3, position 0123456789abcdef"));
+            log(Level::Info, format_args!("game 13 ply 5: applied d2d4, position fedcba9876543210"));
+        }
+
+        for log in logs {
+            let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+            let tagged: Vec<&str> = log.lines().filter(|line| line.contains("game 3 ply 5:")).collect();
+            let [line] = tagged[..] else { panic!("the tag was found on {} lines of\n{log}", tagged.len()) };
+            let (timestamp, message) = line.split_once(' ').unwrap();
+            // like 2024-03-09T17:02:11.482Z
+            assert!(timestamp.len() == 24 && timestamp.ends_with('Z'), "the line `{line}` has no timestamp");
+            assert_eq!(message, "INFO  game 3 ply 5: applied g1f3, position 5c0e4fb2a1d7c39e");
+        }
+    }
+}
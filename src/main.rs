@@ -1,30 +1,74 @@
-#![feature(hash_drain_filter, iter_intersperse)]
-
-use std::{io::Write, error::Error, sync::{mpsc::{Receiver, self, TryRecvError}, Arc}, thread::{self, JoinHandle}, time::Duration};
-use board::Board;
+use std::{fs, io::Write, error::Error, net::Shutdown, sync::mpsc::TryRecvError, time::{Duration, Instant}, path::PathBuf};
+use board::{Board, Castle};
 use color_format::cprintln;
-use console::{Term, Key};
+use backend::{Backend, Input};
+use engine::{Engine, Response};
+use console::Key;
+use log::{debug, info, warn, LevelFilter};
 use piece::{Color, Piece};
-use online::{Move, Remote};
-use vecm::{vec::PolyVec2, vec2};
+use online::{Move, MoveDetails, Remote, ClientMessage, ServerMessage, EndReason, SyncCheck, ServerOptions, Features, ColorPreference, GameInfo};
+use vecm::vec2;
+use termchess_core::{ai, board, fnv1a, notation, piece, Pos};
+use termchess_net as online;
 
-use crate::game::{Game, GameEnd};
+use crate::{game::{Clocks, Game, GameEnd, TimeLeft}, config::Config, difficulty::Difficulty, input::{Action, Mode}, lang::tr, stats::Summary};
 
-mod ai;
-mod board;
+mod backend;
+mod bench;
+mod browser;
+mod colors;
+mod config;
+mod db;
+mod diagram;
+mod difficulty;
+mod engine;
 mod game;
-mod moves;
-mod piece;
-mod online;
+mod glyphs;
+mod input;
+mod lang;
+mod logger;
+mod maintenance;
+mod opening;
+mod perft;
+mod pgn;
+mod positions;
+mod practice;
+#[cfg(feature = "serde")]
+mod save;
+mod score;
+mod smoke;
+mod stats;
+mod threats;
+mod tips;
+mod tutorial;
+mod variations;
 
-type Pos = PolyVec2<i8>;
+/// Where the board is drawn, taps are mapped to squares with it
+const BOARD_X: u16 = 1;
+const BOARD_Y: u16 = 2;
+/// Terminals narrower than this get the compact layout, which puts the sidebar below the board
+const COMPACT_WIDTH: u16 = 60;
 
 enum PlayerType {
     Me,
     Remote(Remote),
     Cpu {
         depth: usize,
-        computation: Option<JoinHandle<ai::Move>>,
+        /// Search deeper until this time is up, `depth` is the limit then
+        time: Option<Duration>,
+        /// Started with the game, it keeps running between the moves
+        engine: Engine,
+        /// Thinking was stopped by the user, it starts over from the current position when resumed
+        paused: bool,
+    }
+}
+
+impl PlayerType {
+    /// Stops the search of a CPU player, it starts a new one when it's asked for a move again
+    fn stop(&self) {
+        if let PlayerType::Cpu { engine, .. } = self {
+            engine.stop();
+        }
     }
 }
 
@@ -33,281 +77,1400 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut server = false;
     let mut fen = None;
     let mut ip = None;
+    let mut prefer = ColorPreference::Random;
     let mut ai = None;
+    let mut ai_time = None;
+    let mut games = false;
+    let mut perft = false;
+    let mut perft_debug = false;
+    let mut bench = false;
+    let mut smoke = false;
+    let mut db = None;
+    let mut depth = None;
+    let mut engine = None;
+    let mut spectate = None;
+    let mut random_middlegame = false;
+    let mut self_play = false;
+    let mut debug_ai = false;
+    let mut book = None;
+    let mut difficulty = None;
+    let mut jitter = None;
+    let mut seed = None;
+    let mut export_diagram = None;
+    let mut strict = false;
+    let mut positions_file = None;
+    let mut server_options = ServerOptions::default();
+    let mut accessible = false;
+    let mut compact = false;
+    let mut no_title = false;
+    let mut tutorial = false;
+    let mut maintain = true;
+    let mut lang = None;
+    let mut backend_name = backend::DEFAULT.to_owned();
+    let mut log_level = None;
+    let mut log_file = None;
+    // `key=value` pairs that override the config file
+    let mut options = Vec::new();
+    // castling rights that replace the ones of the starting position or FEN
+    let mut castle_rights = Vec::new();
     while let Some(arg) = args.next() {
         match arg.as_str() {
             "-s" | "--server" => server = true,
+            "-g" | "--games" => games = true,
+            "perft" => perft = true,
+            "perft-debug" => perft_debug = true,
+            "bench" => bench = true,
+            "smoke" => smoke = true,
+            "db" => db = Some(match args.next().as_deref() {
+                Some("import") => db::Command::Import(PathBuf::from(args.next().expect("give the PGN file to import"))),
+                Some("find") => db::Command::Find,
+                _ => return Err("expected `db import <file>` or `db find --fen <fen>`".into()),
+            }),
+            "--depth" => depth = Some(
+                args.next()
+                    .expect("give the perft or bench depth as argument")
+                    .parse::<usize>()
+                    .expect("depth has to be a positive integer")
+                ),
+            "--engine" => engine = Some(PathBuf::from(args.next().expect("give the path of a UCI engine"))),
+            "--accessible" => accessible = true,
+            "--compact" => compact = true,
+            "--no-title" => no_title = true,
+            "--tutorial" => tutorial = true,
+            "--no-maintenance" => maintain = false,
+            "--lang" => lang = Some(args.next().expect("give a language code like de")),
+            "--backend" => backend_name = args.next()
+                .unwrap_or_else(|| panic!("give a backend: {}", backend::NAMES.join(", "))),
+            "--log-level" => log_level = Some(
+                args.next()
+                    .expect("give a log level (off, error, warn, info, debug or trace) as argument")
+                    .parse::<LevelFilter>()
+                    .expect("invalid log level")
+                ),
+            "--no-white-castle" => castle_rights.push((Color::White, Castle { short: false, long: false })),
+            "--no-black-castle" => castle_rights.push((Color::Black, Castle { short: false, long: false })),
+            "--white-castle-short-only" => castle_rights.push((Color::White, Castle { short: true, long: false })),
+            "--white-castle-long-only" => castle_rights.push((Color::White, Castle { short: false, long: true })),
+            "--black-castle-short-only" => castle_rights.push((Color::Black, Castle { short: true, long: false })),
+            "--black-castle-long-only" => castle_rights.push((Color::Black, Castle { short: false, long: true })),
+            "-o" | "--option" => options.push(args.next().expect("give an option as key=value")),
+            "--log-file" => log_file = Some(PathBuf::from(args.next().expect("give a path for the log file"))),
+            "--random-middlegame" => random_middlegame = true,
+            "--self-play" => self_play = true,
+            "--debug-ai" => debug_ai = true,
+            "--book" => book = Some(PathBuf::from(args.next().expect("give the path of an opening book"))),
+            "--difficulty" => difficulty = Some(
+                args.next()
+                    .as_deref()
+                    .and_then(Difficulty::parse)
+                    .expect("give the CPU's difficulty: easy, medium or hard")
+                ),
+            "--jitter" => jitter = Some(
+                args.next()
+                    .expect("give how far below the best score the CPU's moves may be, a pawn is 1000")
+                    .parse::<ai::Score>()
+                    .ok()
+                    .filter(|jitter| *jitter >= 0)
+                    .expect("jitter has to be a positive integer")
+                ),
+            "--seed" => seed = Some(
+                args.next()
+                    .expect("give the seed of the CPU's random choices")
+                    .parse::<u64>()
+                    .expect("seed has to be a positive integer")
+                ),
+            "--strict" => strict = true,
+            "--export-diagram" => export_diagram = Some(PathBuf::from(args.next().expect("give a path for the diagram"))),
+            "--positions-file" => positions_file = Some(PathBuf::from(args.next().expect("give a path for the positions file"))),
             "-f" | "--fen" => fen = Some(args.next().expect("fen expected after -f/--fen")),
             "-c" | "--connect" => ip = Some(args.next().expect("connect requires ip")),
+            "--color" => prefer = args.next()
+                .as_deref()
+                .and_then(ColorPreference::parse)
+                .expect("give the color you want to play online as white, black or random"),
+            "--spectate" => spectate = Some(args.next().expect("spectate requires ip")),
+            "--spectator-chat-to-players" => server_options.spectator_chat_to_players = true,
+            "--bind" => server_options.bind = args.next()
+                .expect("give the address to listen on, like 127.0.0.1")
+                .parse()
+                .expect("invalid address to listen on"),
+            "--port" => server_options.port = args.next()
+                .expect("give the port to listen on")
+                .parse()
+                .expect("port has to be a number up to 65535"),
+            "--time-control" => server_options.time_control = Some(
+                args.next()
+                    .as_deref()
+                    .and_then(online::TimeControl::parse)
+                    .expect("give the clock of the server's games as minutes+increment like 5+3")
+                ),
+            "--save-games" => server_options.games_dir = Some(
+                PathBuf::from(args.next().expect("give the directory the server saves its games to"))
+            ),
+            "--motd" => server_options.motd = Some(args.next().expect("give the message of the day")),
             "-a" | "--ai" => ai = Some(
                 args.next()
                     .expect("give ai depth as argument")
                     .parse::<usize>()
                     .expect("depth has to be a positive integer")
                 ),
+            "--ai-time" => ai_time = Some(
+                args.next()
+                    .as_deref()
+                    .and_then(parse_duration)
+                    .expect("give the CPU's time per move like 3s or 500ms")
+                ),
             _ => eprintln!("unrecognized arg {arg}")
         }
     }
-    let (board, color) = if let Some(fen) = fen {
-        Board::from_fen(&fen).expect("invalid FEN provided as argument")
+    let mut config = Config::load();
+    for option in &options {
+        config.parse_line(option).map_err(|err| format!("invalid option `{option}`: {err}"))?;
+    }
+    config.accessible |= accessible;
+    config.compact |= compact;
+    config.title &= !no_title;
+    if let Some(lang) = lang {
+        config.lang = lang;
+    }
+    if let Err(err) = lang::init(&config.lang) {
+        eprintln!("{err}");
+    }
+    server_options.strict = strict;
+    // a difficulty alone plays at its depth, a time alone searches as deep as it gets
+    if let (Some(difficulty), None) = (difficulty, ai) {
+        ai = Some(difficulty.depth());
+    }
+    if ai_time.is_some() && ai.is_none() {
+        ai = Some(ai::MAX_DEPTH);
+    }
+    if self_play && ai.is_none() {
+        return Err("--self-play needs the depth of the CPU, give it with -a".into());
+    }
+    let book = match book {
+        Some(path) => {
+            let text = fs::read_to_string(&path).map_err(|err| format!("can't read the book {}: {err}", path.display()))?;
+            Some(ai::OpeningBook::parse(&text).map_err(|err| format!("invalid book {}, {err}", path.display()))?)
+        }
+        None => None,
+    };
+    let mut params = difficulty.map_or_else(ai::EvalParams::default, Difficulty::params);
+    if let Some(jitter) = jitter {
+        params.jitter = jitter;
+    }
+    if bench {
+        bench::run(depth.unwrap_or(bench::DEPTH));
+        return Ok(());
+    }
+    if smoke {
+        return smoke::run();
+    }
+    if maintain && config.maintenance {
+        // a failed cleanup is tried again on the next start, it's no reason not to play. It runs before the logger
+        // opens the log file it might rotate.
+        if let Err(err) = maintenance::run(&config) {
+            eprintln!("{}", tr!("maintenance.failed", error = err));
+        }
+    }
+    if games {
+        return browser::run(&*backend::open(&backend_name, config.colors)?, &config);
+    }
+    if let Some(command) = db {
+        return db::run(command, fen.as_deref(), &backend_name, &config);
+    }
+    if server {
+        // the server logs to stdout unless told otherwise
+        logger::init(log_level.unwrap_or(LevelFilter::Info), log_file.as_deref())?;
+    } else if log_level.is_some() || log_file.is_some() {
+        // stdout is used to draw the board, so the client only logs to a file
+        let file = log_file.unwrap_or_else(|| config::data_dir().join("termchess.log"));
+        logger::init(log_level.unwrap_or(LevelFilter::Info), Some(&file))?;
+    }
+    let mut play_black = false;
+    let mut clocks = Clocks::default();
+    let (mut board, color) = if let Some(fen) = fen {
+        let (board, turn, halfmove, fullmove) = Board::from_fen_with_counters(&fen).expect("invalid FEN provided as argument");
+        clocks = Clocks { halfmove, fullmove };
+        (board, turn)
+    } else if random_middlegame || positions_file.is_some() {
+        let (board, turn, black) = positions::pick(&positions::load(positions_file.as_deref())?);
+        play_black = black;
+        (board, turn)
     } else {
         (Board::starting_position(), Color::White)
-    };  
+    };
+    for (color, castle) in castle_rights {
+        board.set_castle(color, castle)?;
+    }  
+    if perft {
+        perft::run(&board, color, depth.unwrap_or(4));
+        return Ok(());
+    }
+    if perft_debug {
+        let engine = engine.expect("perft-debug needs a UCI engine to compare with, give it with --engine");
+        return perft::debug(board, color, depth.unwrap_or(3), &engine);
+    }
     if server {
         loop {
-            match online::run_server(board, color) {
-                Ok(()) => println!("Server ended"),
+            match online::run_server(board, color, &server_options) {
+                Ok(()) => info!("server ended"),
                 Err(err) => {
-                    println!("Server failed: {err}");
+                    warn!("server failed: {err}");
                     std::thread::sleep(Duration::from_millis(500));
                 }
             }
         }
     } else {
-        print!("Enter Name: ");
+        // the first run has no config file yet, the onboarding creates it
+        if tutorial || (!Config::path().exists() && console::user_attended()) {
+            if tutorial::run(&mut config, &backend_name)? {
+                return Ok(());
+            }
+        } else if config.tips {
+            cprintln!("#y<{}> {}\n", tr!("tip"), tips::random());
+        }
+        if config.name.is_empty() {
+            print!("{} ", tr!("prompt.name"));
+        } else {
+            print!("{} ", tr!("prompt.name_default", name = config.name));
+        }
         std::io::stdout().flush()?;
         let mut name = String::new();
         std::io::stdin().read_line(&mut name)?;
         name = name.trim().to_owned();
+        if name.is_empty() {
+            name = config.name.clone();
+        }
 
-        let (the_game, white, black) = if let Some(ip) = ip {
-            println!("Connecting to ip: {ip}");
-            let (remote, game_info) = online::connect(&ip, name.clone())?;
+        if let Some(ip) = spectate {
+            println!("{}", tr!("online.connecting", address = ip));
+            let (remote, info) = online::spectate(&ip, name)?;
+            let (board, turn) = Board::from_fen(&info.fen).ok_or("the server sent an invalid FEN")?;
+            let mut game = Game::new(vec2![-1, -1], info.white, info.black, board, turn);
+            game.features = Some(info.features);
+            game.glyphs = config.glyphs.clone();
+            game.compact = config.compact;
+            let moves: Vec<(Pos, Pos)> = info.moves.iter().map(|m| (vec2![m.x1, m.y1], vec2![m.x2, m.y2])).collect();
+            game.replace_moves(&moves)?;
+            let (term, mut render) = setup_terminal(&config, &backend_name)?;
+            render(&game, &*term)?;
+            let result = watch(&mut render, &*term, game, remote, &config);
+            term.restore_title()?;
+            return result;
+        }
 
-            
-            let mut white_name = name;
-            let mut black_name = game_info.other_player;
-            if game_info.is_black {
-                std::mem::swap(&mut white_name, &mut black_name);
-            }
+        let (mut the_game, white, black) = setup_game(&name, ip.as_deref(), ai, ai_time, book.as_ref(), params, seed, board, color, play_black, prefer, self_play, &config)?;
+        // online the server gives the position, a FEN from the command line only starts local games
+        if ip.is_none() {
+            the_game.start_clocks = clocks;
+        }
+        the_game.diagram = export_diagram.clone();
+        the_game.assistance &= !strict;
+        the_game.show_pv = debug_ai;
 
-            let mut game = Game::new(vec2![0, 0], white_name, black_name, board, color);
-            game.flip_board = game_info.is_black;
+        let (term, mut render) = setup_terminal(&config, &backend_name)?;
 
-            let me = if let Some(depth) = ai {
-                PlayerType::Cpu { depth, computation: None }
-            } else {
-                PlayerType::Me
-            };
+        render(&the_game, &*term)?;
 
-            if game_info.is_black {
-                (game, PlayerType::Remote(remote), me)
-            } else {
-                (game, me, PlayerType::Remote(remote))
+        let mut end = game(&mut render, &*term, the_game, white, black, &mut config)?;
+
+        // aborted online games don't count, so offer to look for a new one
+        while let (Some(GameEnd::Aborted), Some(ip)) = (&end, &ip) {
+            println!("\n{}", tr!("online.new_opponent"));
+            if term.wait_key()? != Key::Char('n') { break }
+            if !config.accessible {
+                term.clear()?;
             }
-        
-        } else if let Some(depth) = ai { 
-            let game = Game::new(vec2![0, 0], name.clone(), format!("Computer ({depth})"), board, color);
-            (game, PlayerType::Me, PlayerType::Cpu { depth, computation: None })
-        } else {
-            let game = Game::new(vec2![0, 0], name.clone(), name, board, color);
-            (game, PlayerType::Me, PlayerType::Me)
-        };
+            let (mut the_game, white, black) = setup_game(&name, Some(ip), ai, ai_time, book.as_ref(), params, seed, board, color, play_black, prefer, self_play, &config)?;
+            the_game.diagram = export_diagram.clone();
+            the_game.assistance &= !strict;
+            the_game.show_pv = debug_ai;
+            if !config.accessible {
+                term.clear()?;
+            }
+            render(&the_game, &*term)?;
+            end = game(&mut render, &*term, the_game, white, black, &mut config)?;
+        }
+        term.show_cursor()?;
+        term.restore_title()?;
+        Ok(())
+    }
+}
 
+type Renderer = Box<dyn FnMut(&Game, &dyn Backend) -> Result<(), Box<dyn Error>>>;
+
+fn setup_terminal(config: &Config, backend_name: &str) -> Result<(Box<dyn Backend>, Renderer), Box<dyn Error>> {
+    cprintln!("  ~~~  #b<CHESS>   ~~~\n");
  
-        cprintln!("  ~~~  #b<CHESS>   ~~~\n");
- 
-        let term = Arc::new(Term::stdout());
+    let term = backend::open(backend_name, config.colors)?;
+    // screen readers lose track of the text when the cursor is hidden or the screen is redrawn
+    let render: Renderer = if config.accessible {
+        Box::new(accessible_render())
+    } else {
         term.hide_cursor()?;
-        term.clear_screen()?;
-
-        let (keys_tx, keys) = mpsc::channel();
-        {
-            let term = term.clone();
-            thread::spawn(move || {
-                loop {
-                    if keys_tx.send(term.read_key().unwrap()).is_err() {
-                        break;
-                    }
-                }
-            });
+        term.clear()?;
+        Box::new(render)
+    };
+    Ok((term, render))
+}
+
+/// `play_black` and `self_play`, where the computer plays both sides, only apply to games against the computer. Online
+/// the server decides the colors and gives the player the one in `prefer` if the opponent doesn't want it too.
+#[allow(clippy::too_many_arguments)]
+fn setup_game(
+    name: &str, ip: Option<&str>, ai: Option<usize>, ai_time: Option<Duration>, book: Option<&ai::OpeningBook>,
+    params: ai::EvalParams, seed: Option<u64>, board: Board, color: Color, play_black: bool, prefer: ColorPreference,
+    self_play: bool, config: &Config,
+) -> Result<(Game, PlayerType, PlayerType), Box<dyn Error>> {
+    let name = name.to_owned();
+    let (mut the_game, white, black) = if let Some(ip) = ip {
+        println!("{}", tr!("online.connecting", address = ip));
+        let (remote, game_info, resumed) = online::connect(ip, name.clone(), prefer, |other| ask_rejoin(&name, other))?;
+        let game = online_game(name, &game_info, resumed, config)?;
+
+        let me = if let Some(depth) = ai {
+            PlayerType::Cpu { depth, time: ai_time, engine: Engine::cpu(book.cloned(), params, seed), paused: false }
+        } else {
+            PlayerType::Me
+        };
+
+        if game_info.is_black {
+            (game, PlayerType::Remote(remote), me)
+        } else {
+            (game, me, PlayerType::Remote(remote))
         }
+    
+    } else if let Some(depth) = ai { 
+        let cpu = PlayerType::Cpu { depth, time: ai_time, engine: Engine::cpu(book.cloned(), params, seed), paused: false };
+        let computer = match ai_time {
+            Some(time) => tr!("player.computer_timed", time = format!("{time:?}")),
+            None => tr!("player.computer", depth = depth),
+        };
+        if self_play {
+            let mut game = Game::new(vec2![0, 0], computer.clone(), computer, board, color);
+            game.status = Some(tr!("watching"));
+            let other = PlayerType::Cpu { depth, time: ai_time, engine: Engine::cpu(book.cloned(), params, seed), paused: false };
+            (game, cpu, other)
+        } else if play_black {
+            // h8 is the bottom left corner from Black's side
+            let mut game = Game::new(vec2![7, 7], computer, name.clone(), board, color);
+            game.flip_board = true;
+            (game, cpu, PlayerType::Me)
+        } else {
+            let game = Game::new(vec2![0, 0], name.clone(), computer, board, color);
+            (game, PlayerType::Me, cpu)
+        }
+    } else {
+        let game = Game::new(vec2![0, 0], name.clone(), name, board, color);
+        (game, PlayerType::Me, PlayerType::Me)
+    };
+    the_game.show_move_counts = config.move_counts;
+    the_game.glyphs = config.glyphs.clone();
+    the_game.compact = config.compact;
+    Ok((the_game, white, black))
+}
 
-        let render = move |game: &Game, term: &Term| -> Result<(), Box<dyn Error>> {
-            use std::fmt::Write;
+/// The game the server started, from the player's side. `resumed` are the moves played before the player rejoined.
+fn online_game(
+    name: String, game_info: &GameInfo, resumed: Option<Vec<Move>>, config: &Config,
+) -> Result<Game, Box<dyn Error>> {
+    let mut white_name = name;
+    let mut black_name = game_info.other_player.clone();
+    if game_info.is_black {
+        std::mem::swap(&mut white_name, &mut black_name);
+    }
 
-            let y_offset = 2;
-            
-            for i in 0..y_offset {
-                term.move_cursor_to(0, i)?;
-                term.clear_line()?;
+    // the server's position, not the one given on the command line
+    let (board, turn) = Board::from_fen(&game_info.fen).ok_or("the server sent an invalid FEN")?;
+    let mut game = Game::new(vec2![0, 0], white_name, black_name, board, turn);
+    if let Some(moves) = resumed {
+        let moves: Vec<(Pos, Pos)> = moves.iter().map(|m| (vec2![m.x1, m.y1], vec2![m.x2, m.y2])).collect();
+        game.replace_moves(&moves)?;
+    }
+    game.flip_board = game_info.is_black;
+    // the bottom left corner from the player's side
+    game.cursor = game.square_on_screen(0, 7);
+    game.features = Some(game_info.features);
+    if game_info.features.contains(Features::RECONNECT) {
+        game.reconnect_code = Some(game_info.code);
+    }
+    if game_info.initial_time > 0 {
+        let initial = Duration::from_millis(game_info.initial_time.into());
+        game.time_left = Some(TimeLeft::new(initial, Duration::from_millis(game_info.increment.into())));
+    }
+    let motd = online::clean_motd(&game_info.motd);
+    if config.motd && !motd.is_empty() {
+        // shown until the first move, unless a missing feature is more important
+        game.status = Some(tr!("online.motd", motd = motd));
+    }
+    if !game_info.assistance_allowed {
+        game.assistance = false;
+        game.status = Some(tr!("online.fair_play"));
+    }
+    let unavailable = Features::SUPPORTED.missing_from(game_info.features);
+    if !unavailable.is_empty() {
+        let by = if Features::SUPPORTED.missing_from(game_info.other_features).is_empty() {
+            tr!("online.the_server")
+        } else {
+            tr!("online.client_of", name = game_info.other_player)
+        };
+        game.status = Some(tr!("online.unsupported", by = by, features = unavailable.join(", ")));
+    }
+    Ok(game)
+}
+
+/// A duration like `3s`, `1.5s`, `500ms` or `2m`, plain numbers are seconds
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let seconds = match unit {
+        "ms" => 0.001,
+        "s" => 1.0,
+        "m" => 60.0,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    Duration::try_from_secs_f64(number * seconds).ok()
+}
+
+/// Asks whether to take back the seat in a game that lost its connection to a player with this name, and for the
+/// game's code if so
+fn ask_rejoin(name: &str, other_player: &str) -> Option<u16> {
+    let ask = |question: &str| {
+        print!("{question} ");
+        std::io::stdout().flush().ok()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).ok()?;
+        Some(answer.trim().to_owned())
+    };
+    if ask(&tr!("online.rejoin", opponent = other_player, name = name))? != "y" {
+        return None;
+    }
+    ask(&tr!("online.rejoin_code"))?.parse().ok()
+}
+
+/// Takes the seat back after the connection to the server was lost, tried every few seconds for
+/// `online::AUTO_REJOIN_TIME`. `None` if the server didn't give it back in that time.
+fn auto_rejoin(
+    seat: &online::Seat,
+    game: &mut Game,
+    mut render: impl FnMut(&Game, &dyn Backend) -> Result<(), Box<dyn Error>>,
+    term: &dyn Backend,
+) -> Result<Option<(Remote, online::Resumed)>, Box<dyn Error>> {
+    let until = Instant::now() + online::AUTO_REJOIN_TIME;
+    game.status = Some(tr!("online.rejoining"));
+    render(game, term)?;
+    loop {
+        match online::rejoin(seat) {
+            Ok(rejoined) => return Ok(Some(rejoined)),
+            // the server might not have noticed yet that the old connection is gone
+            Err(err) => info!("taking the seat back failed: {err}"),
+        }
+        if Instant::now() >= until {
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_secs(2));
+    }
+}
+
+/// Whether the times on the clocks changed since they were drawn, `drawn` is updated to the current ones
+fn clocks_changed(game: &Game, drawn: &mut String) -> bool {
+    if game.time_left.is_none() {
+        return false;
+    }
+    let clocks = game.clock_tag(Color::White) + &game.clock_tag(Color::Black);
+    if clocks == *drawn {
+        return false;
+    }
+    *drawn = clocks;
+    true
+}
+
+// the remote player if this is an online game
+fn remote<'a>(white: &'a mut PlayerType, black: &'a mut PlayerType) -> Option<&'a mut Remote> {
+    match (white, black) {
+        (PlayerType::Remote(remote), _) | (_, PlayerType::Remote(remote)) => Some(remote),
+        _ => None,
+    }
+}
+
+/// How the end screen of an online game was left
+enum Leave {
+    /// With a key other than x, `None` if the terminal has no keys left
+    Key(Option<Key>),
+    /// Both players asked for a rematch and the server started it
+    Rematch(GameInfo),
+}
+
+/// Waits for a key on the end screen of an online game and tells the player when the opponent wants a rematch. x asks
+/// for one, the server starts it once both players asked.
+fn wait_rematch(term: &dyn Backend, remote: &mut Remote, ply: usize, opponent: &str) -> Result<Leave, Box<dyn Error>> {
+    let mut asked = false;
+    // until the server said no or closed the connection
+    let mut possible = true;
+    loop {
+        match remote.server.try_recv() {
+            Ok(ServerMessage::RematchOffered) => println!("{}", tr!("rematch.offered", name = opponent)),
+            Ok(ServerMessage::Rematch(game_info)) => return Ok(Leave::Rematch(game_info)),
+            Ok(ServerMessage::RematchDeclined) | Err(TryRecvError::Disconnected) if possible => {
+                possible = false;
+                if asked {
+                    println!("{}", tr!("rematch.declined"));
+                }
+            }
+            // whatever else comes after the end doesn't matter anymore
+            _ => {}
+        }
+        match term.read_input(Some(Duration::from_millis(10))) {
+            Ok(Some(Input::Key(Key::Char('x')))) if possible && !asked => {
+                if remote.send(ply, ClientMessage::Rematch).is_ok() {
+                    asked = true;
+                    println!("{}", tr!("rematch.waiting", name = opponent));
+                } else {
+                    possible = false;
+                    println!("{}", tr!("rematch.declined"));
+                }
             }
+            Ok(Some(Input::Key(Key::Char('x')))) if !possible => println!("{}", tr!("rematch.declined")),
+            Ok(Some(Input::Key(Key::Char('x')))) => {}
+            Ok(Some(Input::Key(key))) => return Ok(Leave::Key(Some(key))),
+            Ok(Some(_) | None) => {}
+            // a terminal without any keys left quits too
+            Err(_) => return Ok(Leave::Key(None)),
+        }
+    }
+}
+
+fn render(game: &Game, term: &dyn Backend) -> Result<(), Box<dyn Error>> {
+    for y in 0..BOARD_Y {
+        term.draw(0, y, "")?;
+    }
 
+    // the layout is picked for every frame, so it changes along with the size of the terminal
+    let width = term.size().1 as usize;
+    let s = if game.compact || width < COMPACT_WIDTH as usize { format!("{game:#}") } else { format!("{game:width$}") };
+    let mut max_line = BOARD_Y - 1;
 
-            let mut s = String::new();
-            write!(&mut s, "{game}")?;
+    // rows that didn't change since the last frame are skipped by the backend
+    for (i, line) in s.lines().enumerate() {
+        let y = i as u16 + BOARD_Y;
+        term.draw(BOARD_X, y, line)?;
+        max_line = y;
+    }
 
-            let mut max_line = 0;
+    for y in (max_line + 1)..term.size().0 {
+        term.draw(0, y, "")?;
+    }
 
-            for (i, line) in s.lines().enumerate() {
-                let y = i + 2;
-                term.move_cursor_to(1, y)?;
-                print!("{}", line);
-                max_line = y;
+    term.flush()?;
+    Ok(())
+}
+
+/// Renders for screen readers: nothing is drawn in place, new lines are only printed for what changed. Played moves
+/// are announced in SAN and the square under the cursor is read out when it moves.
+fn accessible_render() -> impl FnMut(&Game, &dyn Backend) -> Result<(), Box<dyn Error>> {
+    // number of plies that were already announced, None until the position was described once
+    let mut announced: Option<usize> = None;
+    let mut cursor = None;
+    let mut selected = None;
+    let mut threats = None;
+    let mut setting = None;
+    let mut menu_entry = None;
+    let mut chat_seen = 0;
+    move |game, _term| {
+        let plies = game.history.len();
+        if announced != Some(plies) {
+            match announced {
+                Some(n) if n < plies => {
+                    for entry in &game.history[n..] {
+                        let (_, color) = entry.board[entry.from].expect("history entry without a moved piece");
+                        println!("{}", tr!("accessible.played", color = lang::color(color), san = entry.san));
+                    }
+                    println!("{}", game.describe_turn());
+                }
+                // a new game or moves that were taken back, describe everything again
+                _ => game.describe().iter().for_each(|line| println!("{line}")),
+            }
+            if let Some(status) = &game.status {
+                println!("{status}");
+            }
+            announced = Some(plies);
+        }
+        if game.selected() != selected {
+            if let Some(from) = game.selected() {
+                println!("{}", tr!("accessible.selected", square = game.describe_square(from)));
+            }
+            selected = game.selected();
+        }
+        if game.threats != threats {
+            match &game.threats {
+                Some(list) if list.is_empty() => println!("{}", tr!("threats.none")),
+                Some(list) => list.iter().for_each(|threat| println!("{threat}")),
+                None => {}
+            }
+            threats = game.threats.clone();
+        }
+        // the settings menu reads out the option under its cursor
+        let current = match &game.mode {
+            Mode::Settings { selected, entries } => entries.get(*selected).cloned(),
+            _ => None,
+        };
+        if current != setting {
+            if let Some(current) = &current {
+                let key = if current.editable { "accessible.setting" } else { "accessible.setting_file_only" };
+                println!("{}", tr!(key, label = current.label, value = current.value));
             }
+            setting = current;
+        }
+        // and so does the pause menu
+        let entry = match &game.mode {
+            Mode::Menu { selected, entries } => entries.get(*selected).copied(),
+            _ => None,
+        };
+        if entry != menu_entry {
+            if let Some(entry) = entry {
+                println!("{}", tr!("accessible.menu", entry = tr!(entry.key())));
+            }
+            menu_entry = entry;
+        }
+        for line in &game.chat[chat_seen.min(game.chat.len())..] {
+            println!("{line}");
+        }
+        chat_seen = game.chat.len();
+        let on_board = (0..8).contains(&game.cursor.x) && (0..8).contains(&game.cursor.y);
+        if cursor != Some(game.cursor) && on_board {
+            println!("{}", game.describe_square(game.cursor));
+            cursor = Some(game.cursor);
+        }
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+}
 
-            for y in (max_line + 1)..(term.size().1 as usize) {
-                term.move_cursor_to(0, y)?;
-                term.clear_line()?;
+/// Shows a game of two other players while it's played. Spectators can't move, only chat.
+fn watch(
+    mut render: impl FnMut(&Game, &dyn Backend) -> Result<(), Box<dyn Error>>,
+    term: &dyn Backend,
+    mut game: Game,
+    mut remote: Remote,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let mut title = String::new();
+    let mut clocks = String::new();
+    loop {
+        if config.title && game.title(&[], None) != title {
+            title = game.title(&[], None);
+            term.set_title(&title)?;
+        }
+        if clocks_changed(&game, &mut clocks) {
+            render(&game, term)?;
+        }
+        let message = remote.server.try_recv();
+        if let (Ok(ServerMessage::GameOver(_)), Some(time_left)) = (&message, &mut game.time_left) {
+            time_left.stop(game.turn);
+        }
+        match message {
+            // the details are for viewers without a move generator, the board checks the move itself
+            Ok(ServerMessage::Move(m) | ServerMessage::MoveDetails(MoveDetails { played: m, .. })) => {
+                let (from, to) = (vec2![m.x1, m.y1], vec2![m.x2, m.y2]);
+                if !game.possible_moves.get(&from).is_some_and(|moves| moves.contains(&to)) {
+                    return Err(format!("the server sent an illegal move: {from} -> {to}").into());
+                }
+                let end = game.play_move(from, to);
+                info!(
+                    "game {} ply {}: applied {}{}, position {:016x}",
+                    remote.game_id, game.history.len(), notation::square(from), notation::square(to),
+                    online::position_hash(&game.board, game.turn),
+                );
+                if let Some(end) = end {
+                    game.status = Some(tr!("end.status", result = end.result()));
+                    if let Some(time_left) = &mut game.time_left {
+                        time_left.stop(game.turn);
+                    }
+                }
+                render(&game, term)?;
+            }
+            Ok(ServerMessage::Chat(chat)) => {
+                game.chat.push(chat.to_string());
+                render(&game, term)?;
+            }
+            Ok(ServerMessage::GameOver(EndReason::Aborted)) => {
+                game.status = Some(tr!("end.aborted_status"));
+                render(&game, term)?;
+            }
+            Ok(ServerMessage::GameOver(EndReason::Abandoned(black_left))) => {
+                let (left, winner) = if black_left { (&game.black, &game.white) } else { (&game.white, &game.black) };
+                game.status = Some(tr!("online.left_wins", left = left.name, winner = winner.name));
+                render(&game, term)?;
+            }
+            Ok(ServerMessage::GameOver(EndReason::Resigned(black))) => {
+                let color = if black { Color::Black } else { Color::White };
+                game.status = Some(tr!("end.resigned", color = lang::color(color)));
+                render(&game, term)?;
+            }
+            Ok(ServerMessage::GameOver(EndReason::DrawAgreed)) => {
+                game.status = Some(tr!("draw.agreed"));
+                render(&game, term)?;
+            }
+            Ok(ServerMessage::GameOver(EndReason::Flagged(black))) => {
+                let color = if black { Color::Black } else { Color::White };
+                game.status = Some(tr!("end.flagged", color = lang::color(color)));
+                render(&game, term)?;
+            }
+            // spectators learn that the game has a clock from the first times, they don't know the increment
+            Ok(ServerMessage::Clock(times)) => {
+                let time_left = game.time_left.get_or_insert(TimeLeft::new(Duration::ZERO, Duration::ZERO));
+                time_left.set(times, game.history.len());
+                render(&game, term)?;
+            }
+            Ok(ServerMessage::TookBack(plies)) => {
+                game.take_back_to(plies as usize).map_err(|err| format!("the server took back moves: {err}"))?;
+                game.status = Some(tr!("takeback.watched"));
+                render(&game, term)?;
             }
+            // only sent to players
+            Ok(
+                ServerMessage::Resync(_) | ServerMessage::OpponentLeft(_) | ServerMessage::OpponentBack
+                | ServerMessage::DrawOffered | ServerMessage::DrawDeclined | ServerMessage::RematchOffered
+                | ServerMessage::RematchDeclined | ServerMessage::Rematch(_) | ServerMessage::TakebackRequested
+                | ServerMessage::TakebackDeclined
+            ) => {}
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                game.status = Some(tr!("online.closed"));
+                render(&game, term)?;
+                term.wait_key()?;
+                return Ok(());
+            }
+        }
 
-            
-            std::io::stdout().flush()?;
-            Ok(())
-        };
+        let Some(key) = term.read_key(Some(Duration::from_millis(10)))? else { continue };
+        if game.mode == Mode::Navigating && matches!(key, Key::Escape | Key::Char('q')) {
+            return Ok(());
+        }
+        // spectators don't move any pieces, only the chat is sent anywhere
+        if let Action::Chat(text) = input::handle_key(&mut game, key, &[]) {
+            remote.send(game.history.len(), ClientMessage::Chat(text))?;
+        }
+        render(&game, term)?;
+    }
+}
 
-        render(&the_game, &term)?;
+/// Plays a move of the side to move and sends it to a remote opponent. Moving a piece of the other side or a move the
+/// piece can't make is an error.
+fn play(
+    game: &mut Game,
+    from: Pos,
+    to: Pos,
+    promotion: Piece,
+    white: &mut PlayerType,
+    black: &mut PlayerType,
+) -> Result<Option<GameEnd>, Box<dyn Error>> {
+    // checked here instead of relying on the selection, moves also come from the network and the AI
+    match game.board[from] {
+        Some((_, color)) if color == game.turn => {}
+        Some((_, color)) => return Err(format!("{color:?} tried to move {from} -> {to} on {:?}'s turn", game.turn).into()),
+        None => return Err(format!("{:?} tried to move from the empty square {from}", game.turn).into()),
+    }
+    if !game.possible_moves.get(&from).is_some_and(|moves| moves.contains(&to)) {
+        return Err(format!("{:?} tried the illegal move {from} -> {to}", game.turn).into());
+    }
+    let ply = game.history.len();
+    let other_player = if game.turn == Color::White { &mut *black } else { &mut *white };
+    if let PlayerType::Remote(remote) = other_player {
+        remote.send(ply, ClientMessage::Move(Move { x1: from.x, y1: from.y, x2: to.x, y2: to.y }))?;
 
-        game(render, &term, keys, the_game, white, black)
     }
+    let end = game.play_move_promote(from, to, promotion);
+    // lets the server detect if our board drifted apart from its own
+    if let Some(remote) = remote(white, black) {
+        info!(
+            "game {} ply {}: applied {}{}, position {:016x}",
+            remote.game_id, ply + 1, notation::square(from), notation::square(to),
+            online::position_hash(&game.board, game.turn),
+        );
+        if remote.features.contains(Features::SYNC_CHECK) {
+            let check = SyncCheck { ply: game.history.len() as u32, fen: game.board.to_fen(game.turn, 0, 1) };
+            remote.send(ply + 1, ClientMessage::SyncCheck(check))?;
+        }
+        if let Some(end) = &end {
+            info!("game {} ply {}: game over, result {}", remote.game_id, ply + 1, end.result());
+        }
+    }
+    Ok(end)
 }
 
 fn game(
-    mut render: impl FnMut(&Game, &Term) -> Result<(), Box<dyn Error>>,
-    term: &Term,
-    keys: Receiver<Key>,
+    mut render: impl FnMut(&Game, &dyn Backend) -> Result<(), Box<dyn Error>>,
+    term: &dyn Backend,
     mut game: Game,
     mut white: PlayerType,
     mut black: PlayerType,
-) -> Result<(), Box<dyn Error>> {
-    fn render_end(mut render: impl FnMut(&Game, &Term) -> Result<(), Box<dyn Error>>, game: Game, term: &Term, end: GameEnd)
-    -> Result<(), Box<dyn Error>> {
-        render(&game, term)?;
+    config: &mut Config,
+) -> Result<Option<GameEnd>, Box<dyn Error>> {
+    /// Shows how the game ended and waits for a key. Online both players can ask for a rematch there, it's played
+    /// right away and the end of the last game of the series is returned.
+    fn render_end(mut render: impl FnMut(&Game, &dyn Backend) -> Result<(), Box<dyn Error>>, game: &Game, players: [PlayerType; 2], term: &dyn Backend, end: GameEnd, me: &[Color], config: &mut Config)
+    -> Result<Option<GameEnd>, Box<dyn Error>> {
+        // a CPU that was thinking when the game ended would keep a core busy while the end screen waits
+        for player in &players {
+            player.stop();
+        }
+        render(game, term)?;
+        if config.title {
+            term.set_title(&game.title(me, Some(&end)))?;
+        }
         match end {
-            GameEnd::Winner(Color::Black) => cprintln!("\n\n{} #g<won> as Black!", game.black.name),
-            GameEnd::Winner(Color::White) => cprintln!("\n\n{} #g<won> as White!", game.white.name),
-            GameEnd::Draw => cprintln!("Game ended in a #rgb(127,127,127)<draw>!")
+            GameEnd::Winner(Color::Black) => cprintln!("\n\n#g<{}>", tr!("end.won_black", name = game.black.name)),
+            GameEnd::Winner(Color::White) => cprintln!("\n\n#g<{}>", tr!("end.won_white", name = game.white.name)),
+            GameEnd::Draw if game.is_stalemate() => cprintln!("#rgb(127,127,127)<{}>", tr!("end.stalemate")),
+            GameEnd::Draw => cprintln!("#rgb(127,127,127)<{}>", tr!("end.draw")),
+            GameEnd::Aborted => {
+                cprintln!("\n\n#y<{}>", tr!("end.aborted"));
+                return Ok(Some(end));
+            }
+        }
+        println!();
+        for line in Summary::new(game).lines() {
+            println!("{line}");
+        }
+        if config.autosave_games {
+            match pgn::autosave(game, &end, &config.games_dir) {
+                Ok(path) => println!("{}", tr!("end.saved", path = path.display())),
+                Err(err) => println!("{}", tr!("end.save_failed", error = err)),
+            }
+        }
+        if let Some(path) = &game.diagram {
+            match diagram::write(game, Some(&end), path) {
+                Ok(()) => println!("{}", tr!("diagram.saved", path = path.display())),
+                Err(err) => println!("{}", tr!("diagram.save_failed", error = err)),
+            }
+        }
+        if config.tips {
+            cprintln!("\n#y<{}> {}", tr!("tip"), tips::random());
         }
 
-        Ok(())
+        let [mut white, mut black] = players;
+        // the player's side online, the other one is the server's
+        let color = if matches!(white, PlayerType::Remote(_)) { Color::Black } else { Color::White };
+        let score = game.score.after(&end, color);
+        let leave = match remote(&mut white, &mut black).filter(|remote| remote.features.contains(Features::REMATCH)) {
+            Some(remote) => {
+                println!("\n{}", tr!("end.score", score = score));
+                println!("{}", tr!("end.rematch_prompt"));
+                let opponent = if color == Color::White { &game.black.name } else { &game.white.name };
+                wait_rematch(term, remote, game.history.len(), opponent)?
+            }
+            None => {
+                println!("\n{}", tr!("end.diagram_prompt"));
+                // a terminal without any keys left quits too
+                Leave::Key(term.wait_key().ok())
+            }
+        };
+        match leave {
+            Leave::Key(Some(Key::Char('d'))) => {
+                let stem = config.games_dir.join(pgn::file_stem(game));
+                for extension in ["txt", "svg"] {
+                    let path = stem.with_extension(extension);
+                    match fs::create_dir_all(&config.games_dir).and_then(|()| diagram::write(game, Some(&end), &path)) {
+                        Ok(()) => println!("{}", tr!("diagram.saved", path = path.display())),
+                        Err(err) => println!("{}", tr!("diagram.save_failed", error = err)),
+                    }
+                }
+            }
+            Leave::Key(_) => {}
+            Leave::Rematch(game_info) => {
+                let name = if color == Color::White { &game.white.name } else { &game.black.name };
+                let mut next = online_game(name.clone(), &game_info, None, config)?;
+                next.score = score;
+                next.show_move_counts = game.show_move_counts;
+                next.glyphs = game.glyphs.clone();
+                next.compact = game.compact;
+                next.diagram = game.diagram.clone();
+                next.assistance &= game.assistance;
+                next.show_pv = game.show_pv;
+                if next.status.is_none() {
+                    next.status = Some(tr!("rematch.started", score = score));
+                }
+                let (local, mut opponent) = if color == Color::White { (white, black) } else { (black, white) };
+                if let PlayerType::Remote(remote) = &mut opponent {
+                    remote.start_rematch(&game_info);
+                }
+                let (white, black) = if game_info.is_black { (opponent, local) } else { (local, opponent) };
+                if !config.accessible {
+                    term.clear()?;
+                }
+                render(&next, term)?;
+                return crate::game(render, term, next, white, black, config);
+            }
+        }
+        // an opponent who asked for a rematch is told right away that there won't be one
+        if let Some(remote) = remote(&mut white, &mut black) {
+            let _ = remote.socket.shutdown(Shutdown::Both);
+        }
+        Ok(Some(end))
     }
 
     let mut last_term_size = term.size();
 
-    fn play(game: &mut Game, from: Pos, to: Pos, white: &mut PlayerType, black: &mut PlayerType) -> Result<Option<GameEnd>, Box<dyn Error>> {
-        if !game.possible_moves.get(&from).map_or(false, |moves| moves.contains(&to)) {
-            panic!("{:?} played illegal move: {from} -> {to}", game.turn);
-        }
-        let other_player = if game.turn == Color::White { black } else { white };
-        if let PlayerType::Remote(remote) = other_player {
-            online::send(&mut remote.socket, Move { x1: from.x, y1: from.y, x2: to.x, y2: to.y })?;
-
-        }
-        Ok(game.play_move(from, to))
-    }
+    // the colors moved with this keyboard, a side of a game between two CPUs can be taken over
+    let mut me: Vec<Color> = [(&white, Color::White), (&black, Color::Black)].into_iter()
+        .filter(|(player, _)| matches!(player, PlayerType::Me))
+        .map(|(_, color)| color)
+        .collect();
+    game.clock = Some(Instant::now());
+    // the title is only sent when it changes, not with every frame
+    let mut title = String::new();
+    // the clocks as they were last drawn, they are drawn again when the time shown changes
+    let mut clocks = String::new();
+    // moves the CPUs still play while they are paused, `.` steps through a game between them one move at a time
+    let mut steps: usize = 0;
+    let paused_status = |me: &[Color]| tr!(if me.is_empty() { "watching.paused" } else { "cpu.paused" });
 
     loop {
+        if tutorial::update(&mut game) {
+            render(&game, term)?;
+        }
+        if config.title && game.title(&me, None) != title {
+            title = game.title(&me, None);
+            term.set_title(&title)?;
+        }
+        if clocks_changed(&game, &mut clocks) {
+            render(&game, term)?;
+        }
         let term_size = term.size();
         
-        if term_size != last_term_size {
+        if term_size != last_term_size && !config.accessible {
             last_term_size = term_size;
-            term.clear_screen()?;
+            term.clear()?;
             render(&game, term)?;
         }
 
-        let active_player = if game.turn == Color::White { &mut white } else { &mut black };
-
-        let key = match active_player {
-            PlayerType::Me => keys.recv().unwrap(),
-            PlayerType::Remote(remote) => {
-                match remote.server.try_recv() {
-                    Ok(m) => {
-                        if let Some(end) = play(&mut game, vec2![m.x1, m.y1], vec2![m.x2, m.y2], &mut white, &mut black)? {
-                            render_end(render, game, term, end)?;
-                            return Ok(());
-                        } else {
-                            render(&game, term)?;
-                            continue;
-                        }
+        // messages from the server can arrive at any time, not only on the remote player's turn
+        if let Some(remote) = remote(&mut white, &mut black) {
+            match remote.server.try_recv() {
+                Ok(ServerMessage::Move(m)) => {
+                    let (from, to) = (vec2![m.x1, m.y1], vec2![m.x2, m.y2]);
+                    let end = play(&mut game, from, to, Piece::Queen, &mut white, &mut black)
+                        .map_err(|err| format!("the server sent a move that can't be played: {err}"))?;
+                    if let Some(end) = end {
+                        return render_end(render, &game, [white, black], term, end, &me, config);
+                    } else {
+                        render(&game, term)?;
+                        continue;
                     }
-                    Err(TryRecvError::Empty) => match keys.try_recv() {
-                        Ok(t) => t,
-                        Err(TryRecvError::Empty) => {
-                            std::thread::sleep(Duration::from_millis(10));
-                            continue;
+                }
+                Ok(ServerMessage::Resync(moves)) => {
+                    warn!("game {} ply {}: resynchronizing {} moves from the server", remote.game_id, game.history.len(), moves.len());
+                    let moves: Vec<(Pos, Pos)> = moves.iter().map(|m| (vec2![m.x1, m.y1], vec2![m.x2, m.y2])).collect();
+                    match game.replace_moves(&moves) {
+                        Ok(Some(end)) => {
+                            return render_end(render, &game, [white, black], term, end, &me, config);
                         }
-                        Err(TryRecvError::Disconnected) => panic!("Keys disconnected")
+                        Ok(None) => game.status = Some(tr!("online.resynced")),
+                        Err(err) => return Err(format!("Failed to resynchronize with the server: {err}").into()),
                     }
-                    Err(TryRecvError::Disconnected) => {
-                        eprintln!("Server disconnected");
-                        return Ok(());
+                    render(&game, term)?;
+                    continue;
+                }
+                Ok(ServerMessage::Chat(chat)) => {
+                    game.chat.push(chat.to_string());
+                    render(&game, term)?;
+                    continue;
+                }
+                Ok(ServerMessage::GameOver(EndReason::Aborted)) => {
+                    info!("game {} ply {}: aborted by the server", remote.game_id, game.history.len());
+                    return render_end(render, &game, [white, black], term, GameEnd::Aborted, &me, config);
+                }
+                Ok(ServerMessage::GameOver(EndReason::Abandoned(black_left))) => {
+                    let end = GameEnd::Winner(if black_left { Color::White } else { Color::Black });
+                    info!("game {} ply {}: the opponent left", remote.game_id, game.history.len());
+                    game.status = Some(tr!("online.opponent_left"));
+                    return render_end(render, &game, [white, black], term, end, &me, config);
+                }
+                Ok(ServerMessage::GameOver(EndReason::Resigned(black_resigned))) => {
+                    let color = if black_resigned { Color::Black } else { Color::White };
+                    info!("game {} ply {}: {color:?} resigned", remote.game_id, game.history.len());
+                    let end = GameEnd::Winner(!color);
+                    game.status = Some(tr!("end.resigned", color = lang::color(color)));
+                    return render_end(render, &game, [white, black], term, end, &me, config);
+                }
+                Ok(ServerMessage::OpponentLeft(seconds)) => {
+                    game.status = Some(tr!("online.opponent_lost", seconds = seconds));
+                    render(&game, term)?;
+                    continue;
+                }
+                Ok(ServerMessage::OpponentBack) => {
+                    game.status = Some(tr!("online.opponent_back"));
+                    render(&game, term)?;
+                    continue;
+                }
+                Ok(ServerMessage::GameOver(EndReason::DrawAgreed)) => {
+                    info!("game {} ply {}: the players agreed to a draw", remote.game_id, game.history.len());
+                    game.status = Some(tr!("draw.agreed"));
+                    return render_end(render, &game, [white, black], term, GameEnd::Draw, &me, config);
+                }
+                Ok(ServerMessage::GameOver(EndReason::Flagged(black_flagged))) => {
+                    let color = if black_flagged { Color::Black } else { Color::White };
+                    info!("game {} ply {}: {color:?} ran out of time", remote.game_id, game.history.len());
+                    // like on the server, the opponent only wins if they could still mate
+                    let end =
+                        if game.board.has_mating_material(!color) { GameEnd::Winner(!color) } else { GameEnd::Draw };
+                    if let Some(time_left) = &mut game.time_left {
+                        time_left.stop(game.turn);
                     }
+                    game.status = Some(tr!("end.flagged", color = lang::color(color)));
+                    return render_end(render, &game, [white, black], term, end, &me, config);
                 }
-            }
-            PlayerType::Cpu { depth, computation } => {
-                if let Some(available_computation) = computation {
-                    if available_computation.is_finished() {
-                        let mov = computation.take().unwrap().join().expect("AI compute thread failed");
-                        if let Some(end) = play(&mut game, mov.from, mov.to, &mut white, &mut black)? {
-                            render_end(render, game, term, end)?;
-                            return Ok(());
-                        } else {
-                            render(&game, term)?;
-                            continue;
-                        }
+                Ok(ServerMessage::Clock(times)) => {
+                    if let Some(time_left) = &mut game.time_left {
+                        time_left.set(times, game.history.len());
                     }
-                } else {
-                    *computation = Some(ai::movalyzer(&game.board, game.turn, *depth));
+                    render(&game, term)?;
+                    continue;
                 }
-                match keys.try_recv() {
-                    Ok(t) => t,
-                    Err(TryRecvError::Empty) => {
-                        std::thread::sleep(Duration::from_millis(10));
-                        continue;
+                Ok(message @ (ServerMessage::DrawOffered | ServerMessage::DrawDeclined)) => {
+                    let opponent = if matches!(white, PlayerType::Remote(_)) { Color::White } else { Color::Black };
+                    let name = if opponent == Color::White { &game.white.name } else { &game.black.name }.clone();
+                    if matches!(message, ServerMessage::DrawOffered) {
+                        game.status = Some(tr!("draw.offered", name = name));
+                        game.draw_offer = Some(opponent);
+                    } else {
+                        game.status = Some(tr!("draw.declined", name = name));
+                        game.draw_offer = None;
+                    }
+                    render(&game, term)?;
+                    continue;
+                }
+                Ok(message @ (ServerMessage::TakebackRequested | ServerMessage::TakebackDeclined)) => {
+                    let opponent = if matches!(white, PlayerType::Remote(_)) { Color::White } else { Color::Black };
+                    let name = if opponent == Color::White { &game.white.name } else { &game.black.name }.clone();
+                    if matches!(message, ServerMessage::TakebackRequested) {
+                        game.status = Some(tr!("takeback.requested", name = name));
+                        game.takeback = Some(opponent);
+                    } else {
+                        game.status = Some(tr!("takeback.declined", name = name));
+                        game.takeback = None;
                     }
-                    Err(err) => panic!("Keys disconnected {err}")
+                    render(&game, term)?;
+                    continue;
+                }
+                Ok(ServerMessage::TookBack(plies)) => {
+                    info!("game {} ply {}: taking back to ply {plies}", remote.game_id, game.history.len());
+                    if let Err(err) = game.take_back_to(plies as usize) {
+                        return Err(format!("Failed to take back moves: {err}").into());
+                    }
+                    game.status = Some(tr!("takeback.done"));
+                    render(&game, term)?;
+                    continue;
+                }
+                // only sent to spectators
+                Ok(ServerMessage::MoveDetails(_)) => {}
+                // only sent after the game, on the end screen
+                Ok(ServerMessage::RematchOffered | ServerMessage::RematchDeclined | ServerMessage::Rematch(_)) => {}
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    let seat = remote.seat.clone().filter(|_| remote.features.contains(Features::AUTO_REJOIN));
+                    let rejoined = match seat {
+                        Some(seat) => auto_rejoin(&seat, &mut game, &mut render, term)?,
+                        None => None,
+                    };
+                    let Some((rejoined, (_, moves))) = rejoined else {
+                        eprintln!("{}", tr!("online.disconnected"));
+                        return Ok(None);
+                    };
+                    *remote = rejoined;
+                    // an offer that was made while the connection was gone is unknown, one before it is forgotten
+                    game.draw_offer = None;
+                    game.takeback = None;
+                    let moves: Vec<(Pos, Pos)> = moves.iter().map(|m| (vec2![m.x1, m.y1], vec2![m.x2, m.y2])).collect();
+                    match game.replace_moves(&moves) {
+                        Ok(Some(end)) => {
+                            return render_end(render, &game, [white, black], term, end, &me, config);
+                        }
+                        Ok(None) => game.status = Some(tr!("online.rejoined")),
+                        Err(err) => return Err(format!("Failed to rebuild the board after rejoining: {err}").into()),
+                    }
+                    render(&game, term)?;
+                    continue;
                 }
             }
-        };
+        }
+        let online = remote(&mut white, &mut black).is_some();
+
+        let active_player = if game.turn == Color::White { &mut white } else { &mut black };
 
-        let up = |game: &mut Game| {
-            if game.cursor.y < 7 {
-                game.cursor.y += 1;
+        let input = match active_player {
+            PlayerType::Me if !online => match term.read_input(None)? {
+                Some(input) => input,
+                None => continue,
             }
-        };
-        let down = |game: &mut Game| {
-            if game.cursor.y > 0 {
-                game.cursor.y -= 1;
+            PlayerType::Me | PlayerType::Remote(_) => match term.read_input(Some(Duration::from_millis(10)))? {
+                Some(input) => input,
+                None => continue,
+            }
+            PlayerType::Cpu { depth, time, engine, paused } => {
+                if *paused && steps == 0 {
+                    // the status is cleared by moves, it has to come back when it's the CPU's turn again
+                    if game.status.is_none() {
+                        game.status = Some(paused_status(&me));
+                        render(&game, term)?;
+                    }
+                } else if engine.is_searching() {
+                    match engine.try_recv_latest() {
+                        Some(Response::Progress(info)) => {
+                            debug!(
+                                "CPU searched {}/{} moves, {} nodes, best {}{} with score {}",
+                                info.searched, info.moves, info.nodes,
+                                notation::square(info.best.from), notation::square(info.best.to), info.score,
+                            );
+                            // only the status row changes, the backend skips the others. Other messages are kept and
+                            // screen readers aren't interrupted by every update.
+                            let thinking = tr!("cpu.thinking");
+                            let shown = game.status.as_deref().map(|status| status.starts_with(&thinking)).unwrap_or(true);
+                            if shown && !config.accessible {
+                                game.status = Some(format!("{thinking} {}/{}", info.searched, info.moves));
+                                render(&game, term)?;
+                            }
+                        }
+                        Some(Response::BestMove(mov, score, pv)) => {
+                            let mov = mov.expect("the CPU has to move in a game that isn't over");
+                            let line = ai::line_san(&game.board, &pv).join(" ");
+                            debug!("CPU plays {}{} with score {score}, expects {line}", notation::square(mov.from), notation::square(mov.to));
+                            steps = steps.saturating_sub(1);
+                            if let Some(end) = play(&mut game, mov.from, mov.to, Piece::Queen, &mut white, &mut black)? {
+                                return render_end(render, &game, [white, black], term, end, &me, config);
+                            } else {
+                                if game.show_pv {
+                                    game.status = Some(tr!("cpu.expects", line = line, score = config.score_format.format(score)));
+                                }
+                                render(&game, term)?;
+                                continue;
+                            }
+                        }
+                        // only pausing stops a search, the next iteration after it starts a new one
+                        Some(Response::Stopped) | None => {}
+                    }
+                } else {
+                    engine.go(&game.board, game.turn, game.earlier_positions(), *depth, *time);
+                }
+                match term.read_input(Some(Duration::from_millis(10)))? {
+                    Some(input) => input,
+                    None => continue,
+                }
             }
         };
 
-        match key {
-            Key::Char('m') | Key::ArrowLeft => if game.cursor.x > 0 { game.cursor.x -= 1; },
-            Key::Char('i') | Key::ArrowRight => if game.cursor.x < 7 { game.cursor.x += 1; },
-            Key::Char('e') | Key::ArrowUp => if game.flip_board { down(&mut game) } else { up(&mut game) }
-            Key::Char('n') | Key::ArrowDown => if game.flip_board { up(&mut game) } else { down(&mut game) }
-            Key::Char(' ') | Key::Char('\n') => {
-                if !matches!(active_player, PlayerType::Me) {
-                    game.moving = None;
-                    continue;
+        // keys that arrived while the last frame was drawn are handled together and drawn once, so held keys don't
+        // pile up on slow terminals. They are handled one by one in their order, selecting never skips a movement.
+        let mut inputs = vec![input];
+        while let Some(input) = term.read_input(Some(Duration::ZERO))? {
+            inputs.push(input);
+        }
+        for input in inputs {
+            let action = match input {
+                Input::Key(key) => input::handle_key(&mut game, key, &me),
+                Input::Tap(x, y) => match game.square_at(i32::from(x) - i32::from(BOARD_X), i32::from(y) - i32::from(BOARD_Y)) {
+                    Some(square) => input::handle_tap(&mut game, square, &me),
+                    None => Action::None,
+                }
+            };
+            match action {
+                Action::Move(from, to) => if let Some(end) = play(&mut game, from, to, Piece::Queen, &mut white, &mut black)? {
+                    return render_end(render, &game, [white, black], term, end, &me, config);
+                }
+                Action::Promote(from, to, piece) => if let Some(end) = play(&mut game, from, to, piece, &mut white, &mut black)? {
+                    return render_end(render, &game, [white, black], term, end, &me, config);
+                }
+                Action::Chat(text) => if let Some(remote) = remote(&mut white, &mut black) {
+                    remote.send(game.history.len(), ClientMessage::Chat(text))?;
+                }
+                Action::Abort => if let Some(remote) = remote(&mut white, &mut black) {
+                    remote.send(game.history.len(), ClientMessage::Abort)?;
+                }
+                // the menu only offers these online with a local player
+                Action::OfferDraw => if let Some(remote) = remote(&mut white, &mut black) {
+                    remote.send(game.history.len(), ClientMessage::OfferDraw)?;
+                    game.draw_offer = Some(me[0]);
+                    game.status = Some(tr!("draw.offering"));
+                }
+                // an accepted draw ends when the server says so, the offer could have been withdrawn in the meantime
+                Action::AcceptDraw => if let Some(remote) = remote(&mut white, &mut black) {
+                    remote.send(game.history.len(), ClientMessage::AcceptDraw)?;
+                    game.draw_offer = None;
+                }
+                Action::DeclineDraw => if let Some(remote) = remote(&mut white, &mut black) {
+                    remote.send(game.history.len(), ClientMessage::DeclineDraw)?;
+                    game.draw_offer = None;
                 }
-                if let Some(moving) = game.moving {
-                    let cursor = game.cursor;
-                    if game.possible_moves.get(&moving).unwrap().contains(&cursor) {
-                        if let Some(end) = play(&mut game, moving, cursor, &mut white, &mut black)? {
-                            render_end(render, game, term, end)?;
-                            return Ok(());
+                // the moves are taken back when the server says so, like accepted draws
+                Action::RequestTakeback => if let Some(remote) = remote(&mut white, &mut black) {
+                    remote.send(game.history.len(), ClientMessage::TakebackRequest)?;
+                    game.takeback = Some(me[0]);
+                    game.status = Some(tr!("takeback.requesting"));
+                }
+                Action::AcceptTakeback => if let Some(remote) = remote(&mut white, &mut black) {
+                    remote.send(game.history.len(), ClientMessage::TakebackAccept)?;
+                    game.takeback = None;
+                }
+                Action::DeclineTakeback => if let Some(remote) = remote(&mut white, &mut black) {
+                    remote.send(game.history.len(), ClientMessage::TakebackDecline)?;
+                    game.takeback = None;
+                }
+                Action::OpenSettings => game.mode = Mode::Settings { selected: 0, entries: game.settings(config) },
+                Action::ChangeSetting(key) => {
+                    config.change(key);
+                    match key {
+                        "move_counts" => game.show_move_counts = config.move_counts,
+                        "glyphs" => game.glyphs = config.glyphs.clone(),
+                        "compact" => game.compact = config.compact,
+                        "title" if !config.title => {
+                            term.restore_title()?;
+                            title.clear();
                         }
+                        _ => {}
                     }
-                    game.moving = None;
-                } else {
-                    match game.board[game.cursor] {
-                        Some((_, color)) if color == game.turn => {
-                            game.moving = Some(game.cursor);
+                    let settings = game.settings(config);
+                    if let Mode::Settings { entries, .. } = &mut game.mode {
+                        *entries = settings;
+                    }
+                }
+                Action::SaveSettings => game.status = Some(match config.save() {
+                    Ok(()) => tr!("settings.saved", path = Config::path().display()),
+                    Err(err) => tr!("settings.save_failed", error = err),
+                }),
+                Action::TogglePause => for player in [&mut white, &mut black] {
+                    if let PlayerType::Cpu { engine, paused, .. } = player {
+                        *paused = !*paused;
+                        engine.stop();
+                        steps = 0;
+                        game.status = paused.then(|| paused_status(&me));
+                    }
+                }
+                Action::Step => {
+                    // a search that is already running is the step, its move is played before the game pauses
+                    let running = [&white, &black].into_iter().any(|player| matches!(player, PlayerType::Cpu { paused: false, .. }));
+                    steps = if running { 1 } else { steps + 1 };
+                    for player in [&mut white, &mut black] {
+                        if let PlayerType::Cpu { paused, .. } = player {
+                            *paused = true;
                         }
-                        _ => {}
                     }
+                    game.status = Some(paused_status(&me));
                 }
+                Action::TakeOver => {
+                    let (player, other) = if game.turn == Color::White { (&mut white, &mut black) } else { (&mut black, &mut white) };
+                    match player {
+                        PlayerType::Cpu { paused: true, .. } if steps == 0 => {
+                            // dropping the engine stops its search and waits for the worker, no answer of it is left
+                            *player = PlayerType::Me;
+                            me.push(game.turn);
+                            if let PlayerType::Cpu { paused, .. } = other {
+                                *paused = false;
+                            }
+                            game.takeovers.push((game.history.len(), game.turn));
+                            game.flip_board = game.turn == Color::Black;
+                            game.cursor = game.square_on_screen(0, 7);
+                            game.status = Some(tr!("watching.took_over", color = lang::color(game.turn)));
+                        }
+                        _ => game.status = Some(tr!("watching.pause_first")),
+                    }
+                }
+                Action::Resign => {
+                    // the menu only offers it with a local player, at one keyboard the side to move gives up
+                    let color = if me.contains(&game.turn) { game.turn } else { me[0] };
+                    if let Some(remote) = remote(&mut white, &mut black) {
+                        remote.send(game.history.len(), ClientMessage::Resign)?;
+                    }
+                    let end = GameEnd::Winner(!color);
+                    game.status = Some(tr!("end.resigned", color = lang::color(color)));
+                    return render_end(render, &game, [white, black], term, end, &me, config);
+                }
+                Action::SkipTutorial | Action::Quit => return Ok(None),
+                Action::None => {}
             }
-            Key::Escape => {
-                game.moving = None;
-            }
-            Key::PageUp => {} // history
-            Key::PageDown => {} // history
-            Key::Char(_) => {}
-            _ => {}
         }
 
         render(&game, term)?;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::{Duration, Instant}};
+
+    use vecm::vec2;
+
+    use crate::{
+        ai::{self, Info, Move, Score}, backend::MemoryBackend, board::Board, colors::Colors,
+        engine::{self, Engine, Go, Response}, game::Game, notation, piece::{Color, Piece}, PlayerType,
+    };
+
+    /// Playing a piece of the side that isn't to move, or from an empty square, is an error and leaves the game alone
+    #[test]
+    fn out_of_turn_play() {
+        let start = Board::starting_position();
+        let mut game = Game::new(vec2![4, 1], "White".to_owned(), "Black".to_owned(), start, Color::White);
+        let (mut white, mut black) = (PlayerType::Me, PlayerType::Me);
+        let square = |name| notation::parse_square(name).unwrap();
+        for (wrong, right) in [(("e7", "e5"), ("e2", "e4")), (("d2", "d4"), ("e7", "e5"))] {
+            let fen = game.board.to_fen(game.turn, 0, 1);
+            for (from, to) in [wrong, ("e5", "e6")] {
+                let played = crate::play(&mut game, square(from), square(to), Piece::Queen, &mut white, &mut black);
+                assert!(played.is_err(), "{from}-{to} was played with {:?} to move", game.turn);
+            }
+            assert_eq!(game.board.to_fen(game.turn, 0, 1), fen, "refused moves changed the game");
+            crate::play(&mut game, square(right.0), square(right.1), Piece::Queen, &mut white, &mut black).unwrap();
+        }
+    }
+
+    /// A move the piece can't make is an error like a move of the other side instead of a panic, moves also come from
+    /// the server
+    #[test]
+    fn illegal_play() {
+        let start = Board::starting_position();
+        let mut game = Game::new(vec2![4, 1], "White".to_owned(), "Black".to_owned(), start, Color::White);
+        let (mut white, mut black) = (PlayerType::Me, PlayerType::Me);
+        let square = |name| notation::parse_square(name).unwrap();
+        for (from, to) in [("e2", "e5"), ("g1", "g3"), ("e1", "e2"), ("a1", "a8")] {
+            let played = crate::play(&mut game, square(from), square(to), Piece::Queen, &mut white, &mut black);
+            assert!(played.is_err(), "the illegal move {from}-{to} was played");
+        }
+        assert!(game.history.is_empty() && game.board == start, "illegal moves changed the game");
+    }
+
+    /// A search that reports far more progress than anyone can read is drawn a few times per second, not once per
+    /// update
+    #[test]
+    fn progress_flood() {
+        fn flood(_: &Go, progress: ai::Progress) -> Option<(Vec<Move>, Score)> {
+            const MOVES: usize = 1_000_000;
+            let best = Move { from: vec2![4, 1], to: vec2![4, 3] };
+            for searched in 1..=MOVES {
+                progress(&Info { searched, moves: MOVES, nodes: searched as u64, best, score: 0 });
+            }
+            Some((vec![best], 0))
+        }
+        let term = MemoryBackend::new((40, 100), [], Colors::None);
+        let board = Board::starting_position();
+        let mut game = Game::new(vec2![0, 0], "White".to_owned(), "Black".to_owned(), board, Color::White);
+        let mut engine = Engine::with_search(flood);
+        let start = Instant::now();
+        engine.go(&game.board, game.turn, Vec::new(), 1, None);
+        loop {
+            match engine.try_recv_latest() {
+                Some(Response::Progress(info)) => {
+                    game.status = Some(format!("thinking... {}/{}", info.searched, info.moves));
+                    crate::render(&game, &term).unwrap();
+                }
+                Some(_) => break,
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        // the first and the last update come on top of the ones per second
+        let allowed = (start.elapsed().as_secs_f64() * f64::from(engine::PROGRESS_PER_SECOND)) as usize + 2;
+        assert!(term.frames() <= allowed, "a flood of progress was drawn {} times, at most {allowed}", term.frames());
+    }
+}
@@ -0,0 +1,303 @@
+//! Housekeeping of the data folder, done at most once a day when termchess starts: the log file is rotated, autosaved
+//! games beyond the limits of the config are deleted oldest first and the folder is backed up into a tar archive in
+//! `backups`, of which only the newest ones are kept. Only files named the way termchess names them are ever deleted,
+//! anything else in the folders stays.
+
+use std::{fs, io, path::{Path, PathBuf}};
+
+use log::warn;
+
+use crate::{config::{self, Config}, pgn};
+
+/// Holds the date of the last maintenance
+const STAMP: &str = "last_maintenance";
+const LOG: &str = "termchess.log";
+/// The log file before the last rotation, it replaces the one before
+const OLD_LOG: &str = "termchess.log.1";
+const BACKUPS: &str = "backups";
+const BLOCK: usize = 512;
+
+/// How much is kept, 0 means no limit except for backups, where it means none are made
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub games: usize,
+    pub games_bytes: u64,
+    pub backups: usize,
+    pub log_bytes: u64,
+}
+impl Limits {
+    pub fn new(config: &Config) -> Self {
+        const MB: u64 = 1024 * 1024;
+        Self {
+            games: config.keep_games,
+            games_bytes: config.games_mb as u64 * MB,
+            backups: config.keep_backups,
+            log_bytes: config.log_mb as u64 * MB,
+        }
+    }
+}
+
+/// What a maintenance did
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub log_rotated: bool,
+    pub games_deleted: usize,
+    pub backup: Option<PathBuf>,
+    pub backups_deleted: usize,
+}
+
+/// Maintains the data folder unless that already happened today
+pub fn run(config: &Config) -> io::Result<Option<Report>> {
+    let dir = config::data_dir();
+    let date = today();
+    if !due(&dir, &date) {
+        return Ok(None);
+    }
+    run_in(&dir, &config.games_dir, &Limits::new(config), &date).map(Some)
+}
+
+/// Whether the last maintenance of the folder was on another day than `date`
+pub fn due(dir: &Path, date: &str) -> bool {
+    fs::read_to_string(dir.join(STAMP)).map_or(true, |stamp| stamp.trim() != date)
+}
+
+/// Maintains a data folder and the folder of its autosaved games and remembers `date` as the day it happened. The
+/// date like `2024-01-31` also names the backup.
+pub fn run_in(dir: &Path, games_dir: &Path, limits: &Limits, date: &str) -> io::Result<Report> {
+    fs::create_dir_all(dir)?;
+    let mut report = Report { log_rotated: rotate_log(dir, limits.log_bytes)?, ..Report::default() };
+    report.games_deleted = prune_games(games_dir, limits)?;
+    if limits.backups > 0 {
+        report.backup = Some(backup(dir, date)?);
+    }
+    report.backups_deleted = prune(&termchess_files(&dir.join(BACKUPS), is_backup_name)?, limits.backups, 0)?;
+    fs::write(dir.join(STAMP), format!("{date}\n"))?;
+    Ok(report)
+}
+
+fn today() -> String {
+    let (year, month, day, _) = pgn::utc_now();
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Moves the log out of the way once it's bigger than the limit
+fn rotate_log(dir: &Path, limit: u64) -> io::Result<bool> {
+    let path = dir.join(LOG);
+    match fs::metadata(&path) {
+        Ok(meta) if limit > 0 && meta.len() > limit => {
+            fs::rename(&path, dir.join(OLD_LOG))?;
+            Ok(true)
+        }
+        Ok(_) => Ok(false),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Deletes the oldest autosaved games until both the count and the size are in the limits
+fn prune_games(games_dir: &Path, limits: &Limits) -> io::Result<usize> {
+    let games = termchess_files(games_dir, is_autosave_name)?;
+    prune(&games, limits.games, limits.games_bytes)
+}
+
+/// Deletes files from the start of the list, which is sorted oldest first, until at most `keep` are left and they
+/// take at most `bytes` together. Both limits are off when they're 0.
+fn prune(files: &[(PathBuf, u64)], keep: usize, bytes: u64) -> io::Result<usize> {
+    let mut count = files.len();
+    let mut size: u64 = files.iter().map(|(_, len)| len).sum();
+    let mut deleted = 0;
+    for (path, len) in files {
+        if (keep == 0 || count <= keep) && (bytes == 0 || size <= bytes) {
+            break;
+        }
+        fs::remove_file(path)?;
+        count -= 1;
+        size -= len;
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+/// The files of a folder that termchess named, with their sizes and oldest first. Their names start with the date, so
+/// sorting them by name sorts them by age. A missing folder has none.
+fn termchess_files(dir: &Path, named: fn(&str) -> bool) -> io::Result<Vec<(PathBuf, u64)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_file() && entry.file_name().to_str().is_some_and(named) {
+            files.push((entry.path(), meta.len()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Whether the name starts with a date like `2024-01-31`
+fn starts_with_date(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() >= 10 && bytes[..10].iter().enumerate().all(|(i, c)| match i {
+        4 | 7 => *c == b'-',
+        _ => c.is_ascii_digit(),
+    })
+}
+
+/// Names like `2024-01-31_142501_White-vs-Black.pgn` from `pgn::file_stem`
+fn is_autosave_name(name: &str) -> bool {
+    let Some(stem) = name.strip_suffix(".pgn") else { return false };
+    let bytes = stem.as_bytes();
+    starts_with_date(stem)
+        && bytes.len() > 18
+        && bytes[10] == b'_'
+        && bytes[11..17].iter().all(u8::is_ascii_digit)
+        && bytes[17] == b'_'
+        && stem[18..].contains("-vs-")
+}
+
+/// Names like `termchess-2024-01-31.tar`
+fn is_backup_name(name: &str) -> bool {
+    name.strip_prefix("termchess-")
+        .and_then(|name| name.strip_suffix(".tar"))
+        .is_some_and(|date| date.len() == 10 && starts_with_date(date))
+}
+
+/// Writes the files of the data folder into `backups/termchess-<date>.tar`, except for the backups themselves. The
+/// archive is only given its name once it's complete, so a failed backup never replaces a good one.
+fn backup(dir: &Path, date: &str) -> io::Result<PathBuf> {
+    let backups = dir.join(BACKUPS);
+    fs::create_dir_all(&backups)?;
+    let mut files = Vec::new();
+    collect(dir, Path::new(""), &mut files)?;
+    let mut archive = Vec::new();
+    for (path, name) in files {
+        let content = fs::read(&path)?;
+        let Some(header) = tar_header(&name, content.len() as u64) else {
+            warn!("{name} isn't backed up, the name is too long for the archive");
+            continue;
+        };
+        archive.extend_from_slice(&header);
+        archive.extend_from_slice(&content);
+        archive.resize(archive.len().next_multiple_of(BLOCK), 0);
+    }
+    // the end of the archive is marked by two empty blocks
+    archive.resize(archive.len() + 2 * BLOCK, 0);
+    let path = backups.join(format!("termchess-{date}.tar"));
+    let partial = path.with_extension("tar.part");
+    fs::write(&partial, archive)?;
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+/// The files below a folder with their names in the archive, which use `/` on every system
+fn collect(dir: &Path, prefix: &Path, files: &mut Vec<(PathBuf, String)>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let relative = prefix.join(entry.file_name());
+        let kind = entry.file_type()?;
+        if kind.is_dir() && relative != Path::new(BACKUPS) {
+            collect(&entry.path(), &relative, files)?;
+        } else if kind.is_file() {
+            let name = relative.components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((entry.path(), name));
+        }
+    }
+    Ok(())
+}
+
+/// The ustar header of a file, None if the name doesn't fit. Names longer than 100 bytes are split at a `/` into the
+/// prefix and the name field.
+fn tar_header(name: &str, size: u64) -> Option<[u8; BLOCK]> {
+    let (prefix, name) = if name.len() <= 100 {
+        ("", name)
+    } else {
+        let split = name.char_indices().rfind(|&(i, c)| c == '/' && i <= 155 && name.len() - i - 1 <= 100)?.0;
+        (&name[..split], &name[split + 1..])
+    };
+    let mut header = [0; BLOCK];
+    let mut field = |offset: usize, value: &[u8]| header[offset..offset + value.len()].copy_from_slice(value);
+    field(0, name.as_bytes());
+    field(100, b"0000644\0");
+    field(108, b"0000000\0");
+    field(116, b"0000000\0");
+    field(124, format!("{size:011o}\0").as_bytes());
+    field(136, b"00000000000\0");
+    // the checksum is computed with its own field filled with spaces
+    field(148, b"        ");
+    field(156, b"0");
+    field(257, b"ustar\0");
+    field(263, b"00");
+    field(345, prefix.as_bytes());
+    let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    Some(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path, process};
+
+    use super::{due, run_in, Limits};
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> =
+            fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        names
+    }
+
+    /// The maintenance of a data folder in the temp folder keeps the newest autosaved games and backups up to the
+    /// limits, rotates a log that grew too big and leaves every file alone that termchess didn't name. It's only due
+    /// once a day.
+    #[test]
+    fn maintenance() {
+        let dir = env::temp_dir().join(format!("termchess-maintenance-{}", process::id()));
+        let games = dir.join("games");
+        let backups = dir.join("backups");
+        fs::create_dir_all(&games).unwrap();
+        fs::create_dir_all(&backups).unwrap();
+        let autosaves: Vec<_> = (1..=5).map(|day| format!("2024-01-0{day}_120000_White-vs-Black.pgn")).collect();
+        let foreign = ["notes.pgn", "2024-01-01 my game.pgn", "2024-01-01_120000_White-vs-Black.txt"];
+        for name in autosaves.iter().map(String::as_str).chain(foreign) {
+            fs::write(games.join(name), "[Event \"?\"]\n\n*\n").unwrap();
+        }
+        for name in ["termchess-2024-01-01.tar", "termchess-2024-01-02.tar", "keep.tar"] {
+            fs::write(backups.join(name), "").unwrap();
+        }
+        fs::write(dir.join("termchess.log"), "x".repeat(100)).unwrap();
+
+        let limits = Limits { games: 2, games_bytes: 0, backups: 2, log_bytes: 50 };
+        let report = run_in(&dir, &games, &limits, "2024-01-06").unwrap();
+        let mut expected: Vec<_> = autosaves[3..].iter().map(String::as_str).chain(foreign).collect();
+        expected.sort();
+        assert_eq!(names(&games), expected, "the games folder after the maintenance");
+        let kept = ["keep.tar", "termchess-2024-01-02.tar", "termchess-2024-01-06.tar"];
+        assert_eq!(names(&backups), kept, "the backups folder after the maintenance");
+        let rotated = !dir.join("termchess.log").exists() && dir.join("termchess.log.1").exists();
+        assert!(report.log_rotated && rotated, "the log wasn't rotated");
+        let backup = backups.join("termchess-2024-01-06.tar");
+        assert_eq!((report.games_deleted, report.backups_deleted, report.backup.as_ref()), (3, 1, Some(&backup)));
+        // the archive has the remaining games and none of the backups
+        let archive = fs::read(&backup).unwrap();
+        let has = |name: &str| archive.windows(name.len()).any(|window| window == name.as_bytes());
+        assert!(has(&format!("games/{}", autosaves[4])) && !has("keep.tar"), "the backup has the wrong files");
+        assert_eq!(archive.len() % 512, 0, "the backup isn't made of tar blocks");
+        assert!(!due(&dir, "2024-01-06") && due(&dir, "2024-01-07"), "the maintenance isn't due once a day");
+
+        // the size limit deletes games too, but still no foreign ones
+        let limits = Limits { games: 0, games_bytes: 1, backups: 0, log_bytes: 0 };
+        run_in(&dir, &games, &limits, "2024-01-07").unwrap();
+        let mut expected = foreign.to_vec();
+        expected.sort();
+        assert_eq!(names(&games), expected, "the size limit deleted foreign games");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
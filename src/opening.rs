@@ -0,0 +1,46 @@
+// (name, moves in SAN), the longest matching line wins
+const OPENINGS: &[(&str, &[&str])] = &[
+    ("King's Pawn Game", &["e4"]),
+    ("Queen's Pawn Game", &["d4"]),
+    ("English Opening", &["c4"]),
+    ("Reti Opening", &["Nf3"]),
+    ("Sicilian Defense", &["e4", "c5"]),
+    ("Sicilian Defense: Najdorf Variation", &["e4", "c5", "Nf3", "d6", "d4", "cxd4", "Nxd4", "Nf6", "Nc3", "a6"]),
+    ("French Defense", &["e4", "e6"]),
+    ("Caro-Kann Defense", &["e4", "c6"]),
+    ("Pirc Defense", &["e4", "d6"]),
+    ("Scandinavian Defense", &["e4", "d5"]),
+    ("Alekhine Defense", &["e4", "Nf6"]),
+    ("Open Game", &["e4", "e5"]),
+    ("King's Gambit", &["e4", "e5", "f4"]),
+    ("Vienna Game", &["e4", "e5", "Nc3"]),
+    ("King's Knight Opening", &["e4", "e5", "Nf3"]),
+    ("Petrov's Defense", &["e4", "e5", "Nf3", "Nf6"]),
+    ("Philidor Defense", &["e4", "e5", "Nf3", "d6"]),
+    ("Scotch Game", &["e4", "e5", "Nf3", "Nc6", "d4"]),
+    ("Italian Game", &["e4", "e5", "Nf3", "Nc6", "Bc4"]),
+    ("Italian Game: Two Knights Defense", &["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6"]),
+    ("Ruy Lopez", &["e4", "e5", "Nf3", "Nc6", "Bb5"]),
+    ("Ruy Lopez: Berlin Defense", &["e4", "e5", "Nf3", "Nc6", "Bb5", "Nf6"]),
+    ("Ruy Lopez: Morphy Defense", &["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]),
+    ("Queen's Gambit", &["d4", "d5", "c4"]),
+    ("Queen's Gambit Accepted", &["d4", "d5", "c4", "dxc4"]),
+    ("Queen's Gambit Declined", &["d4", "d5", "c4", "e6"]),
+    ("Slav Defense", &["d4", "d5", "c4", "c6"]),
+    ("London System", &["d4", "d5", "Bf4"]),
+    ("Indian Defense", &["d4", "Nf6"]),
+    ("King's Indian Defense", &["d4", "Nf6", "c4", "g6"]),
+    ("Nimzo-Indian Defense", &["d4", "Nf6", "c4", "e6", "Nc3", "Bb4"]),
+    ("Dutch Defense", &["d4", "f5"]),
+];
+
+/// Finds the name of the most specific known opening the moves start with. Check and mate markers are ignored.
+pub fn detect<'a>(moves: impl IntoIterator<Item = &'a str>) -> Option<&'static str> {
+    let moves: Vec<&str> = moves.into_iter()
+        .map(|m| m.trim_end_matches(['+', '#']))
+        .collect();
+    OPENINGS.iter()
+        .filter(|(_, line)| moves.starts_with(line))
+        .max_by_key(|(_, line)| line.len())
+        .map(|(name, _)| *name)
+}
@@ -0,0 +1,139 @@
+//! Move generator checks: perft counts the positions reachable in a number of plies, divide splits the count by the
+//! first move. `termchess perft --depth 4` prints the counts of the starting position or the one given with `--fen`.
+//! Comparing divide with another engine shows which move's subtree is wrong:
+//! ```text
+//! $ termchess perft-debug --fen "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1" --depth 2 --engine /usr/bin/stockfish
+//! depth 2: 2039 nodes, engine 2039
+//! no differences
+//! ```
+
+use std::{
+    collections::BTreeMap, error::Error, io::{self, BufRead, BufReader, Write}, ops::ControlFlow, path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio}, time::Instant,
+};
+
+use crate::{board::Board, piece::{Color, Piece}};
+
+/// Prints the perft counts of the position for every depth up to `depth`
+pub fn run(board: &Board, turn: Color, depth: usize) {
+    for depth in 1..=depth {
+        let start = Instant::now();
+        let nodes = board.perft(turn, depth);
+        println!("depth {depth}: {nodes} nodes in {:.2?}", start.elapsed());
+    }
+}
+
+/// Perft of the position after each legal move, keyed by the move in UCI notation. `depth` includes the first move.
+pub fn divide(board: &Board, turn: Color, depth: usize) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    let _ = board.for_each_move(turn, |from, to| {
+        let promotions = if board.is_promotion(from, to) { &Piece::PROMOTIONS[..] } else { &[Piece::Queen] };
+        for &promotion in promotions {
+            let mut after = *board;
+            after.move_piece_promote(from, to, promotion);
+            counts.insert(board.move_to_uci(from, to, Some(promotion)), after.perft(!turn, depth.saturating_sub(1)));
+        }
+        ControlFlow::Continue(())
+    });
+    counts
+}
+
+/// Runs divide on our move generator and the UCI engine at `engine_path` and follows the first move whose counts
+/// differ until the position is found where a move is wrongly generated or missing.
+pub fn debug(board: Board, turn: Color, depth: usize, engine_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut engine = Engine::start(engine_path)?;
+    let (mut board, mut turn) = (board, turn);
+    // moves that led from the given position to the current one
+    let mut line = Vec::new();
+    for depth in (1..=depth).rev() {
+        let fen = board.to_fen(turn, 0, 1);
+        let ours = divide(&board, turn, depth);
+        let theirs = engine.divide(&fen, depth)?;
+        println!("depth {depth}: {} nodes, engine {}", ours.values().sum::<u64>(), theirs.values().sum::<u64>());
+
+        let extra: Vec<&String> = ours.keys().filter(|m| !theirs.contains_key(*m)).collect();
+        let missing: Vec<&String> = theirs.keys().filter(|m| !ours.contains_key(*m)).collect();
+        if !extra.is_empty() || !missing.is_empty() {
+            println!("position: {fen}");
+            if !line.is_empty() {
+                println!("reached with: {}", line.join(" "));
+            }
+            for m in extra {
+                println!("generated but illegal: {m}");
+            }
+            for m in missing {
+                println!("legal but not generated: {m}");
+            }
+            return Ok(());
+        }
+
+        let Some((mv, count)) = ours.iter().find(|(m, count)| theirs[*m] != **count) else {
+            println!("no differences");
+            return Ok(());
+        };
+        println!("{mv}: {count} nodes, engine {}", theirs[mv]);
+        board.apply_uci(mv).expect("move from divide not found");
+        turn = !turn;
+        line.push(mv.clone());
+    }
+    Ok(())
+}
+
+/// An external engine spoken to over UCI
+struct Engine {
+    process: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+}
+impl Engine {
+    fn start(path: &Path) -> io::Result<Self> {
+        let mut process = Command::new(path).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let input = process.stdin.take().expect("stdin is piped");
+        let output = BufReader::new(process.stdout.take().expect("stdout is piped"));
+        let mut engine = Self { process, input, output };
+        engine.send("uci")?;
+        while engine.read_line()? != "uciok" {}
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.input, "{command}")?;
+        self.input.flush()
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.output.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the engine closed its output"));
+        }
+        Ok(line.trim().to_owned())
+    }
+
+    /// Divide with `go perft`, which isn't part of UCI but is understood by Stockfish and most engines based on it.
+    /// The engine prints a `move: count` line for every move and ends with the total.
+    fn divide(&mut self, fen: &str, depth: usize) -> io::Result<BTreeMap<String, u64>> {
+        self.send(&format!("position fen {fen}"))?;
+        self.send(&format!("go perft {depth}"))?;
+        let mut counts = BTreeMap::new();
+        loop {
+            let line = self.read_line()?;
+            if line.starts_with("Nodes searched") {
+                return Ok(counts);
+            }
+            if let Some((mv, count)) = line.split_once(':') {
+                if let Ok(count) = count.trim().parse() {
+                    counts.insert(mv.trim().to_owned(), count);
+                }
+            }
+        }
+    }
+}
+impl Drop for Engine {
+    fn drop(&mut self) {
+        // asking lets the engine exit by itself, killing it is the fallback
+        if self.send("quit").is_err() {
+            let _ = self.process.kill();
+        }
+        let _ = self.process.wait();
+    }
+}
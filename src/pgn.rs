@@ -0,0 +1,425 @@
+use std::{fs, io::{self, BufRead, BufReader}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use vecm::vec2;
+
+use crate::{
+    board::Board, game::{Clocks, Game, GameEnd}, notation, opening, piece::{Color, Piece}, stats::Summary,
+    variations::{self, Branch, Line},
+};
+
+pub use termchess_core::civil_from_days;
+
+pub type Tags = Vec<(String, String)>;
+
+pub fn tag<'a>(tags: &'a Tags, name: &str) -> Option<&'a str> {
+    tags.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+}
+
+/// A game read from a PGN file, the moves are unvalidated SAN strings
+pub struct PgnGame {
+    pub tags: Tags,
+    /// The main line with the variations of the file
+    pub moves: Line,
+}
+impl PgnGame {
+    /// The position the game starts from, given by the FEN tag if there is one
+    pub fn start(&self) -> Result<(Board, Color), String> {
+        self.start_with_clocks().map(|(board, turn, _)| (board, turn))
+    }
+
+    /// Like `start`, with the move counters of the FEN tag
+    pub fn start_with_clocks(&self) -> Result<(Board, Color, Clocks), String> {
+        match tag(&self.tags, "FEN") {
+            Some(fen) => Board::from_fen_with_counters(fen)
+                .map(|(board, turn, halfmove, fullmove)| (board, turn, Clocks { halfmove, fullmove }))
+                .ok_or_else(|| format!("invalid FEN: {fen}")),
+            None => Ok((Board::starting_position(), Color::White, Clocks::default())),
+        }
+    }
+
+    /// Replays the main line on a board to build a game. Fails on the first illegal or unreadable move.
+    pub fn to_game(&self) -> Result<Game, String> {
+        self.replay(&self.moves.mainline())
+    }
+
+    /// Builds the game with the given moves from the starting position, like `to_game`
+    pub fn replay(&self, moves: &[&str]) -> Result<Game, String> {
+        let (board, turn, clocks) = self.start_with_clocks()?;
+        let white = tag(&self.tags, "White").unwrap_or("?").to_owned();
+        let black = tag(&self.tags, "Black").unwrap_or("?").to_owned();
+        let mut game = Game::new(vec2![0, 0], white, black, board, turn);
+        game.start_clocks = clocks;
+        for (i, san) in moves.iter().enumerate() {
+            let (from, to, promotion) = notation::parse_san(&game.board, game.turn, san)
+                .ok_or_else(|| format!("illegal move {san} at ply {}", i + 1))?;
+            game.play_move_promote(from, to, promotion.unwrap_or(Piece::Queen));
+        }
+        Ok(game)
+    }
+
+    /// The game as PGN with its tags as they were read and the variations as RAVs
+    pub fn write(&self) -> String {
+        let (_, start_turn) = self.start().unwrap_or((Board::starting_position(), Color::White));
+        let result = tag(&self.tags, "Result").unwrap_or("*");
+        let tags: Vec<(&str, String)> = self.tags.iter().map(|(name, value)| (name.as_str(), value.clone())).collect();
+        let mut tokens = Vec::new();
+        line_tokens(&self.moves, start_turn, 0, &mut tokens);
+        tokens.push(result.to_owned());
+        write_tags(&tags) + &wrap(tokens)
+    }
+}
+
+/// Appends the movetext of a line, `ply` is the number of moves before it
+fn line_tokens(line: &Line, start_turn: Color, ply: usize, tokens: &mut Vec<String>) {
+    // Black's moves need their number at the start of a line and after variations
+    let mut needs_number = true;
+    for (i, branch) in line.moves.iter().enumerate() {
+        let number = variations::move_number(start_turn, ply + i);
+        if variations::white_to_move(start_turn, ply + i) {
+            tokens.push(format!("{number}."));
+        } else if needs_number {
+            tokens.push(format!("{number}..."));
+        }
+        tokens.push(branch.san.clone());
+        needs_number = !branch.variations.is_empty();
+        for variation in &branch.variations {
+            let start = tokens.len();
+            line_tokens(variation, start_turn, ply + i, tokens);
+            if tokens.len() > start {
+                tokens[start].insert(0, '(');
+                tokens.last_mut().unwrap().push(')');
+            }
+        }
+    }
+}
+
+/// The value of the `Rules` tag, the variant and revision of the rules the game was played under
+pub fn rules() -> String {
+    format!("{} {}", termchess_core::VARIANT, termchess_core::RULES_REVISION)
+}
+
+pub fn write(game: &Game, end: Option<&GameEnd>) -> String {
+    let (start, start_turn) = game.start_position();
+    let (year, month, day, _) = utc_now();
+    let result = end.map_or("*", GameEnd::result);
+
+    let mut tags = vec![
+        ("Event", "Casual game".to_owned()),
+        ("Site", "termchess".to_owned()),
+        ("Date", format!("{year:04}.{month:02}.{day:02}")),
+        ("Round", "-".to_owned()),
+        ("White", game.white.name.clone()),
+        ("Black", game.black.name.clone()),
+        ("Result", result.to_owned()),
+        ("GameHash", format!("{:016x}", game.moves_hash())),
+        ("Rules", rules()),
+    ];
+    let standard_start = start == Board::starting_position() && start_turn == Color::White;
+    if standard_start {
+        if let Some(opening) = opening::detect(game.history.iter().map(|entry| entry.san.as_str())) {
+            tags.push(("Opening", opening.to_owned()));
+        }
+    } else {
+        tags.push(("SetUp", "1".to_owned()));
+        tags.push(("FEN", start.to_fen(start_turn, game.start_clocks.halfmove, game.start_clocks.fullmove)));
+    }
+
+    let mut pgn = write_tags(&tags);
+    pgn.push_str(&format!("{{{}}}\n", Summary::new(game).lines().join("\n")));
+
+    let mut tokens = Vec::new();
+    let takeover = |ply: usize, tokens: &mut Vec<String>| {
+        let sides: Vec<Color> = game.takeovers.iter().filter(|(at, _)| *at == ply).map(|(_, color)| *color).collect();
+        for color in &sides {
+            tokens.push(format!("{{{color:?} is played by hand from here}}"));
+        }
+        !sides.is_empty()
+    };
+    let mut turn = start_turn;
+    let mut move_number = game.start_clocks.fullmove;
+    for (i, entry) in game.history.iter().enumerate() {
+        // Black's move needs its number again after a comment
+        let commented = takeover(i, &mut tokens);
+        if turn == Color::White {
+            tokens.push(format!("{move_number}."));
+        } else if i == 0 || commented {
+            tokens.push(format!("{move_number}..."));
+        }
+        tokens.push(entry.san.clone());
+        if turn == Color::Black {
+            move_number += 1;
+        }
+        turn = !turn;
+    }
+    takeover(game.history.len(), &mut tokens);
+    tokens.push(result.to_owned());
+    pgn + &wrap(tokens)
+}
+
+/// The tag section with the empty line after it
+fn write_tags(tags: &[(&str, String)]) -> String {
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        pgn.push_str(&format!("[{name} \"{}\"]\n", value.replace('\\', "\\\\").replace('"', "\\\"")));
+    }
+    pgn.push('\n');
+    pgn
+}
+
+/// Joins movetext tokens into lines below 80 characters
+fn wrap(tokens: Vec<String>) -> String {
+    let mut pgn = String::new();
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + 1 + token.len() > 79 {
+            pgn.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            pgn.push(' ');
+            line_len += 1;
+        }
+        line_len += token.len();
+        pgn.push_str(&token);
+    }
+    pgn.push('\n');
+    pgn
+}
+
+/// Writes the game into a new file in `dir` and returns its path. If the same game was already saved there, the
+/// existing file's path is returned instead.
+pub fn autosave(game: &Game, end: &GameEnd, dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let hash = format!("{:016x}", game.moves_hash());
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "pgn")
+            && read_tags(&path).is_ok_and(|tags| tag(&tags, "GameHash") == Some(&hash))
+        {
+            return Ok(path);
+        }
+    }
+    let path = dir.join(format!("{}.pgn", file_stem(game)));
+    fs::write(&path, write(game, Some(end)))?;
+    Ok(path)
+}
+
+/// The file name for a game saved now without its extension, the date and time followed by the players
+pub fn file_stem(game: &Game) -> String {
+    let (year, month, day, secs) = utc_now();
+    let name = |name: &str| -> String {
+        name.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect()
+    };
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}{:02}{:02}_{}-vs-{}",
+        secs / 3600, secs / 60 % 60, secs % 60,
+        name(&game.white.name), name(&game.black.name),
+    )
+}
+
+pub fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_owned(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+/// Reads only the tag section of the first game in a PGN file
+pub fn read_tags(path: &Path) -> io::Result<Tags> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a PGN file");
+    let mut tags = Vec::new();
+    for line in BufReader::new(fs::File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            if tags.is_empty() { continue } else { break }
+        }
+        tags.push(parse_tag(&line).ok_or_else(invalid)?);
+    }
+    if tags.is_empty() {
+        return Err(invalid());
+    }
+    Ok(tags)
+}
+
+/// The games of a PGN file with many of them, read one at a time so the file doesn't have to fit into memory. Each
+/// game is its text and the line it starts on. A game ends where the next tag section starts outside of a comment.
+pub struct Games<R> {
+    reader: R,
+    line: usize,
+    /// The first line of the next game, it was read to find the end of the one before
+    next: Option<String>,
+}
+impl<R: BufRead> Games<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, line: 0, next: None }
+    }
+}
+impl<R: BufRead> Iterator for Games<R> {
+    type Item = io::Result<(usize, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut start = self.next.as_ref().map(|_| self.line);
+        let mut text = self.next.take().unwrap_or_default();
+        let mut movetext = false;
+        let mut comment = false;
+        let mut bytes = Vec::new();
+        loop {
+            bytes.clear();
+            match self.reader.read_until(b'\n', &mut bytes) {
+                Ok(0) => break,
+                Ok(_) => self.line += 1,
+                Err(err) => return Some(Err(err)),
+            }
+            // exports aren't always UTF-8, broken characters only end up in names and comments
+            let line = String::from_utf8_lossy(&bytes);
+            let line = line.trim_start_matches('\u{feff}');
+            let trimmed = line.trim();
+            if !comment && trimmed.starts_with('[') {
+                if movetext {
+                    self.next = Some(line.to_owned());
+                    break;
+                }
+            } else if !comment && !trimmed.is_empty() {
+                movetext = true;
+            }
+            for c in line.chars() {
+                match c {
+                    '{' if !comment => comment = true,
+                    '}' if comment => comment = false,
+                    ';' if !comment => break,
+                    _ => {}
+                }
+            }
+            if start.is_none() && !trimmed.is_empty() {
+                start = Some(self.line);
+            }
+            text.push_str(line);
+        }
+        start.map(|start| Ok((start, text)))
+    }
+}
+
+/// Parses the first game of a PGN text with its variations. Comments, move numbers and NAGs are skipped.
+pub fn parse(text: &str) -> Option<PgnGame> {
+    let mut lines = text.lines().peekable();
+    let mut tags = Vec::new();
+    while let Some(line) = lines.peek() {
+        if line.trim().is_empty() {
+            lines.next();
+            if tags.is_empty() { continue } else { break }
+        }
+        if !line.trim_start().starts_with('[') { break }
+        tags.push(parse_tag(line)?);
+        lines.next();
+    }
+
+    let movetext: String = lines
+        .filter(|line| !line.starts_with('%'))
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    // the main line followed by the variations that are being read
+    let mut stack = vec![Line::default()];
+    let mut rest = movetext.as_str();
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else { break };
+        fn skip_to(rest: &str, end: char) -> &str {
+            rest.split_once(end).map_or("", |(_, after)| after)
+        }
+        match c {
+            ';' => rest = skip_to(rest, '\n'),
+            '{' => rest = skip_to(rest, '}'),
+            '(' => {
+                stack.push(Line::default());
+                rest = &rest[1..];
+            }
+            ')' => {
+                // a variation replaces the last move of the line it's in
+                if stack.len() > 1 {
+                    let variation = stack.pop().unwrap();
+                    if let Some(replaced) = stack.last_mut().unwrap().moves.last_mut() {
+                        replaced.variations.push(variation);
+                    }
+                }
+                rest = &rest[1..];
+            }
+            _ => {
+                let end = rest.find(|c: char| c.is_whitespace() || matches!(c, '{' | '(' | ')' | ';'))
+                    .unwrap_or(rest.len());
+                let token = &rest[..end];
+                rest = &rest[end..];
+                if token.starts_with('$') {
+                    continue;
+                }
+                if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+                    if stack.len() == 1 { break } else { continue }
+                }
+                // move numbers like `12.` or `12...`, possibly directly followed by the move
+                let token = match token.rfind('.') {
+                    Some(i) => &token[i + 1..],
+                    None if token.chars().all(|c| c.is_ascii_digit()) => "",
+                    None => token,
+                };
+                if !token.is_empty() {
+                    stack.last_mut().unwrap().moves.push(Branch::new(token.to_owned()));
+                }
+            }
+        }
+    }
+    // variations that aren't closed are dropped with everything in them
+    stack.truncate(1);
+    Some(PgnGame { tags, moves: stack.pop().unwrap() })
+}
+
+// (year, month, day, seconds of the day)
+pub fn utc_now() -> (i64, u32, u32, u64) {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    (year, month, day, secs % 86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, tag};
+    use crate::{board::Board, game::GameEnd, notation, online, piece::Color};
+
+    fn moves(moves: &[(&str, &str)]) -> Vec<online::Move> {
+        moves.iter().map(|(from, to)| {
+            let (from, to) = (notation::parse_square(from).unwrap(), notation::parse_square(to).unwrap());
+            online::Move { x1: from.x, y1: from.y, x2: to.x, y2: to.y }
+        }).collect()
+    }
+
+    /// A game the server saves reads back as PGN with the Seven Tag Roster and replays to the same end, also from a
+    /// FEN with Black to move. Moves that can't be played aren't written.
+    #[test]
+    fn server_pgn() {
+        let start = Board::starting_position().to_fen(Color::White, 0, 1);
+        let fools_mate = moves(&[("f2", "f3"), ("e7", "e5"), ("g2", "g4"), ("d8", "h4")]);
+        let end = GameEnd::Winner(Color::Black);
+        let text = online::game_pgn("Alice", "Bob", &start, &fools_mate, &end, (2024, 3, 9)).unwrap();
+        let game = parse(&text).unwrap();
+        let roster: Vec<_> = game.tags.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(roster, ["Event", "Site", "Date", "Round", "White", "Black", "Result"]);
+        assert_eq!(tag(&game.tags, "Date"), Some("2024.03.09"));
+        assert_eq!(tag(&game.tags, "Result"), Some("0-1"));
+        assert_eq!(tag(&game.tags, "Black"), Some("Bob"));
+        let replayed = game.to_game().unwrap();
+        assert!(
+            replayed.history.len() == 4 && replayed.is_checkmate() && text.contains("2. g4 Qh4# 0-1"),
+            "the saved game doesn't replay to the mate:\n{text}",
+        );
+
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1";
+        let played = moves(&[("e8", "d7"), ("e2", "e4")]);
+        let text = online::game_pgn("Alice", "Bob", fen, &played, &GameEnd::Draw, (2024, 3, 9)).unwrap();
+        let game = parse(&text).unwrap();
+        let replayed = game.to_game().unwrap();
+        assert!(
+            tag(&game.tags, "FEN") == Some(fen) && replayed.history.len() == 2 && text.contains("1... Kd7 2. e4"),
+            "the saved game from a FEN is\n{text}",
+        );
+        let illegal = moves(&[("e2", "e5")]);
+        let saved = online::game_pgn("Alice", "Bob", &start, &illegal, &GameEnd::Draw, (2024, 3, 9));
+        assert!(saved.is_err(), "a game with an illegal move was saved");
+    }
+}
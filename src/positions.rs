@@ -0,0 +1,83 @@
+use std::{fs, io, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::{board::Board, config, piece::Color};
+
+/// Middlegame positions from mainline opening theory, one FEN without move counters per line
+const EMBEDDED: &str = include_str!("positions.txt");
+
+/// Number of recently used positions that won't be picked again
+const RECENT: usize = 50;
+
+/// Reads a position set, either the embedded one or a file with one FEN per line. Empty lines and lines starting with
+/// `#` are skipped, invalid FENs are reported and skipped.
+pub fn load(file: Option<&Path>) -> io::Result<Vec<String>> {
+    let text = match file {
+        Some(path) => fs::read_to_string(path)?,
+        None => EMBEDDED.to_owned(),
+    };
+    let source = file.map_or_else(|| "positions".to_owned(), |path| path.display().to_string());
+    let mut positions = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if Board::from_fen(line).is_some() {
+            positions.push(line.to_owned());
+        } else {
+            eprintln!("{source}:{}: invalid FEN `{line}`", i + 1);
+        }
+    }
+    if positions.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no valid positions found"));
+    }
+    Ok(positions)
+}
+
+/// Picks a random position that wasn't used recently and remembers it. Returns the position and whether the player
+/// should play Black.
+pub fn pick(positions: &[String]) -> (Board, Color, bool) {
+    let path = recent_path();
+    let mut recent: Vec<String> = fs::read_to_string(&path)
+        .map(|text| text.lines().map(str::to_owned).collect())
+        .unwrap_or_default();
+
+    let mut rng = Rng::new();
+    let fresh: Vec<&String> = positions.iter().filter(|fen| !recent.contains(fen)).collect();
+    // with a small set every position might have been used recently
+    let fen = if fresh.is_empty() {
+        &positions[rng.below(positions.len())]
+    } else {
+        fresh[rng.below(fresh.len())]
+    };
+
+    recent.retain(|other| other != fen);
+    recent.push(fen.clone());
+    let start = recent.len().saturating_sub(RECENT);
+    // forgetting the recent positions isn't worth failing the game for
+    let _ = fs::create_dir_all(config::data_dir())
+        .and_then(|()| fs::write(&path, recent[start..].join("\n") + "\n"));
+
+    let (board, turn) = Board::from_fen(fen).expect("positions are validated when loading");
+    (board, turn, rng.below(2) == 1)
+}
+
+fn recent_path() -> PathBuf {
+    config::data_dir().join("recent_positions")
+}
+
+// xorshift, good enough for picking positions
+struct Rng(u64);
+impl Rng {
+    fn new() -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        Self(nanos | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
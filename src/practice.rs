@@ -0,0 +1,120 @@
+use std::error::Error;
+
+use console::{Key, truncate_str};
+use vecm::vec2;
+
+use crate::{ai::{self, Score}, backend::Backend, engine::{Engine, Response}, game::Game, input::Mode, lang::tr, piece::Color, score::ScoreFormat, Pos};
+
+/// Search depth used for the engine's replies and for comparing the moves
+const DEPTH: usize = 3;
+
+/// Lets the user try alternatives to the move that was originally played in `branch` against the engine.
+/// `original` is the move that was played in the game, if there was one.
+pub fn run(term: &dyn Backend, branch: &Game, original: Option<(Pos, Pos)>, format: ScoreFormat) -> Result<(), Box<dyn Error>> {
+    let me = branch.turn;
+    let score_after = |game: &Game| -> Score {
+        // the score is for the side to move, which is the opponent after our move
+        -ai::analyze(&game.board, game.turn, DEPTH)
+    };
+
+    status(term, &tr!("practice.analyzing_original"))?;
+    let original = original.map(|(from, to)| {
+        let mut game = branch.clone();
+        game.play_move(from, to);
+        let san = game.history.last().unwrap().san.clone();
+        (san, score_after(&game))
+    });
+
+    let mut game = reset(branch);
+    let mut engine = Engine::spawn();
+    // the first move of the current attempt and its score
+    let mut attempt: Option<(String, Score)> = None;
+    let mut result: Option<&str> = None;
+
+    term.clear()?;
+    loop {
+        crate::render(&game, term)?;
+        let mut line = match &original {
+            Some((san, score)) => tr!("practice.played", san = san, score = format.format(*score)),
+            None => tr!("practice.no_move"),
+        };
+        if let Some((san, score)) = &attempt {
+            line.push_str(&format!("  {}", tr!("practice.yours", san = san, score = format.format(*score))));
+            if let Some(delta) = original.as_ref().and_then(|(_, original_score)| format.delta(*original_score, *score)) {
+                line.push_str(&format!(" (Δ {delta})"));
+            }
+        }
+        if let Some(result) = result {
+            line.push_str("  ");
+            line.push_str(result);
+        }
+        line.push_str(&format!("  {}", tr!("practice.keys")));
+        status(term, &line)?;
+
+        let key = term.wait_key()?;
+        match key {
+            Key::Char('m') | Key::ArrowLeft => game.move_cursor(-1, 0),
+            Key::Char('i') | Key::ArrowRight => game.move_cursor(1, 0),
+            Key::Char('e') | Key::ArrowUp => game.move_cursor(0, -1),
+            Key::Char('n') | Key::ArrowDown => game.move_cursor(0, 1),
+            Key::Char(' ') | Key::Enter if result.is_none() => {
+                if let Mode::PieceSelected(moving) = std::mem::replace(&mut game.mode, Mode::Navigating) {
+                    let cursor = game.cursor;
+                    if !game.possible_moves.get(&moving).is_some_and(|moves| moves.contains(&cursor)) {
+                        continue;
+                    }
+                    let mut end = game.play_move(moving, cursor);
+                    if attempt.is_none() {
+                        status(term, &tr!("practice.analyzing_yours"))?;
+                        attempt = Some((game.history.last().unwrap().san.clone(), score_after(&game)));
+                    }
+                    if end.is_none() {
+                        crate::render(&game, term)?;
+                        status(term, &tr!("practice.thinking"))?;
+                        engine.go(&game.board, game.turn, game.earlier_positions(), DEPTH, None);
+                        let reply = loop {
+                            match engine.recv_latest() {
+                                Response::Progress(info) => {
+                                    status(term, &format!("{} {}/{}", tr!("practice.thinking"), info.searched, info.moves))?;
+                                }
+                                Response::BestMove(reply, ..) => break reply.expect("the game isn't over"),
+                                Response::Stopped => unreachable!("the search is never stopped"),
+                            }
+                        };
+                        end = game.play_move(reply.from, reply.to);
+                    }
+                    result = end.map(|end| end.result());
+                } else if matches!(game.board[game.cursor], Some((_, color)) if color == me) {
+                    game.mode = Mode::PieceSelected(game.cursor);
+                }
+            }
+            Key::Escape => game.mode = Mode::Navigating,
+            Key::Char('r') => {
+                game = reset(branch);
+                attempt = None;
+                result = None;
+            }
+            Key::Char('q') => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn reset(branch: &Game) -> Game {
+    let mut game = branch.clone();
+    // the user plays the side to move from below
+    game.flip_board = game.turn == Color::Black;
+    game.cursor = vec2![4, if game.flip_board { 6 } else { 1 }];
+    game.mode = Mode::Navigating;
+    game
+}
+
+fn status(term: &dyn Backend, line: &str) -> Result<(), Box<dyn Error>> {
+    let (rows, cols) = term.size();
+    term.draw(0, rows - 1, &truncate_str(line, cols as usize, "…"))?;
+    term.flush()?;
+    Ok(())
+}
+
+// scores are in thousandths of a pawn
\ No newline at end of file
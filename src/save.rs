@@ -0,0 +1,203 @@
+//! Serde types of the save files, only built with the `serde` feature. The network protocol uses binverse instead.
+//! Every file is wrapped in a [`Versioned`] envelope, so a newer version of termchess can still read the files of an
+//! older one after the format changed.
+
+// the save files are written by features that build on these types
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use vecm::vec2;
+
+use crate::{board::Board, game::{Clocks, Game, HistoryEntry}, piece::Color};
+
+/// Format version written into new files
+pub const VERSION: u32 = 1;
+
+/// The outer object of every save file
+#[derive(Serialize, Deserialize)]
+pub struct Versioned<T> {
+    pub version: u32,
+    pub data: T,
+}
+impl<T> Versioned<T> {
+    pub fn new(data: T) -> Self {
+        Self { version: VERSION, data }
+    }
+
+    /// The data of a file written by this version or an older one. Files of newer versions are refused instead of
+    /// guessing what their new fields mean.
+    pub fn into_data(self) -> Result<T, String> {
+        if self.version > VERSION {
+            return Err(format!(
+                "the file has format version {}, this version of termchess reads up to {VERSION}", self.version,
+            ));
+        }
+        Ok(self.data)
+    }
+}
+
+/// A game with everything needed to continue it
+#[derive(Serialize, Deserialize)]
+pub struct SavedGame {
+    pub white: String,
+    pub black: String,
+    pub start: Board,
+    pub start_turn: Color,
+    /// Files from before the counters were kept start at those of a new game
+    #[serde(default)]
+    pub start_clocks: Clocks,
+    pub history: Vec<HistoryEntry>,
+    /// How long the side to move had been thinking, None if the clock wasn't running
+    pub clock: Option<Duration>,
+}
+impl SavedGame {
+    pub fn new(game: &Game) -> Self {
+        let (start, start_turn) = game.start_position();
+        Self {
+            white: game.white.name.clone(),
+            black: game.black.name.clone(),
+            start,
+            start_turn,
+            start_clocks: game.start_clocks,
+            history: game.history.clone(),
+            clock: game.clock.map(|start| start.elapsed()),
+        }
+    }
+
+    /// Replays the moves, the boards stored in the history are only used for the starting position
+    pub fn to_game(&self) -> Result<Game, String> {
+        let mut game = Game::new(vec2![0, 0], self.white.clone(), self.black.clone(), self.start, self.start_turn);
+        game.start_clocks = self.start_clocks;
+        let moves: Vec<_> = self.history.iter().map(|entry| (entry.from, entry.to)).collect();
+        game.replace_moves(&moves)?;
+        for (entry, saved) in game.history.iter_mut().zip(&self.history) {
+            entry.think = saved.think;
+        }
+        game.clock = self.clock.map(|elapsed| Instant::now().checked_sub(elapsed).unwrap_or_else(Instant::now));
+        Ok(game)
+    }
+}
+
+/// Squares as their names like `e4`, for `#[serde(with = "crate::save::square")]`
+pub mod square {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::{notation, Pos};
+
+    pub fn serialize<S: Serializer>(pos: &Pos, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&notation::square(*pos))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pos, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        notation::parse_square(&name).ok_or_else(|| D::Error::custom(format!("invalid square `{name}`")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use serde::{de::DeserializeOwned, Serialize};
+    use vecm::vec2;
+
+    use super::{SavedGame, Versioned};
+    use crate::{board::Board, config::Config, game::{Clocks, Game, HistoryEntry}, notation, piece::{Color, Piece}};
+
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> T {
+        let json = serde_json::to_string(value).unwrap();
+        serde_json::from_str(&json).unwrap_or_else(|err| panic!("can't read back `{json}`: {err}"))
+    }
+
+    fn square(name: &str) -> crate::Pos {
+        notation::parse_square(name).unwrap()
+    }
+
+    /// Black can take the pawn on e4 en passant, White can only castle short and Black only long
+    const EN_PASSANT: &str = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b Kq e3 0 3";
+
+    #[test]
+    fn board() {
+        let (board, turn) = Board::from_fen(EN_PASSANT).unwrap();
+        assert_eq!(board.moved_pawn(), Some(square("e4")));
+        let read = round_trip(&board);
+        assert!(read == board, "{} became {}", board.to_fen(turn, 0, 3), read.to_fen(turn, 0, 3));
+        assert_eq!(read.moved_pawn(), Some(square("e4")));
+        assert_eq!(read.to_fen(turn, 0, 3), EN_PASSANT);
+        assert!(round_trip(&Board::starting_position()) == Board::starting_position());
+    }
+
+    #[test]
+    fn history_entry() {
+        let (board, _) = Board::from_fen(EN_PASSANT).unwrap();
+        let entry = HistoryEntry {
+            board,
+            from: square("d4"),
+            to: square("e3"),
+            taken: Some(Piece::Pawn),
+            san: "dxe3".to_owned(),
+            think: Some(Duration::from_millis(1234)),
+        };
+        let read = round_trip(&entry);
+        assert!(read.board == entry.board);
+        assert_eq!((read.from, read.to), (entry.from, entry.to));
+        assert_eq!(read.taken, entry.taken);
+        assert_eq!(read.san, entry.san);
+        assert_eq!(read.think, entry.think);
+        let unclocked = round_trip(&HistoryEntry { think: None, taken: None, ..entry });
+        assert_eq!((unclocked.think, unclocked.taken), (None, None));
+    }
+
+    /// The clock of the side to move keeps running in a saved game, with the time already thought. The move counters
+    /// the game started from are kept too.
+    #[test]
+    fn clocks() {
+        let clocks = Clocks { halfmove: 7, fullmove: 23 };
+        assert_eq!(round_trip(&clocks), clocks);
+
+        let start = Board::starting_position();
+        let mut game = Game::new(vec2![0, 0], "white".to_owned(), "black".to_owned(), start, Color::White);
+        game.start_clocks = clocks;
+        game.replace_moves(&[(square("g1"), square("f3")), (square("g8"), square("f6"))]).unwrap();
+        game.clock = Instant::now().checked_sub(Duration::from_secs(3));
+        let read = round_trip(&Versioned::new(SavedGame::new(&game))).into_data().unwrap();
+        assert_eq!(read.start_clocks, clocks);
+        let elapsed = read.clock.expect("the running clock was lost");
+        assert!(elapsed >= Duration::from_secs(3) && elapsed < Duration::from_secs(4), "the clock read {elapsed:?}");
+        let continued = read.to_game().unwrap();
+        assert_eq!(continued.history.len(), 2);
+        assert_eq!(continued.start_clocks, clocks);
+        assert!(continued.clock.is_some_and(|since| since.elapsed() >= Duration::from_secs(3)));
+
+        game.clock = None;
+        assert_eq!(round_trip(&SavedGame::new(&game)).clock, None);
+
+        // files from before the counters were kept
+        let mut json: serde_json::Value = serde_json::to_value(SavedGame::new(&game)).unwrap();
+        json.as_object_mut().unwrap().remove("start_clocks");
+        let old: SavedGame = serde_json::from_value(json).unwrap();
+        assert_eq!(old.start_clocks, Clocks::default());
+    }
+
+    #[test]
+    fn settings() {
+        let values = |config: &Config| config.settings().into_iter().map(|setting| setting.value).collect::<Vec<_>>();
+        let mut config = Config::default();
+        for (key, value) in [
+            ("autosave_games", "false"),
+            ("move_counts", "true"),
+            ("glyphs", "\"outlined N=N\""),
+            ("accessible", "true"),
+            ("colors", "256"),
+            ("score_format", "winprob"),
+        ] {
+            config.set(key, value).unwrap();
+        }
+        assert_eq!(values(&round_trip(&config)), values(&config));
+        assert_ne!(values(&config), values(&Config::default()));
+        // missing options keep their defaults
+        assert_eq!(values(&serde_json::from_str::<Config>("{}").unwrap()), values(&Config::default()));
+    }
+}
@@ -0,0 +1,136 @@
+//! How scores are shown. The search scores in thousandths of a pawn, which are shown as pawns with one decimal like
+//! `+1.3` or as the chance to win in percent. Mates are `M5` or `−M3` in both formats. Scores are shown for the side
+//! they are for, the sign says who is better.
+
+use std::fmt::Write;
+
+use crate::ai::{Score, MATE, MATE_BOUND};
+
+/// Set with the `score_format` option
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum ScoreFormat {
+    Pawns,
+    /// The chance to win in percent, from a logistic curve over the score
+    WinProb,
+}
+impl ScoreFormat {
+    pub const ALL: [Self; 2] = [Self::Pawns, Self::WinProb];
+
+    /// The name in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pawns => "pawns",
+            Self::WinProb => "winprob",
+        }
+    }
+
+    pub fn format(self, score: Score) -> String {
+        if let Some(moves) = mate_in(score) {
+            let sign = if score < 0 { "−" } else { "" };
+            return format!("{sign}M{moves}");
+        }
+        match self {
+            Self::Pawns => pawns(score),
+            Self::WinProb => format!("{}%", win_percent(score)),
+        }
+    }
+
+    /// How much better `to` is than `from` in this format, `None` if one of them is a mate
+    pub fn delta(self, from: Score, to: Score) -> Option<String> {
+        if mate_in(from).is_some() || mate_in(to).is_some() {
+            return None;
+        }
+        Some(match self {
+            Self::Pawns => pawns(to - from),
+            Self::WinProb => {
+                let points = i32::from(win_percent(to)) - i32::from(win_percent(from));
+                format!("{}{}%", sign(points), points.abs())
+            }
+        })
+    }
+}
+
+/// The number of moves until the mate if the score is one, the side the score is for mates if it's positive
+pub fn mate_in(score: Score) -> Option<Score> {
+    // plies until the mate, the mate in 1 is one ply away. A mate that is scored without its distance counts as the
+    // nearest one.
+    (score.abs() > MATE_BOUND).then(|| ((MATE - score.abs() + 1) / 2).max(1))
+}
+
+/// `+`, the minus sign `−` or nothing for zero
+fn sign(value: Score) -> &'static str {
+    match value {
+        1.. => "+",
+        0 => "",
+        _ => "−",
+    }
+}
+
+/// Rounded to tenths of a pawn
+fn pawns(score: Score) -> String {
+    let tenths = (score.abs() + 50) / 100;
+    let mut text = String::from(if tenths == 0 { "" } else { sign(score) });
+    write!(text, "{}.{}", tenths / 10, tenths % 10).unwrap();
+    text
+}
+
+/// Lichess' curve over centipawns, a pawn is 100 of them
+fn win_percent(score: Score) -> u8 {
+    let centipawns = f64::from(score) / 10.0;
+    let chance = 1.0 / (1.0 + (-0.00368208 * centipawns).exp());
+    (chance * 100.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mate_in, ScoreFormat};
+    use crate::ai::{Score, MATE, MATE_BOUND};
+
+    /// Scores in both formats for each band: around zero, a pawn up or down, the largest material score right at
+    /// `MATE_BOUND`, which is no mate yet, and the mates beyond it from the furthest one down to the mate on the board
+    #[test]
+    fn bands() {
+        let cases: [(Score, &str, &str); 16] = [
+            (0, "0.0", "50%"),
+            (49, "0.0", "50%"),
+            (-49, "0.0", "50%"),
+            (50, "+0.1", "50%"),
+            (-50, "−0.1", "50%"),
+            (1000, "+1.0", "59%"),
+            (-1349, "−1.3", "38%"),
+            (MATE_BOUND, "+99.0", "100%"),
+            (-MATE_BOUND, "−99.0", "0%"),
+            (MATE_BOUND + 1, "M500", "M500"),
+            (-MATE_BOUND - 1, "−M500", "−M500"),
+            (MATE - 3, "M2", "M2"),
+            (MATE - 1, "M1", "M1"),
+            (-(MATE - 2), "−M1", "−M1"),
+            // a mate scored without its distance is the nearest one
+            (MATE, "M1", "M1"),
+            (-MATE, "−M1", "−M1"),
+        ];
+        for (value, pawns, win_prob) in cases {
+            let shown = (ScoreFormat::Pawns.format(value), ScoreFormat::WinProb.format(value));
+            assert_eq!(shown, (pawns.to_owned(), win_prob.to_owned()), "the score {value}");
+        }
+        assert_eq!((mate_in(MATE_BOUND), mate_in(-MATE_BOUND)), (None, None), "the mate bound counts as a mate");
+    }
+
+    /// Deltas are only given between scores that aren't mates
+    #[test]
+    fn deltas() {
+        let deltas = [
+            (ScoreFormat::Pawns, 0, 1350, Some("+1.4")),
+            (ScoreFormat::Pawns, 1000, -300, Some("−1.3")),
+            (ScoreFormat::WinProb, 0, 1000, Some("+9%")),
+            (ScoreFormat::WinProb, 1000, 0, Some("−9%")),
+            (ScoreFormat::Pawns, 0, MATE_BOUND, Some("+99.0")),
+            (ScoreFormat::Pawns, 0, MATE_BOUND + 1, None),
+            (ScoreFormat::WinProb, -MATE_BOUND - 1, 0, None),
+        ];
+        for (format, from, to, expected) in deltas {
+            assert_eq!(format.delta(from, to).as_deref(), expected, "{} from {from} to {to}", format.name());
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Plays a short game with the keyboard on a [`MemoryBackend`] for every color depth, without a terminal. It runs the
+//! same input handling and drawing as a real game, so it catches platform problems in the terminal layer on machines
+//! that have no terminal, like CI runners. All keys are waiting from the start like on a terminal that is too slow
+//! to keep up, so the game has to handle them all before drawing again. Before that the board is drawn both ways
+//! around and checked against the squares it shows:
+//! ```text
+//! $ termchess smoke
+//! ```
+
+use std::error::Error;
+
+use console::Key;
+use vecm::vec2;
+
+use crate::{
+    backend::{cells, MemoryBackend}, board::Board, colors::Colors, config::Config, game::{Game, GameEnd}, glyphs::Glyphs,
+    lang::{self, Catalog}, piece::Color, PlayerType, Pos,
+};
+
+/// Scholar's mate, White wins with the last move
+const MOVES: [((i8, i8), (i8, i8)); 7] = [
+    ((4, 1), (4, 3)), ((4, 6), (4, 4)),
+    ((5, 0), (2, 3)), ((1, 7), (2, 5)),
+    ((3, 0), (7, 4)), ((6, 7), (5, 5)),
+    ((7, 4), (5, 6)),
+];
+
+pub fn run() -> Result<(), Box<dyn Error>> {
+    // the checks compare English texts, whatever the environment asks for
+    lang::set(Catalog::embedded("en"));
+    for flip in [false, true] {
+        check_orientation(flip)?;
+    }
+    for colors in [Colors::TrueColor, Colors::Palette256, Colors::Basic16, Colors::None] {
+        let mut config = Config { autosave_games: false, ..Config::default() };
+        let term = MemoryBackend::new((40, 100), keys(vec2![0, 0]), colors);
+        let game = Game::new(vec2![0, 0], "White".to_owned(), "Black".to_owned(), Board::starting_position(), Color::White);
+        let end = crate::game(crate::render, &term, game, PlayerType::Me, PlayerType::Me, &mut config)?;
+        if !matches!(end, Some(GameEnd::Winner(Color::White))) {
+            return Err(format!("the game with {} colors didn't end in White's win", colors.name()).into());
+        }
+        // both players are at this terminal, so the title names the winner
+        if !term.title().ends_with("termchess — White won") {
+            return Err(format!("the title after the game is `{}`", term.title()).into());
+        }
+        // only the end of the game is drawn
+        if term.frames() != 1 {
+            return Err(format!("the waiting keys were drawn in {} frames instead of one", term.frames()).into());
+        }
+        // converting again only changes anything if colors the terminal can't show are left
+        let screen = term.screen();
+        if colors.convert(&screen) != screen {
+            return Err(format!("the screen has colors that {} color terminals can't show", colors.name()).into());
+        }
+    }
+    println!("\nsmoke test passed");
+    Ok(())
+}
+
+/// Draws the starting position and checks that the file letters and rank numbers name the squares drawn next to them,
+/// that a1 is dark and h1 light, and that taps hit the square drawn where they are. Whoever plays sits at the bottom
+/// with their left hand on the a-file for White and the h-file for Black, the flipped board is turned, not mirrored.
+fn check_orientation(flip: bool) -> Result<(), Box<dyn Error>> {
+    let board = Board::starting_position();
+    let mut game = Game::new(vec2![-1, -1], "White".to_owned(), "Black".to_owned(), board, Color::White);
+    game.flip_board = flip;
+    game.glyphs = Glyphs::preset("ascii").expect("ascii preset exists");
+    let side = if flip { "flipped" } else { "unflipped" };
+    let text = game.to_string();
+    let lines: Vec<_> = text.lines().map(cells).collect();
+    let corner = (lines[0][2].0, lines[1][0].0);
+    let expected = if flip { ('h', '1') } else { ('a', '8') };
+    if corner != expected {
+        return Err(format!("the {side} board has {}{} in the top left corner", corner.0, corner.1).into());
+    }
+    for row in 0..8 {
+        let line = &lines[row + 1];
+        let rank = line[0].0.to_digit(10).ok_or("no rank number in front of a rank")? as i8 - 1;
+        for column in 0..8 {
+            let x = 2 + 2 * column;
+            let file = lines[0][x].0 as i8 - b'a' as i8;
+            let square = Pos::new(file, rank);
+            let name = format!("{}{}", lines[0][x].0, line[0].0);
+            let expected = board[square].map_or(' ', |(piece, color)| cells(&game.glyphs.character(piece, color))[0].0);
+            if line[x].0 != expected {
+                return Err(format!("the {side} board draws `{}` on {name} instead of `{expected}`", line[x].0).into());
+            }
+            if game.square_at(x as i32, row as i32 + 1) != Some(square) {
+                return Err(format!("a tap on {name} of the {side} board hits another square").into());
+            }
+            let dark = line[x].1.as_deref() == Some("48;2;118;150;86");
+            match name.as_str() {
+                "a1" | "h8" if !dark => return Err(format!("{name} is light on the {side} board").into()),
+                "h1" | "a8" if dark => return Err(format!("{name} is dark on the {side} board").into()),
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The arrow keys and Enters that play `MOVES` with the cursor starting at `cursor`
+fn keys(mut cursor: Pos) -> Vec<Key> {
+    let mut keys = Vec::new();
+    for (from, to) in MOVES {
+        for square in [vec2![from.0, from.1], vec2![to.0, to.1]] {
+            let (dx, dy) = (square.x - cursor.x, square.y - cursor.y);
+            let x = if dx < 0 { Key::ArrowLeft } else { Key::ArrowRight };
+            let y = if dy < 0 { Key::ArrowDown } else { Key::ArrowUp };
+            keys.extend((0..dx.abs()).map(|_| x.clone()));
+            keys.extend((0..dy.abs()).map(|_| y.clone()));
+            keys.push(Key::Enter);
+            cursor = square;
+        }
+    }
+    keys
+}
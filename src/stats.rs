@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use console::measure_text_width;
+
+use crate::{game::Game, lang::{self, tr}, piece::Color};
+
+/// Numbers about a game for the end screen and the saved PGN. Think times are only known for games played here,
+/// games loaded from PGN show dashes instead.
+pub struct Summary {
+    /// Full moves, a move of White and the answer of Black count once
+    pub moves: usize,
+    /// Sum of all think times, None if any of them is unknown
+    pub duration: Option<Duration>,
+    pub white: PlayerStats,
+    pub black: PlayerStats,
+}
+
+#[derive(Default)]
+pub struct PlayerStats {
+    pub captures: usize,
+    pub checks: usize,
+    /// None if the player didn't move or a think time is unknown
+    pub average_think: Option<Duration>,
+    pub longest_think: Option<Duration>,
+}
+
+impl Summary {
+    pub fn new(game: &Game) -> Self {
+        let player = |color: Color| {
+            let entries: Vec<_> = game.history.iter()
+                .filter(|entry| matches!(entry.board[entry.from], Some((_, c)) if c == color))
+                .collect();
+            let thinks: Option<Vec<Duration>> = entries.iter().map(|entry| entry.think).collect();
+            let thinks = thinks.filter(|thinks| !thinks.is_empty());
+            PlayerStats {
+                captures: entries.iter().filter(|entry| entry.taken.is_some()).count(),
+                checks: entries.iter().filter(|entry| entry.san.ends_with(['+', '#'])).count(),
+                average_think: thinks.as_ref()
+                    .map(|thinks| thinks.iter().sum::<Duration>() / thinks.len() as u32),
+                longest_think: thinks.as_ref().and_then(|thinks| thinks.iter().max().copied()),
+            }
+        };
+        let thinks: Option<Vec<Duration>> = game.history.iter().map(|entry| entry.think).collect();
+        Self {
+            moves: game.history.len().div_ceil(2),
+            duration: thinks.filter(|thinks| !thinks.is_empty()).map(|thinks| thinks.iter().sum()),
+            white: player(Color::White),
+            black: player(Color::Black),
+        }
+    }
+
+    /// The summary as a small table of plain text, the labels are as wide as the longest one of the language
+    pub fn lines(&self) -> Vec<String> {
+        let time = |duration: Option<Duration>| duration.map_or_else(|| "-".to_owned(), format_duration);
+        let rows = [
+            (String::new(), lang::color(Color::White), lang::color(Color::Black)),
+            (tr!("stats.captures"), self.white.captures.to_string(), self.black.captures.to_string()),
+            (tr!("stats.checks"), self.white.checks.to_string(), self.black.checks.to_string()),
+            (tr!("stats.average_think"), time(self.white.average_think), time(self.black.average_think)),
+            (tr!("stats.longest_think"), time(self.white.longest_think), time(self.black.longest_think)),
+        ];
+        let width = rows.iter().map(|(label, _, _)| measure_text_width(label)).max().unwrap_or(0) + 2;
+        let mut lines = vec![tr!("stats.moves", moves = self.moves, time = time(self.duration))];
+        for (label, white, black) in rows {
+            let padding = " ".repeat(width - measure_text_width(&label));
+            lines.push(format!("{label}{padding}{white:>8}{black:>8}"));
+        }
+        lines
+    }
+}
+
+/// Seconds with one decimal below a minute, `m:ss` above
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{board::Board, lang::{self, tr}, notation, piece::{Color, Piece}, Pos};
+
+/// Warnings for the side to move about what the opponent could do if it was their turn: pieces that can be won by
+/// capturing them and moves that would mate. Pieces that are defended well enough aren't listed.
+pub fn find(board: &Board, turn: Color) -> Vec<String> {
+    let mut hanging = Vec::new();
+    for y in 0..8 {
+        for x in 0..8 {
+            let pos = Pos::new(x, y);
+            let Some((piece, color)) = board[pos] else { continue };
+            if color != turn || piece == Piece::King {
+                continue;
+            }
+            let loss = board.exchange(pos, !turn);
+            if loss > 0 {
+                hanging.push((loss, tr!("threats.hangs", piece = lang::piece(piece), square = notation::square(pos), loss = loss)));
+            }
+        }
+    }
+    hanging.sort_by_key(|(loss, _)| -loss);
+    let mut threats: Vec<String> = hanging.into_iter().map(|(_, text)| text).collect();
+
+    // while in check the opponent isn't the one who has to find a move
+    let king = board.find_king(turn).expect("king not found");
+    if board.threatens(king, !turn) {
+        return threats;
+    }
+    let (legal, _) = board.moves(!turn);
+    let mut mates = Vec::new();
+    for (&from, targets) in &legal {
+        for &to in targets {
+            let mut after = *board;
+            after.move_piece(from, to);
+            let king = after.find_king(turn).expect("king not found");
+            if !after.has_legal_move(turn) && after.threatens(king, !turn) {
+                mates.push(tr!("threats.mate", san = notation::san(board, &legal, from, to)));
+            }
+        }
+    }
+    // the move sets are unordered
+    mates.sort();
+    threats.extend(mates);
+    threats
+}
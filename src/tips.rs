@@ -0,0 +1,26 @@
+//! Random chess tips for the start and end screens. The embedded ones can be extended with a `tips.txt` in the config
+//! folder that has the same format: one tip per line, empty lines and lines starting with `#` are skipped.
+
+use std::{fs, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::config;
+
+const EMBEDDED: &str = include_str!("tips.txt");
+
+/// All tips, the user's file is left out if it can't be read
+pub fn load() -> Vec<String> {
+    let user = fs::read_to_string(config::config_dir().join("tips.txt")).unwrap_or_default();
+    EMBEDDED.lines()
+        .chain(user.lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
+
+pub fn random() -> String {
+    let mut tips = load();
+    // the time is random enough for picking one
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos() as usize);
+    tips.swap_remove(nanos % tips.len())
+}
@@ -0,0 +1,168 @@
+//! The onboarding of the first run: the controls, the name for a new config file and a guided sample game against the
+//! weakest CPU. It's shown while there is no config file, or with `--tutorial`.
+//!
+//! The guided game is the normal game loop with a script on top. Every step shows a line in the status and moves on
+//! once its condition holds for the game, `x` leaves the game at any point.
+
+use std::{error::Error, io::Write};
+
+use color_format::cprintln;
+use console::measure_text_width;
+use vecm::vec2;
+
+use crate::{board::Board, config::Config, engine::Engine, game::Game, input::Mode, lang::tr, piece::Color, PlayerType};
+
+/// The corner of a board with the cursor next to the keys, the keys and what they do are texts `controls.<key>.keys`
+/// and `controls.<key>`
+const CONTROLS: [(&str, &str); 13] = [
+    ("      c d e f", "move"),
+    ("   4  . . # .", "select"),
+    ("   3  . . # .", "put_back"),
+    ("   2  P P P<P", "control_map"),
+    ("   1  B Q K B", "threats"),
+    ("", "move_counts"),
+    ("", "history"),
+    ("", "undo"),
+    ("", "pause"),
+    ("", "settings"),
+    ("", "resign"),
+    ("", "online"),
+    ("", "quit"),
+];
+
+/// A step of the guided game
+struct Line {
+    /// The key of the text shown in the status
+    text: &'static str,
+    /// Whether the step is done. It also holds once the player is further along, so someone who skips ahead isn't
+    /// asked to go back.
+    done: fn(&Game) -> bool,
+}
+
+const SCRIPT: [Line; 6] = [
+    Line {
+        text: "tutorial.step1",
+        done: |game| game.cursor == vec2![4, 1] || picked_up(game) || !game.history.is_empty(),
+    },
+    Line {
+        text: "tutorial.step2",
+        done: |game| picked_up(game) || !game.history.is_empty(),
+    },
+    Line {
+        text: "tutorial.step3",
+        done: |game| !game.history.is_empty(),
+    },
+    Line {
+        text: "tutorial.step4",
+        done: |game| game.history.len() >= 3,
+    },
+    Line {
+        text: "tutorial.step5",
+        done: |game| game.influence.is_some() || game.threats.is_some() || game.history.len() >= 5,
+    },
+    Line {
+        text: "tutorial.step6",
+        done: |_| false,
+    },
+];
+
+/// Where the guided game is in its script, an index into `SCRIPT`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Step(usize);
+
+fn picked_up(game: &Game) -> bool {
+    matches!(game.mode, Mode::PieceSelected(_))
+}
+
+/// Moves the script of a guided game on and shows the line of its step, returns whether the status changed. The line
+/// is only put back when the status is empty, messages like the reason for an illegal move stay until the next move.
+pub fn update(game: &mut Game) -> bool {
+    let Some(Step(mut step)) = game.guide else { return false };
+    let before = step;
+    while step + 1 < SCRIPT.len() && (SCRIPT[step].done)(game) {
+        step += 1;
+    }
+    game.guide = Some(Step(step));
+    if step == before && game.status.is_some() {
+        return false;
+    }
+    let text = tr!(SCRIPT[step].text);
+    if game.status.as_ref() == Some(&text) {
+        return false;
+    }
+    game.status = Some(text);
+    true
+}
+
+/// Shows the onboarding, returns whether the guided game was played. The program ends after the game, a terminal that
+/// was opened for it would take the keys from the prompts that follow.
+pub fn run(config: &mut Config, backend_name: &str) -> Result<bool, Box<dyn Error>> {
+    cprintln!("#b<{}>\n", tr!("tutorial.welcome"));
+    println!("{}\n", controls());
+
+    let name = ask(&if config.name.is_empty() {
+        tr!("tutorial.name")
+    } else {
+        tr!("tutorial.name_default", name = config.name)
+    })?;
+    if !name.is_empty() {
+        config.name = name;
+    }
+    let path = Config::path();
+    if ask(&tr!("tutorial.save", path = path.display()))? != "n" {
+        match config.save() {
+            Ok(()) => println!("{}", tr!("tutorial.saved")),
+            Err(err) => println!("{}", tr!("settings.save_failed", error = err)),
+        }
+    }
+
+    if ask(&tr!("tutorial.play"))? != "y" {
+        println!();
+        return Ok(false);
+    }
+    let (term, mut render) = crate::setup_terminal(config, backend_name)?;
+    let name = if config.name.is_empty() { tr!("tutorial.you") } else { config.name.clone() };
+    let computer = tr!("player.computer", depth = 1);
+    let mut game = Game::new(vec2![0, 0], name, computer, Board::starting_position(), Color::White);
+    game.show_move_counts = config.move_counts;
+    game.glyphs = config.glyphs.clone();
+    game.compact = config.compact;
+    game.guide = Some(Step::default());
+    update(&mut game);
+    render(&game, &*term)?;
+    let cpu = PlayerType::Cpu { depth: 1, time: None, engine: Engine::spawn(), paused: false };
+    // the sample game isn't worth keeping
+    let autosave = std::mem::replace(&mut config.autosave_games, false);
+    let result = crate::game(&mut render, &*term, game, PlayerType::Me, cpu, config);
+    config.autosave_games = autosave;
+    term.show_cursor()?;
+    term.restore_title()?;
+    result?;
+
+    println!("\n\n{}", tr!("tutorial.start_again"));
+    println!("  termchess -a 3          {}", tr!("tutorial.usage_ai"));
+    println!("  termchess -c <address>  {}", tr!("tutorial.usage_connect"));
+    println!("  termchess -s            {}", tr!("tutorial.usage_server"));
+    println!("  termchess --tutorial    {}", tr!("tutorial.usage_tutorial"));
+    Ok(true)
+}
+
+/// The table of the keys, the columns are as wide as the longest text of the language
+pub fn controls() -> String {
+    let keys: Vec<String> = CONTROLS.iter().map(|(_, key)| tr!(&format!("controls.{key}.keys"))).collect();
+    let width = keys.iter().map(|keys| measure_text_width(keys)).max().unwrap_or(0);
+    let mut text = tr!("controls.title") + "\n";
+    for ((board, key), keys) in CONTROLS.iter().zip(&keys) {
+        let padding = " ".repeat(width - measure_text_width(keys));
+        text += &format!("\n{board:<13}    {keys}{padding}   {}", tr!(&format!("controls.{key}")));
+    }
+    text
+}
+
+fn ask(question: &str) -> Result<String, Box<dyn Error>> {
+    print!("{question} ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_owned())
+}
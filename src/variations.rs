@@ -0,0 +1,249 @@
+//! Move trees of replayed games. Every move can have variations, lines that were tried instead of it, and those can
+//! have variations of their own, like the RAVs of a PGN file.
+
+use color_format::cformat;
+use console::measure_text_width;
+
+use crate::piece::Color;
+
+/// A sequence of moves in SAN
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Line {
+    pub moves: Vec<Branch>,
+}
+
+/// A move with the lines that were tried instead of it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Branch {
+    pub san: String,
+    pub variations: Vec<Line>,
+}
+impl Branch {
+    pub fn new(san: String) -> Self {
+        Self { san, variations: Vec::new() }
+    }
+}
+
+/// A position in a move tree
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cursor {
+    /// The variations that were entered, starting from the main line. Each is the index of the move in the parent line
+    /// that the variation replaces and the index of the variation.
+    pub path: Vec<(usize, usize)>,
+    /// Number of moves played of the innermost line
+    pub ply: usize,
+}
+
+impl Line {
+    /// The main line's moves
+    pub fn mainline(&self) -> Vec<&str> {
+        self.moves.iter().map(|branch| branch.san.as_str()).collect()
+    }
+
+    fn line(&self, path: &[(usize, usize)]) -> &Line {
+        path.iter().fold(self, |line, &(i, v)| &line.moves[i].variations[v])
+    }
+
+    fn line_mut(&mut self, path: &[(usize, usize)]) -> &mut Line {
+        path.iter().fold(self, |line, &(i, v)| &mut line.moves[i].variations[v])
+    }
+
+    /// The moves from the start of the game to the cursor
+    pub fn moves_to(&self, cursor: &Cursor) -> Vec<&str> {
+        let mut moves = Vec::new();
+        let mut line = self;
+        for &(i, v) in &cursor.path {
+            moves.extend(line.moves[..i].iter().map(|branch| branch.san.as_str()));
+            line = &line.moves[i].variations[v];
+        }
+        moves.extend(line.moves[..cursor.ply].iter().map(|branch| branch.san.as_str()));
+        moves
+    }
+
+    /// Moves the cursor along a move. If it isn't the next move of the line or the start of one of its variations
+    /// it's added, at the end of the line or as a new variation. Returns whether the tree changed.
+    pub fn play(&mut self, cursor: &mut Cursor, san: &str) -> bool {
+        let line = self.line_mut(&cursor.path);
+        let Some(next) = line.moves.get_mut(cursor.ply) else {
+            line.moves.push(Branch::new(san.to_owned()));
+            cursor.ply += 1;
+            return true;
+        };
+        if same_move(&next.san, san) {
+            cursor.ply += 1;
+            return false;
+        }
+        let existing = next.variations.iter()
+            .position(|line| line.moves.first().is_some_and(|first| same_move(&first.san, san)));
+        let (v, added) = match existing {
+            Some(v) => (v, false),
+            None => {
+                next.variations.push(Line { moves: vec![Branch::new(san.to_owned())] });
+                (next.variations.len() - 1, true)
+            }
+        };
+        cursor.path.push((cursor.ply, v));
+        cursor.ply = 1;
+        added
+    }
+
+    /// One move forward in the current line
+    pub fn forward(&self, cursor: &mut Cursor) -> bool {
+        let possible = cursor.ply < self.line(&cursor.path).moves.len();
+        if possible {
+            cursor.ply += 1;
+        }
+        possible
+    }
+
+    /// One move back, at the start of a variation this leaves it
+    pub fn back(&self, cursor: &mut Cursor) -> bool {
+        if cursor.ply > 1 || (cursor.ply == 1 && cursor.path.is_empty()) {
+            cursor.ply -= 1;
+            true
+        } else {
+            self.exit(cursor)
+        }
+    }
+
+    /// Goes back to the parent line at the position where the variation branched off
+    pub fn exit(&self, cursor: &mut Cursor) -> bool {
+        match cursor.path.pop() {
+            Some((i, _)) => {
+                cursor.ply = i;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Enters the first variation of the next move
+    pub fn enter(&self, cursor: &mut Cursor) -> bool {
+        let has_variation = self.line(&cursor.path).moves.get(cursor.ply)
+            .is_some_and(|next| !next.variations.is_empty());
+        if has_variation {
+            cursor.path.push((cursor.ply, 0));
+            cursor.ply = 1;
+        }
+        has_variation
+    }
+
+    /// Switches to the first move of the next variation that branches off at the same move, the last one is followed
+    /// by the first
+    pub fn next_sibling(&self, cursor: &mut Cursor) -> bool {
+        let Some(&(i, v)) = cursor.path.last() else { return false };
+        let parent = self.line(&cursor.path[..cursor.path.len() - 1]);
+        let count = parent.moves[i].variations.len();
+        *cursor.path.last_mut().unwrap() = (i, (v + 1) % count);
+        cursor.ply = 1;
+        true
+    }
+
+    /// The tree as text for the move list. Variations start on a new row after the move they replace, indented and in
+    /// parentheses, rows longer than `width` are wrapped. The move the cursor is on is highlighted, if there are more
+    /// than `height` rows only the ones around it are returned.
+    pub fn display(&self, start_turn: Color, cursor: &Cursor, width: usize, height: usize) -> Vec<String> {
+        let mut rows = Rows { rows: Vec::new(), width, current_row: 0 };
+        // the highlighted move is identified by the path to its line and its index in it
+        let current = (cursor.ply > 0).then(|| (cursor.path.as_slice(), cursor.ply - 1));
+        display_line(self, start_turn, 0, 0, &mut Vec::new(), current, &mut rows);
+        let first = rows.current_row.saturating_sub(height / 2).min(rows.rows.len().saturating_sub(height));
+        rows.rows.into_iter().skip(first).take(height).collect()
+    }
+
+    /// The next move of the line the cursor is in
+    pub fn next_move(&self, cursor: &Cursor) -> Option<&str> {
+        self.line(&cursor.path).moves.get(cursor.ply).map(|branch| branch.san.as_str())
+    }
+}
+
+struct Rows {
+    rows: Vec<String>,
+    width: usize,
+    /// The row of the highlighted move
+    current_row: usize,
+}
+impl Rows {
+    /// Adds a token to the last row, or to a new one with the indent if it doesn't fit or `new_row` is set
+    fn push(&mut self, token: &str, indent: usize, new_row: bool) {
+        let fits = self.rows.last().is_some_and(|row| {
+            measure_text_width(row) + 1 + measure_text_width(token) <= self.width
+        });
+        match self.rows.last_mut() {
+            Some(row) if fits && !new_row => {
+                row.push(' ');
+                row.push_str(token);
+            }
+            _ => self.rows.push(format!("{}{token}", "  ".repeat(indent))),
+        }
+    }
+}
+
+/// Appends the rows of a line and its variations. `ply` is the number of moves played before the line from the start
+/// of the game and `path` the line's position in the tree.
+fn display_line(
+    line: &Line,
+    start_turn: Color,
+    ply: usize,
+    depth: usize,
+    path: &mut Vec<(usize, usize)>,
+    current: Option<(&[(usize, usize)], usize)>,
+    rows: &mut Rows,
+) {
+    // variations and the moves after them start on a new row
+    let mut new_row = true;
+    // Black's moves need their number at the start of a line and after variations
+    let mut needs_number = true;
+    for (i, branch) in line.moves.iter().enumerate() {
+        let number = move_number(start_turn, ply + i);
+        let mut token = if white_to_move(start_turn, ply + i) {
+            format!("{number}. ")
+        } else if needs_number {
+            format!("{number}... ")
+        } else {
+            String::new()
+        };
+        if depth > 0 && i == 0 {
+            token.insert(0, '(');
+        }
+        if current == Some((path.as_slice(), i)) {
+            token.push_str(&cformat!("#bg:rgb(238,238,238);rgb(0,0,0)<{}>", branch.san));
+        } else {
+            token.push_str(&branch.san);
+        }
+        let last = i + 1 == line.moves.len();
+        if depth > 0 && last && branch.variations.is_empty() {
+            token.push(')');
+        }
+        rows.push(&token, depth, new_row);
+        if current == Some((path.as_slice(), i)) {
+            rows.current_row = rows.rows.len() - 1;
+        }
+        new_row = !branch.variations.is_empty();
+        needs_number = new_row;
+        for (v, variation) in branch.variations.iter().enumerate() {
+            path.push((i, v));
+            display_line(variation, start_turn, ply + i, depth + 1, path, current, rows);
+            path.pop();
+        }
+        // the variations of the last move are still inside this one
+        if depth > 0 && last && !branch.variations.is_empty() {
+            rows.rows.last_mut().expect("variation was displayed").push(')');
+        }
+    }
+}
+
+/// Moves read from files can have annotations or leave out the check marks
+fn same_move(a: &str, b: &str) -> bool {
+    let strip = |san: &str| san.trim_end_matches(['+', '#', '!', '?']).to_owned();
+    strip(a) == strip(b)
+}
+
+pub fn white_to_move(start_turn: Color, ply: usize) -> bool {
+    (start_turn == Color::White) == ply.is_multiple_of(2)
+}
+
+/// The move number of the move at `ply` plies from the start
+pub fn move_number(start_turn: Color, ply: usize) -> usize {
+    1 + (ply + usize::from(start_turn == Color::Black)) / 2
+}
@@ -0,0 +1,1047 @@
+use std::{
+    cmp::Reverse, collections::{HashMap, HashSet}, sync::{atomic::{AtomicBool, AtomicI32, Ordering}, mpsc}, thread,
+    time::{Duration, Instant},
+};
+
+use vecm::vec2;
+
+use crate::{board::Board, notation, Pos, piece::{Color, Piece}};
+
+/// Scores are for the side to move, a pawn is worth about 1000
+pub type Score = i32;
+
+/// The score of being checkmated. A mate that is `n` plies away from the root scores `n` closer to zero, so
+/// every score beyond `MATE_BOUND` is a mate and material can never get there.
+pub const MATE: Score = 100_000;
+/// The furthest mate that can be told apart from material, in plies
+pub const MAX_MATE_PLIES: Score = 1000;
+pub const MATE_BOUND: Score = MATE - MAX_MATE_PLIES;
+/// The deepest `search_timed` goes when it isn't given a limit
+pub const MAX_DEPTH: usize = 64;
+/// The most positions the transposition table of a search keeps, around 40 MB. A search on several threads shares
+/// them out among the threads' tables.
+const TABLE_ENTRIES: usize = 1 << 20;
+
+// Positional bonuses of the pieces for White, written like a board with the eighth rank first. Black's are mirrored.
+// They stay far below the values of the pieces, so material always comes first.
+
+/// Pawns are pushed forward, the center ones first
+pub const PAWN_TABLE: [[Score; 8]; 8] = [
+    [   0,    0,    0,    0,    0,    0,    0,    0],
+    [ 500,  500,  500,  500,  500,  500,  500,  500],
+    [ 100,  100,  200,  300,  300,  200,  100,  100],
+    [  50,   50,  100,  250,  250,  100,   50,   50],
+    [   0,    0,    0,  200,  200,    0,    0,    0],
+    [  50,  -50, -100,    0,    0, -100,  -50,   50],
+    [  50,  100,  100, -200, -200,  100,  100,   50],
+    [   0,    0,    0,    0,    0,    0,    0,    0],
+];
+
+/// Knights go to the center and stay off the edges
+pub const KNIGHT_TABLE: [[Score; 8]; 8] = [
+    [-500, -400, -300, -300, -300, -300, -400, -500],
+    [-400, -200,    0,    0,    0,    0, -200, -400],
+    [-300,    0,  100,  150,  150,  100,    0, -300],
+    [-300,   50,  150,  200,  200,  150,   50, -300],
+    [-300,    0,  150,  200,  200,  150,    0, -300],
+    [-300,   50,  100,  150,  150,  100,   50, -300],
+    [-400, -200,    0,   50,   50,    0, -200, -400],
+    [-500, -400, -300, -300, -300, -300, -400, -500],
+];
+
+/// Bishops are developed and avoid the corners
+pub const BISHOP_TABLE: [[Score; 8]; 8] = [
+    [-200, -100, -100, -100, -100, -100, -100, -200],
+    [-100,    0,    0,    0,    0,    0,    0, -100],
+    [-100,    0,   50,  100,  100,   50,    0, -100],
+    [-100,   50,   50,  100,  100,   50,   50, -100],
+    [-100,    0,  100,  100,  100,  100,    0, -100],
+    [-100,  100,  100,  100,  100,  100,  100, -100],
+    [-100,   50,    0,    0,    0,    0,   50, -100],
+    [-200, -100, -100, -100, -100, -100, -100, -200],
+];
+
+/// Rooks like the seventh rank and the center files
+pub const ROOK_TABLE: [[Score; 8]; 8] = [
+    [   0,    0,    0,    0,    0,    0,    0,    0],
+    [  50,  100,  100,  100,  100,  100,  100,   50],
+    [ -50,    0,    0,    0,    0,    0,    0,  -50],
+    [ -50,    0,    0,    0,    0,    0,    0,  -50],
+    [ -50,    0,    0,    0,    0,    0,    0,  -50],
+    [ -50,    0,    0,    0,    0,    0,    0,  -50],
+    [ -50,    0,    0,    0,    0,    0,    0,  -50],
+    [   0,    0,    0,   50,   50,    0,    0,    0],
+];
+
+/// The queen stays a little central and off the edges
+pub const QUEEN_TABLE: [[Score; 8]; 8] = [
+    [-200, -100, -100,  -50,  -50, -100, -100, -200],
+    [-100,    0,    0,    0,    0,    0,    0, -100],
+    [-100,    0,   50,   50,   50,   50,    0, -100],
+    [ -50,    0,   50,   50,   50,   50,    0,  -50],
+    [   0,    0,   50,   50,   50,   50,    0,  -50],
+    [-100,   50,   50,   50,   50,   50,    0, -100],
+    [-100,    0,   50,    0,    0,    0,    0, -100],
+    [-200, -100, -100,  -50,  -50, -100, -100, -200],
+];
+
+/// The king stays behind its pawns, best castled. There is no endgame table yet, where it should come out.
+pub const KING_TABLE: [[Score; 8]; 8] = [
+    [-300, -400, -400, -500, -500, -400, -400, -300],
+    [-300, -400, -400, -500, -500, -400, -400, -300],
+    [-300, -400, -400, -500, -500, -400, -400, -300],
+    [-300, -400, -400, -500, -500, -400, -400, -300],
+    [-200, -300, -300, -400, -400, -300, -300, -200],
+    [-100, -200, -200, -200, -200, -200, -200, -100],
+    [ 200,  200,    0,    0,    0,    0,  200,  200],
+    [ 200,  300,  100,    0,    0,  100,  300,  200],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub from: Pos,
+    pub to: Pos,
+}
+
+/// The weights of the evaluation, which make the CPU stronger or weaker besides the depth. The values are in the
+/// engine's units, where a pawn is about 1000.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalParams {
+    pub pawn: Score,
+    pub knight: Score,
+    pub bishop: Score,
+    pub rook: Score,
+    pub queen: Score,
+    /// Bonus for every rank a pawn has come forward, on top of the pawn table
+    pub pawn_advance: Score,
+    /// How much the piece tables count in percent, 0 plays on material alone
+    pub positional_percent: Score,
+    /// Moves at the root that score less than this below the best are good enough too, one of them is played at
+    /// random so the games differ. With 0 the first best move is always played.
+    pub jitter: Score,
+}
+impl EvalParams {
+    /// The value of a piece without its position, the king has none
+    pub fn value(&self, piece: Piece) -> Score {
+        match piece {
+            Piece::King => 0,
+            Piece::Queen => self.queen,
+            Piece::Bishop => self.bishop,
+            Piece::Knight => self.knight,
+            Piece::Rook => self.rook,
+            Piece::Pawn => self.pawn,
+        }
+    }
+}
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self {
+            pawn: 1000, knight: 3000, bishop: 3000, rook: 5000, queen: 9000, pawn_advance: 0, positional_percent: 100,
+            jitter: 0,
+        }
+    }
+}
+
+/// How far a search got, reported after each move at the root
+#[derive(Clone, Copy, Debug)]
+pub struct Info {
+    /// Root moves searched so far out of all `moves`
+    pub searched: usize,
+    pub moves: usize,
+    pub nodes: u64,
+    /// The best root move so far and its score
+    pub best: Move,
+    pub score: Score,
+}
+
+pub type Progress<'a> = &'a mut dyn FnMut(&Info);
+
+/// The best move for the side to move and its score, `None` if the search was cancelled. The move is `None` if there
+/// is no legal move. `history` has the Zobrist hashes of the game's positions before this one, oldest first, so
+/// repetitions score as draws.
+pub fn search(
+    board: &Board,
+    turn: Color,
+    history: &[u64],
+    depth: usize,
+    cancelled: &AtomicBool,
+    progress: Progress,
+) -> Option<(Option<Move>, Score)> {
+    search_pv(board, turn, history, depth, 1, EvalParams::default(), 0, cancelled, progress)
+        .map(|(pv, score)| (pv.first().copied(), score))
+}
+
+/// Like `search`, but with the principal variation, the line both sides are expected to play starting with the best
+/// move. It's empty if there is no legal move. The line ends early where a position was taken from the transposition
+/// table, which only knows the next move, or at a repetition.
+///
+/// The moves at the root are divided among `threads` threads. The score is the same as with one thread, but between
+/// moves with the same score another one can be picked. Positions are evaluated with `params`. `roll` is a random
+/// number that picks between the moves within `params.jitter` of the best, the same roll picks the same move.
+#[allow(clippy::too_many_arguments)]
+pub fn search_pv(
+    board: &Board,
+    turn: Color,
+    history: &[u64],
+    depth: usize,
+    threads: usize,
+    params: EvalParams,
+    roll: u64,
+    cancelled: &AtomicBool,
+    progress: Progress,
+) -> Option<(Vec<Move>, Score)> {
+    search_root(board, turn, history, depth, threads, params, roll, cancelled, None, progress)
+}
+
+/// Like `search`, but deeper and deeper until `time` is up or `max_depth` is reached. The result is that of the deepest
+/// search that finished, one that runs out of time is thrown away. The search at depth 1 always finishes, so there is
+/// a move however short the time is.
+pub fn search_timed(
+    board: &Board,
+    turn: Color,
+    history: &[u64],
+    max_depth: usize,
+    time: Duration,
+    cancelled: &AtomicBool,
+    progress: Progress,
+) -> Option<(Option<Move>, Score)> {
+    search_timed_pv(board, turn, history, max_depth, time, 1, EvalParams::default(), 0, cancelled, progress)
+        .map(|(pv, score)| (pv.first().copied(), score))
+}
+
+/// Like `search_timed` with the principal variation of the deepest search that finished on `threads` threads and with
+/// `params` and `roll`, see `search_pv`
+#[allow(clippy::too_many_arguments)]
+pub fn search_timed_pv(
+    board: &Board,
+    turn: Color,
+    history: &[u64],
+    max_depth: usize,
+    time: Duration,
+    threads: usize,
+    params: EvalParams,
+    roll: u64,
+    cancelled: &AtomicBool,
+    progress: Progress,
+) -> Option<(Vec<Move>, Score)> {
+    let deadline = Instant::now() + time;
+    let mut result = None;
+    for depth in 1..=max_depth.max(1) {
+        let limit = (depth > 1).then_some(deadline);
+        let searched = search_root(board, turn, history, depth, threads, params, roll, cancelled, limit, &mut *progress);
+        let Some((pv, score)) = searched else {
+            if cancelled.load(Ordering::Relaxed) {
+                return None;
+            }
+            break;
+        };
+        let done = pv.is_empty();
+        result = Some((pv, score));
+        // without a move or with a mate found searching deeper doesn't change anything
+        if done || score.abs() > MATE_BOUND || Instant::now() >= deadline {
+            break;
+        }
+    }
+    result
+}
+
+/// Score of the position for the side to move, searched to the given depth
+pub fn analyze(board: &Board, turn: Color, depth: usize) -> Score {
+    analyze_counted(board, turn, depth).0
+}
+
+/// Like `analyze`, also returns the number of positions the search looked at
+pub fn analyze_counted(board: &Board, turn: Color, depth: usize) -> (Score, u64) {
+    let cancelled = AtomicBool::new(false);
+    let mut progress = |_: &Info| {};
+    let mut state = State::new(board, turn, &[], &cancelled, &mut progress);
+    let (_, score) = find_best(board, turn, depth, 0, -MATE, MATE, &mut state);
+    (score, state.nodes)
+}
+
+/// Moves that are played without a search, read from lines like `<fen> <uci move> <weight>`. Positions are found by
+/// their Zobrist hash, so a position reached by other moves finds the same book moves. Empty lines and lines starting
+/// with `#` are skipped. A move is picked with a chance that goes with its weight, one with weight 0 never is.
+/// ```text
+/// # the king's pawn twice as often as the queen's
+/// rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 e2e4 2
+/// rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 d2d4 1
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct OpeningBook {
+    positions: HashMap<u64, Vec<(Move, u32)>>,
+}
+impl OpeningBook {
+    /// Reads a book, the error names the first line that isn't valid
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut book = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |what: &str| format!("line {}: {what} in `{line}`", i + 1);
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let [fen @ .., uci, weight] = fields.as_slice() else {
+                return Err(invalid("expected a FEN, a move and a weight"));
+            };
+            let weight = weight.parse().map_err(|_| invalid("invalid weight"))?;
+            let (board, turn) = Board::from_fen(&fen.join(" ")).ok_or_else(|| invalid("invalid FEN"))?;
+            let (from, to, _) = notation::parse_uci(&board, uci)
+                .filter(|&(from, _, _)| matches!(board[from], Some((_, color)) if color == turn))
+                .ok_or_else(|| invalid("illegal move"))?;
+            book.positions.entry(board.zobrist(turn)).or_default().push((Move { from, to }, weight));
+        }
+        Ok(book)
+    }
+
+    /// Whether there is no position in the book
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// The book moves of the position with their weights
+    pub fn moves(&self, board: &Board, turn: Color) -> &[(Move, u32)] {
+        self.positions.get(&board.zobrist(turn)).map_or(&[], Vec::as_slice)
+    }
+
+    /// Picks a book move for the position, `roll` is a random number that decides between them. `None` if the
+    /// position isn't in the book, the search has to find a move then. Moves that aren't legal here, which can only
+    /// come from two positions with the same hash, are left out.
+    pub fn pick(&self, board: &Board, turn: Color, roll: u64) -> Option<Move> {
+        let legal = board.moves(turn).0;
+        let moves: Vec<_> = self.moves(board, turn).iter()
+            .filter(|(mov, weight)| *weight > 0 && legal.get(&mov.from).is_some_and(|moves| moves.contains(&mov.to)))
+            .collect();
+        let total: u64 = moves.iter().map(|(_, weight)| u64::from(*weight)).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = roll % total;
+        for &&(mov, weight) in &moves {
+            match roll.checked_sub(u64::from(weight)) {
+                Some(rest) => roll = rest,
+                None => return Some(mov),
+            }
+        }
+        unreachable!("the roll is below the total weight")
+    }
+}
+
+/// A position that was searched before, found by its Zobrist hash
+#[derive(Clone, Copy)]
+struct Entry {
+    depth: usize,
+    score: Score,
+    best: Move,
+}
+
+/// What the search carries from one position to the next
+struct State<'a> {
+    cancelled: &'a AtomicBool,
+    nodes: u64,
+    progress: Progress<'a>,
+    /// Hashes of the game's positions up to the root followed by the positions on the way to the current one
+    positions: Vec<u64>,
+    /// Where the root is in `positions`
+    root: usize,
+    /// When a timed search has to stop
+    deadline: Option<Instant>,
+    /// The deadline passed during the search
+    timed_out: bool,
+    /// The positions searched so far with the depth they were searched to. Only a search to the same depth is
+    /// replaced by an entry, so the scores are those of a search without the table.
+    table: Option<HashMap<u64, Entry>>,
+    /// The most entries the table takes
+    table_limit: usize,
+    /// How often a position scored as a repetition, scores below one depend on the way to the position
+    repetitions: u64,
+    /// Whether the moves of the main search are searched with the likely best first, captures always are
+    ordered: bool,
+    params: EvalParams,
+    /// Picks between the moves within the jitter of the best at the root
+    roll: u64,
+}
+impl<'a> State<'a> {
+    fn new(board: &Board, turn: Color, history: &[u64], cancelled: &'a AtomicBool, progress: Progress<'a>) -> Self {
+        let mut positions = history.to_vec();
+        positions.push(board.zobrist(turn));
+        Self {
+            cancelled, nodes: 0, progress, positions, root: history.len(), deadline: None, timed_out: false,
+            table: Some(HashMap::new()), table_limit: TABLE_ENTRIES, repetitions: 0,
+            ordered: true, params: EvalParams::default(), roll: 0,
+        }
+    }
+
+    /// Whether the search has to stop, the tree it leaves is unfinished
+    fn stopped(&mut self) -> bool {
+        self.timed_out |= self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        self.timed_out || self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Whether the position is a draw by repetition. A third time in the game is one by the rules. Coming back to a
+    /// position of the search is counted as one too, a side that can force it could also repeat it a third time.
+    fn repeated(&self, hash: u64) -> bool {
+        self.positions[self.root..].contains(&hash)
+            || self.positions[..self.root].iter().filter(|&&earlier| earlier == hash).count() >= 2
+    }
+}
+
+/// Searches the position with its moves divided among `threads` threads, `None` if the search was cancelled or ran
+/// out of time. Each thread takes every `threads`th move of the ordered list, so each gets some of the likely best
+/// moves, and searches them with its own state. The best score found so far is shared, so the leaves of a search of
+/// depth 0 are cut off like in a single thread. With a jitter the bound is lowered by it, so the scores of the moves
+/// close to the best are exact.
+#[allow(clippy::too_many_arguments)]
+fn search_root(
+    board: &Board,
+    turn: Color,
+    history: &[u64],
+    depth: usize,
+    threads: usize,
+    params: EvalParams,
+    roll: u64,
+    cancelled: &AtomicBool,
+    deadline: Option<Instant>,
+    progress: Progress,
+) -> Option<(Vec<Move>, Score)> {
+    let (all_moves, count) = board.moves(turn);
+    if threads <= 1 || count <= 1 {
+        let mut state = State::new(board, turn, history, cancelled, progress);
+        state.deadline = deadline;
+        state.params = params;
+        state.roll = roll;
+        let result = find_best(board, turn, depth, 0, -MATE, MATE, &mut state);
+        // the move of a stopped search is based on an unfinished tree
+        return (!state.timed_out && !cancelled.load(Ordering::Relaxed)).then_some(result);
+    }
+    let moves = order(board, turn, all_moves, true, &params);
+    let threads = threads.min(count);
+    let shared = AtomicI32::new(Score::MIN);
+    let (sender, receiver) = mpsc::channel();
+    let (best, mut close, timed_out) = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|first| {
+            let (sender, moves, shared) = (sender.clone(), &moves, &shared);
+            scope.spawn(move || {
+                let mut progress = |_: &Info| {};
+                let mut state = State::new(board, turn, history, cancelled, &mut progress);
+                state.deadline = deadline;
+                state.params = params;
+                state.table_limit = TABLE_ENTRIES / threads;
+                let mut best = Score::MIN;
+                for (index, &(from, to)) in moves.iter().enumerate().skip(first).step_by(threads) {
+                    let nodes = state.nodes;
+                    let bound = best.max(shared.load(Ordering::Relaxed)).saturating_sub(params.jitter.max(0));
+                    let (line, score) = search_move(board, turn, Move { from, to }, depth, bound, MATE, 0, &mut state);
+                    if state.stopped() {
+                        break;
+                    }
+                    // a score that doesn't beat the bound may only be the bound, a move that has it is known already
+                    let line = (score > bound).then_some(line);
+                    if line.is_some() {
+                        best = best.max(score);
+                        shared.fetch_max(score, Ordering::Relaxed);
+                    }
+                    let _ = sender.send((index, score, line, state.nodes - nodes));
+                }
+                state.timed_out
+            })
+        }).collect();
+        drop(sender);
+
+        // the root itself counts as a node like in `find_best`
+        let (mut searched, mut nodes) = (0, 1);
+        let mut best: Option<(usize, Score, Vec<Move>)> = None;
+        // the moves that were close to the best when they were searched, only kept with a jitter
+        let mut close = Vec::new();
+        for (index, score, line, searched_nodes) in receiver {
+            searched += 1;
+            nodes += searched_nodes;
+            if let Some(line) = line {
+                if params.jitter > 0 {
+                    close.push((index, score, line.clone()));
+                }
+                // between equal scores the first move wins like in a single thread
+                let better = |&(best_index, best_score, _): &(usize, Score, _)| {
+                    (score, Reverse(index)) > (best_score, Reverse(best_index))
+                };
+                if best.as_ref().is_none_or(better) {
+                    best = Some((index, score, line));
+                }
+            }
+            if let Some((index, score, _)) = &best {
+                let (from, to) = moves[*index];
+                (progress)(&Info { searched, moves: count, nodes, best: Move { from, to }, score: *score });
+            }
+        }
+        // every worker has to be joined, `any` would stop at the first one that timed out
+        #[allow(clippy::unnecessary_fold)]
+        let timed_out = workers.into_iter()
+            .fold(false, |timed_out, worker| worker.join().expect("search thread panicked") || timed_out);
+        (best, close, timed_out)
+    });
+    if timed_out || cancelled.load(Ordering::Relaxed) {
+        return None;
+    }
+    let best = best.expect("a finished search has a best move");
+    // the threads report in any order, sorted the same roll picks the same move
+    close.sort_by_key(|&(index, ..)| index);
+    let (index, score, line) = pick_close(close, best.1, params.jitter, roll).unwrap_or(best);
+    let (from, to) = moves[index];
+    let mut pv = vec![Move { from, to }];
+    pv.extend(line);
+    Some((pv, score))
+}
+
+/// The principal variation and its score, the line is empty without a legal move. Only a score between `alpha` and
+/// `beta` is exact, the search stops at the first move that reaches `beta` and a score of `alpha` or below only says
+/// that no move beats it. Only exact scores go into the table.
+fn find_best(
+    board: &Board,
+    turn: Color,
+    depth: usize,
+    level: usize,
+    alpha: Score,
+    beta: Score,
+    state: &mut State,
+) -> (Vec<Move>, Score) {
+    if state.stopped() {
+        return (Vec::new(), 0);
+    }
+    state.nodes += 1;
+    let (all_moves, count) = board.moves(turn);
+
+    if count == 0 {
+        let king = board.find_king(turn).expect("ai lost the king");
+        if board.threatens(king, !turn) {
+            // a mate that comes sooner scores higher for the side that mates
+            return (Vec::new(), -(MATE - level as Score));
+        } else {
+            return (Vec::new(), 0);
+        }
+    }
+
+    let key = *state.positions.last().expect("the root is in the positions");
+    // the move of the entry has to be legal here, so a position with the same hash can't lead to an illegal move
+    if let Some(entry) = state.table.as_ref().and_then(|table| table.get(&key)) {
+        if entry.depth == depth && all_moves.get(&entry.best.from).is_some_and(|moves| moves.contains(&entry.best.to)) {
+            return (vec![entry.best], from_table(entry.score, level));
+        }
+    }
+    let repetitions = state.repetitions;
+
+    let mut best_move = (Move { from: Pos::zero(), to: Pos::zero() }, Score::MIN);
+    // the line after the best move
+    let mut best_line = Vec::new();
+
+    // the moves close to the best so far, only kept at the root with a jitter
+    let jitter = if level == 0 { state.params.jitter.max(0) } else { 0 };
+    let mut close = Vec::new();
+
+    for (checked, (from, to)) in order(board, turn, all_moves, state.ordered, &state.params).into_iter().enumerate() {
+        let bound = best_move.1.max(alpha).saturating_sub(jitter);
+        let (line, score) = search_move(board, turn, Move { from, to }, depth, bound, beta, level, state);
+        if jitter > 0 && score > bound {
+            close.push((Move { from, to }, score, line.clone()));
+        }
+        if score > best_move.1 {
+            best_move = (Move { from, to }, score);
+            best_line = line;
+        }
+        if level == 0 {
+            let info = Info { searched: checked + 1, moves: count, nodes: state.nodes, best: best_move.0, score: best_move.1 };
+            (state.progress)(&info);
+        }
+        // the opponent avoids this position, it has a better move before it
+        if best_move.1 >= beta {
+            break;
+        }
+    }
+    if let Some((mov, score, line)) = pick_close(close, best_move.1, jitter, state.roll) {
+        best_move = (mov, score);
+        best_line = line;
+    }
+    let exact = alpha < best_move.1 && best_move.1 < beta;
+    if exact && state.repetitions == repetitions && !state.stopped() {
+        if let Some(table) = state.table.as_mut().filter(|table| table.len() < state.table_limit) {
+            table.insert(key, Entry { depth, score: to_table(best_move.1, level), best: best_move.0 });
+        }
+    }
+    best_line.insert(0, best_move.0);
+    (best_line, best_move.1)
+}
+
+/// Picks one of the moves that score less than `jitter` below `best` with the roll, `None` without a jitter. The moves
+/// are in the order they were searched in.
+fn pick_close<T>(
+    close: Vec<(T, Score, Vec<Move>)>,
+    best: Score,
+    jitter: Score,
+    roll: u64,
+) -> Option<(T, Score, Vec<Move>)> {
+    if jitter <= 0 {
+        return None;
+    }
+    let mut close: Vec<_> = close.into_iter().filter(|&(_, score, _)| score > best.saturating_sub(jitter)).collect();
+    if close.is_empty() {
+        return None;
+    }
+    let index = (roll % close.len() as u64) as usize;
+    Some(close.swap_remove(index))
+}
+
+/// Mate scores count the plies from the root, in the table they count them from the position itself so an entry fits
+/// wherever the position comes up again
+fn to_table(score: Score, level: usize) -> Score {
+    match score {
+        score if score > MATE_BOUND => score + level as Score,
+        score if score < -MATE_BOUND => score - level as Score,
+        score => score,
+    }
+}
+
+/// The score of an entry for a position `level` plies from the root, see `to_table`
+fn from_table(score: Score, level: usize) -> Score {
+    match score {
+        score if score > MATE_BOUND => score - level as Score,
+        score if score < -MATE_BOUND => score + level as Score,
+        score => score,
+    }
+}
+
+/// The score of a move for the side that makes it and the line after it. Only a score between `alpha` and `beta` has to
+/// be exact, the others only have to be on the same side of the bound.
+#[allow(clippy::too_many_arguments)]
+fn search_move(
+    board: &Board,
+    turn: Color,
+    mov: Move,
+    depth: usize,
+    alpha: Score,
+    beta: Score,
+    level: usize,
+    state: &mut State,
+) -> (Vec<Move>, Score) {
+    let mut new_board = *board;
+    new_board.move_piece(mov.from, mov.to);
+    let hash = new_board.zobrist(!turn);
+    if state.repeated(hash) {
+        // a winning side has to make progress, a losing one is happy to repeat
+        state.nodes += 1;
+        state.repetitions += 1;
+        (Vec::new(), 0)
+    } else if depth == 0 {
+        // only a move that beats the best so far matters, so the reply just has to be good enough to refute it
+        (Vec::new(), -quiesce(&new_board, !turn, -beta, -alpha.max(-MATE), state))
+    } else {
+        state.positions.push(hash);
+        let (line, enemy_score) = find_best(&new_board, !turn, depth-1, level + 1, -beta, -alpha.max(-MATE), state);
+        state.positions.pop();
+        (line, -enemy_score)
+    }
+}
+
+/// The moves of a line like the principal variation in SAN, played from `board`
+pub fn line_san(board: &Board, line: &[Move]) -> Vec<String> {
+    let mut board = *board;
+    line.iter().map(|mov| {
+        let san = board.to_san(mov.from, mov.to, None);
+        board.move_piece(mov.from, mov.to);
+        san
+    }).collect()
+}
+
+/// Searches only captures from the end of the search until the position is quiet, so the search doesn't stop right
+/// before a recapture. The side to move can always stand pat instead of taking, so the score is at least the
+/// evaluation. The score is exact between `alpha` and `beta`, a score outside of them is cut off to the bound.
+fn quiesce(board: &Board, turn: Color, mut alpha: Score, beta: Score, state: &mut State) -> Score {
+    state.nodes += 1;
+    let stand_pat = evaluate(board, turn, &state.params);
+    if stand_pat >= beta {
+        return beta;
+    }
+    alpha = alpha.max(stand_pat);
+
+    // always ordered, `State::ordered` only turns the ordering of the main search off
+    for (from, to) in order(board, turn, board.captures(turn), true, &state.params) {
+        let mut new_board = *board;
+        new_board.move_piece(from, to);
+        let score = -quiesce(&new_board, !turn, -beta, -alpha, state);
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+/// The piece a move takes, a pawn moving sideways onto an empty square takes en passant
+fn taken(board: &Board, from: Pos, to: Pos) -> Option<Piece> {
+    let pawn = matches!(board[from], Some((Piece::Pawn, _)));
+    board[to].map(|(piece, _)| piece).or((pawn && from.x != to.x).then_some(Piece::Pawn))
+}
+
+/// The moves as a list, if `ordered` with the most valuable victims taken by the least valuable attackers first and
+/// the moves that take nothing last. A king only takes pieces that nobody defends, so it's the best attacker. Ties
+/// are in board order, which keeps the map's order out of the search.
+fn order(
+    board: &Board,
+    turn: Color,
+    moves: HashMap<Pos, HashSet<Pos>>,
+    ordered: bool,
+    params: &EvalParams,
+) -> Vec<(Pos, Pos)> {
+    let mut moves: Vec<_> = moves.into_iter().flat_map(|(from, to)| to.into_iter().map(move |to| (from, to))).collect();
+    if ordered {
+        moves.sort_unstable_by_key(|&(from, to)| {
+            let victim = taken(board, from, to).map_or(0, |piece| piece_score(piece, to, !turn, params));
+            let attacker = board[from].map_or(0, |(piece, _)| piece_score(piece, from, turn, params));
+            (Reverse(victim), attacker, from.y, from.x, to.y, to.x)
+        });
+    }
+    moves
+}
+
+/// The score of the position for the side to move without searching, weighted with `params`
+pub fn evaluate(board: &Board, turn: Color, params: &EvalParams) -> Score {
+    let mut score = 0;
+    for (y, row) in board.iter().enumerate() {
+        for (x, piece) in row.iter().enumerate() {
+            if let Some((piece, color)) = *piece {
+                let mut piece_score = piece_score(piece, vec2![x as _, y as _], color, params);
+                if color != turn {
+                    piece_score *= -1;
+                }
+                score += piece_score;
+            }
+            
+        }
+    }
+    score
+}
+fn piece_score(piece: Piece, pos: Pos, color: Color, params: &EvalParams) -> i32 {
+    // ranks forward from the pawn's starting rank
+    let advance = if color == Color::White { pos.y - 1 } else { 6 - pos.y };
+    let advance = if piece == Piece::Pawn { params.pawn_advance * Score::from(advance) } else { 0 };
+    params.value(piece) + advance + positional_score(piece, pos, color) * params.positional_percent / 100
+}
+
+/// The bonus of the piece's table for standing on `pos`
+pub fn positional_score(piece: Piece, pos: Pos, color: Color) -> Score {
+    let table = match piece {
+        Piece::King => &KING_TABLE,
+        Piece::Queen => &QUEEN_TABLE,
+        Piece::Bishop => &BISHOP_TABLE,
+        Piece::Knight => &KNIGHT_TABLE,
+        Piece::Rook => &ROOK_TABLE,
+        Piece::Pawn => &PAWN_TABLE,
+    };
+    // the tables start with the eighth rank, which is Black's first
+    let row = if color == Color::White { 7 - pos.y } else { pos.y };
+    table[row as usize][pos.x as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicBool, time::Duration};
+
+    use vecm::vec2;
+
+    use super::{
+        analyze, analyze_counted, evaluate, find_best, line_san, search, search_pv, search_timed, EvalParams, Info,
+        Move, OpeningBook, Score, State, MATE, MAX_DEPTH,
+    };
+    use crate::{board::Board, piece::Color, Pos};
+
+    const SCHOLARS_MATE: &str = "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    /// Like `analyze_counted` with the best move and without the transposition table, which must not change the score
+    fn analyze_without_table(board: &Board, turn: Color, depth: usize) -> (Option<Move>, Score, u64) {
+        let cancelled = AtomicBool::new(false);
+        let mut progress = |_: &Info| {};
+        let mut state = State::new(board, turn, &[], &cancelled, &mut progress);
+        state.table = None;
+        let (pv, score) = find_best(board, turn, depth, 0, -MATE, MATE, &mut state);
+        (pv.first().copied(), score, state.nodes)
+    }
+
+    /// Like `analyze_counted`, but the moves of the main search are searched in the order the move generator returns
+    /// them. The captures after it are still ordered, so the node count shows how much the ordering saves in the main
+    /// search.
+    fn analyze_unordered(board: &Board, turn: Color, depth: usize) -> (Score, u64) {
+        let cancelled = AtomicBool::new(false);
+        let mut progress = |_: &Info| {};
+        let mut state = State::new(board, turn, &[], &cancelled, &mut progress);
+        state.ordered = false;
+        let (_, score) = find_best(board, turn, depth, 0, -MATE, MATE, &mut state);
+        (score, state.nodes)
+    }
+
+    /// The transposition table saves work where moves in another order reach the same position, but the search finds
+    /// the same scores as without it and the same move where only one is best
+    #[test]
+    fn transpositions() {
+        let positions = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 3),
+            (KIWIPETE, 2),
+            ("8/8/8/4k3/8/3r4/8/4K1Q1 w - - 0 1", 3),
+        ];
+        for (fen, depth) in positions {
+            let (board, turn) = Board::from_fen(fen).unwrap();
+            let (score, nodes) = analyze_counted(&board, turn, depth);
+            let (_, expected, all_nodes) = analyze_without_table(&board, turn, depth);
+            assert!(
+                score == expected && nodes <= all_nodes,
+                "`{fen}` scores {score} in {nodes} nodes with the table, {expected} in {all_nodes} without",
+            );
+            assert!(depth != 3 || nodes < all_nodes, "the table saved nothing in `{fen}`");
+        }
+
+        // Qxf7# is the only mate
+        let (board, turn) = Board::from_fen(SCHOLARS_MATE).unwrap();
+        let (without, _, _) = analyze_without_table(&board, turn, 3);
+        let with = search(&board, turn, &[], 3, &AtomicBool::new(false), &mut |_| {}).and_then(|(best, _)| best);
+        let mate = Move { from: vec2![7, 4], to: vec2![5, 6] };
+        assert!(
+            with == Some(mate) && without == Some(mate),
+            "the mate was found as {with:?} with the table and {without:?} without",
+        );
+    }
+
+    /// Trying captures of valuable pieces first finds good scores early, which cuts off more of the main search in a
+    /// position full of captures. The captures after it are ordered either way. The order must not change the score.
+    #[test]
+    fn move_ordering() {
+        let (board, turn) = Board::from_fen(KIWIPETE).unwrap();
+        let (score, nodes) = analyze_counted(&board, turn, 1);
+        let (unordered_score, unordered_nodes) = analyze_unordered(&board, turn, 1);
+        assert!(
+            score == unordered_score && nodes < unordered_nodes,
+            "ordered the search scores {score} in {nodes} nodes, unordered {unordered_score} in {unordered_nodes}",
+        );
+    }
+
+    /// The best move and its score of a search without a history, which must find one
+    fn best(board: &Board, turn: Color, history: &[u64], depth: usize) -> (Move, Score) {
+        match search(board, turn, history, depth, &AtomicBool::new(false), &mut |_| {}) {
+            Some((Some(best), score)) => (best, score),
+            result => panic!("the search of `{}` ended with {result:?}", board.to_fen(turn, 0, 1)),
+        }
+    }
+
+    /// A timed search without any time still finishes depth 1 and has a move, one with plenty of time and a depth
+    /// limit ends up with the move of a search of that depth. A cancelled one has no result.
+    #[test]
+    fn timed_search() {
+        let board = Board::starting_position();
+        let (legal, _) = board.moves(Color::White);
+        let running = AtomicBool::new(false);
+        match search_timed(&board, Color::White, &[], MAX_DEPTH, Duration::ZERO, &running, &mut |_| {}) {
+            Some((Some(best), _)) if legal.get(&best.from).is_some_and(|moves| moves.contains(&best.to)) => {}
+            result => panic!("a timed search without time ended with {result:?}"),
+        }
+
+        let (board, turn) = Board::from_fen(SCHOLARS_MATE).unwrap();
+        let timed = search_timed(&board, turn, &[], 2, Duration::from_secs(60), &running, &mut |_| {});
+        let fixed = search(&board, turn, &[], 2, &running, &mut |_| {});
+        assert!(timed.is_some() && timed == fixed, "a timed search with a depth limit ended with {timed:?}");
+
+        let cancelled = AtomicBool::new(true);
+        let result = search_timed(&board, turn, &[], 3, Duration::from_secs(60), &cancelled, &mut |_| {});
+        assert!(result.is_none(), "a cancelled timed search had a result");
+    }
+
+    /// The pawn on d5 looks free when the search stops right after taking it, the captures after the end of the search
+    /// show that the queen gets taken back
+    #[test]
+    fn quiescence() {
+        let (board, turn) = Board::from_fen("6k1/8/4p3/3p4/8/8/8/3Q2K1 w - - 0 1").unwrap();
+        for depth in [0, 2] {
+            let (best, score) = best(&board, turn, &[], depth);
+            assert!(best.to != vec2![3, 4] && score > 0, "at depth {depth} the search plays {best:?} with {score}");
+        }
+    }
+
+    /// The piece tables make the search develop toward the center, a knight on f3 is worth more than one on the rim
+    #[test]
+    fn development() {
+        let score = |from: Pos, to: Pos| {
+            let mut board = Board::starting_position();
+            board.move_piece(from, to);
+            -analyze(&board, Color::Black, 1)
+        };
+        let (nf3, na3) = (score(vec2![6, 0], vec2![5, 2]), score(vec2![1, 0], vec2![0, 2]));
+        assert!(nf3 > na3, "Nf3 scores {nf3}, not more than Na3 with {na3}");
+    }
+
+    /// The principal variation starts with the best move and is a line of legal moves as long as the search is deep
+    #[test]
+    fn principal_variation() {
+        let running = AtomicBool::new(false);
+        let board = Board::starting_position();
+        let (pv, score) =
+            search_pv(&board, Color::White, &[], 2, 1, EvalParams::default(), 0, &running, &mut |_| {}).unwrap();
+        let best = search(&board, Color::White, &[], 2, &running, &mut |_| {});
+        assert!(
+            pv.len() == 3 && best == Some((pv.first().copied(), score)),
+            "the principal variation {pv:?} doesn't go with the search's move {best:?}",
+        );
+        let (mut board, mut turn) = (board, Color::White);
+        for mov in &pv {
+            let legal = board.moves(turn).0.get(&mov.from).is_some_and(|moves| moves.contains(&mov.to));
+            assert!(legal, "the principal variation {pv:?} has an illegal move");
+            board.move_piece(mov.from, mov.to);
+            turn = !turn;
+        }
+
+        let (board, turn) = Board::from_fen(SCHOLARS_MATE).unwrap();
+        let (pv, _) = search_pv(&board, turn, &[], 2, 1, EvalParams::default(), 0, &running, &mut |_| {}).unwrap();
+        assert_eq!(line_san(&board, &pv), ["Qxf7#"]);
+    }
+
+    /// Dividing the root moves among threads gives the same scores as searching them in one thread, also at depth 0
+    /// where the threads share the best score to cut off the replies. The move can differ between equal scores.
+    #[test]
+    fn parallel_search() {
+        let running = AtomicBool::new(false);
+        let positions = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 2),
+            (KIWIPETE, 0),
+            (KIWIPETE, 1),
+            (SCHOLARS_MATE, 2),
+            ("8/8/8/4k3/8/3r4/8/4K1Q1 w - - 0 1", 2),
+        ];
+        for (fen, depth) in positions {
+            let (board, turn) = Board::from_fen(fen).unwrap();
+            let search = |threads| {
+                search_pv(&board, turn, &[], depth, threads, EvalParams::default(), 0, &running, &mut |_| {}).unwrap()
+            };
+            let (_, expected) = search(1);
+            for threads in [2, 3, 8] {
+                let (pv, score) = search(threads);
+                let legal = pv.first().is_some_and(|best| {
+                    board.moves(turn).0.get(&best.from).is_some_and(|moves| moves.contains(&best.to))
+                });
+                assert!(
+                    score == expected && legal,
+                    "`{fen}` at depth {depth} on {threads} threads scores {score} with {pv:?} instead of {expected}",
+                );
+            }
+        }
+    }
+
+    /// A search deep enough to see slower mates still mates right away on the back rank, a sooner mate scores higher
+    #[test]
+    fn mate_distance() {
+        let (board, turn) = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        let (best, score) = best(&board, turn, &[], 3);
+        let mut after = board;
+        after.move_piece(best.from, best.to);
+        assert!(
+            score == MATE - 1 && after.moves(!turn).1 == 0,
+            "the back rank mate was played as {best:?} with score {score}",
+        );
+    }
+
+    /// The search knows the positions the game went through. With a queen against a rook the side that is ahead
+    /// doesn't go back to a position a third time and the one that is behind goes for it.
+    #[test]
+    fn repetition() {
+        let after = |board: &Board, from: Pos, to: Pos, turn: Color| {
+            let mut board = *board;
+            board.move_piece(from, to);
+            board.zobrist(!turn)
+        };
+        let (board, turn) = Board::from_fen("8/8/8/4k3/8/3r4/8/4K1Q1 w - - 0 1").unwrap();
+
+        // all moves but Qg6 lead to positions that were there twice already
+        let progress = Move { from: vec2![6, 0], to: vec2![6, 5] };
+        let mut history = Vec::new();
+        for (&from, moves) in &board.moves(turn).0 {
+            for &to in moves.iter().filter(|&&to| Move { from, to } != progress) {
+                history.extend([after(&board, from, to, turn); 2]);
+            }
+        }
+        let (played, score) = best(&board, turn, &history, 2);
+        assert!(played == progress && score > 0, "White repeated with {played:?} instead of Qg6, score {score}");
+
+        // Black is a rook down and takes the draw by going back with Ke6
+        let mut board = board;
+        board.move_piece(vec2![6, 0], vec2![7, 0]);
+        let repeat = Move { from: vec2![4, 4], to: vec2![4, 5] };
+        let history = [after(&board, repeat.from, repeat.to, !turn); 2];
+        let (played, score) = best(&board, !turn, &history, 2);
+        assert!(played == repeat && score == 0, "Black played {played:?} with score {score} instead of Ke6");
+    }
+
+    /// Book moves are picked by their weight. Lines that aren't valid are reported with their number.
+    #[test]
+    fn opening_book() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let text = format!("# openings\n\n{start} e2e4 3\n{start}  d2d4\t1\n{start} a2a3 0\n");
+        let book = OpeningBook::parse(&text).unwrap();
+        let board = Board::starting_position();
+        let (e4, d4) = (Move { from: vec2![4, 1], to: vec2![4, 3] }, Move { from: vec2![3, 1], to: vec2![3, 3] });
+        let picks: Vec<_> = (0..4).map(|roll| book.pick(&board, Color::White, roll)).collect();
+        assert_eq!(picks, [Some(e4), Some(e4), Some(e4), Some(d4)]);
+        assert_eq!(book.moves(&board, Color::White).len(), 3);
+        let mut after = board;
+        after.move_piece(e4.from, e4.to);
+        assert!(book.pick(&after, Color::Black, 0).is_none(), "the book has a move after 1. e4");
+        for (text, line) in [
+            (format!("{start} e2e5 1"), "line 1"),
+            (format!("\n{start} e2e4 often"), "line 2"),
+            ("8/8/8 e2e4 1".to_owned(), "line 1"),
+            (format!("{start} e7e5 1"), "line 1"),
+        ] {
+            match OpeningBook::parse(&text) {
+                Err(err) if err.starts_with(line) => {}
+                result => panic!("the book `{text}` was read as {result:?}"),
+            }
+        }
+    }
+
+    /// A cheaper rook makes the side with more rooks worse off
+    #[test]
+    fn eval_params() {
+        let (board, turn) = Board::from_fen("4k3/8/8/8/8/8/8/R3K2R w - - 0 1").unwrap();
+        let cheap_rooks = EvalParams { rook: 3000, ..EvalParams::default() };
+        let (full, cheap) = (evaluate(&board, turn, &EvalParams::default()), evaluate(&board, turn, &cheap_rooks));
+        assert_eq!(full - cheap, 4000, "two rooks score {full} and {cheap} when they're worth less");
+    }
+
+    /// Without a jitter the roll changes nothing. With one different rolls play different moves that are all close to
+    /// the best, the same roll always the same one.
+    #[test]
+    fn jitter() {
+        let running = AtomicBool::new(false);
+        let board = Board::starting_position();
+        let search = |params, threads, roll| {
+            search_pv(&board, Color::White, &[], 1, threads, params, roll, &running, &mut |_| {}).unwrap()
+        };
+        let exact = EvalParams::default();
+        let (best, score) = search(exact, 1, 0);
+        for roll in [1, 12345, u64::MAX] {
+            let unchanged = search(exact, 1, roll) == (best.clone(), score);
+            assert!(unchanged, "without a jitter the roll {roll} changed the move");
+        }
+
+        let jittered = EvalParams { jitter: 1000, ..exact };
+        for threads in [1, 3] {
+            let mut moves = Vec::new();
+            for roll in 0..16 {
+                let (pv, jittered_score) = search(jittered, threads, roll);
+                let again = search(jittered, threads, roll);
+                assert!(
+                    jittered_score > score - jittered.jitter && again == (pv.clone(), jittered_score),
+                    "the roll {roll} on {threads} threads played {pv:?} with {jittered_score}",
+                );
+                if !moves.contains(&pv[0]) {
+                    moves.push(pv[0]);
+                }
+            }
+            assert!(moves.len() >= 2, "16 rolls on {threads} threads all played {moves:?}");
+        }
+    }
+}
@@ -0,0 +1,1150 @@
+use std::{ops::{Index, ControlFlow}, collections::{HashMap, HashSet}};
+
+use vecm::vec2;
+
+use crate::{piece::{Piece, Color}, Pos, moves::{moves, attacks}, notation::{self, square, parse_square}};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Castle {
+    pub short: bool,
+    pub long: bool,
+}
+impl Castle {
+    fn new() -> Self {
+        Self { short: true, long: true }
+    }
+
+    /// (white, black)
+    fn from_fen(fen: &str) -> Option<(Self, Self)> {
+        let mut white = Castle { short: false, long: false };
+        let mut black = Castle { short: false, long: false };
+
+        if fen == "-" { return Some((white, black)) }
+        
+        for c in fen.chars() {
+            match c {
+                'k' => black.short = true,
+                'q' => black.long = true,
+                'K' => white.short = true,
+                'Q' => white.long = true,
+                _ => return None
+            }
+        }
+        Some((white, black))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Board {
+    // rows then files
+    board: [[Option<(Piece, Color)>; 8]; 8],
+    moved_pawn: Option<Pos>,
+    white_castle: Castle,
+    black_castle: Castle,
+}
+/// A board is written as a FEN. It doesn't know whose turn it is, the side to move is the opponent of the pawn that
+/// can be taken en passant, or White if there is none.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Board {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let turn = match self.moved_pawn.and_then(|pawn| self[pawn]) {
+            Some((_, color)) => !color,
+            None => Color::White,
+        };
+        serializer.serialize_str(&self.to_fen(turn, 0, 1))
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Board {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fen = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Board::from_fen(&fen)
+            .map(|(board, _)| board)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid FEN `{fen}`")))
+    }
+}
+/// Whether a move is legal, or the first reason found why it isn't
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveLegality {
+    Legal,
+    /// A square is outside of the board, only sent by broken or malicious clients
+    OffTheBoard,
+    NoPieceThere,
+    NotYourPiece,
+    /// The target is the piece's own square or has a piece of the same color on it
+    OwnPieceThere,
+    PieceCantMoveThatWay,
+    /// A pawn moved diagonally to a square without anything to take
+    NothingToCapture,
+    /// A piece is in the way, for pawns also the piece on the square in front of them
+    PathBlocked,
+    WouldLeaveKingInCheck,
+    /// The king is in check and the move doesn't change that
+    DoesntEscapeCheck,
+    /// The king or the rook already moved, or the rook was taken
+    CastlingRightLost,
+    KingCantCastleOutOfCheck,
+    KingWouldCastleThroughCheck,
+}
+
+impl Index<Pos> for Board {
+    type Output = Option<(Piece, Color)>;
+
+    fn index(&self, index: Pos) -> &Self::Output {
+        &self.board[index.y as usize][index.x as usize]
+    }
+}
+impl Board {
+    pub fn starting_position() -> Self {
+        let mut board = [[None; 8]; 8];
+        
+        board[6] = [Some((Piece::Pawn, Color::Black)); 8];
+        board[1] = [Some((Piece::Pawn, Color::White)); 8];
+
+        let first_rank = {
+            use Piece::*;
+
+            [Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook]
+        };
+        for (i, piece) in first_rank.into_iter().enumerate() {
+            board[7][i] = Some((piece, Color::Black));
+            board[0][i] = Some((piece, Color::White));
+        }
+        Self {
+            board,
+            moved_pawn: None,
+            white_castle: Castle::new(),
+            black_castle: Castle::new()
+        }
+    }
+
+    pub fn from_fen(fen: &str) -> Option<(Self, Color)> {
+        Self::from_fen_with_counters(fen).map(|(board, turn, _, _)| (board, turn))
+    }
+
+    /// Like `from_fen`, also returns the halfmove clock and the fullmove number, which the board doesn't keep
+    pub fn from_fen_with_counters(fen: &str) -> Option<(Self, Color, u32, u32)> {
+        fn piece(c: char) -> Option<Piece> {
+            Some(match c {
+                'k' => Piece::King,
+                'p' => Piece::Pawn,
+                'n' => Piece::Knight,
+                'b' => Piece::Bishop,
+                'r' => Piece::Rook,
+                'q' => Piece::Queen,
+                _ => return None
+            })
+        }
+
+        let mut sections = fen.split_ascii_whitespace();
+
+        let pieces = sections.next()?;
+
+        let mut board = [[None; 8]; 8];
+
+        let mut file = 0;
+        let mut rank = 7;
+
+        for c in pieces.chars() {
+            match c {
+                '/' => {
+                    file = 0;
+                    rank -= 1;
+                    if rank < 0 { return None }
+                }
+                '0'..='9' => {
+                    file += c as u8 - b'0';
+                }
+                'a'..='z' | 'A'..='Z' => {
+                    if file > 7 { return None }
+                    board[rank as usize][file as usize] = Some((
+                        piece(c.to_ascii_lowercase())?,
+                        if c.is_ascii_lowercase() { Color::Black } else { Color::White }
+                    ));
+                    file += 1;
+                }
+                _ => return None
+            }
+        }
+
+        let turn = match sections.next()? {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return None,
+        };
+        
+        let (white_castle, black_castle) = Castle::from_fen(sections.next()?)?;
+
+        // the target square is the one the pawn skipped over, the pawn itself is one further. It has to be on the
+        // third or sixth rank behind a pawn of the side that just moved.
+        let moved_pawn = match sections.next()? {
+            "-" => None,
+            s => {
+                let target = parse_square(s)?;
+                let (rank, forward) = if turn == Color::White { (5, -1) } else { (2, 1) };
+                let pawn = target + vec2![0, forward];
+                if target.y != rank || board[pawn.y as usize][pawn.x as usize] != Some((Piece::Pawn, !turn)) {
+                    return None;
+                }
+                Some(pawn)
+            }
+        };
+
+        // some tools leave out the move counters, they default to the values of a new game. Only both can be left out.
+        let (halfmoves, fullmoves): (u32, u32) = match (sections.next(), sections.next()) {
+            (Some(halfmoves), Some(fullmoves)) => (halfmoves.parse().ok()?, fullmoves.parse().ok()?),
+            (None, None) => (0, 1),
+            _ => return None,
+        };
+
+        if sections.next().is_some() { return None }
+
+        let none = Castle { short: false, long: false };
+        let mut position = Self { board, moved_pawn, white_castle: none, black_castle: none };
+        // a right to castle with a rook that isn't there would bring the rook back
+        position.set_castle(Color::White, white_castle).ok()?;
+        position.set_castle(Color::Black, black_castle).ok()?;
+        Some((position, turn, halfmoves, fullmoves))
+    }
+
+    /// The position as FEN with all six fields, `from_fen` reads it back into the same board. The board doesn't count
+    /// moves, so the counters are given.
+    pub fn to_fen(&self, turn: Color, halfmove: u32, fullmove: u32) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for square in self.board[rank] {
+                if let Some((piece, color)) = square {
+                    if empty > 0 {
+                        fen.push((b'0' + empty) as char);
+                        empty = 0;
+                    }
+                    let c = piece.letter();
+                    fen.push(if color == Color::White { c } else { c.to_ascii_lowercase() });
+                } else {
+                    empty += 1;
+                }
+            }
+            if empty > 0 {
+                fen.push((b'0' + empty) as char);
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(if turn == Color::White { " w " } else { " b " });
+
+        let castle_len = fen.len();
+        for (enabled, c) in [
+            (self.white_castle.short, 'K'),
+            (self.white_castle.long, 'Q'),
+            (self.black_castle.short, 'k'),
+            (self.black_castle.long, 'q'),
+        ] {
+            if enabled { fen.push(c) }
+        }
+        if fen.len() == castle_len {
+            fen.push('-');
+        }
+
+        // the target square is the one the pawn skipped over
+        match self.moved_pawn {
+            Some(pawn) => {
+                let dir = if turn == Color::White { 1 } else { -1 };
+                fen.push(' ');
+                fen.push_str(&square(pawn + vec2![0, dir]));
+            }
+            None => fen.push_str(" -"),
+        }
+
+        fen.push_str(&format!(" {halfmove} {fullmove}"));
+        fen
+    }
+
+    pub fn moves(&self, turn: Color) -> (HashMap<Pos, HashSet<Pos>>, usize) {
+        let mut all_moves = HashMap::new();
+
+        let mut total_moves = 0;
+        for y in 0..8 {
+            for x in 0..8 {
+                let pos = vec2![x, y];
+                if let Some((piece, color)) = self[pos] {
+                    if color == turn {
+                        let mut piece_moves = moves(self, piece, pos, color, false);
+                        piece_moves.retain(|to_pos| !self.in_check_after(pos, *to_pos, turn));
+                        total_moves += piece_moves.len();
+                        all_moves.insert(pos, piece_moves);
+                    }
+                }
+            }
+        }
+        (all_moves, total_moves)
+    }
+
+    /// The legal moves of `turn` that take a piece, en passant included. Only the captures are checked for legality,
+    /// which makes this much cheaper than filtering `moves`.
+    pub fn captures(&self, turn: Color) -> HashMap<Pos, HashSet<Pos>> {
+        let mut captures = HashMap::new();
+        for y in 0..8 {
+            for x in 0..8 {
+                let from = vec2![x, y];
+                let Some((piece, color)) = self[from] else { continue };
+                if color != turn { continue }
+                // castling never captures
+                let piece_captures: HashSet<Pos> = moves(self, piece, from, color, true)
+                    .into_iter()
+                    .filter(|&to| self[to].is_some() || (piece == Piece::Pawn && from.x != to.x))
+                    .filter(|&to| !self.in_check_after(from, to, turn))
+                    .collect();
+                if !piece_captures.is_empty() {
+                    captures.insert(from, piece_captures);
+                }
+            }
+        }
+        captures
+    }
+
+    /// Calls `f` with each legal move of `turn` until it returns `ControlFlow::Break`. Legality is only checked for
+    /// the moves that are actually visited, so breaking early skips most of the work of `moves`.
+    pub fn for_each_move(&self, turn: Color, mut f: impl FnMut(Pos, Pos) -> ControlFlow<()>) -> ControlFlow<()> {
+        for y in 0..8 {
+            for x in 0..8 {
+                let from = vec2![x, y];
+                let Some((piece, color)) = self[from] else { continue };
+                if color != turn { continue }
+                for to in moves(self, piece, from, color, false) {
+                    if !self.in_check_after(from, to, turn) {
+                        f(from, to)?;
+                    }
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Number of leaf nodes of the legal move tree of `turn` with the given depth. Every piece a pawn can be promoted
+    /// to counts as its own move, like in the perft counts of other engines.
+    pub fn perft(&self, turn: Color, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut nodes = 0;
+        let _ = self.for_each_move(turn, |from, to| {
+            let promotions = if self.is_promotion(from, to) { &Piece::PROMOTIONS[..] } else { &[Piece::Queen] };
+            for &promotion in promotions {
+                if depth == 1 {
+                    nodes += 1;
+                } else {
+                    let mut after = *self;
+                    after.move_piece_promote(from, to, promotion);
+                    nodes += after.perft(!turn, depth - 1);
+                }
+            }
+            ControlFlow::Continue(())
+        });
+        nodes
+    }
+
+    /// Returns true if `turn` has at least one legal move, stopping at the first one found
+    pub fn has_legal_move(&self, turn: Color) -> bool {
+        self.for_each_move(turn, |_, _| ControlFlow::Break(())).is_break()
+    }
+
+    /// Whether `turn` can play the move, and why not if it can't. Legal moves are the ones of the move generator, the
+    /// others are looked at again to find the reason.
+    pub fn classify_move(&self, from: Pos, to: Pos, turn: Color) -> MoveLegality {
+        let inside = |pos: Pos| (0..8).contains(&pos.x) && (0..8).contains(&pos.y);
+        if !inside(from) || !inside(to) {
+            return MoveLegality::OffTheBoard;
+        }
+        let Some((piece, color)) = self[from] else { return MoveLegality::NoPieceThere };
+        if color != turn {
+            return MoveLegality::NotYourPiece;
+        }
+        if from == to || matches!(self[to], Some((_, c)) if c == turn) {
+            return MoveLegality::OwnPieceThere;
+        }
+        let in_check = self.find_king(turn).is_some_and(|king| self.threatens(king, !turn));
+        if moves(self, piece, from, turn, false).contains(&to) {
+            return match (self.in_check_after(from, to, turn), in_check) {
+                (false, _) => MoveLegality::Legal,
+                (true, true) => MoveLegality::DoesntEscapeCheck,
+                (true, false) => MoveLegality::WouldLeaveKingInCheck,
+            };
+        }
+
+        let d = to - from;
+        let rank = if turn == Color::White { 0 } else { 7 };
+        match piece {
+            Piece::King if from == vec2![4, rank] && to.y == rank && d.x.abs() == 2 => {
+                let long = d.x < 0;
+                let castle = self.can_castle(turn);
+                let between = if long { 1..4 } else { 5..7 };
+                if !(if long { castle.long } else { castle.short }) {
+                    MoveLegality::CastlingRightLost
+                } else if between.into_iter().any(|x| self[vec2![x, rank]].is_some()) {
+                    MoveLegality::PathBlocked
+                } else if in_check {
+                    MoveLegality::KingCantCastleOutOfCheck
+                } else if self.attackers(from + vec2![d.x.signum(), 0], !turn) > 0 {
+                    MoveLegality::KingWouldCastleThroughCheck
+                } else {
+                    MoveLegality::WouldLeaveKingInCheck
+                }
+            }
+            Piece::Pawn => {
+                let forward = if turn == Color::White { 1 } else { -1 };
+                let start = if turn == Color::White { 1 } else { 6 };
+                match (d.x.abs(), d.y * forward) {
+                    (1, 1) => MoveLegality::NothingToCapture,
+                    (0, 1) => MoveLegality::PathBlocked,
+                    (0, 2) if from.y == start => MoveLegality::PathBlocked,
+                    _ => MoveLegality::PieceCantMoveThatWay,
+                }
+            }
+            Piece::Queen | Piece::Rook | Piece::Bishop => {
+                let straight = (d.x == 0) != (d.y == 0);
+                let diagonal = d.x.abs() == d.y.abs();
+                let shape = match piece {
+                    Piece::Queen => straight || diagonal,
+                    Piece::Rook => straight,
+                    _ => diagonal,
+                };
+                // a move of the piece's shape that isn't generated has something between the squares
+                if shape { MoveLegality::PathBlocked } else { MoveLegality::PieceCantMoveThatWay }
+            }
+            Piece::King | Piece::Knight => MoveLegality::PieceCantMoveThatWay,
+        }
+    }
+
+    /// Moves a piece and returns the piece it took. A pawn that reaches the last rank becomes a queen.
+    pub fn move_piece(&mut self, from: Pos, to: Pos) -> Option<Piece> {
+        self.move_piece_promote(from, to, Piece::Queen)
+    }
+
+    /// The move in standard algebraic notation like `Nbd2`, `exd5`, `O-O` or `e8=Q+`, see `notation::san`. A pawn
+    /// that reaches the last rank becomes `promotion`, or a queen without one.
+    pub fn to_san(&self, from: Pos, to: Pos, promotion: Option<Piece>) -> String {
+        let (_, color) = self[from].expect("no piece to generate SAN for");
+        notation::san_promote(self, &self.moves(color).0, from, to, promotion.unwrap_or(Piece::Queen))
+    }
+
+    /// The move in the notation of UCI like `e2e4` or `e7e8n`, see `notation::uci`. A pawn that reaches the last rank
+    /// becomes `promotion`, or a queen without one.
+    pub fn move_to_uci(&self, from: Pos, to: Pos, promotion: Option<Piece>) -> String {
+        let mut s = square(from) + &square(to);
+        if self.is_promotion(from, to) {
+            s.push(promotion.unwrap_or(Piece::Queen).letter().to_ascii_lowercase());
+        }
+        s
+    }
+
+    /// Plays a move given in the notation of UCI, see `notation::parse_uci`. Returns None without changing the board
+    /// if the string isn't a legal move, otherwise the piece that was taken, if any.
+    pub fn apply_uci(&mut self, s: &str) -> Option<Option<Piece>> {
+        let (from, to, promotion) = notation::parse_uci(self, s)?;
+        Some(self.move_piece_promote(from, to, promotion.unwrap_or(Piece::Queen)))
+    }
+
+    /// Whether the move takes a pawn to the last rank, where it has to become another piece
+    pub fn is_promotion(&self, from: Pos, to: Pos) -> bool {
+        matches!(self[from], Some((Piece::Pawn, _))) && (to.y == 0 || to.y == 7)
+    }
+
+    /// Like `move_piece`, a pawn that reaches the last rank becomes `promotion`
+    pub fn move_piece_promote(&mut self, from: Pos, to: Pos, promotion: Piece) -> Option<Piece> {
+        let Some((piece, color)) = self[from] else { panic!("Tried to move nonexistant piece") };
+        // a rook leaving its corner or getting taken there loses its castling right
+        for corner in [from, to] {
+            match (corner.x, corner.y) {
+                (0, 7) => self.black_castle.long = false,
+                (7, 7) => self.black_castle.short = false,
+                (0, 0) => self.white_castle.long = false,
+                (7, 0) => self.white_castle.short = false,
+                _ => {}
+            }
+        }
+        if piece == Piece::King {
+            match color {
+                Color::Black => {
+                    if to == vec2![2, 7] && self.black_castle.long {
+                        self.board[7][3] = self.board[7][0];
+                        self.board[7][0] = None;
+                    } else if to == vec2![6, 7] && self.black_castle.short {
+                        self.board[7][5] = self.board[7][7];
+                        self.board[7][7] = None;
+                    }
+                    self.black_castle.short = false;
+                    self.black_castle.long = false;
+                }
+                Color::White => {
+                    if to == vec2![2, 0] && self.white_castle.long {
+                        self.board[0][3] = self.board[0][0];
+                        self.board[0][0] = None;
+                    } else if to == vec2![6, 0] && self.white_castle.short {
+                        self.board[0][5] = self.board[0][7];
+                        self.board[0][7] = None;
+                    }
+                    self.white_castle.short = false;
+                    self.white_castle.long = false;
+                }
+            }
+        } else if piece == Piece::Pawn && !self.is_promotion(from, to) {
+            let y_dir = if color == Color::White { 1 } else { -1 };
+            if let Some(moved_pawn) = self.moved_pawn {
+                if to == moved_pawn + vec2![0, y_dir] {
+                    let (taken, _) = self.board[moved_pawn.y as usize][moved_pawn.x as usize]
+                        .take()
+                        .expect("moved pawn internal tracking error");  
+                        self.board[to.y as usize][to.x as usize] = self[from];
+                        self.board[from.y as usize][from.x as usize] = None;
+                        self.moved_pawn = None;
+                    return Some(taken);
+                }
+            }
+        }
+        let taken = self[to];
+        if let Some((_, taken_color)) = taken {
+            assert_ne!(color, taken_color, "Tried to move into own piece");
+        }
+        let moved = if self.is_promotion(from, to) {
+            assert!(Piece::PROMOTIONS.contains(&promotion), "Tried to promote to a {}", promotion.name());
+            Some((promotion, color))
+        } else {
+            self[from]
+        };
+        self.board[to.y as usize][to.x as usize] = moved;
+        self.board[from.y as usize][from.x as usize] = None;
+        // only a double step can be taken en passant
+        self.moved_pawn = (piece == Piece::Pawn && (to.y - from.y).abs() == 2).then_some(to);
+
+        taken.map(|(piece, _)| piece)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = [Option<(Piece, Color)>; 8]> + ExactSizeIterator {
+        self.board.into_iter()
+    }
+
+    // (long castle, short castle)
+    pub fn can_castle(&self, color: Color) -> Castle {
+        match color {
+            Color::Black => self.black_castle,
+            Color::White => self.white_castle,
+        }
+    }
+
+    /// Replaces the castling rights of a color. Fails if a right is enabled while the king or the rook isn't on its
+    /// starting square.
+    pub fn set_castle(&mut self, color: Color, castle: Castle) -> Result<(), String> {
+        let rank = if color == Color::White { 0 } else { 7 };
+        if (castle.short || castle.long) && self.board[rank][4] != Some((Piece::King, color)) {
+            return Err(format!("{color:?} can't castle because the king isn't on e{}", rank + 1));
+        }
+        for (enabled, file, side) in [(castle.short, 7, "short"), (castle.long, 0, "long")] {
+            if enabled && self.board[rank][file] != Some((Piece::Rook, color)) {
+                return Err(format!("{color:?} can't castle {side} without a rook on {}", square(vec2![file as i8, rank as i8])));
+            }
+        }
+        match color {
+            Color::White => self.white_castle = castle,
+            Color::Black => self.black_castle = castle,
+        }
+        Ok(())
+    }
+
+    /// Zobrist hash of the position with `turn` to move, the same position reached by different moves gets the same
+    /// hash. Like in FEN the en passant file only counts when a pawn can actually take there.
+    pub fn zobrist(&self, turn: Color) -> u64 {
+        // the keys are stored in the game database's index, so they must never change
+        fn key(index: u64) -> u64 {
+            // splitmix64
+            let mut z = index.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15);
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        }
+        let mut hash = 0;
+        for (rank, row) in self.board.iter().enumerate() {
+            for (file, square) in row.iter().enumerate() {
+                if let Some((piece, color)) = square {
+                    let kind = *color as u64 * 6 + *piece as u64;
+                    hash ^= key(kind * 64 + (rank * 8 + file) as u64);
+                }
+            }
+        }
+        let rights = [self.white_castle.short, self.white_castle.long, self.black_castle.short, self.black_castle.long];
+        for (i, right) in rights.into_iter().enumerate() {
+            if right {
+                hash ^= key(768 + i as u64);
+            }
+        }
+        if turn == Color::Black {
+            hash ^= key(772);
+        }
+        if let Some(pawn) = self.moved_pawn {
+            let takes = [-1, 1].into_iter()
+                .map(|dx| pawn + vec2![dx, 0])
+                .any(|from| (0..8).contains(&from.x) && self[from] == Some((Piece::Pawn, turn)));
+            if takes {
+                hash ^= key(773 + pawn.x as u64);
+            }
+        }
+        hash
+    }
+
+    /// Whether neither side can mate anymore: only the kings are left, one of them with a knight or a bishop, or each
+    /// with a bishop and both bishops on squares of the same color
+    pub fn has_insufficient_material(&self) -> bool {
+        // the minor pieces with the color of their square
+        let mut minors = Vec::new();
+        for (y, row) in self.board.iter().enumerate() {
+            for (x, square) in row.iter().enumerate() {
+                match *square {
+                    None | Some((Piece::King, _)) => {}
+                    Some((piece @ (Piece::Knight | Piece::Bishop), color)) => minors.push((piece, color, (x + y) % 2)),
+                    Some(_) => return false,
+                }
+            }
+        }
+        match minors[..] {
+            [] | [_] => true,
+            [(Piece::Bishop, first, first_square), (Piece::Bishop, second, second_square)] => {
+                first != second && first_square == second_square
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether `color` has more than its king and at most one knight or bishop. A player whose time ran out only loses
+    /// if the opponent has this, otherwise the game is a draw.
+    pub fn has_mating_material(&self, color: Color) -> bool {
+        let mut minors = 0;
+        for square in self.board.iter().flatten() {
+            match *square {
+                Some((Piece::Knight | Piece::Bishop, piece_color)) if piece_color == color => minors += 1,
+                Some((Piece::King, _)) | None => {}
+                Some((_, piece_color)) if piece_color == color => return true,
+                Some(_) => {}
+            }
+        }
+        minors > 1
+    }
+
+    pub fn find_king(&self, color: Color) -> Option<Pos> {
+        for (y, row) in self.board.iter().enumerate() {
+            for (x, piece) in row.iter().enumerate() {
+                if let Some((Piece::King, king_color)) = piece {
+                    if *king_color == color {
+                        return Some(Pos::new(x as i8, y as i8))
+                    }
+                }
+            }
+        }
+        None
+    }
+    pub fn in_check_after(&self, from: Pos, to: Pos, color: Color) -> bool {
+        assert!(self[from].unwrap().1 == color);
+
+        // board after the move to find checks
+        let mut board_copy = *self;
+        board_copy.move_piece(from, to);
+        
+        let king_pos = board_copy.find_king(color).expect("No king found");
+
+        board_copy.threatens(king_pos, !color)
+    }
+
+    /// Whether a piece of `color` can move to `pos`. Only right for occupied squares, pawns can't move diagonally to
+    /// empty ones, use `attackers` for those.
+    pub fn threatens(&self, pos: Pos, color: Color) -> bool {
+        for y in 0..8 {
+            for x in 0..8 {
+                let other_pos = vec2![x, y];
+                if let Some((other_piece, other_color)) = self[other_pos] {
+                    if other_color == color {
+                        // castling never captures
+                        let moves = moves(self, other_piece, other_pos, other_color, true);
+                        if moves.contains(&pos) {
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        
+        false
+    }
+
+    /// Number of pieces of the given color attacking the square
+    pub fn attackers(&self, pos: Pos, color: Color) -> usize {
+        let mut count = 0;
+        for y in 0..8 {
+            for x in 0..8 {
+                let from = vec2![x, y];
+                if let Some((piece, piece_color)) = self[from] {
+                    if piece_color == color && from != pos && attacks(self, piece, from, color, pos) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    /// Static exchange evaluation: the material in pawns `color` wins by capturing on `target` and then recapturing
+    /// with the least valuable piece for as long as that pays off, which both sides may stop doing. Pins are ignored.
+    /// Zero if `color` doesn't attack the square.
+    pub fn exchange(&self, target: Pos, color: Color) -> i32 {
+        let mut board = *self;
+        let Some((mut victim, _)) = board[target] else { return 0 };
+        // material gained by each capture in the sequence
+        let mut gains = Vec::new();
+        let mut side = color;
+        loop {
+            let attacker = (0..64)
+                .map(|i| vec2![i % 8, i / 8])
+                .filter_map(|from: Pos| match board[from] {
+                    Some((piece, c)) if c == side && from != target && attacks(&board, piece, from, side, target) => {
+                        Some((piece, from))
+                    }
+                    _ => None,
+                })
+                .min_by_key(|(piece, _)| piece.value());
+            let Some((piece, from)) = attacker else { break };
+            gains.push(victim.value());
+            victim = piece;
+            board.board[target.y as usize][target.x as usize] = board[from];
+            board.board[from.y as usize][from.x as usize] = None;
+            side = !side;
+        }
+        let Some(first) = gains.first() else { return 0 };
+        // every capture after the first is only made if it wins something
+        let rest = gains[1..].iter().rev().fold(0, |after, gain| (gain - after).max(0));
+        first - rest
+    }
+
+    pub fn moved_pawn(&self) -> Option<Pos> {
+        self.moved_pawn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{hint::black_box, time::Instant};
+
+    use vecm::vec2;
+
+    use super::{Board, MoveLegality};
+    use crate::{notation, piece::{Color, Piece}, Pos};
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+
+    /// Games in SAN that go through castling on both sides, en passant for both colors, promotions and rooks taken in
+    /// their corners, the board state FEN has to carry along
+    const CORPUS: [(&str, &str); 6] = [
+        ("Ruy Lopez", "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7 Re1 b5 Bb3 d6 c3 O-O h3 Nb8 d4 Nbd7"),
+        ("en passant", "e4 c5 Nf3 c4 d4 cxd3 Bxd3 d5 e5 f5 exf6 Nxf6 O-O e5 Nc3 Bd6 Bg5 O-O"),
+        ("long castling", "d4 d5 Nc3 Nc6 Bf4 Bf5 Qd2 Qd7 O-O-O O-O-O f3 f6 Kb1 Kb8"),
+        ("rook taken in its corner", "b3 g5 Bb2 g4 Bxh8 Nf6 Bxf6 exf6 Nc3 Bd6 e4 Ke7"),
+        ("promotions", "h4 g5 hxg5 h6 gxh6 Bg7 hxg7 Nf6 gxh8=Q+ Ng8 a4 b5 axb5 a6 bxa6 Bb7 axb7 Nc6 bxa8=N Qb8"),
+        ("Scholar's mate", "e4 e5 Bc4 Nc6 Qh5 Nf6 Qxf7#"),
+    ];
+
+    /// The move counters can be left out, they are those of a new game then. Anything else missing or left over breaks
+    /// the FEN.
+    #[test]
+    fn fen_fields() {
+        let counted = Board::from_fen(&format!("{START} w KQkq - 3 17"));
+        assert!(matches!(counted, Some((_, Color::White))), "six fields weren't read");
+        let uncounted = Board::from_fen(&format!("{START} w KQkq -"));
+        assert!(uncounted == counted, "four fields weren't read as the same position");
+        let broken = [
+            format!("{START} w KQkq"),
+            format!("{START} w KQkq - 0"),
+            format!("{START} w KQkq - 0 1 0"),
+            format!("{START} w KQkq - zero 1"),
+            format!("{START} x KQkq - 0 1"),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1".to_owned(),
+        ];
+        for fen in broken {
+            assert!(Board::from_fen(&fen).is_none(), "the broken FEN `{fen}` was read");
+        }
+    }
+
+    /// After every ply of the corpus games the position written as FEN and read back has the same legal moves and is
+    /// written as the same FEN, so no board state is lost on the way
+    #[test]
+    fn fen_round_trips() {
+        for (name, moves) in CORPUS {
+            let (mut board, mut turn) = (Board::starting_position(), Color::White);
+            for (ply, san) in moves.split(' ').enumerate() {
+                let ply = ply + 1;
+                let (from, to, promotion) = notation::parse_san(&board, turn, san)
+                    .unwrap_or_else(|| panic!("{san} isn't legal at ply {ply} of {name}"));
+                board.move_piece_promote(from, to, promotion.unwrap_or(Piece::Queen));
+                turn = !turn;
+                let fen = board.to_fen(turn, 0, 1);
+                let (read, read_turn) =
+                    Board::from_fen(&fen).unwrap_or_else(|| panic!("`{fen}` of {name} didn't parse"));
+                let written = read.to_fen(read_turn, 0, 1);
+                let (moves, read_moves) = (board.moves(turn), read.moves(read_turn));
+                assert!(
+                    written == fen && read_moves.0 == moves.0,
+                    "{name}, ply {ply} ({san}): `{fen}` with {} legal moves was read back as `{written}` with {}",
+                    moves.1, read_moves.1,
+                );
+            }
+        }
+    }
+
+    /// Castling of both colors to both sides on a rank with only the kings and rooks. It is stopped by each piece
+    /// between the king and the rook, by each attacked square the king starts on, passes or lands on, also when only a
+    /// pawn attacks it, and by a lost right. The b-file may be attacked as long as it's empty.
+    #[test]
+    fn castling_rules() {
+        // the kings and all four rooks on their squares, with `extra` pieces given as FEN letters
+        let position = |extra: &[(i8, i8, char)], rights: &str, turn: Color| {
+            let mut squares = [[None; 8]; 8];
+            for (file, rank, letter) in [(0, 0, 'R'), (4, 0, 'K'), (7, 0, 'R'), (0, 7, 'r'), (4, 7, 'k'), (7, 7, 'r')]
+                .into_iter()
+                .chain(extra.iter().copied())
+            {
+                squares[rank as usize][file as usize] = Some(letter);
+            }
+            let rows: Vec<String> = squares.iter().rev().map(|row| {
+                let mut fen = String::new();
+                let mut empty = 0;
+                for square in row {
+                    match square {
+                        Some(letter) => {
+                            if empty > 0 {
+                                fen.push_str(&empty.to_string());
+                            }
+                            empty = 0;
+                            fen.push(*letter);
+                        }
+                        None => empty += 1,
+                    }
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                }
+                fen
+            }).collect();
+            let turn = if turn == Color::White { 'w' } else { 'b' };
+            let fen = format!("{} {turn} {rights} - 0 1", rows.join("/"));
+            Board::from_fen(&fen).unwrap_or_else(|| panic!("invalid FEN {fen}")).0
+        };
+        let castle = |color: Color, long: bool| {
+            let rank = if color == Color::White { 0 } else { 7 };
+            (vec2![4, rank], vec2![if long { 2 } else { 6 }, rank])
+        };
+        let expect = |board: &Board, color: Color, long: bool, legal: bool, case: &str| {
+            let (king, target) = castle(color, long);
+            let listed = board.moves(color).0.get(&king).is_some_and(|moves| moves.contains(&target));
+            let side = if long { "long" } else { "short" };
+            assert!(
+                listed == legal,
+                "{color:?} castling {side} {case}: listed {listed}, expected {legal} ({})",
+                board.to_fen(color, 0, 1),
+            );
+        };
+        let file_name = |file: i8| char::from(b'a' + file as u8);
+
+        for color in [Color::White, Color::Black] {
+            let rank = if color == Color::White { 0 } else { 7 };
+            let forward = if color == Color::White { 1 } else { -1 };
+            let own = |letter: char| if color == Color::White { letter.to_ascii_uppercase() } else { letter };
+            let enemy = |letter: char| if color == Color::White { letter } else { letter.to_ascii_uppercase() };
+            for long in [false, true] {
+                let other = !long;
+                let step = if long { -1 } else { 1 };
+                let between: &[i8] = if long { &[1, 2, 3] } else { &[5, 6] };
+
+                let open = position(&[], "KQkq", color);
+                expect(&open, color, long, true, "on an open rank");
+                // the rook lands next to the king, its corner is left empty
+                let (king, target) = castle(color, long);
+                let mut castled = open;
+                castled.move_piece(king, target);
+                let (rook, corner) = (vec2![4 + step, rank], vec2![if long { 0 } else { 7 }, rank]);
+                assert!(
+                    castled[target] == Some((Piece::King, color)) && castled[rook] == Some((Piece::Rook, color))
+                        && castled[corner].is_none(),
+                    "{color:?} castled {} to {}", if long { "long" } else { "short" }, castled.to_fen(!color, 1, 1),
+                );
+
+                for &file in between {
+                    for blocker in [own('n'), enemy('n')] {
+                        let board = position(&[(file, rank, blocker)], "KQkq", color);
+                        expect(&board, color, long, false, &format!("past {blocker} on the {}-file", file_name(file)));
+                    }
+                }
+
+                // the king's square, the one it passes and the one it lands on, each attacked by a rook on its file or
+                // a pawn next to it on the side of the rook
+                for i in 0..3 {
+                    let file = 4 + step * i;
+                    let rook = position(&[(file, rank + 3 * forward, enemy('r'))], "KQkq", color);
+                    expect(&rook, color, long, false, &format!("with a rook on the {}-file", file_name(file)));
+                    if file != 4 {
+                        let case = format!("with a rook only on the {}-file of the other side", file_name(file));
+                        expect(&rook, color, other, true, &case);
+                    }
+                    let pawn = position(&[(file + step, rank + forward, enemy('p'))], "KQkq", color);
+                    expect(&pawn, color, long, false, &format!("with {} attacked by a pawn", file_name(file)));
+                }
+                if long {
+                    // the rook passes the b-file, the king doesn't
+                    let rook = position(&[(1, rank + 3 * forward, enemy('r'))], "KQkq", color);
+                    expect(&rook, color, true, true, "with a rook on the b-file");
+                    let pawn = position(&[(0, rank + forward, enemy('p'))], "KQkq", color);
+                    expect(&pawn, color, true, true, "with b attacked by a pawn");
+                }
+
+                let right = match (color, long) {
+                    (Color::White, false) => 'K',
+                    (Color::White, true) => 'Q',
+                    (Color::Black, false) => 'k',
+                    (Color::Black, true) => 'q',
+                };
+                let rights: String = "KQkq".chars().filter(|&c| c != right).collect();
+                let board = position(&[], &rights, color);
+                expect(&board, color, long, false, &format!("with the rights {rights}"));
+                expect(&board, color, other, true, &format!("with the rights {rights}"));
+                // a rook that went back to its corner doesn't bring the right back
+                let mut board = open;
+                board.move_piece(corner, corner + vec2![0, forward]);
+                board.move_piece(corner + vec2![0, forward], corner);
+                expect(&board, color, long, false, "after the rook moved");
+                expect(&board, color, other, true, "after the other rook moved");
+            }
+            // neither does a king that went back
+            let mut board = position(&[], "KQkq", color);
+            board.move_piece(vec2![4, rank], vec2![4, rank + forward]);
+            board.move_piece(vec2![4, rank + forward], vec2![4, rank]);
+            for long in [false, true] {
+                expect(&board, color, long, false, "after the king moved");
+            }
+        }
+    }
+
+    /// A rook taken in its corner takes the right to castle to its side with it, and a FEN can't give a right to castle
+    /// without the rook
+    #[test]
+    fn rook_taken_in_its_corner() {
+        let (mut board, _) = Board::from_fen("rnbqk2r/pppppppp/8/8/8/6n1/PPPPPP1P/RNBQK2R b KQkq - 0 1").unwrap();
+        board.move_piece(vec2![6, 2], vec2![7, 0]);
+        let castle = board.can_castle(Color::White);
+        assert!(
+            !castle.short && castle.long && board.can_castle(Color::Black).short,
+            "taking the rook on h1 didn't take White's right to castle short, and only that",
+        );
+        let castles = board.moves(Color::White).0.get(&vec2![4, 0]).is_some_and(|moves| moves.contains(&vec2![6, 0]));
+        assert!(!castles, "White can castle short after the rook on h1 was taken");
+        let without_rook = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w K - 0 1");
+        assert!(without_rook.is_none(), "a right to castle without a rook was read");
+    }
+
+    /// Positions written as FEN and read back are the same board, and the FEN is the one that was read
+    #[test]
+    fn fen_written_back() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k2r/8/8/8/8/8/8/4K2R w Kq - 12 40",
+            "8/5k2/8/8/8/8/1K6/8 b - - 0 71",
+        ];
+        for fen in fens {
+            let (board, turn, halfmoves, fullmoves) =
+                Board::from_fen_with_counters(fen).unwrap_or_else(|| panic!("`{fen}` didn't parse"));
+            assert_eq!(board.to_fen(turn, halfmoves, fullmoves), fen);
+        }
+    }
+
+    /// The en-passant target is the square behind the pawn that just moved two squares, for pawns of both sides. A
+    /// target read from FEN lets the pawn next to it take en passant, a target that doesn't fit isn't read.
+    #[test]
+    fn en_passant_targets() {
+        let square = |name| notation::parse_square(name).unwrap();
+        let mut board = Board::starting_position();
+        for (i, (from, to)) in [("e2", "e4"), ("c7", "c5"), ("e4", "e5"), ("d7", "d5")].into_iter().enumerate() {
+            board.move_piece(square(from), square(to));
+            let turn = if i % 2 == 0 { Color::Black } else { Color::White };
+            let fen = board.to_fen(turn, 0, i as u32 / 2 + 1);
+            let read = Board::from_fen(&fen);
+            assert!(read == Some((board, turn)), "`{fen}` isn't read back as the board it was written from");
+        }
+        let fen = board.to_fen(Color::White, 0, 3);
+        assert_eq!(fen.split(' ').nth(3), Some("d6"), "after d7-d5 the FEN is `{fen}`");
+
+        let captures = [
+            ("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3", "d4", "e3"),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3", "e5", "d6"),
+        ];
+        for (fen, from, to) in captures {
+            let (board, turn) = Board::from_fen(fen).unwrap();
+            let legal = board.moves(turn).0.get(&square(from)).is_some_and(|moves| moves.contains(&square(to)));
+            assert!(legal, "{from} can't take en passant on {to} in `{fen}`");
+        }
+        let invalid = [
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e3 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq d3 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e33 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e 0 1",
+        ];
+        for fen in invalid {
+            assert!(Board::from_fen(fen).is_none(), "`{fen}` was read although its en-passant target doesn't fit");
+        }
+    }
+
+    /// Every reason a move can be illegal, each in a position where it's the one that applies
+    #[test]
+    fn move_legality() {
+        const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let cases = [
+            (START, (4, 1, 4, 3), MoveLegality::Legal),
+            (START, (4, 1, 4, 8), MoveLegality::OffTheBoard),
+            (START, (4, 3, 4, 4), MoveLegality::NoPieceThere),
+            (START, (4, 6, 4, 4), MoveLegality::NotYourPiece),
+            (START, (3, 0, 3, 1), MoveLegality::OwnPieceThere),
+            (START, (4, 1, 4, 4), MoveLegality::PieceCantMoveThatWay),
+            (START, (4, 1, 3, 2), MoveLegality::NothingToCapture),
+            (START, (0, 0, 0, 2), MoveLegality::PathBlocked),
+            // the bishop on e2 is pinned by the rook on e7
+            ("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1", (4, 1, 3, 2), MoveLegality::WouldLeaveKingInCheck),
+            ("4k3/4r3/8/8/8/8/8/R3K3 w - - 0 1", (0, 0, 0, 1), MoveLegality::DoesntEscapeCheck),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w Qkq - 0 1", (4, 0, 6, 0), MoveLegality::CastlingRightLost),
+            ("4k3/4r3/8/8/8/8/8/R3K2R w KQ - 0 1", (4, 0, 6, 0), MoveLegality::KingCantCastleOutOfCheck),
+            ("4k3/5r2/8/8/8/8/8/R3K2R w KQ - 0 1", (4, 0, 6, 0), MoveLegality::KingWouldCastleThroughCheck),
+        ];
+        for (fen, (x1, y1, x2, y2), expected) in cases {
+            let (board, turn) = Board::from_fen(fen).unwrap();
+            let (from, to): (Pos, Pos) = (vec2![x1, y1], vec2![x2, y2]);
+            assert_eq!(board.classify_move(from, to, turn), expected, "{from} -> {to} in {fen}");
+        }
+    }
+
+    /// Moves in the notation of UCI play the same as the moves they're written for, castling is the king's move and
+    /// promotions name the piece. Anything that isn't a legal move leaves the board alone.
+    #[test]
+    fn uci_moves() {
+        let mut board = Board::starting_position();
+        for uci in ["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "e1g1"] {
+            assert!(board.apply_uci(uci).is_some(), "{uci} wasn't played");
+        }
+        let castled = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 5 4";
+        assert_eq!(board.to_fen(Color::Black, 5, 4), castled);
+
+        let promotions = "3r3k/4P3/8/8/8/8/8/K7 w - - 0 1";
+        let (board, _) = Board::from_fen(promotions).unwrap();
+        for invalid in ["e7e8", "e7e8k", "e7d8p", "a1a2q", "a1a3", "e2e4", "e7e9q", "e7", "e7e8qq", "é7e8"] {
+            let mut after = board;
+            assert!(after.apply_uci(invalid).is_none() && after == board, "{invalid} was played in `{promotions}`");
+        }
+        let mut after = board;
+        assert_eq!(after.apply_uci("e7d8n"), Some(Some(Piece::Rook)), "e7d8n didn't take the rook");
+        assert_eq!(after[vec2![3, 7]], Some((Piece::Knight, Color::White)), "e7d8n didn't make a knight");
+        for (&from, moves) in &board.moves(Color::White).0 {
+            for &to in moves {
+                let pieces = if board.is_promotion(from, to) { &Piece::PROMOTIONS[..] } else { &[Piece::Queen] };
+                for &promotion in pieces {
+                    let uci = board.move_to_uci(from, to, Some(promotion));
+                    let (mut expected, mut after) = (board, board);
+                    expected.move_piece_promote(from, to, promotion);
+                    assert!(
+                        after.apply_uci(&uci).is_some() && after == expected,
+                        "{uci} isn't played as the move it was written for",
+                    );
+                }
+            }
+        }
+    }
+
+    /// The move generator counts the known numbers of positions of the usual perft positions, which between them have
+    /// castling, en passant, promotions and discovered checks
+    #[test]
+    fn perft_counts() {
+        let positions: [(&str, &[u64]); 5] = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281]),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039]),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812]),
+            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264]),
+            ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486]),
+        ];
+        for (fen, counts) in positions {
+            let (board, turn) = Board::from_fen(fen).unwrap();
+            for (depth, &expected) in (1..).zip(counts) {
+                assert_eq!(board.perft(turn, depth), expected, "perft {depth} of `{fen}`");
+            }
+        }
+    }
+
+    /// Neither side has the material to mate with only kings and a minor piece, or bishops of both sides on squares of
+    /// the same color. A pawn, a rook or two knights can still mate.
+    #[test]
+    fn insufficient_material() {
+        let positions = [
+            ("8/8/4k3/8/8/3K4/8/8 w - - 0 1", true),
+            ("8/8/4k3/8/8/3K4/8/6N1 w - - 0 1", true),
+            ("8/8/4k3/8/8/3K4/8/5b2 w - - 0 1", true),
+            ("8/8/4k3/8/8/3K4/8/2B3b1 w - - 0 1", true),
+            ("8/8/4k3/8/8/3K4/8/2B2b2 w - - 0 1", false),
+            ("8/8/4kb2/8/8/3K4/8/5b2 w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/8/1N4N1 w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/4P3/8 w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/8/7r w - - 0 1", false),
+        ];
+        for (fen, insufficient) in positions {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            assert_eq!(board.has_insufficient_material(), insufficient, "`{fen}`");
+        }
+    }
+
+    /// A side has mating material with more than its king and at most one knight or bishop
+    #[test]
+    fn mating_material() {
+        let material = [
+            ("4k3/8/8/8/8/8/8/4K1N1 w - - 0 1", false, false),
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", true, false),
+            ("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", true, false),
+            ("3nk3/8/8/8/8/8/8/4K3 w - - 0 1", false, false),
+            ("3qk3/8/8/8/8/8/8/4K3 w - - 0 1", false, true),
+        ];
+        for (fen, white, black) in material {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            let has = (board.has_mating_material(Color::White), board.has_mating_material(Color::Black));
+            assert_eq!(has, (white, black), "mating material of White and Black in `{fen}`");
+        }
+    }
+
+    /// Not a check but a benchmark of the early exit, run it with
+    /// `cargo test --release legal_move_timing -- --ignored --nocapture`. Times telling whether the side to move has a
+    /// legal move, which every check for mate and stalemate does, by stopping at the first one against generating all.
+    #[test]
+    #[ignore]
+    fn legal_move_timing() {
+        let positions = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4",
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+        ];
+        let positions: Vec<_> = positions.iter().map(|fen| Board::from_fen(fen).expect("invalid position")).collect();
+        let time = |has_move: &dyn Fn(&Board, Color) -> bool| {
+            let start = Instant::now();
+            for _ in 0..1000 {
+                for (board, turn) in &positions {
+                    black_box(has_move(black_box(board), *turn));
+                }
+            }
+            start.elapsed()
+        };
+        let first = time(&|board, turn| board.has_legal_move(turn));
+        let all = time(&|board, turn| board.moves(turn).1 > 0);
+        println!("legal move check: first move {first:.2?}  all moves {all:.2?}");
+    }
+}
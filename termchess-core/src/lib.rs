@@ -0,0 +1,82 @@
+//! The chess of termchess without any I/O: the board and its legal moves, FEN and the move notations, and the search
+//! the CPU players use.
+//!
+//! ```
+//! use termchess_core::{ai, board::Board, notation, piece::Color};
+//!
+//! let board = Board::starting_position();
+//! let (legal, count) = board.moves(Color::White);
+//! assert_eq!(count, 20);
+//!
+//! let (from, to, _) = notation::parse_san(&board, Color::White, "Nf3").unwrap();
+//! assert_eq!(notation::san(&board, &legal, from, to), "Nf3");
+//!
+//! // White mates with Qh5xf7
+//! let (board, turn) = Board::from_fen("r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+//! let (best, score) = ai::search(&board, turn, &[], 2, &Default::default(), &mut |_| {}).unwrap();
+//! assert_eq!(best.map(|m| notation::san(&board, &board.moves(turn).0, m.from, m.to)).as_deref(), Some("Qxf7#"));
+//! assert!(score > ai::MATE_BOUND);
+//! ```
+
+use vecm::vec::PolyVec2;
+
+use piece::Color;
+
+pub mod ai;
+pub mod board;
+mod moves;
+pub mod notation;
+pub mod piece;
+
+/// The variant of chess the rules are for, only standard chess so far
+pub const VARIANT: &str = "standard";
+/// Raised whenever a change makes a move legal or illegal that wasn't before, like fixes of castling or en passant.
+/// Games played under another revision might not replay the same, and online clients with different revisions
+/// don't play each other.
+pub const RULES_REVISION: u32 = 1;
+
+/// A square, `x` is the file and `y` the rank, both from 0 to 7. a1 is `(0, 0)`.
+pub type Pos = PolyVec2<i8>;
+
+pub enum GameEnd {
+    Draw,
+    Winner(Color),
+    /// Ended before both players moved, the game has no result
+    Aborted,
+}
+impl GameEnd {
+    /// The PGN result string
+    pub fn result(&self) -> &'static str {
+        match self {
+            GameEnd::Draw => "1/2-1/2",
+            GameEnd::Winner(Color::White) => "1-0",
+            GameEnd::Winner(Color::Black) => "0-1",
+            GameEnd::Aborted => "*",
+        }
+    }
+}
+
+// days since 1970-01-01 to a proleptic gregorian date, see http://howardhinnant.github.io/date_algorithms.html.
+// Shared with the other termchess crates, it isn't part of the chess API.
+#[doc(hidden)]
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// 64-bit FNV-1a, shared with the other termchess crates for hashes that have to stay the same between versions
+#[doc(hidden)]
+pub fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    bytes.into_iter().fold(OFFSET, |hash, byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
@@ -1,9 +1,11 @@
 use std::collections::HashSet;
 use vecm::vec2;
 
-use crate::{Piece, Pos, Color, board::Board};
+use crate::{piece::{Piece, Color}, Pos, board::Board};
 
-pub fn moves(game: &Board, piece: Piece, pos: Pos, color: Color, checked_by_castle: bool) -> HashSet<Pos> {
+/// The squares the piece at `pos` can move to, including moves that leave the own king in check. `without_castling`
+/// leaves out castling, which never captures, for callers that only want the squares a piece threatens.
+pub fn moves(game: &Board, piece: Piece, pos: Pos, color: Color, without_castling: bool) -> HashSet<Pos> {
     #[derive(PartialEq, Eq)]
     enum Ty { No, Enemy, Ally }
     let occupied = |p: Pos| -> Ty {
@@ -56,21 +58,24 @@ pub fn moves(game: &Board, piece: Piece, pos: Pos, color: Color, checked_by_cast
 
             let castle = game.can_castle(color);
             let y = if color == Color::Black { 7 } else { 0 };
-            // performance optimization possible here by not recalculating all moves
+            // The king can't castle out of, through or into check, so its square and the next two must be safe. The
+            // squares between the king and the rook have to be empty, on the long side that includes the b-file which
+            // may be attacked. Pawns only move diagonally to occupied squares, so the attacks on the empty squares are
+            // checked with `attackers` instead of the opponent's moves.
 
             if
-                !checked_by_castle
+                !without_castling
                 && castle.long
                 && (1..4).all(|x| occupied(vec2![x, y]) == Ty::No)
-                && (2..=4).all(|x| !game.threatens(vec2![x, y], !color, true))
+                && (2..=4).all(|x| game.attackers(vec2![x, y], !color) == 0)
             {
                 moves.insert(vec2![2, y]);
             }
             if
-                !checked_by_castle
+                !without_castling
                 && castle.short
                 && (5..7).all(|x| occupied(vec2![x, y]) == Ty::No)
-                && (4..=6).all(|x| !game.threatens(vec2![x, y], !color, true))
+                && (4..=6).all(|x| game.attackers(vec2![x, y], !color) == 0)
             {
                 moves.insert(vec2![6, y]);
             }
@@ -126,3 +131,27 @@ pub fn moves(game: &Board, piece: Piece, pos: Pos, color: Color, checked_by_cast
 fn inside(pos: Pos) -> bool {
     pos.x >= 0 && pos.y >= 0 && pos.x <= 7 && pos.y <= 7
 }
+
+/// Whether the piece at `from` attacks `target`, no matter what stands on the target square. Pins are ignored.
+pub fn attacks(board: &Board, piece: Piece, from: Pos, color: Color, target: Pos) -> bool {
+    let d = target - from;
+    let line_clear = || {
+        let dir = vec2![d.x.signum(), d.y.signum()];
+        let mut cur = from + dir;
+        while cur != target {
+            if board[cur].is_some() { return false }
+            cur += dir;
+        }
+        true
+    };
+    let straight = (d.x == 0) != (d.y == 0);
+    let diagonal = d.x != 0 && d.x.abs() == d.y.abs();
+    match piece {
+        Piece::King => d != Pos::zero() && d.x.abs() <= 1 && d.y.abs() <= 1,
+        Piece::Queen => (straight || diagonal) && line_clear(),
+        Piece::Bishop => diagonal && line_clear(),
+        Piece::Knight => matches!((d.x.abs(), d.y.abs()), (1, 2) | (2, 1)),
+        Piece::Rook => straight && line_clear(),
+        Piece::Pawn => d.x.abs() == 1 && d.y == if color == Color::White { 1 } else { -1 },
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet};
+
+use vecm::vec2;
+
+use crate::{board::Board, piece::{Piece, Color}, Pos};
+
+pub fn square(pos: Pos) -> String {
+    format!("{}{}", (b'a' + pos.x as u8) as char, pos.y + 1)
+}
+
+pub fn parse_square(s: &str) -> Option<Pos> {
+    let mut chars = s.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?;
+    if chars.next().is_some() || !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+        return None;
+    }
+    Some(Pos::new((file as u8 - b'a') as i8, (rank as u8 - b'1') as i8))
+}
+
+/// Standard algebraic notation for a legal move. `legal` has to be the legal move set of the moving side,
+/// it is used for disambiguation. A pawn that reaches the last rank becomes a queen.
+pub fn san(board: &Board, legal: &HashMap<Pos, HashSet<Pos>>, from: Pos, to: Pos) -> String {
+    san_promote(board, legal, from, to, Piece::Queen)
+}
+
+/// Like `san`, a pawn that reaches the last rank becomes `promotion`
+pub fn san_promote(board: &Board, legal: &HashMap<Pos, HashSet<Pos>>, from: Pos, to: Pos, promotion: Piece) -> String {
+    let (_, color) = board[from].expect("no piece to generate SAN for");
+    let mut s = san_body(board, legal, from, to);
+    if board.is_promotion(from, to) {
+        s.push('=');
+        s.push(promotion.letter());
+    }
+
+    let mut after = *board;
+    after.move_piece_promote(from, to, promotion);
+    let king = after.find_king(!color).expect("king not found");
+    if after.threatens(king, color) {
+        s.push(if after.has_legal_move(!color) { '+' } else { '#' });
+    }
+    s
+}
+
+// SAN without the promotion and the check/mate suffix
+fn san_body(board: &Board, legal: &HashMap<Pos, HashSet<Pos>>, from: Pos, to: Pos) -> String {
+    let (piece, _) = board[from].expect("no piece to generate SAN for");
+
+    if piece == Piece::King && (to.x - from.x).abs() == 2 {
+        return if to.x > from.x { "O-O" } else { "O-O-O" }.to_owned();
+    }
+
+    let capture = board[to].is_some() || piece == Piece::Pawn && from.x != to.x;
+    let mut s = String::new();
+
+    if piece == Piece::Pawn {
+        if capture {
+            s.push((b'a' + from.x as u8) as char);
+        }
+    } else {
+        s.push(piece.letter());
+        let others: Vec<Pos> = legal.iter()
+            .filter(|(pos, moves)| **pos != from && moves.contains(&to) && matches!(board[**pos], Some((p, _)) if p == piece))
+            .map(|(pos, _)| *pos)
+            .collect();
+        // as little as needed: the file if it tells the pieces apart, else the rank, else both
+        if !others.is_empty() {
+            let from_square = square(from);
+            if others.iter().all(|other| other.x != from.x) {
+                s.push_str(&from_square[..1]);
+            } else if others.iter().all(|other| other.y != from.y) {
+                s.push_str(&from_square[1..]);
+            } else {
+                s.push_str(&from_square);
+            }
+        }
+    }
+    if capture {
+        s.push('x');
+    }
+    s.push_str(&square(to));
+    s
+}
+
+/// The move in the long algebraic notation used by UCI engines, like `e2e4` or `e7e8q`. Promotions are to a queen
+/// like with `Board::move_piece`.
+pub fn uci(board: &Board, from: Pos, to: Pos) -> String {
+    board.move_to_uci(from, to, None)
+}
+
+/// Finds the legal move of a UCI string like `e2e4`, `e1g1` or `e7e8q` and the piece a pawn is promoted to. The board
+/// doesn't know whose turn it is, the move is legal if it is for the side of the piece. Promotions have to name the
+/// piece and other moves must not.
+pub fn parse_uci(board: &Board, s: &str) -> Option<(Pos, Pos, Option<Piece>)> {
+    if !s.is_ascii() || !(4..=5).contains(&s.len()) {
+        return None;
+    }
+    let (from, to) = (parse_square(&s[..2])?, parse_square(&s[2..4])?);
+    let (_, color) = board[from]?;
+    if !board.moves(color).0.get(&from).is_some_and(|moves| moves.contains(&to)) {
+        return None;
+    }
+    let promotion = match s[4..].chars().next() {
+        Some(c) => Some(Piece::from_letter(c.to_ascii_uppercase()).filter(|piece| Piece::PROMOTIONS.contains(piece))?),
+        None => None,
+    };
+    (promotion.is_some() == board.is_promotion(from, to)).then_some((from, to, promotion))
+}
+
+/// Finds the legal move described by a SAN string and the piece a pawn is promoted to, if the SAN names one.
+/// Annotations like `+`, `#`, `!` or `?` are ignored and a missing capture marker or castling written with zeros is
+/// accepted, a promotion without a piece becomes a queen.
+pub fn parse_san(board: &Board, turn: Color, san: &str) -> Option<(Pos, Pos, Option<Piece>)> {
+    let s = san.trim_end_matches(['+', '#', '!', '?']);
+    let (legal, _) = board.moves(turn);
+    let is_legal = |from: Pos, to: Pos| legal.get(&from).is_some_and(|moves| moves.contains(&to));
+
+    let rank = if turn == Color::White { 0 } else { 7 };
+    let castle = match s {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None
+    };
+    if let Some(file) = castle {
+        let (from, to) = (vec2![4, rank], vec2![file, rank]);
+        return (matches!(board[from], Some((Piece::King, _))) && is_legal(from, to)).then_some((from, to, None));
+    }
+
+    let mut chars: Vec<char> = s.chars().filter(|c| !matches!(c, 'x' | '-' | '=')).collect();
+    let first = *chars.first()?;
+    let piece = if first.is_ascii_uppercase() {
+        chars.remove(0);
+        Piece::from_letter(first)?
+    } else {
+        Piece::Pawn
+    };
+    let promotion = match chars.last() {
+        Some(&c) if piece == Piece::Pawn && c.is_ascii_uppercase() => {
+            chars.pop();
+            Some(Piece::from_letter(c).filter(|promotion| Piece::PROMOTIONS.contains(promotion))?)
+        }
+        _ => None,
+    };
+    if chars.len() < 2 {
+        return None;
+    }
+    let to = parse_square(&chars[chars.len() - 2..].iter().collect::<String>())?;
+    // the disambiguation is a file, a rank or both like `Qh4e1`
+    let mut from_file = None;
+    let mut from_rank = None;
+    for &c in &chars[..chars.len() - 2] {
+        match c {
+            'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some((c as u8 - b'a') as i8),
+            '1'..='8' if from_rank.is_none() => from_rank = Some((c as u8 - b'1') as i8),
+            _ => return None
+        }
+    }
+
+    let mut candidates = legal.iter()
+        .filter(|(from, moves)| {
+            moves.contains(&to)
+                && matches!(board[**from], Some((p, _)) if p == piece)
+                && from_file.is_none_or(|x| from.x == x)
+                && from_rank.is_none_or(|y| from.y == y)
+        })
+        .map(|(from, _)| *from);
+    let from = candidates.next()?;
+    if promotion.is_some() && !board.is_promotion(from, to) {
+        return None;
+    }
+    candidates.next().is_none().then_some((from, to, promotion))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::Board, notation, piece::Piece};
+
+    /// Three queens after promotions, two share a file and two a rank, so every kind of disambiguation is needed
+    const QUEENS: &str = "8/8/k7/8/4Q2Q/8/K7/7Q w - - 0 1";
+    const KNIGHTS: &str = "k7/8/8/8/8/5N2/8/1N2K3 w - - 0 1";
+    const PROMOTIONS: &str = "3r3k/4P3/8/8/8/8/8/K7 w - - 0 1";
+
+    /// The square of the piece is only named as far as needed, the file is preferred. A move that doesn't name one
+    /// piece isn't read.
+    #[test]
+    fn disambiguation() {
+        let cases = [
+            (QUEENS, "h4", "e1", "Qh4e1"), (QUEENS, "e4", "e1", "Qee1"), (QUEENS, "h1", "e1", "Q1e1"),
+            (KNIGHTS, "b1", "d2", "Nbd2"),
+        ];
+        for (fen, from, to, expected) in cases {
+            let (board, turn) = Board::from_fen(fen).unwrap();
+            let (legal, _) = board.moves(turn);
+            let (from, to) = (notation::parse_square(from).unwrap(), notation::parse_square(to).unwrap());
+            assert_eq!(notation::san(&board, &legal, from, to), expected, "in {fen}");
+        }
+        let (board, turn) = Board::from_fen(QUEENS).unwrap();
+        for ambiguous in ["Qe1", "Qhe1", "Q4e1", "Qhhe1"] {
+            assert!(notation::parse_san(&board, turn, ambiguous).is_none(), "{ambiguous} was read as a move");
+        }
+    }
+
+    /// All moves of the positions, promotions to every piece included, come back from their SAN
+    #[test]
+    fn read_back() {
+        let rooks = "k7/8/8/1R6/8/8/1R6/1R2K3 w - - 0 1";
+        for fen in [QUEENS, KNIGHTS, PROMOTIONS, "1k6/7K/8/8/q2q4/8/8/q7 b - - 0 1", rooks] {
+            let (board, turn) = Board::from_fen(fen).unwrap();
+            let (legal, _) = board.moves(turn);
+            for (&from, moves) in &legal {
+                for &to in moves {
+                    let pieces = if board.is_promotion(from, to) { &Piece::PROMOTIONS[..] } else { &[Piece::Queen] };
+                    for &promotion in pieces {
+                        let san = notation::san_promote(&board, &legal, from, to, promotion);
+                        let expected = (from, to, board.is_promotion(from, to).then_some(promotion));
+                        assert_eq!(notation::parse_san(&board, turn, &san), Some(expected), "{san} in {fen}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// A promotion without a piece is played as a queen, a king can't be promoted to
+    #[test]
+    fn promotions() {
+        let (board, turn) = Board::from_fen(PROMOTIONS).unwrap();
+        let cases = [
+            ("e8=N", Some(Some(Piece::Knight))), ("exd8R+", Some(Some(Piece::Rook))),
+            ("e8", Some(None)), ("e8=K", None),
+        ];
+        for (san, expected) in cases {
+            let promotion = notation::parse_san(&board, turn, san).map(|(_, _, promotion)| promotion);
+            assert_eq!(promotion, expected, "{san} was read with another promotion");
+        }
+    }
+
+    /// Castling, captures including en passant, promotions with check and a mate as `Board::to_san` writes them
+    #[test]
+    fn special_moves() {
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let cases = [
+            (castling, "e1", "g1", None, "O-O"),
+            (castling, "e1", "c1", None, "O-O-O"),
+            ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", "e4", "d5", None, "exd5"),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", "e5", "f6", None, "exf6"),
+            ("7k/4P3/8/8/8/8/8/K7 w - - 0 1", "e7", "e8", None, "e8=Q+"),
+            ("7k/4P3/8/8/8/8/8/K7 w - - 0 1", "e7", "e8", Some(Piece::Knight), "e8=N"),
+            ("3rkr2/3pnp2/8/8/1B6/8/8/K3Q3 w - - 0 1", "e1", "e7", None, "Qxe7#"),
+            (QUEENS, "h4", "e1", None, "Qh4e1"),
+        ];
+        for (fen, from, to, promotion, expected) in cases {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            let (from, to) = (notation::parse_square(from).unwrap(), notation::parse_square(to).unwrap());
+            assert_eq!(board.to_san(from, to, promotion), expected, "in {fen}");
+        }
+    }
+}
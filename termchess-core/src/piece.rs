@@ -0,0 +1,76 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Piece {
+    King,
+    Queen,
+    Bishop,
+    Knight,
+    Rook,
+    Pawn,
+}
+impl Piece {
+    /// What a pawn can become on the last rank, the usual choice first
+    pub const PROMOTIONS: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
+    pub fn letter(self) -> char {
+        match self {
+            Piece::King => 'K',
+            Piece::Queen => 'Q',
+            Piece::Bishop => 'B',
+            Piece::Knight => 'N',
+            Piece::Rook => 'R',
+            Piece::Pawn => 'P',
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Piece::King => "king",
+            Piece::Queen => "queen",
+            Piece::Bishop => "bishop",
+            Piece::Knight => "knight",
+            Piece::Rook => "rook",
+            Piece::Pawn => "pawn",
+        }
+    }
+
+    /// Material value in pawns. The king can't be traded, so it's worth more than everything else together.
+    pub fn value(self) -> i32 {
+        match self {
+            Piece::King => 100,
+            Piece::Queen => 9,
+            Piece::Rook => 5,
+            Piece::Bishop | Piece::Knight => 3,
+            Piece::Pawn => 1,
+        }
+    }
+
+    pub fn from_letter(c: char) -> Option<Self> {
+        Some(match c {
+            'K' => Piece::King,
+            'Q' => Piece::Queen,
+            'B' => Piece::Bishop,
+            'N' => Piece::Knight,
+            'R' => Piece::Rook,
+            'P' => Piece::Pawn,
+            _ => return None
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Color {
+    Black,
+    White,
+}
+impl std::ops::Not for Color {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Self::Black => Self::White,
+            Self::White => Self::Black,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,201 @@
+//! Joining a game on a server as a player or a spectator
+
+use std::{error::Error, io, net::TcpStream, sync::mpsc::{self, Receiver}, thread, time::Duration};
+
+use binverse::error::BinverseError;
+use log::{debug, info, warn};
+
+use crate::protocol::{
+    recv, send, ClientMessage, ColorPreference, Features, GameInfo, Handshake, Hello, Lobby, Move, PlayerInfo,
+    RecvError, Rejoin, Role, Rules, ServerMessage, SpectateInfo,
+};
+
+pub struct Remote {
+    pub socket: TcpStream,
+    pub server: Receiver<ServerMessage>,
+    pub game_id: u32,
+    /// The features that can be used in the game
+    pub features: Features,
+    /// How a player takes the seat back after losing the connection, `None` for spectators
+    pub seat: Option<Seat>,
+}
+impl Remote {
+    /// Switches over to the rematch the server started on this connection
+    pub fn start_rematch(&mut self, game_info: &GameInfo) {
+        info!(
+            "game {}: rematch as game {} with {}, features {:#x}",
+            self.game_id, game_info.game_id, if game_info.is_black { "Black" } else { "White" },
+            game_info.features.bits,
+        );
+        self.game_id = game_info.game_id;
+        self.features = game_info.features;
+        if let Some(seat) = &mut self.seat {
+            seat.code = game_info.code;
+        }
+    }
+
+    /// Sends a message to the server. `ply` is the number of moves played so far, only used for logging.
+    pub fn send(&mut self, ply: usize, message: ClientMessage) -> Result<(), BinverseError> {
+        debug!("game {} ply {ply}: sending {message:?}", self.game_id);
+        send(&mut self.socket, message)
+    }
+}
+
+/// The starting position and the moves played so far of a rejoined game
+pub type Resumed = (String, Vec<Move>);
+
+/// How long a client that lost the connection tries to take its seat back by itself, the server keeps it longer
+pub const AUTO_REJOIN_TIME: Duration = Duration::from_secs(60);
+
+/// What `rejoin` needs to take a player's seat back
+#[derive(Clone, Debug)]
+pub struct Seat {
+    pub ip: String,
+    pub name: String,
+    pub code: u16,
+}
+
+/// Waits for an opponent. If a game lost its connection to a player with this name, `rejoin` is called with the
+/// opponent's name and can return the code of the game to take the seat back. The moves played so far are returned
+/// when that worked, the game starts from `GameInfo::fen` either way. `prefer` only counts for a new game.
+#[allow(clippy::type_complexity)]
+pub fn connect(ip: &str, my_name: String, prefer: ColorPreference, rejoin: impl FnOnce(&str) -> Option<u16>)
+-> Result<(Remote, GameInfo, Option<Vec<Move>>), Box<dyn Error>> {
+    let mut server = TcpStream::connect(ip)?;
+    info!("connected to {ip} as {my_name}");
+    hello(&mut server)?;
+    let info = PlayerInfo {
+        name: my_name.clone(), role: Role::Player, features: Features::SUPPORTED, rules: Rules::current(), prefer,
+    };
+    send(&mut server, info)?;
+    let mut rejoin = Some(rejoin);
+    let (game_info, resumed) = loop {
+        match recv(&mut server)? {
+            Lobby::Game(game_info) => break (game_info, None),
+            Lobby::Reconnect(other_player) => {
+                // the server only offers a seat once per connection
+                let answer = match rejoin.take().and_then(|rejoin| rejoin(&other_player)) {
+                    Some(code) => Rejoin::Code(code),
+                    None => Rejoin::Decline,
+                };
+                send(&mut server, answer)?;
+            }
+            Lobby::Rejoined(rejoined) => break (rejoined.game, Some(rejoined.moves)),
+            Lobby::Refused => return Err("wrong code, the game can't be rejoined".into()),
+            Lobby::Mismatch(difference) => return Err(difference.into()),
+        }
+    };
+    let game_id = game_info.game_id;
+    info!(
+        "game {game_id}: {} as {} against {}, features {:#x} (opponent {:#x})",
+        if resumed.is_some() { "rejoined" } else { "joined" },
+        if game_info.is_black { "Black" } else { "White" }, game_info.other_player,
+        game_info.features.bits, game_info.other_features.bits,
+    );
+    let seat = Seat { ip: ip.to_owned(), name: my_name, code: game_info.code };
+    Ok((listen(server, game_id, game_info.features, Some(seat))?, game_info, resumed))
+}
+
+/// Takes the seat back after the connection to the server was lost, in games with `Features::AUTO_REJOIN`. Fails if
+/// the server doesn't know yet that the connection is gone, so it's tried again until the server does.
+pub fn rejoin(seat: &Seat) -> Result<(Remote, Resumed), Box<dyn Error>> {
+    let mut server = TcpStream::connect(&seat.ip)?;
+    info!("connected to {} as {} to rejoin", seat.ip, seat.name);
+    hello(&mut server)?;
+    let info = PlayerInfo {
+        name: seat.name.clone(), role: Role::Rejoin(seat.code), features: Features::SUPPORTED, rules: Rules::current(),
+        prefer: ColorPreference::Random,
+    };
+    send(&mut server, info)?;
+    let rejoined = match recv(&mut server)? {
+        Lobby::Rejoined(rejoined) => rejoined,
+        Lobby::Refused => return Err("the server has no seat to rejoin".into()),
+        Lobby::Mismatch(difference) => return Err(difference.into()),
+        Lobby::Game(_) | Lobby::Reconnect(_) => return Err("the server didn't answer the rejoin".into()),
+    };
+    let game_id = rejoined.game.game_id;
+    info!("game {game_id}: rejoined after {} moves", rejoined.moves.len());
+    let remote = listen(server, game_id, rejoined.game.features, Some(seat.clone()))?;
+    Ok((remote, (rejoined.game.fen, rejoined.moves)))
+}
+
+pub fn spectate(ip: &str, my_name: String) -> Result<(Remote, SpectateInfo), Box<dyn Error>> {
+    let mut server = TcpStream::connect(ip)?;
+    info!("connected to {ip} as {my_name} to spectate");
+    hello(&mut server)?;
+    let info = PlayerInfo {
+        name: my_name, role: Role::Spectator, features: Features::SUPPORTED, rules: Rules::current(),
+        prefer: ColorPreference::Random,
+    };
+    send(&mut server, info)?;
+    let info: SpectateInfo = match recv(&mut server) {
+        Ok(info) => info,
+        // the server closes the connection right away if there is nothing to watch
+        Err(RecvError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            return Err("the server has no running game to watch".into())
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(difference) = Rules::current().mismatch(&info.rules, "the server") {
+        return Err(difference.into());
+    }
+    info!("game {}: watching {} against {}", info.game_id, info.white, info.black);
+    Ok((listen(server, info.game_id, info.features, None)?, info))
+}
+
+/// Tells the server which protocol version this client speaks and fails if it speaks another one
+fn hello(server: &mut TcpStream) -> Result<(), Box<dyn Error>> {
+    send(&mut *server, Hello::current())?;
+    match recv(&mut *server) {
+        Ok(Handshake::Hello(theirs)) => match Hello::current().mismatch(&theirs, "the server") {
+            Some(difference) => Err(difference.into()),
+            None => Ok(()),
+        },
+        Ok(Handshake::Reject(reason)) => Err(reason.into()),
+        // servers from before the handshake take the `Hello` for a broken `PlayerInfo`
+        Err(RecvError::BadFrame(_)) => Err("incompatible version: the server speaks an older protocol".into()),
+        Err(RecvError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            Err("incompatible version: the server closed the connection, it might speak an older protocol".into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+// reads the server's messages on a separate thread
+fn listen(server: TcpStream, game_id: u32, features: Features, seat: Option<Seat>) -> Result<Remote, Box<dyn Error>> {
+    let (tx, rx) = mpsc::channel();
+
+    let server2 = server.try_clone()?;
+    thread::spawn(move || {
+        let mut server = server2;
+        loop {
+            match recv::<ServerMessage, _>(&mut server) {
+                Ok(message) => {
+                    debug!("game {game_id}: received {message:?}");
+                    if tx.send(message).is_err() {
+                        break
+                    }
+                }
+                Err(RecvError::Io(io)) if io.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    info!("game {game_id}: server closed the connection");
+                    break
+                }
+                Err(RecvError::BadFrame(err)) => {
+                    warn!("game {game_id}: skipping invalid message from server: {err:?}");
+                }
+                Err(err) => {
+                    warn!("game {game_id}: server disconnected: {err}");
+                    break
+                }
+            }
+        }
+    });
+
+    Ok(Remote {
+        server: rx,
+        socket: server,
+        game_id,
+        features,
+        seat,
+    })
+}
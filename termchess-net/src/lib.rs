@@ -0,0 +1,50 @@
+//! Online games of termchess: the protocol, the client that joins a game and the server that hosts them.
+//!
+//! Every message is a frame with the length of the serialized message in front, see [`send`] and [`recv`]:
+//! ```
+//! use termchess_net::{recv, send, ClientMessage, Move};
+//!
+//! let mut stream = Vec::new();
+//! send(&mut stream, ClientMessage::Move(Move { x1: 4, y1: 1, x2: 4, y2: 3 })).unwrap();
+//! let message: ClientMessage = recv(&stream[..]).unwrap();
+//! assert!(matches!(message, ClientMessage::Move(Move { x1: 4, y1: 1, x2: 4, y2: 3 })));
+//! ```
+//!
+//! Every connection starts with a [`Hello`] from both sides, the server rejects clients with another
+//! [`PROTOCOL_VERSION`] before their messages can be misread. [`connect`] and [`spectate`] take care of it.
+//!
+//! A server on port 1337 and a player who waits there for an opponent:
+//! ```no_run
+//! use termchess_core::{board::Board, piece::Color};
+//! use termchess_net::{connect, run_server, ColorPreference, ServerOptions};
+//!
+//! std::thread::spawn(|| run_server(Board::starting_position(), Color::White, &ServerOptions::default()).unwrap());
+//! let (remote, info, _) = connect("localhost:1337", "Alice".to_owned(), ColorPreference::White, |_| None).unwrap();
+//! println!("playing {} as {}", info.other_player, if info.is_black { "Black" } else { "White" });
+//! for message in remote.server.iter() {
+//!     println!("{message:?}");
+//! }
+//! ```
+//!
+//! After a game with [`Features::REMATCH`] both players can send `ClientMessage::Rematch`. The server then starts the
+//! next game on the same connections with the colors swapped and announces it with `ServerMessage::Rematch`.
+//!
+//! Anyone else who connects with `Role::Spectator` in the `PlayerInfo` watches the most recently started game. They get
+//! the starting position and the moves so far, then every move as it's played, and can only chat:
+//! ```no_run
+//! use termchess_net::spectate;
+//!
+//! let (remote, info) = spectate("localhost:1337", "Carol".to_owned()).unwrap();
+//! println!("watching {} against {} from {} after {} moves", info.white, info.black, info.fen, info.moves.len());
+//! for message in remote.server.iter() {
+//!     println!("{message:?}");
+//! }
+//! ```
+
+mod client;
+mod protocol;
+mod server;
+
+pub use client::*;
+pub use protocol::*;
+pub use server::*;
@@ -0,0 +1,590 @@
+//! The messages between the server and its clients and how they are sent over a connection
+
+use std::{error::Error, fmt, io::{self, Read, Write, Cursor}};
+
+use binverse::{streams::{Serializer, Deserializer}, serialize::{Serialize, Deserialize}, error::BinverseError};
+use binverse_derive::serializable;
+use termchess_core::{board::Board, fnv1a, piece::Color, RULES_REVISION, VARIANT};
+
+/// The layout of the messages. Both sides send it in `Hello` before anything else and the server closes connections
+/// with another one, messages of another layout would be read as garbage. It goes up with every change to a message
+/// that not every client understands, optional parts are negotiated with `Features` instead.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// The first message of every connection, in both directions. Its layout never changes.
+#[serializable]
+#[derive(Clone, Copy, Debug)]
+pub struct Hello {
+    pub protocol_version: u32,
+}
+impl Hello {
+    pub fn current() -> Self {
+        Self { protocol_version: PROTOCOL_VERSION }
+    }
+
+    /// What differs from the other side's version, `them` names it like `the server`. The text is for the side with
+    /// this version.
+    pub fn mismatch(&self, theirs: &Hello, them: &str) -> Option<String> {
+        (self.protocol_version != theirs.protocol_version).then(|| format!(
+            "incompatible version: {them} speaks protocol {}, you speak {}, update one side",
+            theirs.protocol_version, self.protocol_version,
+        ))
+    }
+}
+
+/// The server's answer to a client's `Hello`
+#[serializable]
+#[derive(Debug)]
+pub enum Handshake {
+    /// The versions match, the client goes on with its `PlayerInfo`
+    Hello(Hello),
+    /// The client speaks another version, with what differs. The server closes the connection.
+    Reject(String),
+}
+
+#[serializable]
+pub struct PlayerInfo {
+    pub name: String,
+    pub role: Role,
+    /// Everything the client supports
+    pub features: Features,
+    pub rules: Rules,
+    /// The color the player would like, only used when the server starts a new game
+    pub prefer: ColorPreference,
+}
+
+/// The rules a client or server plays by. Unlike features they can't be negotiated, two sides with different rules
+/// would disagree about which moves are legal. The server only takes players with its own rules, so the two players
+/// of a game always agree.
+#[serializable]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rules {
+    pub variant: String,
+    pub revision: u32,
+}
+impl Rules {
+    /// The rules of this version
+    pub fn current() -> Self {
+        Self { variant: VARIANT.to_owned(), revision: RULES_REVISION }
+    }
+
+    /// What differs from the rules of the other side, `them` names it like `the server`. The text is for the player
+    /// with these rules.
+    pub fn mismatch(&self, theirs: &Rules, them: &str) -> Option<String> {
+        if self.variant != theirs.variant {
+            Some(format!("{them} plays {}, you play {}", theirs.variant, self.variant))
+        } else if self.revision != theirs.revision {
+            Some(format!("{them} uses rules rev {}, you have rev {}, update one side", theirs.revision, self.revision))
+        } else {
+            None
+        }
+    }
+}
+
+/// Optional parts of the protocol as a bitset. Clients announce what they support when they connect and the server
+/// tells the players which features both of them have, only those are used in the game. This lets clients of
+/// different versions play each other as long as the messages they need are the same, changes every client has to
+/// understand still need a new `PROTOCOL_VERSION`.
+#[serializable]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Features {
+    pub bits: u32,
+}
+impl Features {
+    pub const NONE: Self = Self { bits: 0 };
+    /// `ClientMessage::Chat` and `ServerMessage::Chat`
+    pub const CHAT: Self = Self { bits: 1 };
+    /// `ClientMessage::Abort`
+    pub const ABORT: Self = Self { bits: 1 << 1 };
+    /// `ClientMessage::SyncCheck` and `ServerMessage::Resync`
+    pub const SYNC_CHECK: Self = Self { bits: 1 << 2 };
+    /// The server keeps the seat of a player who lost the connection, see `Lobby::Reconnect`. Also enables
+    /// `ServerMessage::OpponentLeft` and `ServerMessage::OpponentBack`. Without it the game ends with
+    /// `EndReason::Abandoned` as soon as the connection is lost.
+    pub const RECONNECT: Self = Self { bits: 1 << 3 };
+    /// Spectators get `ServerMessage::MoveDetails` instead of `ServerMessage::Move`
+    pub const MOVE_DETAILS: Self = Self { bits: 1 << 4 };
+    /// `ClientMessage::Resign` and `EndReason::Resigned`
+    pub const RESIGN: Self = Self { bits: 1 << 5 };
+    /// `ClientMessage::OfferDraw`, `AcceptDraw` and `DeclineDraw`, `ServerMessage::DrawOffered` and `DrawDeclined` and
+    /// `EndReason::DrawAgreed`
+    pub const DRAW_OFFER: Self = Self { bits: 1 << 6 };
+    /// A player who lost the connection takes the seat back by themselves with `Role::Rejoin`
+    pub const AUTO_REJOIN: Self = Self { bits: 1 << 7 };
+    /// Games with a time control, see `GameInfo::initial_time`, `ServerMessage::Clock` and `EndReason::Flagged`
+    pub const CLOCK: Self = Self { bits: 1 << 8 };
+    /// `ClientMessage::Rematch`, `ServerMessage::RematchOffered`, `RematchDeclined` and `Rematch`
+    pub const REMATCH: Self = Self { bits: 1 << 9 };
+    /// `ClientMessage::TakebackRequest`, `TakebackAccept` and `TakebackDecline`, `ServerMessage::TakebackRequested`,
+    /// `TakebackDeclined` and `TookBack`
+    pub const TAKEBACK: Self = Self { bits: 1 << 10 };
+    /// Everything this version implements
+    pub const SUPPORTED: Self = Self {
+        bits: Self::CHAT.bits | Self::ABORT.bits | Self::SYNC_CHECK.bits | Self::RECONNECT.bits
+            | Self::MOVE_DETAILS.bits | Self::RESIGN.bits | Self::DRAW_OFFER.bits | Self::AUTO_REJOIN.bits
+            | Self::CLOCK.bits | Self::REMATCH.bits | Self::TAKEBACK.bits,
+    };
+
+    // only features of players are named, they don't need to know what the other one's client does for spectators
+    const NAMES: [(Self, &'static str); 9] = [
+        (Self::CHAT, "chat"),
+        (Self::ABORT, "aborting"),
+        (Self::SYNC_CHECK, "board sync checks"),
+        (Self::RECONNECT, "reconnecting"),
+        (Self::RESIGN, "resigning"),
+        (Self::DRAW_OFFER, "draw offers"),
+        (Self::CLOCK, "clocks"),
+        (Self::REMATCH, "rematches"),
+        (Self::TAKEBACK, "takebacks"),
+    ];
+
+    pub fn contains(self, other: Self) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    pub fn intersection(self, other: Self) -> Self {
+        Self { bits: self.bits & other.bits }
+    }
+
+    /// Names of the features of `self` that `other` doesn't have
+    pub fn missing_from(self, other: Self) -> Vec<&'static str> {
+        Self::NAMES.iter()
+            .filter(|(feature, _)| self.contains(*feature) && !other.contains(*feature))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+#[serializable]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Player,
+    /// Watches the most recently started game that is still running, can only chat
+    Spectator,
+    /// Takes back the seat of a game that lost the connection to a player with this name, with the game's code. The
+    /// server answers with `Lobby::Rejoined` or, if there is no such seat yet, `Lobby::Refused`. The player is never
+    /// put in the queue for a new game.
+    Rejoin(u16),
+}
+
+/// Which color a player wants to play. The server gives each player the color they want if it can and decides by
+/// chance otherwise, `GameInfo::is_black` tells the player what they got.
+#[serializable]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorPreference {
+    Random,
+    White,
+    Black,
+}
+impl ColorPreference {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "random" => Some(Self::Random),
+            "white" => Some(Self::White),
+            "black" => Some(Self::Black),
+            _ => None,
+        }
+    }
+}
+
+#[serializable]
+#[derive(Clone, Debug)]
+pub struct Move {
+    pub x1: i8,
+    pub y1: i8,
+    pub x2: i8,
+    pub y2: i8,
+}
+
+/// Sent by a client to the server during a game
+#[serializable]
+#[derive(Debug)]
+pub enum ClientMessage {
+    Move(Move),
+    /// Ends the game without a result, only possible before both players have moved
+    Abort,
+    /// Sent after every move so the server can detect if the boards got out of sync
+    SyncCheck(SyncCheck),
+    Chat(String),
+    /// Gives up the game, the opponent wins
+    Resign,
+    /// Offers the opponent a draw, also while it's their turn. The offer stands until they answer it or the player
+    /// who made it moves. Offering while the opponent's offer stands accepts it.
+    OfferDraw,
+    /// Ends the game in a draw if the opponent's offer still stands
+    AcceptDraw,
+    DeclineDraw,
+    /// Asks for another game against the same opponent with the colors swapped, only after the game is over. The
+    /// server stops reading the connection for the finished game once it got this.
+    Rematch,
+    /// Asks the opponent to take back the player's last move, together with the opponent's answer to it if it's the
+    /// player's turn. The request stands until it's answered or either player moves.
+    TakebackRequest,
+    /// Takes back the moves if the opponent's request still stands
+    TakebackAccept,
+    TakebackDecline,
+}
+impl ClientMessage {
+    /// The feature both players need for the message to be used
+    pub(crate) fn feature(&self) -> Features {
+        match self {
+            ClientMessage::Move(_) => Features::NONE,
+            ClientMessage::Abort => Features::ABORT,
+            ClientMessage::SyncCheck(_) => Features::SYNC_CHECK,
+            ClientMessage::Chat(_) => Features::CHAT,
+            ClientMessage::Resign => Features::RESIGN,
+            ClientMessage::OfferDraw | ClientMessage::AcceptDraw | ClientMessage::DeclineDraw => Features::DRAW_OFFER,
+            ClientMessage::Rematch => Features::REMATCH,
+            ClientMessage::TakebackRequest | ClientMessage::TakebackAccept | ClientMessage::TakebackDecline => {
+                Features::TAKEBACK
+            }
+        }
+    }
+}
+
+#[serializable]
+#[derive(Debug)]
+pub struct SyncCheck {
+    /// Number of moves played in the game
+    pub ply: u32,
+    /// The client's position after that many moves
+    pub fen: String,
+}
+
+/// Plies between the positions sent with `MoveDetails`
+pub const DETAILS_FEN_PLIES: u32 = 10;
+
+/// A move with what the server knows about it, so a viewer can show it without generating moves itself
+#[serializable]
+#[derive(Clone, Debug)]
+pub struct MoveDetails {
+    pub played: Move,
+    /// Number of moves played in the game, including this one
+    pub ply: u32,
+    /// The move in Standard Algebraic Notation, ending in `+` or `#` if it gives check or mate
+    pub san: String,
+    /// The letter of the taken piece like `N`, `P` for a pawn and empty if nothing was taken
+    pub captured: String,
+    pub check: bool,
+    pub mate: bool,
+    /// The position after the move every `DETAILS_FEN_PLIES` plies and when the game ends, empty otherwise. A viewer
+    /// that joined late can start from it.
+    pub fen: String,
+}
+
+/// Sent by the server to a client during a game
+#[serializable]
+#[derive(Clone, Debug)]
+pub enum ServerMessage {
+    Move(Move),
+    /// Sent to spectators instead of `Move`
+    MoveDetails(MoveDetails),
+    GameOver(EndReason),
+    /// All moves played so far, the client has to rebuild its board from them because it got out of sync
+    Resync(Vec<Move>),
+    Chat(ChatMessage),
+    /// The opponent lost the connection, the game waits this many seconds for them to reconnect
+    OpponentLeft(u32),
+    OpponentBack,
+    /// The opponent offers a draw
+    DrawOffered,
+    /// The opponent declined the draw offer
+    DrawDeclined,
+    /// The time both players have left, sent after every move of a game with a clock
+    Clock(ClockTimes),
+    /// The opponent asked for a rematch after the game
+    RematchOffered,
+    /// The player asked for a rematch but the opponent left or didn't ask in time
+    RematchDeclined,
+    /// Both players asked for a rematch and it starts over the same connection, as a new game with its own id
+    Rematch(GameInfo),
+    /// The opponent asks to take back their last move
+    TakebackRequested,
+    /// The opponent declined the takeback, also sent when the game ended before it could be answered
+    TakebackDeclined,
+    /// Moves were taken back, the game continues after this many plies. Followed by `Clock` in a game with a clock.
+    TookBack(u32),
+}
+impl ServerMessage {
+    /// The feature a client needs to understand the message
+    pub(crate) fn feature(&self) -> Features {
+        match self {
+            ServerMessage::Chat(_) => Features::CHAT,
+            ServerMessage::Resync(_) => Features::SYNC_CHECK,
+            ServerMessage::OpponentLeft(_) | ServerMessage::OpponentBack => Features::RECONNECT,
+            ServerMessage::MoveDetails(_) => Features::MOVE_DETAILS,
+            ServerMessage::DrawOffered | ServerMessage::DrawDeclined => Features::DRAW_OFFER,
+            ServerMessage::GameOver(EndReason::Resigned(_)) => Features::RESIGN,
+            ServerMessage::GameOver(EndReason::DrawAgreed) => Features::DRAW_OFFER,
+            ServerMessage::Clock(_) | ServerMessage::GameOver(EndReason::Flagged(_)) => Features::CLOCK,
+            ServerMessage::RematchOffered | ServerMessage::RematchDeclined => Features::REMATCH,
+            ServerMessage::Rematch(_) => Features::REMATCH,
+            ServerMessage::TakebackRequested | ServerMessage::TakebackDeclined => Features::TAKEBACK,
+            ServerMessage::TookBack(_) => Features::TAKEBACK,
+            ServerMessage::Move(_) | ServerMessage::GameOver(_) => Features::NONE,
+        }
+    }
+}
+
+/// Why the server ended a game
+#[serializable]
+#[derive(Clone, Debug)]
+pub enum EndReason {
+    Aborted,
+    /// A player lost the connection and didn't come back in time, or their seat wasn't kept at all. The other player
+    /// wins. Whether it was Black.
+    Abandoned(bool),
+    /// A player gave up, the other player wins. Whether it was Black.
+    Resigned(bool),
+    /// A player accepted the other's draw offer
+    DrawAgreed,
+    /// A player's time ran out, whether it was Black. The other player wins if they still have mating material, see
+    /// `Board::has_mating_material`, otherwise it's a draw.
+    Flagged(bool),
+}
+
+/// The milliseconds left on the clocks of both players. The server measures them when it sends them, the clock of
+/// the side to move runs on from then. Clocks only run once both players have made their first move.
+#[serializable]
+#[derive(Clone, Copy, Debug)]
+pub struct ClockTimes {
+    pub white: u32,
+    pub black: u32,
+}
+
+#[serializable]
+#[derive(Clone, Debug)]
+pub struct ChatMessage {
+    pub scope: ChatScope,
+    pub sender: String,
+    pub text: String,
+}
+impl fmt::Display for ChatMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scope {
+            ChatScope::Players => write!(f, "{}: {}", self.sender, self.text),
+            ChatScope::Spectators => write!(f, "{} (spectator): {}", self.sender, self.text),
+        }
+    }
+}
+
+/// Who a chat message was written by. Player chat is sent to everyone, spectator chat only to the spectators unless
+/// the server is started with `--spectator-chat-to-players`.
+#[serializable]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatScope {
+    Players,
+    Spectators,
+}
+
+
+#[serializable]
+#[derive(Clone, Debug)]
+pub struct GameInfo {
+    /// Assigned by the server, used to find a game's messages in the logs of the server and both clients
+    pub game_id: u32,
+    pub other_player: String,
+    pub is_black: bool,
+    /// The position the game started from, the server can start its games from any FEN
+    pub fen: String,
+    /// What both players and the server support, the game doesn't use anything else
+    pub features: Features,
+    /// Everything the other player's client supports
+    pub other_features: Features,
+    /// Random number from 0 to 9999 that lets this player take the seat back after losing the connection. Only the
+    /// player gets it, so someone who connects with the same name can't take over the game.
+    pub code: u16,
+    /// The server's message of the day, empty if it has none
+    pub motd: String,
+    /// False on servers that enforce fair play, the client turns off everything that assists the player
+    pub assistance_allowed: bool,
+    /// Milliseconds on each player's clock when the game starts, 0 if the game has no clock. Only games with
+    /// `Features::CLOCK` have one.
+    pub initial_time: u32,
+    /// Milliseconds added to a player's clock after each of their moves
+    pub increment: u32,
+}
+
+/// The server's answers to a player's `PlayerInfo`
+#[serializable]
+pub enum Lobby {
+    /// An opponent was found and the game starts
+    Game(GameInfo),
+    /// A game is waiting for a player with this name who lost the connection, with the name of the opponent. The
+    /// client answers with `Rejoin`, declining puts it in the queue for a new game.
+    Reconnect(String),
+    /// The code was right, the game continues
+    Rejoined(Rejoined),
+    /// The code was wrong or the seat isn't free (anymore), the server closes the connection
+    Refused,
+    /// The player's rules aren't the server's, with what differs. The server closes the connection.
+    Mismatch(String),
+}
+
+/// A client's answer to `Lobby::Reconnect`
+#[serializable]
+pub enum Rejoin {
+    Decline,
+    Code(u16),
+}
+
+#[serializable]
+pub struct Rejoined {
+    pub game: GameInfo,
+    /// The moves played so far, from `GameInfo::fen`
+    pub moves: Vec<Move>,
+}
+
+/// Sent to a spectator instead of `GameInfo`
+#[serializable]
+pub struct SpectateInfo {
+    pub game_id: u32,
+    pub white: String,
+    pub black: String,
+    /// The position the game started from
+    pub fen: String,
+    /// The moves played so far, the following ones are sent as `ServerMessage::Move`
+    pub moves: Vec<Move>,
+    /// What the spectator and the server support
+    pub features: Features,
+    /// The rules of the server, a spectator with other ones couldn't follow the game
+    pub rules: Rules,
+}
+
+/// Hash of a position written to the logs after every move, so the boards of the server and the clients can be
+/// compared without printing whole FENs
+pub fn position_hash(board: &Board, turn: Color) -> u64 {
+    fnv1a(board.to_fen(turn, 0, 1).bytes())
+}
+
+/// Messages larger than this are treated as a corrupted stream
+const MAX_FRAME_LEN: u32 = 1 << 20;
+
+#[derive(Debug)]
+pub enum RecvError {
+    /// Reading from the connection failed or it was closed
+    Io(io::Error),
+    /// A complete frame was received but its content is invalid. The connection can still be used.
+    BadFrame(BinverseError),
+}
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Io(err) => write!(f, "connection error: {err}"),
+            RecvError::BadFrame(err) => write!(f, "invalid message: {err:?}"),
+        }
+    }
+}
+impl Error for RecvError {}
+
+/// Every message is sent as a frame: its length as a little endian u32 followed by the serialized message. This way a
+/// message that can't be read is skipped as a whole instead of misinterpreting all following bytes.
+pub fn send<T: for<'a> Serialize<&'a mut Vec<u8>>, W: Write>(mut p: W, t: T) -> Result<(), BinverseError> {
+    let mut payload = Vec::new();
+    t.serialize(&mut Serializer::new_no_revision(&mut payload))?;
+    let len = u32::try_from(payload.len()).ok().filter(|len| *len <= MAX_FRAME_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+    p.write_all(&len.to_le_bytes())?;
+    p.write_all(&payload)?;
+    Ok(())
+}
+pub fn recv<T: for<'a> Deserialize<&'a mut Cursor<Vec<u8>>>, R: Read>(mut p: R) -> Result<T, RecvError> {
+    let mut len = [0; 4];
+    p.read_exact(&mut len).map_err(RecvError::Io)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_FRAME_LEN {
+        // the length itself is garbage so there is no way to find the next frame
+        return Err(RecvError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {len} bytes is too large"))));
+    }
+    let mut payload = vec![0; len as usize];
+    p.read_exact(&mut payload).map_err(RecvError::Io)?;
+
+    let mut payload = Cursor::new(payload);
+    let message = Deserializer::new_no_revision(&mut payload, 0).deserialize().map_err(RecvError::BadFrame)?;
+    if payload.position() != len as u64 {
+        let trailing = io::Error::new(io::ErrorKind::InvalidData, "trailing bytes after message");
+        return Err(RecvError::BadFrame(BinverseError::IO(trailing)));
+    }
+    Ok(message)
+}
+
+/// Messages of the day longer than this are cut off
+const MAX_MOTD_LEN: usize = 200;
+
+/// The message of the day without anything that would mess up the terminal. The client checks it again, it can't
+/// trust the server either.
+pub fn clean_motd(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).take(MAX_MOTD_LEN).collect::<String>().trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read};
+
+    use super::{recv, send, RecvError, MAX_FRAME_LEN};
+
+    /// Hands out the bytes one at a time like a slow connection
+    struct OneByte<R>(R);
+    impl<R: Read> Read for OneByte<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let end = buf.len().min(1);
+            self.0.read(&mut buf[..end])
+        }
+    }
+
+    fn frames(messages: &[&str]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for message in messages {
+            send(&mut bytes, message.to_string()).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn byte_by_byte() {
+        let messages = ["", "e2e4", "a message that is longer than the length prefix"];
+        let mut connection = OneByte(Cursor::new(frames(&messages)));
+        for message in messages {
+            assert_eq!(recv::<String, _>(&mut connection).unwrap(), message);
+        }
+        assert!(matches!(recv::<String, _>(&mut connection), Err(RecvError::Io(_))), "read past the last frame");
+    }
+
+    /// A frame whose payload can't be read is skipped as a whole, the connection goes on with the next one
+    #[test]
+    fn bad_frame() {
+        let mut bytes = frames(&["corrupted"]);
+        // one byte of the message is missing, the frame ends before the string does
+        bytes.pop();
+        let len = u32::from_le_bytes(bytes[..4].try_into().unwrap()) - 1;
+        bytes[..4].copy_from_slice(&len.to_le_bytes());
+        // one byte too many after the message
+        let mut trailing = frames(&["trailing"]);
+        trailing.push(0);
+        let len = u32::from_le_bytes(trailing[..4].try_into().unwrap()) + 1;
+        trailing[..4].copy_from_slice(&len.to_le_bytes());
+        bytes.extend(trailing);
+        bytes.extend(frames(&["valid"]));
+
+        let mut connection = Cursor::new(bytes);
+        for _ in 0..2 {
+            assert!(matches!(recv::<String, _>(&mut connection), Err(RecvError::BadFrame(_))));
+        }
+        assert_eq!(recv::<String, _>(&mut connection).unwrap(), "valid");
+    }
+
+    /// A length above the limit is garbage, nothing after it can be found, so it ends the connection
+    #[test]
+    fn frame_too_large() {
+        let mut bytes = (MAX_FRAME_LEN + 1).to_le_bytes().to_vec();
+        bytes.extend(frames(&["valid"]));
+        match recv::<String, _>(Cursor::new(bytes)) {
+            Err(RecvError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+            result => panic!("a frame above the limit was read as {result:?}"),
+        }
+
+        let mut sent = Vec::new();
+        assert!(send(&mut sent, "x".repeat(MAX_FRAME_LEN as usize)).is_err(), "a message above the limit was sent");
+        assert!(sent.is_empty(), "a part of the message above the limit was sent");
+    }
+}
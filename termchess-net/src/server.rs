@@ -0,0 +1,1403 @@
+//! The server: pairs up the players who connect, relays the moves of their games and lets spectators watch
+
+use std::{error::Error, fs, io::{Read, Write}, net::{TcpListener, IpAddr, TcpStream, SocketAddr, Shutdown}, io, path::{Path, PathBuf}, thread, sync::{mpsc::{Receiver, Sender, self, RecvTimeoutError, TryRecvError}, Arc, Mutex, Weak}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}, collections::{HashMap, VecDeque, hash_map::RandomState}, hash::{BuildHasher, Hasher}};
+
+use binverse::error::BinverseError;
+use log::{debug, info, warn};
+use vecm::vec2;
+use termchess_core::{board::{Board, MoveLegality}, civil_from_days, notation::{self, square}, piece::{Color, Piece}, GameEnd};
+
+use crate::protocol::{
+    clean_motd, position_hash, recv, send, ChatMessage, ChatScope, ClientMessage, ClockTimes, ColorPreference,
+    EndReason, Features, GameInfo, Handshake, Hello, Lobby, Move, MoveDetails, PlayerInfo, RecvError, Rejoin, Rejoined, Role,
+    Rules, ServerMessage, SpectateInfo, DETAILS_FEN_PLIES,
+};
+
+/// Chat messages longer than this are cut off
+const MAX_CHAT_LEN: usize = 200;
+/// Every connection can send this many chat messages within `CHAT_WINDOW`, further ones are dropped
+const CHAT_BURST: usize = 5;
+const CHAT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Games where a player doesn't make their first move in this time are aborted
+const ABORT_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the seat of a player who lost the connection is kept before the game is lost for them
+const REJOIN_TIMEOUT: Duration = Duration::from_secs(120);
+/// Wrong codes a seat accepts before it can't be rejoined anymore, so the code can't be guessed
+const MAX_REJOIN_ATTEMPTS: u32 = 3;
+/// How long a client offered a seat has to answer before the connection is dropped
+const REJOIN_ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the players have to ask for a rematch after a game
+const REMATCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The port the server listens on unless told otherwise
+pub const DEFAULT_PORT: u16 = 1337;
+
+#[derive(Clone)]
+pub struct ServerOptions {
+    /// The address to listen on, `0.0.0.0` accepts connections from everywhere and `127.0.0.1` only from this host
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Relay the chat of spectators to the players too
+    pub spectator_chat_to_players: bool,
+    /// Sent to the players when a game starts
+    pub motd: Option<String>,
+    /// Players can't use any assistance, like the control map or the threats panel
+    pub strict: bool,
+    /// The clock of every game, only used if both players have `Features::CLOCK`
+    pub time_control: Option<TimeControl>,
+    /// Where every finished game is written to as a PGN file, nowhere if `None`
+    pub games_dir: Option<PathBuf>,
+}
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::from([0, 0, 0, 0]),
+            port: DEFAULT_PORT,
+            spectator_chat_to_players: false,
+            motd: None,
+            strict: false,
+            time_control: None,
+            games_dir: None,
+        }
+    }
+}
+
+/// The time each player has for a game and what they get back after every move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    pub initial: Duration,
+    pub increment: Duration,
+}
+impl TimeControl {
+    /// Parses the usual notation of minutes and seconds of increment, like `5+3` or `0.5+0` for thirty seconds
+    pub fn parse(s: &str) -> Option<Self> {
+        let (minutes, seconds) = s.split_once('+')?;
+        Some(Self {
+            initial: Duration::try_from_secs_f64(minutes.parse::<f64>().ok()? * 60.0).ok().filter(|d| !d.is_zero())?,
+            increment: Duration::try_from_secs_f64(seconds.parse().ok()?).ok()?,
+        })
+    }
+
+    /// The initial time and the increment as they are sent in `GameInfo`
+    fn millis(self) -> (u32, u32) {
+        (millis(self.initial), millis(self.increment))
+    }
+}
+
+// times are sent in milliseconds, a u32 holds more than a month
+fn millis(duration: Duration) -> u32 {
+    u32::try_from(duration.as_millis()).unwrap_or(u32::MAX)
+}
+
+/// The clock of a game, games where a player's client has none are played without one
+fn time_control(options: &ServerOptions, features: Features) -> Option<TimeControl> {
+    options.time_control.filter(|_| features.contains(Features::CLOCK))
+}
+
+/// Where a message to a game's thread came from
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Origin {
+    Player(Color),
+    /// A spectator with its connection id
+    Spectator(u32),
+}
+
+/// What the connections tell a game's thread
+enum GameEvent {
+    Message(Origin, Result<ClientMessage, RecvError>),
+    /// A player who lost the connection entered the right code with a new one
+    Rejoined(Color, TcpStream),
+}
+
+/// The state of a running game that is shared with the server for spectators to join
+struct Watched {
+    game_id: u32,
+    white: String,
+    black: String,
+    fen: String,
+    moves: Vec<Move>,
+    /// What both players and the server support
+    features: Features,
+    /// Everything the clients of White and Black support
+    player_features: [Features; 2],
+    /// The rejoin codes of White and Black
+    codes: [u16; 2],
+    /// The player whose seat is kept after they lost the connection
+    missing: Option<Missing>,
+    spectators: Vec<Spectator>,
+    /// Passes the messages of all connections to the game's thread
+    tx: Sender<GameEvent>,
+}
+impl Watched {
+    fn name(&self, color: Color) -> &str {
+        if color == Color::White { &self.white } else { &self.black }
+    }
+
+    /// Sends a message to all spectators that understand it. Spectators whose connection failed are dropped.
+    fn broadcast(&mut self, message: &ServerMessage) {
+        self.spectators.retain_mut(|spectator| {
+            !spectator.features.contains(message.feature()) || send(&mut spectator.socket, message.clone()).is_ok()
+        });
+    }
+
+    /// Sends a move to all spectators, with its details to those that understand them
+    fn broadcast_move(&mut self, details: MoveDetails) {
+        let slim = ServerMessage::Move(details.played.clone());
+        let detailed = ServerMessage::MoveDetails(details);
+        self.spectators.retain_mut(|spectator| {
+            let message = if spectator.features.contains(Features::MOVE_DETAILS) { &detailed } else { &slim };
+            send(&mut spectator.socket, message.clone()).is_ok()
+        });
+    }
+}
+
+struct Missing {
+    color: Color,
+    /// Wrong codes entered so far
+    attempts: u32,
+}
+
+struct Spectator {
+    /// Connection id
+    id: u32,
+    name: String,
+    socket: TcpStream,
+    features: Features,
+}
+
+// reads messages from a connection until it fails, or until the player asks for a rematch. The rematch's thread reads
+// the connection from then on, this one would take its first message.
+fn forward(mut socket: TcpStream, origin: Origin, tx: Sender<GameEvent>) {
+    thread::spawn(move || loop {
+        let message = recv::<ClientMessage, _>(&mut socket);
+        let failed = matches!(message, Err(RecvError::Io(_)));
+        let rematch = matches!(message, Ok(ClientMessage::Rematch));
+        if tx.send(GameEvent::Message(origin, message)).is_err() || failed || rematch {
+            break;
+        }
+    });
+}
+
+pub fn run_server(board: Board, turn: Color, options: &ServerOptions) -> Result<(), Box<dyn Error>> {
+    let bind = SocketAddr::from((options.bind, options.port));
+    let listener = TcpListener::bind(bind).map_err(|err| format!("can't listen on {bind}: {err}"))?;
+    // players that declined to rejoin a game come back through a channel, so accepting can't block
+    listener.set_nonblocking(true)?;
+
+    info!("listening on {}", listener.local_addr()?);
+
+    let mut next_game_id = 1;
+    let mut next_spectator_id = 0;
+    // a player that is waiting for an opponent
+    let mut waiting: Option<(TcpStream, PlayerInfo)> = None;
+    let mut games: Vec<Weak<Mutex<Watched>>> = Vec::new();
+    let (declined_tx, declined) = mpsc::channel();
+    // games whose players both asked for a rematch hand their connections back
+    let (rematch_tx, rematches) = mpsc::channel::<Pair>();
+
+    loop {
+        if let Ok(pair) = rematches.try_recv() {
+            let game_id = next_game_id;
+            next_game_id += 1;
+            info!("game {game_id}: rematch of {} and {}", pair[0].1.name, pair[1].1.name);
+            match start_game(pair, game_id, board, turn, true, options, rematch_tx.clone()) {
+                Ok(watched) => games.push(Arc::downgrade(&watched)),
+                Err(err) => warn!("game {game_id}: the rematch couldn't start: {err}"),
+            }
+            continue;
+        }
+        let (mut socket, addr, info, may_rejoin) = match declined.try_recv() {
+            Ok((socket, addr, info)) => (socket, addr, info, false),
+            Err(TryRecvError::Empty | TryRecvError::Disconnected) => match listener.accept() {
+                Ok((mut socket, addr)) => {
+                    socket.set_nonblocking(false)?;
+                    let Some(info) = greet(&mut socket)? else {
+                        info!("refused {addr}, the client speaks another protocol or plays by other rules");
+                        continue;
+                    };
+                    (socket, addr, info, true)
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(10));
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            },
+        };
+        games.retain(|game| game.strong_count() > 0);
+
+        if info.role == Role::Spectator {
+            let Some(game) = games.last().and_then(Weak::upgrade) else {
+                info!("spectator {} from {addr} has no game to watch", info.name);
+                continue;
+            };
+            let mut game = game.lock().unwrap();
+            let id = next_spectator_id;
+            next_spectator_id += 1;
+            let features = info.features.intersection(Features::SUPPORTED);
+            let spectate_info = SpectateInfo {
+                game_id: game.game_id,
+                white: game.white.clone(),
+                black: game.black.clone(),
+                fen: game.fen.clone(),
+                moves: game.moves.clone(),
+                features,
+                rules: Rules::current(),
+            };
+            if let Err(err) = send(&mut socket, spectate_info) {
+                warn!("game {}: failed to send the game to spectator {}: {err:?}", game.game_id, info.name);
+                continue;
+            }
+            info!("game {}: {} connected from {addr} as spectator {id}", game.game_id, info.name);
+            forward(socket.try_clone()?, Origin::Spectator(id), game.tx.clone());
+            game.spectators.push(Spectator { id, name: info.name, socket, features });
+            continue;
+        }
+
+        let seat = games.iter().filter_map(Weak::upgrade).find(|game| {
+            let game = game.lock().unwrap();
+            game.missing.as_ref().is_some_and(|missing| {
+                missing.attempts < MAX_REJOIN_ATTEMPTS && game.name(missing.color) == info.name
+            })
+        });
+        // a client that lost the connection comes back by itself, it's never queued for a new game
+        if let Role::Rejoin(code) = info.role {
+            match seat {
+                Some(game) => if let Err(err) = take_seat(socket, code, &game) {
+                    warn!("game {}: {} from {addr} couldn't rejoin: {err}", game.lock().unwrap().game_id, info.name);
+                }
+                None => {
+                    info!("{} from {addr} has no seat to rejoin", info.name);
+                    let _ = send(&mut socket, Lobby::Refused);
+                }
+            }
+            continue;
+        }
+        if let (Some(game), true) = (seat, may_rejoin) {
+            let declined_tx = declined_tx.clone();
+            thread::spawn(move || {
+                if let Err(err) = offer_seat(socket, addr, info, &game, declined_tx) {
+                    warn!("game {}: offering the seat to {addr} failed: {err}", game.lock().unwrap().game_id);
+                }
+            });
+            continue;
+        }
+
+        let Some((first, first_info)) = waiting.take() else {
+            info!("game {next_game_id}: {} connected from {addr}, waiting for an opponent", info.name);
+            waiting = Some((socket, info));
+            continue;
+        };
+        let game_id = next_game_id;
+        next_game_id += 1;
+        info!("game {game_id}: {} connected from {addr}", info.name);
+        let colors = assign_colors(first_info.prefer, info.prefer, coin_flip());
+        let pair = if colors[0] == Color::White {
+            [(first, first_info), (socket, info)]
+        } else {
+            [(socket, info), (first, first_info)]
+        };
+        info!(
+            "game {game_id}: {} plays White ({:?}), {} plays Black ({:?})",
+            pair[0].1.name, pair[0].1.prefer, pair[1].1.name, pair[1].1.prefer,
+        );
+        let watched = start_game(pair, game_id, board, turn, false, options, rematch_tx.clone())?;
+        games.push(Arc::downgrade(&watched));
+    }
+}
+
+/// White and Black with what they told the server
+type Pair = [(TcpStream, PlayerInfo); 2];
+
+/// Tells both players about their new game and hosts it on its own thread. A rematch is announced with
+/// `ServerMessage::Rematch` instead of `Lobby::Game` because the clients are still listening for the messages of their
+/// last game. If the players want a rematch after this game their connections come back through `rematch_tx`.
+fn start_game(
+    [(mut white, white_info), (mut black, black_info)]: Pair,
+    game_id: u32,
+    board: Board,
+    turn: Color,
+    rematch: bool,
+    options: &ServerOptions,
+    rematch_tx: Sender<Pair>,
+) -> Result<Arc<Mutex<Watched>>, Box<dyn Error>> {
+    let features = white_info.features.intersection(black_info.features).intersection(Features::SUPPORTED);
+    info!("game {game_id}: using features {:#x}", features.bits);
+    let codes = [rejoin_code(game_id, Color::White), rejoin_code(game_id, Color::Black)];
+    // the players already saw it before their first game
+    let motd = if rematch { String::new() } else { options.motd.as_deref().map_or_else(String::new, clean_motd) };
+    let (initial_time, increment) = time_control(options, features).map_or((0, 0), TimeControl::millis);
+
+    let fen = board.to_fen(turn, 0, 1);
+    let infos = [
+        GameInfo {
+            game_id, other_player: black_info.name.clone(), is_black: false, fen: fen.clone(), features,
+            other_features: black_info.features, code: codes[0], motd: motd.clone(),
+            assistance_allowed: !options.strict, initial_time, increment,
+        },
+        GameInfo {
+            game_id, other_player: white_info.name.clone(), is_black: true, fen: fen.clone(), features,
+            other_features: white_info.features, code: codes[1], motd, assistance_allowed: !options.strict,
+            initial_time, increment,
+        },
+    ];
+    for (socket, info) in [&mut white, &mut black].into_iter().zip(infos) {
+        if rematch {
+            send(socket, ServerMessage::Rematch(info))?;
+        } else {
+            send(socket, Lobby::Game(info))?;
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let watched = Arc::new(Mutex::new(Watched {
+        game_id,
+        white: white_info.name,
+        black: black_info.name,
+        fen,
+        moves: Vec::new(),
+        features,
+        player_features: [white_info.features, black_info.features],
+        codes,
+        missing: None,
+        spectators: Vec::new(),
+        tx,
+    }));
+
+    let options = options.clone();
+    let game = watched.clone();
+    thread::spawn(move || {
+        match host_game(board, turn, [white, black], &game, rx, &options) {
+            Ok((end, rematch)) => {
+                info!("game {game_id}: finished successfully");
+                if let Some(dir) = &options.games_dir {
+                    match save_game(&game.lock().unwrap(), &end, dir) {
+                        Ok(path) => info!("game {game_id}: saved to {}", path.display()),
+                        Err(err) => warn!("game {game_id}: saving failed: {err}"),
+                    }
+                }
+                if let Some(pair) = rematch {
+                    // fails only if the server stopped
+                    let _ = rematch_tx.send(pair);
+                }
+            }
+            Err(err) => warn!("game {game_id}: ended early: {err}"),
+        }
+    });
+    Ok(watched)
+}
+
+/// Exchanges `Hello` with a client and reads the `PlayerInfo` it goes on with. A client with another protocol version
+/// or a player with other rules than the server's is told what differs and None is returned, a spectator finds out
+/// about the rules from `SpectateInfo::rules`.
+pub fn greet(mut socket: impl Read + Write) -> Result<Option<PlayerInfo>, Box<dyn Error>> {
+    let hello = match recv::<Hello, _>(&mut socket) {
+        Ok(hello) => hello,
+        // clients from before the handshake start with their `PlayerInfo`, which doesn't read as a `Hello`
+        Err(RecvError::BadFrame(_)) => Hello { protocol_version: 0 },
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(difference) = hello.mismatch(&Hello::current(), "the server") {
+        send(&mut socket, Handshake::Reject(difference))?;
+        return Ok(None);
+    }
+    send(&mut socket, Handshake::Hello(Hello::current()))?;
+    let info: PlayerInfo = recv(&mut socket)?;
+    if info.role != Role::Spectator {
+        if let Some(difference) = info.rules.mismatch(&Rules::current(), "the server") {
+            send(&mut socket, Lobby::Mismatch(difference))?;
+            return Ok(None);
+        }
+    }
+    Ok(Some(info))
+}
+
+/// Asks a player who connected with the name of a missing player whether they want to rejoin and checks their code.
+/// Players who decline go back to the server's queue.
+fn offer_seat(
+    mut socket: TcpStream,
+    addr: SocketAddr,
+    info: PlayerInfo,
+    game: &Mutex<Watched>,
+    declined: Sender<(TcpStream, SocketAddr, PlayerInfo)>,
+) -> Result<(), Box<dyn Error>> {
+    let (game_id, other_player) = {
+        let game = game.lock().unwrap();
+        let color = game.missing.as_ref().ok_or("the seat was taken")?.color;
+        (game.game_id, game.name(!color).to_owned())
+    };
+    info!("game {game_id}: offering the seat of {} to {addr}", info.name);
+    send(&mut socket, Lobby::Reconnect(other_player))?;
+    // the game might end while the player is typing, a client that never answers shouldn't keep the thread around
+    socket.set_read_timeout(Some(REJOIN_ANSWER_TIMEOUT))?;
+    let code = match recv(&mut socket)? {
+        Rejoin::Decline => {
+            info!("game {game_id}: {addr} declined to rejoin");
+            socket.set_read_timeout(None)?;
+            declined.send((socket, addr, info))?;
+            return Ok(());
+        }
+        Rejoin::Code(code) => code,
+    };
+    take_seat(socket, code, game)
+}
+
+/// Gives the missing player's seat to the connection if the code is right, the game's thread sends it the game
+fn take_seat(mut socket: TcpStream, code: u16, game: &Mutex<Watched>) -> Result<(), Box<dyn Error>> {
+    let mut game = game.lock().unwrap();
+    let color = match &mut game.missing {
+        Some(missing) if missing.attempts < MAX_REJOIN_ATTEMPTS => {
+            missing.attempts += 1;
+            missing.color
+        }
+        _ => {
+            send(&mut socket, Lobby::Refused)?;
+            return Err("the seat isn't free anymore".into());
+        }
+    };
+    if game.codes[seat(color)] != code {
+        send(&mut socket, Lobby::Refused)?;
+        return Err(format!("wrong code for {color:?}").into());
+    }
+    socket.set_read_timeout(None)?;
+    game.missing = None;
+    // the game's thread sends the moves, so none can be played in between
+    game.tx.send(GameEvent::Rejoined(color, socket))?;
+    Ok(())
+}
+
+/// The colors of the player who waited for an opponent and of the one who came second. Each gets the color they
+/// prefer unless both prefer the same one, then `coin` decides like it does when neither has a preference.
+pub fn assign_colors(first: ColorPreference, second: ColorPreference, coin: bool) -> [Color; 2] {
+    let first_is_white = match (first, second) {
+        (ColorPreference::White, ColorPreference::Random | ColorPreference::Black)
+        | (ColorPreference::Random, ColorPreference::Black) => true,
+        (ColorPreference::Black, ColorPreference::Random | ColorPreference::White)
+        | (ColorPreference::Random, ColorPreference::White) => false,
+        _ => coin,
+    };
+    if first_is_white { [Color::White, Color::Black] } else { [Color::Black, Color::White] }
+}
+
+/// True or false by chance, std's hasher keys are random
+fn coin_flip() -> bool {
+    RandomState::new().build_hasher().finish() & 1 == 1
+}
+
+/// A number from 0 to 9999 that is hard to guess, std's hasher keys are random
+fn rejoin_code(game_id: u32, color: Color) -> u16 {
+    (RandomState::new().hash_one((game_id, color == Color::White, Instant::now())) % 10_000) as u16
+}
+
+fn seat(color: Color) -> usize {
+    if color == Color::White { 0 } else { 1 }
+}
+
+/// Drops chat messages of connections that send too many
+#[derive(Default)]
+struct ChatLimiter {
+    sent: HashMap<Origin, VecDeque<Instant>>,
+}
+impl ChatLimiter {
+    fn allow(&mut self, origin: Origin) -> bool {
+        let now = Instant::now();
+        let sent = self.sent.entry(origin).or_default();
+        while sent.front().is_some_and(|time| now.duration_since(*time) > CHAT_WINDOW) {
+            sent.pop_front();
+        }
+        if sent.len() >= CHAT_BURST {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+}
+
+/// Plays a game to its end. If both players want a rematch their connections are returned with the colors swapped,
+/// otherwise they are closed, however the game ended.
+fn host_game(
+    board: Board,
+    turn: Color,
+    mut players: [TcpStream; 2],
+    watched: &Mutex<Watched>,
+    rx: Receiver<GameEvent>,
+    options: &ServerOptions,
+) -> Result<(GameEnd, Option<Pair>), Box<dyn Error>> {
+    let result = play_game(board, turn, &mut players, watched, &rx, options);
+    if matches!(result, Ok(GameEnd::Winner(_) | GameEnd::Draw)) && rematch(&mut players, watched, &rx) {
+        let watched = watched.lock().unwrap();
+        let info = |color: Color| PlayerInfo {
+            name: watched.name(color).to_owned(),
+            role: Role::Player,
+            features: watched.player_features[seat(color)],
+            rules: Rules::current(),
+            prefer: ColorPreference::Random,
+        };
+        let [white, black] = players;
+        let pair = [(black, info(Color::Black)), (white, info(Color::White))];
+        return result.map(|end| (end, Some(pair)));
+    }
+    // the threads reading the players hold clones of the sockets, shutting down ends them too
+    for player in &players {
+        let _ = player.shutdown(Shutdown::Both);
+    }
+    result.map(|end| (end, None))
+}
+
+/// Waits for both players to ask for a rematch after their game. Gives up when one of them leaves or doesn't ask in
+/// time, a player who asked is told then.
+fn rematch(players: &mut [TcpStream; 2], watched: &Mutex<Watched>, rx: &Receiver<GameEvent>) -> bool {
+    let (game_id, features) = {
+        let watched = watched.lock().unwrap();
+        (watched.game_id, watched.features)
+    };
+    if !features.contains(Features::REMATCH) {
+        return false;
+    }
+    let deadline = Instant::now() + REMATCH_TIMEOUT;
+    let mut asked = [false; 2];
+    while let Ok(event) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        match event {
+            GameEvent::Message(Origin::Player(color), Ok(ClientMessage::Rematch)) => {
+                info!("game {game_id}: {color:?} wants a rematch");
+                asked[seat(color)] = true;
+                if asked == [true; 2] {
+                    return true;
+                }
+                if send(&mut players[seat(!color)], ServerMessage::RematchOffered).is_err() {
+                    break;
+                }
+            }
+            GameEvent::Message(Origin::Player(color), Err(RecvError::Io(_))) => {
+                info!("game {game_id}: {color:?} left after the game");
+                break;
+            }
+            // a takeback can't undo the end of the game
+            GameEvent::Message(Origin::Player(color), Ok(ClientMessage::TakebackRequest)) => {
+                debug!("game {game_id}: {color:?} asked for a takeback after the game");
+                if send(&mut players[seat(color)], ServerMessage::TakebackDeclined).is_err() {
+                    break;
+                }
+            }
+            // moves and chat that come after the end are dropped
+            _ => {}
+        }
+    }
+    info!("game {game_id}: no rematch");
+    for color in [Color::White, Color::Black] {
+        if asked[seat(color)] {
+            let _ = send(&mut players[seat(color)], ServerMessage::RematchDeclined);
+        }
+    }
+    false
+}
+
+fn play_game(
+    mut board: Board,
+    mut turn: Color,
+    players: &mut [TcpStream; 2],
+    watched: &Mutex<Watched>,
+    rx: &Receiver<GameEvent>,
+    options: &ServerOptions,
+) -> Result<GameEnd, Box<dyn Error>> {
+    let (game_id, tx, names, features, player_features, codes) = {
+        let watched = watched.lock().unwrap();
+        let names = [watched.white.clone(), watched.black.clone()];
+        (watched.game_id, watched.tx.clone(), names, watched.features, watched.player_features, watched.codes)
+    };
+    // both players are read at the same time because either of them can abort
+    forward(players[0].try_clone()?, Origin::Player(Color::White), tx.clone());
+    forward(players[1].try_clone()?, Origin::Player(Color::Black), tx.clone());
+    let mut chat_limiter = ChatLimiter::default();
+
+    let mut plies = 0;
+    let mut moves = Vec::new();
+    // positions after each ply for checking the clients' boards
+    let mut fens = vec![board.to_fen(turn, 0, 1)];
+    let mut abort_deadline = Instant::now() + ABORT_TIMEOUT;
+    // the player who lost the connection, messages to them are dropped until they rejoin
+    let mut missing: Option<Color> = None;
+    let mut rejoin_deadline = Instant::now();
+    // the player whose draw offer stands
+    let mut draw_offer: Option<Color> = None;
+    // the player whose takeback request stands
+    let mut takeback: Option<Color> = None;
+    let time_control = time_control(options, features);
+    let (initial_time, increment) = time_control.map_or((0, 0), TimeControl::millis);
+    // the time White and Black have left, the clock of the side to move runs since `turn_start` once both moved
+    let mut time_left = [time_control.map_or(Duration::ZERO, |time_control| time_control.initial); 2];
+    let mut turn_start = Instant::now();
+    loop {
+        let flag_deadline = time_control.filter(|_| plies >= 2).map(|_| turn_start + time_left[seat(turn)]);
+        let deadline = if plies < 2 {
+            Some(abort_deadline)
+        } else {
+            [missing.map(|_| rejoin_deadline), flag_deadline].into_iter().flatten().min()
+        };
+        let event = match deadline.map(|deadline| rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))) {
+            None => rx.recv()?,
+            Some(Ok(event)) => event,
+            Some(Err(RecvTimeoutError::Timeout)) if plies < 2 => {
+                info!("game {game_id} ply {plies}: no move was made in time, aborting");
+                return abort(players, watched);
+            }
+            Some(Err(RecvTimeoutError::Timeout)) if flag_deadline.is_some_and(|at| Instant::now() >= at) => {
+                return flag(players, missing, watched, &board, turn, game_id, plies);
+            }
+            Some(Err(RecvTimeoutError::Timeout)) => {
+                let color = missing.expect("only waiting with a deadline for a missing player");
+                let mut watched = watched.lock().unwrap();
+                // the right code was entered just now, the new connection is already in the channel
+                if watched.missing.take().is_none() {
+                    continue;
+                }
+                info!("game {game_id} ply {plies}: {color:?} didn't come back, {:?} wins", !color);
+                let message = ServerMessage::GameOver(EndReason::Abandoned(color == Color::Black));
+                let _ = send(&mut players[seat(!color)], message.clone());
+                watched.broadcast(&message);
+                return Ok(GameEnd::Winner(!color));
+            }
+            Some(Err(RecvTimeoutError::Disconnected)) => return Err("player connections closed".into()),
+        };
+        let (origin, message) = match event {
+            GameEvent::Message(origin, message) => (origin, message),
+            GameEvent::Rejoined(color, mut socket) => {
+                let other = seat(!color);
+                let rejoined = Rejoined {
+                    game: GameInfo {
+                        game_id, other_player: names[other].clone(), is_black: color == Color::Black,
+                        fen: fens[0].clone(), features, other_features: player_features[other],
+                        code: codes[seat(color)],
+                        // only sent when a game starts
+                        motd: String::new(),
+                        assistance_allowed: !options.strict,
+                        initial_time, increment,
+                    },
+                    moves: moves.clone(),
+                };
+                if let Err(err) = send(&mut socket, Lobby::Rejoined(rejoined)) {
+                    warn!("game {game_id} ply {plies}: failed to send the game to {color:?} after rejoining: {err:?}");
+                    watched.lock().unwrap().missing = Some(Missing { color, attempts: 0 });
+                    continue;
+                }
+                info!("game {game_id} ply {plies}: {color:?} rejoined");
+                forward(socket.try_clone()?, Origin::Player(color), tx.clone());
+                players[seat(color)] = socket;
+                missing = None;
+                send(&mut players[other], ServerMessage::OpponentBack)?;
+                if time_control.is_some() {
+                    let mut now_left = time_left;
+                    if plies >= 2 {
+                        now_left[seat(turn)] = now_left[seat(turn)].saturating_sub(turn_start.elapsed());
+                    }
+                    send(&mut players[seat(color)], ServerMessage::Clock(clock_times(now_left)))?;
+                }
+                continue;
+            }
+        };
+        let color = match origin {
+            Origin::Player(color) => color,
+            Origin::Spectator(id) => {
+                match message {
+                    Ok(ClientMessage::Chat(text)) => if chat_limiter.allow(origin) {
+                        let mut watched = watched.lock().unwrap();
+                        let Some(spectator) = watched.spectators.iter().find(|spectator| spectator.id == id) else {
+                            continue;
+                        };
+                        let chat = ChatMessage {
+                            scope: ChatScope::Spectators, sender: spectator.name.clone(), text: clean_chat(&text),
+                        };
+                        debug!("game {game_id} ply {plies}: spectator {id} says {:?}", chat.text);
+                        if options.spectator_chat_to_players && features.contains(Features::CHAT) {
+                            send_players(players, missing, ServerMessage::Chat(chat.clone()))?;
+                        }
+                        watched.broadcast(&ServerMessage::Chat(chat));
+                    } else {
+                        debug!("game {game_id} ply {plies}: dropped chat message of spectator {id}");
+                    }
+                    Ok(message) => warn!("game {game_id} ply {plies}: ignoring {message:?} from spectator {id}"),
+                    Err(RecvError::BadFrame(err)) => {
+                        warn!("game {game_id} ply {plies}: skipping invalid message from spectator {id}: {err:?}");
+                    }
+                    Err(RecvError::Io(_)) => {
+                        info!("game {game_id} ply {plies}: spectator {id} left");
+                        watched.lock().unwrap().spectators.retain(|spectator| spectator.id != id);
+                    }
+                }
+                continue;
+            }
+        };
+        if let Ok(message) = &message {
+            debug!("game {game_id} ply {plies}: received {message:?} from {color:?}");
+        }
+        let played_move = match message {
+            Ok(ClientMessage::Move(played_move)) if color == turn => played_move,
+            Ok(ClientMessage::Move(_)) => {
+                warn!("game {game_id} ply {plies}: {color:?} tried to move out of turn");
+                continue;
+            }
+            Ok(message) if !features.contains(message.feature()) => {
+                warn!("game {game_id} ply {plies}: {color:?} sent {message:?} which the game doesn't support");
+                continue;
+            }
+            Ok(ClientMessage::Abort) if plies < 2 => {
+                info!("game {game_id} ply {plies}: {color:?} aborted the game");
+                return abort(players, watched);
+            }
+            Ok(ClientMessage::Abort) => continue,
+            Ok(ClientMessage::Resign) => {
+                info!("game {game_id} ply {plies}: {color:?} resigned, {:?} wins", !color);
+                let message = ServerMessage::GameOver(EndReason::Resigned(color == Color::Black));
+                if missing != Some(!color) {
+                    send(&mut players[seat(!color)], message.clone())?;
+                }
+                watched.lock().unwrap().broadcast(&message);
+                return Ok(GameEnd::Winner(!color));
+            }
+            // offering back is the same as accepting
+            Ok(ClientMessage::OfferDraw | ClientMessage::AcceptDraw) if draw_offer == Some(!color) => {
+                info!("game {game_id} ply {plies}: {color:?} accepted the draw offer");
+                let message = ServerMessage::GameOver(EndReason::DrawAgreed);
+                send_players(players, missing, message.clone())?;
+                watched.lock().unwrap().broadcast(&message);
+                return Ok(GameEnd::Draw);
+            }
+            Ok(ClientMessage::OfferDraw) => {
+                if draw_offer != Some(color) {
+                    info!("game {game_id} ply {plies}: {color:?} offers a draw");
+                    draw_offer = Some(color);
+                    if missing != Some(!color) {
+                        send(&mut players[seat(!color)], ServerMessage::DrawOffered)?;
+                    }
+                }
+                continue;
+            }
+            Ok(ClientMessage::AcceptDraw) => {
+                // the offer was withdrawn by a move that crossed the answer
+                debug!("game {game_id} ply {plies}: {color:?} accepted a draw that isn't offered");
+                continue;
+            }
+            Ok(ClientMessage::DeclineDraw) => {
+                if draw_offer == Some(!color) {
+                    info!("game {game_id} ply {plies}: {color:?} declined the draw offer");
+                    draw_offer = None;
+                    if missing != Some(!color) {
+                        send(&mut players[seat(!color)], ServerMessage::DrawDeclined)?;
+                    }
+                }
+                continue;
+            }
+            Ok(ClientMessage::TakebackRequest) => {
+                let undone = if turn == color { 2 } else { 1 };
+                if undone > plies {
+                    debug!("game {game_id} ply {plies}: {color:?} asked for a takeback without a move to take back");
+                    send(&mut players[seat(color)], ServerMessage::TakebackDeclined)?;
+                } else if takeback != Some(color) {
+                    info!("game {game_id} ply {plies}: {color:?} asks for a takeback");
+                    takeback = Some(color);
+                    if missing != Some(!color) {
+                        send(&mut players[seat(!color)], ServerMessage::TakebackRequested)?;
+                    }
+                }
+                continue;
+            }
+            Ok(ClientMessage::TakebackAccept) if takeback == Some(!color) => {
+                takeback = None;
+                let requester = !color;
+                let undone = if turn == requester { 2 } else { 1 };
+                info!("game {game_id} ply {plies}: {color:?} accepted the takeback of {undone} plies");
+                if let Some(time_control) = time_control {
+                    if plies >= 2 {
+                        time_left[seat(turn)] = time_left[seat(turn)].saturating_sub(turn_start.elapsed());
+                    }
+                    // the increments of the moves that are taken back are gone again, the time spent on them isn't.
+                    // The last move was made by the side that isn't to move.
+                    for mover in [!turn, turn].into_iter().take(undone as usize) {
+                        time_left[seat(mover)] = time_left[seat(mover)].saturating_sub(time_control.increment);
+                    }
+                    turn_start = Instant::now();
+                }
+                plies -= undone;
+                moves.truncate(plies as usize);
+                fens.truncate(plies as usize + 1);
+                (board, turn) = Board::from_fen(&fens[plies as usize]).ok_or("invalid position after a takeback")?;
+                // the draw offer was made in a position that's gone now
+                draw_offer = None;
+                abort_deadline = Instant::now() + ABORT_TIMEOUT;
+                let message = ServerMessage::TookBack(plies);
+                send_players(players, missing, message.clone())?;
+                let clock = time_control.map(|_| ServerMessage::Clock(clock_times(time_left)));
+                if let Some(clock) = &clock {
+                    send_players(players, missing, clock.clone())?;
+                }
+                let mut watched = watched.lock().unwrap();
+                watched.moves.truncate(plies as usize);
+                watched.broadcast(&message);
+                if let Some(clock) = &clock {
+                    watched.broadcast(clock);
+                }
+                continue;
+            }
+            Ok(ClientMessage::TakebackAccept) => {
+                // the request was withdrawn by a move that crossed the answer
+                debug!("game {game_id} ply {plies}: {color:?} accepted a takeback that isn't requested");
+                continue;
+            }
+            Ok(ClientMessage::TakebackDecline) => {
+                if takeback == Some(!color) {
+                    info!("game {game_id} ply {plies}: {color:?} declined the takeback");
+                    takeback = None;
+                    if missing != Some(!color) {
+                        send(&mut players[seat(!color)], ServerMessage::TakebackDeclined)?;
+                    }
+                }
+                continue;
+            }
+            Ok(ClientMessage::Chat(text)) => {
+                if !chat_limiter.allow(origin) {
+                    debug!("game {game_id} ply {plies}: dropped chat message of {color:?}");
+                    continue;
+                }
+                let sender = names[seat(color)].clone();
+                let message = ServerMessage::Chat(ChatMessage { scope: ChatScope::Players, sender, text: clean_chat(&text) });
+                send_players(players, missing, message.clone())?;
+                watched.lock().unwrap().broadcast(&message);
+                continue;
+            }
+            Ok(ClientMessage::Rematch) => {
+                // reading the connection stopped with it, the game isn't over yet
+                warn!("game {game_id} ply {plies}: {color:?} asked for a rematch during the game");
+                forward(players[seat(color)].try_clone()?, Origin::Player(color), tx.clone());
+                continue;
+            }
+            Err(RecvError::BadFrame(err)) => {
+                warn!("game {game_id} ply {plies}: skipping invalid message from {color:?}: {err:?}");
+                continue;
+            }
+            Ok(ClientMessage::SyncCheck(check)) => {
+                match fens.get(check.ply as usize) {
+                    Some(fen) if *fen != check.fen => {
+                        warn!(
+                            "game {game_id} ply {}: {color:?} is out of sync, server: {fen}, client: {}",
+                            check.ply, check.fen,
+                        );
+                        let resync = ServerMessage::Resync(moves.clone());
+                        debug!("game {game_id} ply {plies}: sending {resync:?} to {color:?}");
+                        send(&mut players[seat(color)], resync)?;
+                    }
+                    Some(_) => {}
+                    None => warn!(
+                        "game {game_id} ply {plies}: {color:?} sent a sync check for ply {} which wasn't played yet",
+                        check.ply,
+                    ),
+                }
+                continue;
+            }
+            Err(_) if plies < 2 => {
+                info!("game {game_id} ply {plies}: {color:?} left before the game started");
+                return abort(players, watched);
+            }
+            Err(RecvError::Io(err)) if missing.is_none() && features.contains(Features::RECONNECT) => {
+                info!("game {game_id} ply {plies}: {color:?} lost the connection ({err}), keeping the seat");
+                missing = Some(color);
+                rejoin_deadline = Instant::now() + REJOIN_TIMEOUT;
+                watched.lock().unwrap().missing = Some(Missing { color, attempts: 0 });
+                send(&mut players[seat(!color)], ServerMessage::OpponentLeft(REJOIN_TIMEOUT.as_secs() as u32))?;
+                continue;
+            }
+            // without a seat to keep the game is over right away
+            Err(RecvError::Io(err)) if missing.is_none() => {
+                info!("game {game_id} ply {plies}: {color:?} lost the connection ({err}), {:?} wins", !color);
+                let message = ServerMessage::GameOver(EndReason::Abandoned(color == Color::Black));
+                send(&mut players[seat(!color)], message.clone())?;
+                watched.lock().unwrap().broadcast(&message);
+                return Ok(GameEnd::Winner(!color));
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        // the timeout can lose the race against a move that came too late
+        let thought = turn_start.elapsed();
+        if flag_deadline.is_some() && thought >= time_left[seat(turn)] {
+            return flag(players, missing, watched, &board, turn, game_id, plies);
+        }
+        let (from, to) = (vec2![played_move.x1, played_move.y1], vec2![played_move.x2, played_move.y2]);
+        let (san, taken) = match apply_move(&mut board, turn, &played_move) {
+            Ok(applied) => applied,
+            // the client's board disagrees with the server's, it gets the game again if it can take it
+            Err(legality) => {
+                warn!(
+                    "game {game_id} ply {plies}: rejected the move {from} -> {to} of {color:?}, {legality:?}",
+                );
+                if features.contains(Features::SYNC_CHECK) {
+                    send(&mut players[seat(color)], ServerMessage::Resync(moves.clone()))?;
+                }
+                continue;
+            }
+        };
+        // moving withdraws the player's own draw offer, the opponent's stays until it's answered
+        if draw_offer == Some(color) {
+            draw_offer = None;
+        }
+        // any move changes what a takeback would undo
+        takeback = None;
+        if let Some(time_control) = time_control {
+            if flag_deadline.is_some() {
+                time_left[seat(turn)] -= thought;
+            }
+            time_left[seat(turn)] += time_control.increment;
+            turn_start = Instant::now();
+        }
+        turn = !turn;
+        plies += 1;
+        info!(
+            "game {game_id} ply {plies}: applied {}{}{}, position {:016x}",
+            square(from), square(to), taken.map_or(String::new(), |taken| format!(" taking {taken:?}")),
+            position_hash(&board, turn),
+        );
+        moves.push(played_move.clone());
+        fens.push(board.to_fen(turn, 0, 1));
+        abort_deadline = Instant::now() + ABORT_TIMEOUT;
+        
+        let king_pos = board.find_king(turn).ok_or("king not found")?;
+        let check = board.threatens(king_pos, !turn);
+        let game_end = if !board.has_legal_move(turn) {
+            if check {
+                Some(GameEnd::Winner(!turn))
+            } else {
+                Some(GameEnd::Draw)
+            }
+        } else if board.has_insufficient_material() {
+            Some(GameEnd::Draw)
+        } else { None };
+
+        let message = ServerMessage::Move(played_move.clone());
+        if missing != Some(turn) {
+            debug!("game {game_id} ply {plies}: sending {message:?} to {turn:?}");
+            send(&mut players[seat(turn)], message.clone())?;
+        }
+        // the clients stop their clocks at the end of the game themselves
+        let clock = time_control.filter(|_| game_end.is_none()).map(|_| ServerMessage::Clock(clock_times(time_left)));
+        if let Some(clock) = &clock {
+            send_players(players, missing, clock.clone())?;
+        }
+        {
+            let mut watched = watched.lock().unwrap();
+            watched.moves.push(played_move.clone());
+            let with_fen = plies % DETAILS_FEN_PLIES == 0 || game_end.is_some();
+            watched.broadcast_move(MoveDetails {
+                played: played_move,
+                ply: plies,
+                san,
+                captured: taken.map_or_else(String::new, |taken| taken.letter().to_string()),
+                check,
+                mate: check && game_end.is_some(),
+                fen: if with_fen { fens[plies as usize].clone() } else { String::new() },
+            });
+            if let Some(clock) = &clock {
+                watched.broadcast(clock);
+            }
+        }
+
+        if let Some(end) = game_end {
+            info!("game {game_id} ply {plies}: game over, result {}", end.result());
+            break Ok(end);
+        }
+    }
+}
+
+/// Plays a move a client sent on the server's board, returns its SAN and the piece it took. Only legal moves of the
+/// side to move are played, anything else leaves the board as it was and says why, so a broken or malicious client
+/// can't get the game out of sync or make `Board::move_piece` panic.
+pub fn apply_move(board: &mut Board, turn: Color, played: &Move) -> Result<(String, Option<Piece>), MoveLegality> {
+    let (from, to) = (vec2![played.x1, played.y1], vec2![played.x2, played.y2]);
+    match board.classify_move(from, to, turn) {
+        MoveLegality::Legal => {
+            let san = notation::san(board, &board.moves(turn).0, from, to);
+            Ok((san, board.move_piece(from, to)))
+        }
+        legality => Err(legality),
+    }
+}
+
+/// A finished game as PGN: the Seven Tag Roster with the `date` as (year, month, day), the starting position if it
+/// isn't the standard one, and the moves in SAN
+pub fn game_pgn(
+    white: &str,
+    black: &str,
+    fen: &str,
+    moves: &[Move],
+    end: &GameEnd,
+    date: (i64, u32, u32),
+) -> Result<String, Box<dyn Error>> {
+    let (mut board, mut turn) = Board::from_fen(fen).ok_or("invalid starting position")?;
+    let (year, month, day) = date;
+    let mut tags = vec![
+        ("Event", "Online game".to_owned()),
+        ("Site", "termchess server".to_owned()),
+        ("Date", format!("{year:04}.{month:02}.{day:02}")),
+        ("Round", "-".to_owned()),
+        ("White", white.to_owned()),
+        ("Black", black.to_owned()),
+        ("Result", end.result().to_owned()),
+    ];
+    if (board, turn) != (Board::starting_position(), Color::White) {
+        tags.push(("SetUp", "1".to_owned()));
+        tags.push(("FEN", fen.to_owned()));
+    }
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        pgn.push_str(&format!("[{name} \"{}\"]\n", value.replace('\\', "\\\\").replace('"', "\\\"")));
+    }
+    pgn.push('\n');
+
+    let mut tokens = Vec::new();
+    // the server starts its games with move number 1, whatever the FEN it was given says
+    let mut number = 1;
+    for (ply, played) in moves.iter().enumerate() {
+        if turn == Color::White {
+            tokens.push(format!("{number}."));
+        } else if ply == 0 {
+            tokens.push(format!("{number}..."));
+        }
+        let (san, _) = apply_move(&mut board, turn, played)
+            .map_err(|legality| format!("move {} can't be played: {legality:?}", ply + 1))?;
+        tokens.push(san);
+        if turn == Color::Black {
+            number += 1;
+        }
+        turn = !turn;
+    }
+    tokens.push(end.result().to_owned());
+    // lines stay below 80 characters
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + 1 + token.len() > 79 {
+            pgn.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            pgn.push(' ');
+            line_len += 1;
+        }
+        line_len += token.len();
+        pgn.push_str(&token);
+    }
+    pgn.push('\n');
+    Ok(pgn)
+}
+
+/// Writes a finished game into `dir` and returns the path of the file
+fn save_game(watched: &Watched, end: &GameEnd, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let pgn = game_pgn(&watched.white, &watched.black, &watched.fen, &watched.moves, end, (year, month, day))?;
+    fs::create_dir_all(dir)?;
+    // game ids start over with every start of the server, the time keeps the files apart
+    let path = dir.join(format!(
+        "{year:04}-{month:02}-{day:02}_{:02}{:02}{:02}_game-{}.pgn",
+        secs / 3600 % 24, secs / 60 % 60, secs % 60, watched.game_id,
+    ));
+    fs::write(&path, pgn)?;
+    Ok(path)
+}
+
+/// Sends a message to both players, except one who lost the connection
+fn send_players(players: &mut [TcpStream; 2], missing: Option<Color>, message: ServerMessage) -> Result<(), BinverseError> {
+    for color in [Color::White, Color::Black] {
+        if missing != Some(color) {
+            send(&mut players[seat(color)], message.clone())?;
+        }
+    }
+    Ok(())
+}
+
+/// Ends the game because the time of `color` ran out
+fn flag(
+    players: &mut [TcpStream; 2],
+    missing: Option<Color>,
+    watched: &Mutex<Watched>,
+    board: &Board,
+    color: Color,
+    game_id: u32,
+    plies: u32,
+) -> Result<GameEnd, Box<dyn Error>> {
+    info!("game {game_id} ply {plies}: {color:?} ran out of time");
+    let message = ServerMessage::GameOver(EndReason::Flagged(color == Color::Black));
+    send_players(players, missing, message.clone())?;
+    watched.lock().unwrap().broadcast(&message);
+    Ok(if board.has_mating_material(!color) { GameEnd::Winner(!color) } else { GameEnd::Draw })
+}
+
+fn clock_times(time_left: [Duration; 2]) -> ClockTimes {
+    ClockTimes { white: millis(time_left[0]), black: millis(time_left[1]) }
+}
+
+fn abort(players: &mut [TcpStream; 2], watched: &Mutex<Watched>) -> Result<GameEnd, Box<dyn Error>> {
+    // one of the players might already be gone
+    for player in players {
+        let _ = send(player, ServerMessage::GameOver(EndReason::Aborted));
+    }
+    watched.lock().unwrap().broadcast(&ServerMessage::GameOver(EndReason::Aborted));
+    Err("game aborted".into())
+}
+
+// control characters could be used to mess with the other terminals
+fn clean_chat(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).take(MAX_CHAT_LEN).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, net::Shutdown, thread, time::Duration};
+
+    use termchess_core::{board::{Board, MoveLegality}, piece::{Color, Piece}, RULES_REVISION};
+    use vecm::vec2;
+
+    use super::{apply_move, assign_colors, greet, run_server, ServerOptions, TimeControl};
+    use crate::{
+        client::{connect, Remote},
+        protocol::{
+            recv, send, ClientMessage, ColorPreference, Features, GameInfo, Handshake, Hello, Lobby, Move, PlayerInfo,
+            Role, Rules, ServerMessage, PROTOCOL_VERSION,
+        },
+    };
+
+    /// A connection in memory, the server reads what the client sent before and writes into `output`
+    struct Duplex {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+    impl io::Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            io::Read::read(&mut self.input, buf)
+        }
+    }
+    impl io::Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            io::Write::write(&mut self.output, buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Starts a server on a free port of this host and returns its address
+    fn test_server() -> String {
+        // the OS picks a free port, which is free again for the server right after
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let options = ServerOptions { bind: [127, 0, 0, 1].into(), port, ..ServerOptions::default() };
+        thread::spawn(move || run_server(Board::starting_position(), Color::White, &options).unwrap());
+        format!("127.0.0.1:{port}")
+    }
+
+    /// Alice and Bob join the server at `address` at the same time and get a game against each other
+    fn pair_up(address: &str, prefer: ColorPreference) -> [(Remote, GameInfo); 2] {
+        let join = |name: &str| {
+            // the server might not listen yet
+            for _ in 0..50 {
+                match connect(address, name.to_owned(), prefer, |_| None) {
+                    Ok((remote, info, _)) => return (remote, info),
+                    Err(_) => thread::sleep(Duration::from_millis(20)),
+                }
+            }
+            panic!("{name} couldn't join the test server");
+        };
+        thread::scope(|scope| {
+            let alice = scope.spawn(|| join("Alice"));
+            let bob = join("Bob");
+            [alice.join().unwrap(), bob]
+        })
+    }
+
+    /// The next message from the server that is `wanted`, the ones before it are skipped
+    fn next_message(remote: &mut Remote, wanted: fn(&ServerMessage) -> bool) -> ServerMessage {
+        loop {
+            let message = remote.server.recv_timeout(Duration::from_secs(5)).unwrap();
+            if wanted(&message) {
+                return message;
+            }
+        }
+    }
+
+    /// The server only plays legal moves of the side to move that clients send, anything else leaves its board
+    /// untouched instead of panicking or corrupting the game
+    #[test]
+    fn server_moves() {
+        let (mut board, turn) = Board::from_fen("4r1k1/8/8/8/8/8/4R3/4K3 w - - 0 1").unwrap();
+        let fen = board.to_fen(turn, 0, 1);
+        for ((x1, y1, x2, y2), expected) in [
+            ((4, 1, 0, 1), MoveLegality::WouldLeaveKingInCheck),
+            ((4, 1, 4, 100), MoveLegality::OffTheBoard),
+            ((-1, 0, 0, 0), MoveLegality::OffTheBoard),
+            ((4, 7, 4, 6), MoveLegality::NotYourPiece),
+            ((0, 0, 0, 1), MoveLegality::NoPieceThere),
+            ((4, 0, 4, 1), MoveLegality::OwnPieceThere),
+        ] {
+            let played = Move { x1, y1, x2, y2 };
+            match apply_move(&mut board, turn, &played) {
+                Err(legality) if legality == expected && board.to_fen(turn, 0, 1) == fen => {}
+                result => panic!("the server answered {played:?} with {result:?}"),
+            }
+        }
+        match apply_move(&mut board, turn, &Move { x1: 4, y1: 0, x2: 5, y2: 1 }) {
+            Ok((san, None)) if san == "Kf2" && board[vec2![5, 1]] == Some((Piece::King, Color::White)) => {}
+            result => panic!("the server played Kf2 as {result:?}"),
+        }
+    }
+
+    /// Time controls are read as minutes and seconds of increment
+    #[test]
+    fn time_controls() {
+        let parsed = [("5+3", Some((300, 3))), ("0.5+0", Some((30, 0))), ("5", None), ("0+2", None), ("x+1", None)];
+        for (text, expected) in parsed {
+            let time_control = TimeControl::parse(text);
+            let got = time_control.map(|control| (control.initial.as_secs(), control.increment.as_secs()));
+            assert_eq!(got, expected, "the time control `{text}` was read as {time_control:?}");
+        }
+    }
+
+    /// The server lets players with its own rules in and refuses those of another revision with a message that names
+    /// both, also when they come back to a seat. Spectators get in either way and find out from the `SpectateInfo`.
+    /// Before that, clients with another protocol version are rejected, also those from before the handshake.
+    #[test]
+    fn rules_handshake() {
+        let connect = |hello: Option<Hello>, role, revision| {
+            let rules = Rules { revision, ..Rules::current() };
+            let mut input = Vec::new();
+            if let Some(hello) = hello {
+                send(&mut input, hello).unwrap();
+            }
+            let info = PlayerInfo {
+                name: "Alice".to_owned(), role, features: Features::SUPPORTED, rules, prefer: ColorPreference::Random,
+            };
+            send(&mut input, info).unwrap();
+            let mut connection = Duplex { input: io::Cursor::new(input), output: Vec::new() };
+            let info = greet(&mut connection).unwrap();
+            (info, connection.output)
+        };
+        let newer = Hello { protocol_version: PROTOCOL_VERSION + 1 };
+        for (hello, client) in [(Some(newer), "a newer client"), (None, "a client from before the handshake")] {
+            let (info, output) = connect(hello, Role::Player, RULES_REVISION);
+            let mut output = io::Cursor::new(output);
+            match recv(&mut output).unwrap() {
+                Handshake::Reject(reason) if info.is_none() && reason.starts_with("incompatible version") => {}
+                _ => panic!("{client} wasn't rejected for its protocol version"),
+            }
+            assert_eq!(output.position(), output.get_ref().len() as u64, "{client} got more than the rejection");
+        }
+
+        // the server's own `Hello` comes first, the rest is what the rules check answered
+        let greet = |role, revision| {
+            let (info, output) = connect(Some(Hello::current()), role, revision);
+            let mut output = io::Cursor::new(output);
+            match recv(&mut output).unwrap() {
+                Handshake::Hello(hello) if Hello::current().mismatch(&hello, "the server").is_none() => {}
+                handshake => panic!("a client with the server's version got {handshake:?}"),
+            }
+            (info, output.get_ref()[output.position() as usize..].to_vec())
+        };
+        let (info, output) = greet(Role::Player, RULES_REVISION);
+        assert!(info.is_some() && output.is_empty(), "a player with the server's rules wasn't let in");
+        let (info, output) = greet(Role::Player, RULES_REVISION + 1);
+        let expected = format!("the server uses rules rev {RULES_REVISION}, you have rev {}", RULES_REVISION + 1);
+        match recv(&output[..]).unwrap() {
+            Lobby::Mismatch(text) if info.is_none() && text.contains(&expected) => {}
+            _ => panic!("a player with another rules revision wasn't refused with the difference"),
+        }
+        let (info, output) = greet(Role::Rejoin(1234), RULES_REVISION + 1);
+        let refused = info.is_none() && matches!(recv(&output[..]).unwrap(), Lobby::Mismatch(_));
+        assert!(refused, "a player with another rules revision was let back into a seat");
+        let (info, _) = greet(Role::Spectator, RULES_REVISION + 1);
+        assert!(info.is_some(), "a spectator with another rules revision wasn't let in");
+    }
+
+    /// Players get the color they prefer unless both want the same one, then chance decides. Two players who want
+    /// White on a real server are told they play different colors and the other's name.
+    #[test]
+    fn color_assignment() {
+        use ColorPreference::{Black, Random, White};
+
+        let expected = [
+            (White, Random, [true, true]), (Random, Black, [true, true]), (White, Black, [true, true]),
+            (Black, Random, [false, false]), (Random, White, [false, false]), (Black, White, [false, false]),
+            (White, White, [true, false]), (Black, Black, [true, false]), (Random, Random, [true, false]),
+        ];
+        for (first, second, first_is_white) in expected {
+            for (coin, first_is_white) in [true, false].into_iter().zip(first_is_white) {
+                let colors = assign_colors(first, second, coin);
+                assert!(
+                    colors[0] != colors[1] && (colors[0] == Color::White) == first_is_white,
+                    "{first:?} and {second:?} with the coin on {coin} got {colors:?}",
+                );
+            }
+        }
+
+        let [(alice, alice_info), (bob, bob_info)] = pair_up(&test_server(), White);
+        for remote in [alice, bob] {
+            remote.socket.shutdown(Shutdown::Both).unwrap();
+        }
+        assert!(
+            alice_info.is_black != bob_info.is_black && alice_info.game_id == bob_info.game_id
+                && alice_info.other_player == "Bob" && bob_info.other_player == "Alice",
+            "two players who want White were told {alice_info:?} and {bob_info:?}",
+        );
+    }
+
+    /// After a game both players asking for a rematch start a new game on the same connections with the colors swapped
+    #[test]
+    fn rematch() {
+        let [(mut alice, alice_info), (mut bob, bob_info)] = pair_up(&test_server(), ColorPreference::Random);
+        // White resigns right away, Black only asks for the rematch once the game is over for the server too
+        let (white, black) = if alice_info.is_black { (&mut bob, &mut alice) } else { (&mut alice, &mut bob) };
+        white.send(0, ClientMessage::Resign).unwrap();
+        next_message(black, |message| matches!(message, ServerMessage::GameOver(_)));
+        for remote in [&mut alice, &mut bob] {
+            remote.send(0, ClientMessage::Rematch).unwrap();
+        }
+        let mut rematches = Vec::new();
+        for remote in [&mut alice, &mut bob] {
+            match next_message(remote, |message| matches!(message, ServerMessage::Rematch(_))) {
+                ServerMessage::Rematch(info) => rematches.push(info),
+                message => panic!("waited for a rematch and got {message:?}"),
+            }
+            remote.socket.shutdown(Shutdown::Both).unwrap();
+        }
+        let (alice_next, bob_next) = (&rematches[0], &rematches[1]);
+        assert!(
+            alice_next.is_black != alice_info.is_black && bob_next.is_black != bob_info.is_black
+                && alice_next.game_id == bob_next.game_id && alice_next.game_id != alice_info.game_id
+                && alice_next.other_player == "Bob" && bob_next.other_player == "Alice",
+            "the rematch after game {} started as {alice_next:?} and {bob_next:?}",
+            alice_info.game_id,
+        );
+    }
+
+    /// The accepted takeback goes back to where the game was before the move, and the server turns down requests
+    /// without a move to take back and after the end
+    #[test]
+    fn takeback() {
+        let [(mut alice, alice_info), (mut bob, _)] = pair_up(&test_server(), ColorPreference::Random);
+        let (white, black) = if alice_info.is_black { (&mut bob, &mut alice) } else { (&mut alice, &mut bob) };
+        white.send(0, ClientMessage::Move(Move { x1: 4, y1: 1, x2: 4, y2: 3 })).unwrap();
+        white.send(1, ClientMessage::TakebackRequest).unwrap();
+        next_message(black, |message| matches!(message, ServerMessage::TakebackRequested));
+        black.send(1, ClientMessage::TakebackAccept).unwrap();
+        for remote in [&mut *white, &mut *black] {
+            match next_message(remote, |message| matches!(message, ServerMessage::TookBack(_))) {
+                ServerMessage::TookBack(0) => {}
+                message => panic!("the accepted takeback of 1. e4 came back as {message:?}"),
+            }
+        }
+        // nothing is left to take back, and once the game is over it can't be either
+        white.send(0, ClientMessage::TakebackRequest).unwrap();
+        next_message(white, |message| matches!(message, ServerMessage::TakebackDeclined));
+        white.send(0, ClientMessage::Resign).unwrap();
+        white.send(0, ClientMessage::TakebackRequest).unwrap();
+        next_message(white, |message| matches!(message, ServerMessage::TakebackDeclined));
+        for remote in [&mut alice, &mut bob] {
+            remote.socket.shutdown(Shutdown::Both).unwrap();
+        }
+    }
+}